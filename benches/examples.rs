@@ -0,0 +1,89 @@
+//! Benchmarks running the programs in `examples/`
+//!
+//! Run with `cargo bench`, or `cargo bench -- fib mandelbrot` to only run
+//! some of the examples. Each example is compiled and run the same way as
+//! by the interpreter, with its output discarded, and the fastest and
+//! median times of its runs are reported.
+
+extern crate haru;
+extern crate libc;
+use haru::ast::grammar;
+use haru::compiler::Compiler;
+use haru::hanayo;
+use haru::vmbindings::vm::VmOpcode;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+// examples that don't need a terminal, native libraries or modules
+const EXAMPLES: &[&str] = &[
+    "closures",
+    "fib",
+    "fib-tail-call",
+    "fizzbuzz",
+    "mandelbrot",
+    "records",
+    "reverse-bintree",
+    "sieve",
+];
+const RUNS: usize = 10;
+
+fn run(source: &str) -> Duration {
+    let prog = grammar::start(source).unwrap();
+    let mut c = Compiler::new(true);
+    for stmt in prog {
+        stmt.emit(&mut c).unwrap();
+    }
+    c.optimize(0);
+    c.cpushop(VmOpcode::OP_HALT);
+    let mut vm = c.into_vm();
+    vm.optimize = true;
+    hanayo::init(&mut vm);
+    vm.gc_enable();
+    let start = Instant::now();
+    vm.execute();
+    start.elapsed()
+}
+
+// runs the closure with stdout redirected to /dev/null
+fn silenced<T, F: FnOnce() -> T>(f: F) -> T {
+    std::io::stdout().flush().unwrap();
+    unsafe {
+        let stdout = libc::dup(1);
+        let null = libc::open(
+            b"/dev/null\0".as_ptr() as *const libc::c_char,
+            libc::O_WRONLY,
+        );
+        libc::dup2(null, 1);
+        libc::close(null);
+        let result = f();
+        std::io::stdout().flush().unwrap();
+        libc::dup2(stdout, 1);
+        libc::close(stdout);
+        result
+    }
+}
+
+fn main() {
+    // cargo passes --bench
+    let filters: Vec<String> = std::env::args()
+        .skip(1)
+        .filter(|arg| !arg.starts_with('-'))
+        .collect();
+    let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+    println!("{:<20} {:>12} {:>12}", "example", "min (ms)", "median (ms)");
+    for name in EXAMPLES {
+        if !filters.is_empty() && !filters.iter().any(|filter| name.contains(filter.as_str())) {
+            continue;
+        }
+        let source = std::fs::read_to_string(dir.join(format!("{}.hana", name))).unwrap();
+        let mut times: Vec<Duration> = (0..RUNS).map(|_| silenced(|| run(&source))).collect();
+        times.sort();
+        let millis = |time: Duration| time.as_micros() as f64 / 1000.0;
+        println!(
+            "{:<20} {:>12.3} {:>12.3}",
+            name,
+            millis(times[0]),
+            millis(times[RUNS / 2])
+        );
+    }
+}
@@ -14,6 +14,8 @@ fn main() {
         for path in std::fs::read_dir("./src/vm").unwrap() {
             if let Some(path) = path.unwrap().path().to_str() {
                 let pstr = path.to_string();
+                // peg only reruns the build script when the grammar changes
+                println!("cargo:rerun-if-changed={}", pstr);
                 if pstr.ends_with(".c") {
                     build.file(pstr);
                 }
@@ -26,7 +28,9 @@ fn main() {
             build.flag("-pg");
         }
         if is_release {
-            build.flag("-flto");
+            // fat objects keep regular code next to the lto bytecode, so
+            // binaries that link libhana through the haru rlib still resolve
+            build.flag("-flto").flag("-ffat-lto-objects");
         }
         build
             .flag("-Wall")
@@ -19,6 +19,7 @@ pub mod ast {
     //! Provides abstract syntax trees for language blocks.
     use super::compiler;
     use super::VmOpcode;
+    use crate::vmbindings::bigint::BigInt;
     use num_traits::cast::FromPrimitive;
     use std::any::Any;
     use std::fmt;
@@ -56,39 +57,72 @@ pub mod ast {
     }
 
     macro_rules! try_nil {
-        ($e:expr) => {
+        ($self:ident, $e:expr) => {
             if let Err(_) = $e {
-                return Err(CodeGenError::NilString);
+                return Err(CodeGenError::new(CodeGenErrorKind::NilString, $self.span()));
             }
         };
     }
 
     macro_rules! op_push_str {
-        ($c:ident, $s:expr) => {
+        ($self:ident, $c:ident, $s:expr) => {
             if let Some(interned_strings) = $c.interned_strings.as_mut() {
                 if let Some(idx) = interned_strings.get_or_insert(&$s) {
                     $c.cpushop(VmOpcode::OP_PUSHSTR_INTERNED);
                     $c.cpush16(idx);
                 } else {
                     $c.cpushop(VmOpcode::OP_PUSHSTR);
-                    try_nil!($c.cpushs($s.clone()));
+                    try_nil!($self, $c.cpushs($s.clone()));
                 }
             } else {
                 $c.cpushop(VmOpcode::OP_PUSHSTR);
-                try_nil!($c.cpushs($s.clone()));
+                try_nil!($self, $c.cpushs($s.clone()));
             }
         };
     }
     // #endregion
 
-    /// Code generation result
-    #[derive(Debug)]
-    pub enum CodeGenError {
+    /// Kind of code generation error
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum CodeGenErrorKind {
         InvalidLeftHandSide,
         ExpectedIdentifier,
         ExpectedInFunction,
+        ExpectedInLoop,
+        ExpectedYieldInFunction,
         NilString,
     }
+
+    /// Code generation error, located at the offending node
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct CodeGenError {
+        pub kind: CodeGenErrorKind,
+        pub span: Span,
+    }
+    impl CodeGenError {
+        pub fn new(kind: CodeGenErrorKind, span: &Span) -> CodeGenError {
+            CodeGenError { kind, span: *span }
+        }
+
+        /// Human readable description of the error
+        pub fn message(&self) -> &'static str {
+            match self.kind {
+                CodeGenErrorKind::InvalidLeftHandSide => "invalid left hand side of assignment",
+                CodeGenErrorKind::ExpectedIdentifier => "expected an identifier",
+                CodeGenErrorKind::ExpectedInFunction => "return statement outside of a function",
+                CodeGenErrorKind::ExpectedInLoop => "loop control statement outside of a loop",
+                CodeGenErrorKind::ExpectedYieldInFunction => {
+                    "yield expression outside of a function"
+                }
+                CodeGenErrorKind::NilString => "string contains a null character",
+            }
+        }
+    }
+    impl fmt::Display for CodeGenError {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "{}", self.message())
+        }
+    }
     pub type CodeGenResult = Result<(), CodeGenError>;
 
     /// Span of the AST node, represented by a tuple of (from, to) indexes
@@ -138,7 +172,32 @@ pub mod ast {
         fn emit(&self, c: &mut compiler::Compiler) -> CodeGenResult {
             emit_begin!(self, c);
             let _smap_begin = smap_begin!(c);
-            op_push_str!(c, self.val);
+            op_push_str!(self, c, self.val);
+            emit_end!(c, _smap_begin);
+            Ok(())
+        }
+    }
+    /// Interpolated string literal
+    pub struct StrInterpolation {
+        pub _span: Span,
+        pub parts: Vec<std::boxed::Box<AST>>,
+    }
+    #[cfg_attr(tarpaulin, skip)]
+    impl fmt::Debug for StrInterpolation {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "{{\"interpolation\": {:?}}}", self.parts)
+        }
+    }
+    impl AST for StrInterpolation {
+        ast_impl!();
+        fn emit(&self, c: &mut compiler::Compiler) -> CodeGenResult {
+            emit_begin!(self, c);
+            let _smap_begin = smap_begin!(c);
+            for part in &self.parts {
+                part.emit(c)?;
+            }
+            c.cpushop(VmOpcode::OP_STR_CONCAT);
+            c.cpush16(self.parts.len() as u16);
             emit_end!(c, _smap_begin);
             Ok(())
         }
@@ -183,6 +242,30 @@ pub mod ast {
             Ok(())
         }
     }
+    /// Integer literal too large for 64 bits
+    pub struct BigIntLiteral {
+        pub _span: Span,
+        pub val: BigInt,
+    }
+    #[cfg_attr(tarpaulin, skip)]
+    impl fmt::Debug for BigIntLiteral {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "{{\"integer\": {}}}", self.val)
+        }
+    }
+    impl AST for BigIntLiteral {
+        ast_impl!();
+        #[cfg_attr(tarpaulin, skip)]
+        fn emit(&self, c: &mut compiler::Compiler) -> CodeGenResult {
+            emit_begin!(self, c);
+            let _smap_begin = smap_begin!(c);
+            c.cpushop(VmOpcode::OP_PUSH_BIGINT);
+            // the digits never contain a null byte
+            c.cpushs(self.val.to_string()).unwrap();
+            emit_end!(c, _smap_begin);
+            Ok(())
+        }
+    }
     /// Float literal
     pub struct FloatLiteral {
         pub _span: Span,
@@ -236,13 +319,82 @@ pub mod ast {
             Ok(())
         }
     }
+    /// Map expression
+    pub struct MapExpr {
+        pub _span: Span,
+        pub entries: Vec<(std::boxed::Box<AST>, std::boxed::Box<AST>)>,
+    }
+    #[cfg_attr(tarpaulin, skip)]
+    impl fmt::Debug for MapExpr {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "{{\"map\": {:?}}}", self.entries)
+        }
+    }
+    impl AST for MapExpr {
+        ast_impl!();
+        fn emit(&self, c: &mut compiler::Compiler) -> CodeGenResult {
+            emit_begin!(self, c);
+            let _smap_begin = smap_begin!(c);
+            for (key, val) in &self.entries {
+                key.emit(c)?;
+                val.emit(c)?;
+            }
+            if self.entries.len() < 0x100 {
+                c.cpushop(VmOpcode::OP_PUSH8);
+                c.cpush8(self.entries.len() as u8);
+            } else {
+                c.cpushop(VmOpcode::OP_PUSH64);
+                c.cpush64(self.entries.len() as u64);
+            }
+            c.cpushop(VmOpcode::OP_MAP_LOAD);
+            emit_end!(c, _smap_begin);
+            Ok(())
+        }
+    }
+    // Emits the definition of a function taking the args, which is followed
+    // by its body. Returns the position of the label for the function's end.
+    fn emit_function_push(
+        c: &mut compiler::Compiler, args: &[String], nrequired: usize, rest: bool,
+    ) -> usize {
+        c.cpushop(VmOpcode::OP_DEF_FUNCTION_PUSH);
+        c.cpush16(args.len() as u16);
+        let function_end = c.reserve_label16();
+        c.cpush16(nrequired as u16);
+        c.cpush8(rest as u8);
+        for arg in args {
+            c.cpushs(arg.as_str()).unwrap();
+        }
+        function_end
+    }
+    /// Ends the scope of a function's body, filling in the number of slots
+    /// reserved by its OP_ENV_NEW. Functions that yield create a generator instead.
+    fn emit_function_scope_end(c: &mut compiler::Compiler, nslot_label: usize) {
+        if c.scope_yields() {
+            c.cfill_label8(nslot_label - 1, VmOpcode::OP_GENERATOR_NEW as u8);
+        }
+        let nslots = c.unscope();
+        c.fill_label16(nslot_label, nslots);
+    }
     /// Function expression
     pub struct FunctionDefinition {
         pub _span: Span,
         pub id: Option<String>,
         pub args: Vec<String>,
+        /// Default values of the args following the required ones
+        pub defaults: Vec<std::boxed::Box<AST>>,
+        /// Whether the last arg collects the remaining positional args
+        pub rest: bool,
+        /// Patterns destructuring the args at the given positions,
+        /// whose names are placeholders
+        pub patterns: Vec<(usize, Pattern)>,
         pub stmt: std::boxed::Box<AST>,
     }
+    impl FunctionDefinition {
+        /// Number of args that must be given
+        pub fn nrequired(&self) -> usize {
+            self.args.len() - self.defaults.len() - self.rest as usize
+        }
+    }
     #[cfg_attr(tarpaulin, skip)]
     impl fmt::Debug for FunctionDefinition {
         fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -255,10 +407,14 @@ pub mod ast {
                 "{{
                 \"id\": \"{}\",
                 \"args\": {},
+                \"defaults\": {:?},
+                \"rest\": {},
                 \"stmt\": {:?},
                 \"type\": \"fnstmt\"}}",
                 self.id.as_ref().map_or("".to_string(), |x| x.clone()),
                 args,
+                self.defaults,
+                self.rest,
                 self.stmt
             )
         }
@@ -269,9 +425,8 @@ pub mod ast {
             emit_begin!(self, c);
             let _smap_begin = smap_begin!(c);
             // definition
-            c.cpushop(VmOpcode::OP_DEF_FUNCTION_PUSH);
-            c.cpush16(self.args.len() as u16);
-            let function_end = c.reserve_label16();
+            let nrequired = self.nrequired();
+            let function_end = emit_function_push(c, &self.args, nrequired, self.rest);
 
             if let Some(id) = &self.id {
                 c.set_local(id.clone());
@@ -284,6 +439,24 @@ pub mod ast {
             for arg in &self.args {
                 c.set_local(arg.clone());
             }
+            // default values are only computed for args that weren't given
+            for (i, default) in self.defaults.iter().enumerate() {
+                let slot = (nrequired + i) as u16;
+                c.cpushop(VmOpcode::OP_JARG);
+                c.cpush16(slot);
+                let default_end = c.reserve_label16();
+                default.emit(c)?;
+                c.cpushop(VmOpcode::OP_SET_LOCAL);
+                c.cpush16(slot);
+                c.cpushop(VmOpcode::OP_POP);
+                c.fill_label16(default_end, (c.clen() - default_end) as u16);
+            }
+            for (i, pattern) in &self.patterns {
+                c.cpushop(VmOpcode::OP_GET_LOCAL);
+                c.cpush16(*i as u16);
+                pattern.emit_destructure(c)?;
+                c.cpushop(VmOpcode::OP_POP);
+            }
             self.stmt.emit(c)?;
             if let Some(id) = &self.id {
                 let len = c.clen() - 1;
@@ -302,8 +475,7 @@ pub mod ast {
             };
 
             // end
-            let nslots = c.unscope();
-            c.fill_label16(nslot_label, nslots);
+            emit_function_scope_end(c, nslot_label);
             c.fill_label16(function_end, (c.clen() - function_end) as u16);
             emit_end!(c, _smap_begin);
             Ok(())
@@ -330,19 +502,22 @@ pub mod ast {
                 let any = stmt.as_any();
                 if let Some(stmt) = any.downcast_ref::<FunctionStatement>() {
                     stmt.def().emit(c)?;
-                    op_push_str!(c, stmt.def().id.as_ref().unwrap());
+                    op_push_str!(self, c, stmt.def().id.as_ref().unwrap());
                 } else if let Some(stmt) = any.downcast_ref::<RecordStatement>() {
                     stmt.def().emit(c)?;
-                    op_push_str!(c, stmt.def().id.as_ref().unwrap());
+                    op_push_str!(self, c, stmt.def().id.as_ref().unwrap());
                 } else if let Some(stmt) = any.downcast_ref::<ExprStatement>() {
                     let binexpr = stmt.expr.as_any().downcast_ref::<BinExpr>().unwrap();
                     let id = if let Some(id) = binexpr.left.as_any().downcast_ref::<Identifier>() {
                         id
                     } else {
-                        return Err(CodeGenError::InvalidLeftHandSide);
+                        return Err(CodeGenError::new(
+                            CodeGenErrorKind::InvalidLeftHandSide,
+                            binexpr.left.span(),
+                        ));
                     };
                     binexpr.right.emit(c)?;
-                    op_push_str!(c, id.val);
+                    op_push_str!(self, c, id.val);
                 }
             }
             if self.stmts.len() < 0x100 {
@@ -413,7 +588,7 @@ pub mod ast {
             //   [else]
             //   [done]
             self.cond.emit(c)?;
-            c.cpushop(VmOpcode::OP_JNCOND); // TODO: maybe do peephole opt?
+            c.cpushop(VmOpcode::OP_JNCOND); // NOT; JNCOND is fused in optimizer.rs
             let else_label = c.reserve_label16();
 
             if is_tail {
@@ -557,11 +732,30 @@ pub mod ast {
                     } else if let Some(memexpr) = any.downcast_ref::<MemExpr>() {
                         self.right.emit(c)?;
                         memexpr._emit(c, MemExprEmit::SetOp)?;
+                    } else if let Some(slice) = any.downcast_ref::<SliceExpr>() {
+                        self.right.emit(c)?;
+                        slice._emit(c, true)?;
                     } else if let Some(callexpr) = any.downcast_ref::<CallExpr>() {
+                        if !callexpr.kwargs.is_empty() {
+                            return Err(CodeGenError::new(
+                                CodeGenErrorKind::InvalidLeftHandSide,
+                                self.left.span(),
+                            ));
+                        }
+                        let mut args = Vec::new();
+                        for arg in &callexpr.args {
+                            if let Some(arg) = arg.as_any().downcast_ref::<Identifier>() {
+                                args.push(arg.val.clone());
+                            } else {
+                                return Err(CodeGenError::new(
+                                    CodeGenErrorKind::ExpectedIdentifier,
+                                    arg.span(),
+                                ));
+                            }
+                        }
+
                         // definition
-                        c.cpushop(VmOpcode::OP_DEF_FUNCTION_PUSH);
-                        c.cpush16(callexpr.args.len() as u16);
-                        let function_end = c.reserve_label16();
+                        let function_end = emit_function_push(c, &args, args.len(), false);
 
                         c.set_local(
                             if let Some(callee) =
@@ -569,7 +763,10 @@ pub mod ast {
                             {
                                 callee.val.clone()
                             } else {
-                                return Err(CodeGenError::ExpectedIdentifier);
+                                return Err(CodeGenError::new(
+                                    CodeGenErrorKind::ExpectedIdentifier,
+                                    callexpr.callee.span(),
+                                ));
                             },
                         );
                         c.scope();
@@ -577,14 +774,8 @@ pub mod ast {
                         // body
                         c.cpushop(VmOpcode::OP_ENV_NEW);
                         let nslot_label = c.reserve_label16();
-                        for arg in &callexpr.args {
-                            c.set_local(
-                                if let Some(arg) = arg.as_any().downcast_ref::<Identifier>() {
-                                    arg.val.clone()
-                                } else {
-                                    return Err(CodeGenError::ExpectedIdentifier);
-                                },
-                            );
+                        for arg in args {
+                            c.set_local(arg);
                         }
 
                         if let Some(expr) = self.right.as_any().downcast_ref::<CallExpr>() {
@@ -598,8 +789,7 @@ pub mod ast {
                         }
 
                         // end
-                        let nslots = c.unscope();
-                        c.fill_label16(nslot_label, nslots);
+                        emit_function_scope_end(c, nslot_label);
                         c.fill_label16(function_end, (c.clen() - function_end) as u16);
 
                         let id = if let Some(id) =
@@ -607,14 +797,20 @@ pub mod ast {
                         {
                             id.val.clone()
                         } else {
-                            return Err(CodeGenError::ExpectedIdentifier);
+                            return Err(CodeGenError::new(
+                                CodeGenErrorKind::ExpectedIdentifier,
+                                callexpr.callee.span(),
+                            ));
                         };
                         if id != "_" {
                             // _ for id is considered a anonymous function decl
                             c.emit_set_var(id, true);
                         }
                     } else {
-                        return Err(CodeGenError::InvalidLeftHandSide);
+                        return Err(CodeGenError::new(
+                            CodeGenErrorKind::InvalidLeftHandSide,
+                            self.left.span(),
+                        ));
                     }
                 }
                 BinOp::Adds | BinOp::Subs | BinOp::Muls | BinOp::Divs | BinOp::Mods => {
@@ -631,7 +827,7 @@ pub mod ast {
                     if let Some(id) = any.downcast_ref::<Identifier>() {
                         c.emit_get_var(id.val.clone());
                         self.right.emit(c)?;
-                        c.cpushop(opcode.clone());
+                        c.cpushop(opcode);
                         match opcode {
                             VmOpcode::OP_IADD | VmOpcode::OP_IMUL => {
                                 in_place_addr = c.clen();
@@ -653,17 +849,19 @@ pub mod ast {
                                 None
                             }
                         };
+                        // x["key"] may get and set a map's entry
+                        let indexed = memexpr.right.as_any().is::<StrLiteral>();
                         // prologue
                         if val.is_some() && !memexpr.is_expr {
-                            c.cpushop(VmOpcode::OP_MEMBER_GET_NO_POP);
-                            try_nil!(c.cpushs(val.unwrap().clone()));
+                            c.cpushop(member_get_op(indexed, true));
+                            try_nil!(self, c.cpushs(val.unwrap().clone()));
                         } else {
                             memexpr.right.emit(c)?;
                             c.cpushop(VmOpcode::OP_INDEX_GET_NO_POP);
                         }
                         // body
                         self.right.emit(c)?;
-                        c.cpushop(opcode.clone());
+                        c.cpushop(opcode);
                         match opcode {
                             VmOpcode::OP_IADD | VmOpcode::OP_IMUL => {
                                 in_place_addr = c.clen();
@@ -677,10 +875,10 @@ pub mod ast {
                             let len = c.clen();
                             c.cfill_label8(in_place_addr, (len - in_place_addr) as u8);
                         }
-                        if val.is_some() && !memexpr.is_expr {
+                        if val.is_some() && !memexpr.is_expr && !indexed {
                             c.cpushop(VmOpcode::OP_SWAP);
                             c.cpushop(VmOpcode::OP_MEMBER_SET);
-                            try_nil!(c.cpushs(val.unwrap().clone()));
+                            try_nil!(self, c.cpushs(val.unwrap().clone()));
                         } else {
                             // otherwise, do OP_INDEX_SET as normal
                             c.cpushop(VmOpcode::OP_SWAP);
@@ -690,7 +888,10 @@ pub mod ast {
                         emit_end!(c, _smap_begin);
                         return Ok(());
                     } else {
-                        return Err(CodeGenError::InvalidLeftHandSide);
+                        return Err(CodeGenError::new(
+                            CodeGenErrorKind::InvalidLeftHandSide,
+                            self.left.span(),
+                        ));
                     }
                     if in_place_addr != std::usize::MAX {
                         // jmp here if we can do it in place
@@ -745,6 +946,16 @@ pub mod ast {
         pub is_expr: bool,
         pub is_namespace: bool,
     }
+    // opcode getting a static key, which gets a map's entry
+    // rather than its member if the key is `indexed` (x["key"])
+    fn member_get_op(indexed: bool, no_pop: bool) -> VmOpcode {
+        match (indexed, no_pop) {
+            (false, false) => VmOpcode::OP_MEMBER_GET,
+            (false, true) => VmOpcode::OP_MEMBER_GET_NO_POP,
+            (true, false) => VmOpcode::OP_MEMBER_INDEX_GET,
+            (true, true) => VmOpcode::OP_MEMBER_INDEX_GET_NO_POP,
+        }
+    }
     #[derive(PartialEq)]
     enum MemExprEmit {
         Default,
@@ -782,21 +993,25 @@ pub mod ast {
                             return Ok(());
                         }
                     }
-                    // or optimize statically
-                    c.cpushop(VmOpcode::OP_MEMBER_SET);
-                    try_nil!(c.cpushs(val.clone()));
+                    // or optimize statically (x["key"] may set a map's entry)
+                    if any.is::<StrLiteral>() {
+                        self.right.emit(c)?;
+                        c.cpushop(VmOpcode::OP_INDEX_SET);
+                    } else {
+                        c.cpushop(VmOpcode::OP_MEMBER_SET);
+                        try_nil!(self, c.cpushs(val.clone()));
+                    }
                 } else {
                     // do it normally
                     self.right.emit(c)?;
                     c.cpushop(VmOpcode::OP_INDEX_SET);
                 }
             } else if val.is_some() && !self.is_expr {
-                c.cpushop(if emit_type == MemExprEmit::MethodCall {
-                    VmOpcode::OP_MEMBER_GET_NO_POP
-                } else {
-                    VmOpcode::OP_MEMBER_GET
-                });
-                try_nil!(c.cpushs(val.unwrap().clone()));
+                c.cpushop(member_get_op(
+                    any.is::<StrLiteral>(),
+                    emit_type == MemExprEmit::MethodCall,
+                ));
+                try_nil!(self, c.cpushs(val.unwrap().clone()));
             } else {
                 self.right.emit(c)?;
                 c.cpushop(if emit_type == MemExprEmit::MethodCall {
@@ -826,39 +1041,132 @@ pub mod ast {
         }
     }
 
+    /// Slice expressions
+    pub struct SliceExpr {
+        pub _span: Span,
+        pub left: std::boxed::Box<AST>,
+        pub from: Option<std::boxed::Box<AST>>,
+        pub to: Option<std::boxed::Box<AST>>,
+        pub step: Option<std::boxed::Box<AST>>,
+    }
+    impl SliceExpr {
+        fn _emit(&self, c: &mut compiler::Compiler, is_set: bool) -> CodeGenResult {
+            emit_begin!(self, c);
+            let _smap_begin = smap_begin!(c);
+            self.left.emit(c)?;
+            // missing bounds are given as nil
+            for bound in &[&self.from, &self.to, &self.step] {
+                match bound {
+                    Some(bound) => bound.emit(c)?,
+                    None => c.cpushop(VmOpcode::OP_PUSH_NIL),
+                }
+            }
+            c.cpushop(if is_set {
+                VmOpcode::OP_SLICE_SET
+            } else {
+                VmOpcode::OP_SLICE_GET
+            });
+            emit_end!(c, _smap_begin);
+            Ok(())
+        }
+    }
+    #[cfg_attr(tarpaulin, skip)]
+    impl fmt::Debug for SliceExpr {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(
+                f,
+                "{{\"left\": {:?}, \"from\": {:?}, \"to\": {:?}, \"step\": {:?}, \"op\": \"slice\"}}",
+                self.left, self.from, self.to, self.step
+            )
+        }
+    }
+    impl AST for SliceExpr {
+        ast_impl!();
+        fn emit(&self, c: &mut compiler::Compiler) -> CodeGenResult {
+            self._emit(c, false)
+        }
+    }
+
+    /// Yield expressions
+    pub struct YieldExpr {
+        pub _span: Span,
+        pub expr: Option<std::boxed::Box<AST>>,
+    }
+    #[cfg_attr(tarpaulin, skip)]
+    impl fmt::Debug for YieldExpr {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "{{\"expr\": {:?}, \"type\": \"yield\"}}", self.expr)
+        }
+    }
+    impl AST for YieldExpr {
+        ast_impl!();
+        fn emit(&self, c: &mut compiler::Compiler) -> CodeGenResult {
+            emit_begin!(self, c);
+            let _smap_begin = smap_begin!(c);
+            if !c.is_in_function() {
+                return Err(CodeGenError::new(
+                    CodeGenErrorKind::ExpectedYieldInFunction,
+                    self.span(),
+                ));
+            }
+            match &self.expr {
+                Some(expr) => expr.emit(c)?,
+                None => c.cpushop(VmOpcode::OP_PUSH_NIL),
+            }
+            c.set_yields();
+            c.cpushop(VmOpcode::OP_YIELD);
+            emit_end!(c, _smap_begin);
+            Ok(())
+        }
+    }
+
     /// Call expressions
     pub struct CallExpr {
         pub _span: Span,
         pub callee: std::boxed::Box<AST>,
         pub args: Vec<std::boxed::Box<AST>>,
+        /// Keyword args given after the positional ones
+        pub kwargs: Vec<(String, std::boxed::Box<AST>)>,
     }
     impl CallExpr {
         fn _emit(&self, c: &mut compiler::Compiler, is_tail: bool) -> CodeGenResult {
             emit_begin!(self, c);
             let _smap_begin = smap_begin!(c);
-            let op = if is_tail {
+            let op = if !self.kwargs.is_empty() {
+                VmOpcode::OP_CALL_KW
+            } else if is_tail {
                 VmOpcode::OP_RETCALL
             } else {
                 VmOpcode::OP_CALL
             };
+            for (_, arg) in self.kwargs.iter().rev() {
+                arg.emit(c)?;
+            }
             for arg in self.args.iter().rev() {
                 arg.emit(c)?;
             }
+            let mut nargs = self.args.len() as u16;
             if let Some(memexpr) = self.callee.as_any().downcast_ref::<MemExpr>() {
-                let right = memexpr.right.as_any();
                 if memexpr.is_namespace {
                     memexpr._emit(c, MemExprEmit::Default)?;
-                    c.cpushop(op);
-                    c.cpush16(self.args.len() as u16);
                 } else {
                     memexpr._emit(c, MemExprEmit::MethodCall)?;
-                    c.cpushop(op);
-                    c.cpush16((self.args.len() as u16) + 1);
+                    nargs += 1;
                 }
             } else {
                 self.callee.emit(c)?;
-                c.cpushop(op);
-                c.cpush16(self.args.len() as u16);
+            }
+            c.cpushop(op);
+            c.cpush16(nargs);
+            if !self.kwargs.is_empty() {
+                c.cpush16(self.kwargs.len() as u16);
+                for (name, _) in &self.kwargs {
+                    try_nil!(self, c.cpushs(name.as_str()));
+                }
+                // calls with keyword args aren't tail calls
+                if is_tail {
+                    c.cpushop(VmOpcode::OP_RET);
+                }
             }
             emit_end!(c, _smap_begin);
             Ok(())
@@ -867,10 +1175,17 @@ pub mod ast {
     #[cfg_attr(tarpaulin, skip)]
     impl fmt::Debug for CallExpr {
         fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            let kwargs: Vec<String> = self
+                .kwargs
+                .iter()
+                .map(|(name, arg)| format!("{{\"{}\": {:?}}}", name, arg))
+                .collect();
             write!(
                 f,
-                "{{\"callee\": {:?}, \"args\": {:?}, \"op\": \"call\"}}",
-                self.callee, self.args
+                "{{\"callee\": {:?}, \"args\": {:?}, \"kwargs\": [{}], \"op\": \"call\"}}",
+                self.callee,
+                self.args,
+                kwargs.join(",")
             )
         }
     }
@@ -885,7 +1200,365 @@ pub mod ast {
         MemExprIden(std::boxed::Box<AST>),
         MemExprNs(std::boxed::Box<AST>),
         MemExpr(std::boxed::Box<AST>),
-        CallExpr(Vec<std::boxed::Box<AST>>),
+        CallExpr((Vec<std::boxed::Box<AST>>, Vec<(String, std::boxed::Box<AST>)>)),
+        /// Bounds and step of the slice, then where it ends
+        Slice(
+            (
+                Option<std::boxed::Box<AST>>,
+                Option<std::boxed::Box<AST>>,
+                Option<std::boxed::Box<AST>>,
+            ),
+            usize,
+        ),
+    }
+    impl CallExprArm {
+        /// Arm for indexing with the expression, string literal keys are static
+        pub fn index(e: std::boxed::Box<dyn AST>) -> CallExprArm {
+            if e.as_any().downcast_ref::<StrLiteral>().is_some() {
+                CallExprArm::MemExprIden(e)
+            } else {
+                CallExprArm::MemExpr(e)
+            }
+        }
+        /// Applies the arm to the expression on its left
+        pub fn apply(self, left: std::boxed::Box<AST>) -> std::boxed::Box<AST> {
+            match self {
+                CallExprArm::MemExprIden(x) => Box::new(MemExpr {
+                    _span: (left.span().0, x.span().1),
+                    left,
+                    right: x,
+                    is_expr: false,
+                    is_namespace: false,
+                }),
+                CallExprArm::MemExprNs(x) => Box::new(MemExpr {
+                    _span: (left.span().0, x.span().1),
+                    left,
+                    right: x,
+                    is_expr: false,
+                    is_namespace: true,
+                }),
+                CallExprArm::MemExpr(x) => Box::new(MemExpr {
+                    _span: (left.span().0, x.span().1),
+                    left,
+                    right: x,
+                    is_expr: true,
+                    is_namespace: false,
+                }),
+                CallExprArm::CallExpr((x, kw)) => Box::new(CallExpr {
+                    _span: (
+                        left.span().0,
+                        if let Some((_, last)) = kw.last() {
+                            last.span().1
+                        } else if let Some(last) = x.last() {
+                            last.span().1
+                        } else {
+                            left.span().0
+                        },
+                    ),
+                    callee: left,
+                    args: x,
+                    kwargs: kw,
+                }),
+                CallExprArm::Slice((from, to, step), end) => Box::new(SliceExpr {
+                    _span: (left.span().0, end),
+                    left,
+                    from,
+                    to,
+                    step,
+                }),
+            }
+        }
+    }
+    /// Pattern of a match case
+    pub enum Pattern {
+        /// `_`, matches anything
+        Wildcard,
+        /// Identifier, matches anything and binds it to the variable
+        Bind(String, Span),
+        /// Integer, float or string literal, matches values equal to it
+        Literal(std::boxed::Box<AST>),
+        /// `of Record { fields }`, matches values whose prototype
+        /// chain contains the record, then the fields if there are any
+        Of(std::boxed::Box<AST>, Vec<(String, Pattern)>),
+        /// `[a, *rest, b]`, matches arrays of exactly as many elements,
+        /// or at least as many if there's a rest element, which is
+        /// given with its position and is a wildcard or a binding
+        Array(Vec<Pattern>, Option<(usize, std::boxed::Box<Pattern>)>),
+        /// `{ x, y: pattern }`, matches records with all of the keys
+        Record(Vec<(String, Pattern)>),
+    }
+    impl Pattern {
+        // Emits code testing the value on top of the stack, leaving it there.
+        // Each jump taken when it doesn't match is collected with how many
+        // values the test pushed on top of the matched value.
+        fn emit(
+            &self,
+            c: &mut compiler::Compiler,
+            depth: usize,
+            fails: &mut Vec<(usize, usize)>,
+        ) -> CodeGenResult {
+            match self {
+                Pattern::Wildcard => {}
+                Pattern::Bind(id, _) => c.emit_set_var(id.clone(), false),
+                Pattern::Literal(literal) => {
+                    literal.emit(c)?;
+                    c.cpushop(VmOpcode::OP_MATCH_EQ);
+                    fails.push((c.reserve_label16(), depth));
+                }
+                Pattern::Of(record, fields) => {
+                    c.cpushop(VmOpcode::OP_DUP);
+                    record.emit(c)?;
+                    c.cpushop(VmOpcode::OP_OF);
+                    c.cpushop(VmOpcode::OP_JNCOND);
+                    fails.push((c.reserve_label16(), depth));
+                    Pattern::emit_fields(fields, c, depth, fails)?;
+                }
+                Pattern::Array(elems, rest) => {
+                    c.cpushop(VmOpcode::OP_MATCH_ARRAY);
+                    fails.push((c.reserve_label16(), depth));
+                    c.cpush16(elems.len() as u16);
+                    c.cpush8(if rest.is_some() { 1 } else { 0 });
+                    let rest_pos = rest.as_ref().map_or(elems.len(), |rest| rest.0);
+                    for (i, elem) in elems.iter().enumerate() {
+                        if let Pattern::Wildcard = elem {
+                            continue;
+                        }
+                        // elements after the rest are indexed from the end
+                        let index = if i < rest_pos {
+                            i as i16
+                        } else {
+                            (i as isize - elems.len() as isize) as i16
+                        };
+                        c.cpushop(VmOpcode::OP_MATCH_INDEX);
+                        c.cpush16(index as u16);
+                        elem.emit(c, depth + 1, fails)?;
+                        c.cpushop(VmOpcode::OP_POP);
+                    }
+                    if let Some((_, rest)) = rest {
+                        if let Pattern::Bind(id, _) = rest.as_ref() {
+                            c.cpushop(VmOpcode::OP_MATCH_REST);
+                            c.cpush16(rest_pos as u16);
+                            c.cpush16((elems.len() - rest_pos) as u16);
+                            c.emit_set_var(id.clone(), false);
+                            c.cpushop(VmOpcode::OP_POP);
+                        }
+                    }
+                }
+                Pattern::Record(fields) => Pattern::emit_fields(fields, c, depth, fails)?,
+            }
+            Ok(())
+        }
+
+        // Emits code destructuring the value on top of the stack, leaving
+        // it there, and stopping with an error if it doesn't match.
+        fn emit_destructure(&self, c: &mut compiler::Compiler) -> CodeGenResult {
+            if let Pattern::Bind(id, _) = self {
+                c.emit_set_var(id.clone(), false);
+                return Ok(());
+            }
+            let mut fails = Vec::new();
+            self.emit(c, 0, &mut fails)?;
+            c.cpushop(VmOpcode::OP_JMP);
+            let done_label = c.reserve_label16();
+            let max_depth = fails.iter().map(|fail| fail.1).max().unwrap_or(0);
+            for depth in (0..=max_depth).rev() {
+                for &(label, _) in fails.iter().filter(|fail| fail.1 == depth) {
+                    c.fill_label16(label, (c.clen() - label) as u16);
+                }
+                if depth > 0 {
+                    c.cpushop(VmOpcode::OP_POP);
+                }
+            }
+            c.cpushop(VmOpcode::OP_MATCH_FAIL);
+            c.fill_label16(done_label, (c.clen() - done_label) as u16);
+            Ok(())
+        }
+
+        fn emit_fields(
+            fields: &[(String, Pattern)],
+            c: &mut compiler::Compiler,
+            depth: usize,
+            fails: &mut Vec<(usize, usize)>,
+        ) -> CodeGenResult {
+            for (key, pattern) in fields {
+                c.cpushop(VmOpcode::OP_MATCH_MEMBER);
+                fails.push((c.reserve_label16(), depth));
+                c.cpushs(key.as_str()).unwrap();
+                pattern.emit(c, depth + 1, fails)?;
+                c.cpushop(VmOpcode::OP_POP);
+            }
+            Ok(())
+        }
+    }
+    #[cfg_attr(tarpaulin, skip)]
+    impl fmt::Debug for Pattern {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            let fields_fmt = |fields: &Vec<(String, Pattern)>| -> String {
+                let fields: Vec<String> = fields
+                    .iter()
+                    .map(|(key, pattern)| format!("{{\"{}\": {:?}}}", key, pattern))
+                    .collect();
+                format!("[{}]", fields.join(","))
+            };
+            match self {
+                Pattern::Wildcard => write!(f, "{{\"type\": \"wildcard\"}}"),
+                Pattern::Bind(id, _) => write!(f, "{{\"bind\": \"{}\"}}", id),
+                Pattern::Literal(literal) => write!(f, "{{\"literal\": {:?}}}", literal),
+                Pattern::Of(record, fields) => write!(
+                    f,
+                    "{{\"of\": {:?}, \"fields\": {}}}",
+                    record,
+                    fields_fmt(fields)
+                ),
+                Pattern::Array(elems, rest) => match rest {
+                    Some((pos, rest)) => write!(
+                        f,
+                        "{{\"elems\": {:?}, \"rest\": {:?}, \"rest_pos\": {}}}",
+                        elems, rest, pos
+                    ),
+                    None => write!(f, "{{\"elems\": {:?}}}", elems),
+                },
+                Pattern::Record(fields) => write!(f, "{{\"fields\": {}}}", fields_fmt(fields)),
+            }
+        }
+    }
+    /// Body of a match case
+    pub enum MatchBody {
+        /// `then expr`, the value of the match
+        Expr(std::boxed::Box<AST>),
+        /// Statements, after which the match evaluates to nil
+        Stmts(Vec<std::boxed::Box<AST>>),
+    }
+    /// Match case
+    pub struct MatchCase {
+        pub _span: Span,
+        pub pattern: Pattern,
+        pub guard: Option<std::boxed::Box<AST>>,
+        pub body: MatchBody,
+    }
+    #[cfg_attr(tarpaulin, skip)]
+    impl fmt::Debug for MatchCase {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            let guard = match &self.guard {
+                Some(guard) => format!("{:?}", guard),
+                None => "null".to_string(),
+            };
+            let body = match &self.body {
+                MatchBody::Expr(expr) => format!("{:?}", expr),
+                MatchBody::Stmts(stmts) => format!("{:?}", stmts),
+            };
+            write!(
+                f,
+                "{{\"pattern\": {:?}, \"guard\": {}, \"body\": {}}}",
+                self.pattern, guard, body
+            )
+        }
+    }
+    /// Match expression
+    pub struct MatchExpr {
+        pub _span: Span,
+        pub expr: std::boxed::Box<AST>,
+        pub cases: Vec<MatchCase>,
+    }
+    #[cfg_attr(tarpaulin, skip)]
+    impl fmt::Debug for MatchExpr {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(
+                f,
+                "{{\"expr\": {:?}, \"cases\": {:?}, \"type\": \"match\"}}",
+                self.expr, self.cases
+            )
+        }
+    }
+    impl AST for MatchExpr {
+        ast_impl!();
+        fn emit(&self, c: &mut compiler::Compiler) -> CodeGenResult {
+            emit_begin!(self, c);
+            let _smap_begin = smap_begin!(c);
+            // Pseudo code of the generated bytecode
+            //   [value]
+            //   [pattern], jumping to next_case if it doesn't match
+            //   [guard]
+            //   jncond [next_case]
+            //   pop
+            //   [body]
+            //   jmp [done]
+            //   pop values pushed by the pattern
+            //   next_case: ...
+            //   pop
+            //   push nil
+            //   done:
+            self.expr.emit(c)?;
+            let mut done_labels = Vec::new();
+            for case in &self.cases {
+                let mut fails = Vec::new();
+                case.pattern.emit(c, 0, &mut fails)?;
+                if let Some(guard) = &case.guard {
+                    guard.emit(c)?;
+                    c.cpushop(VmOpcode::OP_JNCOND);
+                    fails.push((c.reserve_label16(), 0));
+                }
+                c.cpushop(VmOpcode::OP_POP);
+                match &case.body {
+                    MatchBody::Expr(expr) => expr.emit(c)?,
+                    MatchBody::Stmts(stmts) => {
+                        for stmt in stmts {
+                            stmt.emit(c)?;
+                        }
+                        c.cpushop(VmOpcode::OP_PUSH_NIL);
+                    }
+                }
+                c.cpushop(VmOpcode::OP_JMP);
+                done_labels.push(c.reserve_label16());
+                // each failing test lands where the values it
+                // pushed are popped
+                let max_depth = fails.iter().map(|fail| fail.1).max().unwrap_or(0);
+                for depth in (0..=max_depth).rev() {
+                    for &(label, _) in fails.iter().filter(|fail| fail.1 == depth) {
+                        c.fill_label16(label, (c.clen() - label) as u16);
+                    }
+                    if depth > 0 {
+                        c.cpushop(VmOpcode::OP_POP);
+                    }
+                }
+            }
+            c.cpushop(VmOpcode::OP_POP);
+            c.cpushop(VmOpcode::OP_PUSH_NIL);
+            for label in done_labels {
+                c.fill_label16(label, (c.clen() - label) as u16);
+            }
+            emit_end!(c, _smap_begin);
+            Ok(())
+        }
+    }
+    /// Destructuring assignment expression
+    pub struct DestructureExpr {
+        pub _span: Span,
+        pub pattern: Pattern,
+        pub expr: std::boxed::Box<AST>,
+    }
+    #[cfg_attr(tarpaulin, skip)]
+    impl fmt::Debug for DestructureExpr {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(
+                f,
+                "{{\"pattern\": {:?}, \"expr\": {:?}, \"op\": \"destructure\"}}",
+                self.pattern, self.expr
+            )
+        }
+    }
+    impl AST for DestructureExpr {
+        ast_impl!();
+        fn emit(&self, c: &mut compiler::Compiler) -> CodeGenResult {
+            emit_begin!(self, c);
+            let _smap_begin = smap_begin!(c);
+            // the value is computed before any variable is set,
+            // so [a, b] = [b, a] swaps them
+            self.expr.emit(c)?;
+            self.pattern.emit_destructure(c)?;
+            emit_end!(c, _smap_begin);
+            Ok(())
+        }
     }
 
     // #region statement
@@ -928,7 +1601,7 @@ pub mod ast {
             //   [else]
             //   [done]
             self.expr.emit(c)?;
-            c.cpushop(VmOpcode::OP_JNCOND); // TODO: maybe do peephole opt?
+            c.cpushop(VmOpcode::OP_JNCOND); // NOT; JNCOND is fused in optimizer.rs
             let else_label = c.reserve_label16();
             self.then.emit(c)?;
             if let Some(alt) = &self.alt {
@@ -1074,7 +1747,7 @@ pub mod ast {
     /// For..in statements
     pub struct ForInStatement {
         pub _span: Span,
-        pub id: String,
+        pub pattern: Pattern,
         pub expr: std::boxed::Box<AST>,
         pub stmt: std::boxed::Box<AST>,
     }
@@ -1084,11 +1757,11 @@ pub mod ast {
             write!(
                 f,
                 "{{
-                \"id\": {:?},
+                \"pattern\": {:?},
                 \"expr\": {:?},
                 \"statement\": {:?},
                 \"type\": \"forinstmt\"}}",
-                self.id, self.expr, self.stmt
+                self.pattern, self.expr, self.stmt
             )
         }
     }
@@ -1100,7 +1773,7 @@ pub mod ast {
             // code:
             //  [PUSH array]
             //  next_it: OP_FOR [end]
-            //  set id
+            //  set id (or destructure)
             //  [body]
             //  jmp [next_it]
             //  [end]
@@ -1111,7 +1784,7 @@ pub mod ast {
             let next_it_label = c.clen();
             c.cpushop(VmOpcode::OP_FOR_IN);
             let end_label = c.reserve_label16();
-            c.emit_set_var(self.id.clone(), false);
+            self.pattern.emit_destructure(c)?;
             c.cpushop(VmOpcode::OP_POP);
             self.stmt.emit(c)?;
             c.cpushop(VmOpcode::OP_JMP);
@@ -1137,6 +1810,12 @@ pub mod ast {
         fn emit(&self, c: &mut compiler::Compiler) -> CodeGenResult {
             emit_begin!(self, c);
             let _smap_begin = smap_begin!(c);
+            if !c.is_in_loop() {
+                return Err(CodeGenError::new(
+                    CodeGenErrorKind::ExpectedInLoop,
+                    self.span(),
+                ));
+            }
             c.cpushop(VmOpcode::OP_JMP);
             c.loop_continue();
             emit_end!(c, _smap_begin);
@@ -1158,6 +1837,12 @@ pub mod ast {
         fn emit(&self, c: &mut compiler::Compiler) -> CodeGenResult {
             emit_begin!(self, c);
             let _smap_begin = smap_begin!(c);
+            if !c.is_in_loop() {
+                return Err(CodeGenError::new(
+                    CodeGenErrorKind::ExpectedInLoop,
+                    self.span(),
+                ));
+            }
             c.cpushop(VmOpcode::OP_JMP);
             c.loop_break();
             emit_end!(c, _smap_begin);
@@ -1217,7 +1902,10 @@ pub mod ast {
             emit_begin!(self, c);
             let _smap_begin = smap_begin!(c);
             if !c.is_in_function() {
-                return Err(CodeGenError::ExpectedInFunction);
+                return Err(CodeGenError::new(
+                    CodeGenErrorKind::ExpectedInFunction,
+                    self.span(),
+                ));
             }
             match &self.expr {
                 Some(expr) => {
@@ -1293,17 +1981,17 @@ pub mod ast {
             let mut cases_to_fill: Vec<usize> = Vec::new();
             for case in &self.cases {
                 // function will take in 1 arg if id is set
-                c.cpushop(VmOpcode::OP_DEF_FUNCTION_PUSH);
-                c.cpush16(if case.id.is_some() { 1 } else { 0 });
-                let body_start = c.reserve_label16();
-                // id
-                if let Some(id) = &case.id {
-                    let id = id
-                        .as_any()
+                let id = case.id.as_ref().map(|id| {
+                    id.as_any()
                         .downcast_ref::<Identifier>()
                         .unwrap()
                         .val
-                        .clone();
+                        .clone()
+                });
+                let args: Vec<String> = id.iter().cloned().collect();
+                let body_start = emit_function_push(c, &args, args.len(), false);
+                // id
+                if let Some(id) = id {
                     c.emit_set_var(id, false);
                     c.cpushop(VmOpcode::OP_POP);
                 }
@@ -1396,6 +2084,7 @@ pub mod ast {
     pub struct UseStatement {
         pub _span: Span,
         pub path: String,
+        pub namespace: Option<String>,
     }
     #[cfg_attr(tarpaulin, skip)]
     impl fmt::Debug for UseStatement {
@@ -1408,8 +2097,17 @@ pub mod ast {
         fn emit(&self, c: &mut compiler::Compiler) -> CodeGenResult {
             emit_begin!(self, c);
             let _smap_begin = smap_begin!(c);
-            c.cpushop(VmOpcode::OP_USE);
-            try_nil!(c.cpushs(self.path.clone()));
+            if self.namespace.is_some() {
+                c.cpushop(VmOpcode::OP_USE_AS);
+            } else {
+                c.cpushop(VmOpcode::OP_USE);
+            }
+            try_nil!(self, c.cpushs(self.path.clone()));
+            if let Some(namespace) = &self.namespace {
+                // bind the module's exports
+                c.emit_set_var(namespace.clone(), false);
+                c.cpushop(VmOpcode::OP_POP);
+            }
             emit_end!(c, _smap_begin);
             Ok(())
         }
@@ -0,0 +1,435 @@
+//! Provides a serialized container for compiled bytecode.
+//!
+//! Raw bytecode alone can't be run on its own, as it references
+//! the interned string table and relies on the source maps and symbols
+//! for error reporting. The container stores all of these together:
+//!
+//! ```text
+//! magic      "HANA"
+//! format     u16
+//! version    null-terminated interpreter version
+//! code       u32 length + bytes
+//! interned   u32 count + strings
+//! files      u32 count + strings
+//! sources    u32 count + strings
+//! loaded     u32 count + strings (paths of loaded modules)
+//! smap       u32 count + (file range, bytecode range, fileno)
+//! symbols    u32 count + (bytecode index, symbol)
+//! ```
+//!
+//! All integers are stored in big endian and strings are stored
+//! with a u32 length prefix.
+//!
+//! ```
+//! use haru::{ast, bytecode};
+//! use haru::compiler::Compiler;
+//! use haru::vmbindings::vm::VmOpcode;
+//! let mut c = Compiler::new(true);
+//! for stmt in ast::grammar::start("print('Hello World')\n").unwrap() {
+//!     stmt.emit(&mut c).unwrap();
+//! }
+//! c.cpushop(VmOpcode::OP_HALT);
+//! let bytes = bytecode::serialize(&c);
+//! let c = bytecode::deserialize(&bytes).unwrap();
+//! ```
+//!
+//! Modules imported with `use` are cached in this format next to their
+//! sources (`module.hana` is cached as `module.hanac`). Cache files begin
+//! with a key describing the source file they were compiled from:
+//!
+//! ```text
+//! magic      "HANC"
+//! mtime      u64 seconds + u32 nanoseconds
+//! size       u64
+//! hash       u64 hash of the source code
+//! optimized  u8 (1 if the module was optimized, 0 otherwise)
+//! container  bytecode container (see above)
+//! ```
+//!
+//! A cache file is used if the source's modification time and size
+//! or its hash still match, if it was optimized the same way as
+//! the running virtual machine would, and if it was generated by
+//! the running interpreter version.
+
+use std::cell::RefCell;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::UNIX_EPOCH;
+
+use crate::compiler::{Compiler, ModulesInfo, SourceMap};
+use crate::vmbindings::interned_string_map::InternedStringMap;
+use crate::vmbindings::vm::{checked_instruction_size, VmOpcode};
+
+/// Magic bytes at the start of every bytecode file
+pub const MAGIC: &[u8; 4] = b"HANA";
+/// Version of the container format
+pub const FORMAT_VERSION: u16 = 1;
+/// Version of the interpreter that generated the bytecode
+pub const INTERPRETER_VERSION: &str = env!("CARGO_PKG_VERSION");
+/// Magic bytes at the start of every module cache file
+pub const CACHE_MAGIC: &[u8; 4] = b"HANC";
+/// Extension of module cache files
+pub const CACHE_EXTENSION: &str = "hanac";
+
+/// Bytecode loading error
+#[derive(Debug, PartialEq)]
+pub enum BytecodeError {
+    InvalidMagic,
+    UnsupportedFormat(u16),
+    VersionMismatch(String),
+    UnexpectedEof,
+    InvalidUtf8,
+    /// Instruction at the index is invalid or cut off
+    InvalidInstruction(usize),
+    /// Interned string index that is out of the table's bounds
+    InvalidInternedString(u16),
+}
+
+impl std::fmt::Display for BytecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            BytecodeError::InvalidMagic => write!(f, "file is not a hana bytecode file"),
+            BytecodeError::UnsupportedFormat(n) => write!(
+                f,
+                "unsupported bytecode format {} (expected {})",
+                n, FORMAT_VERSION
+            ),
+            BytecodeError::VersionMismatch(version) => write!(
+                f,
+                "bytecode was generated by haru {} (running {})",
+                version, INTERPRETER_VERSION
+            ),
+            BytecodeError::UnexpectedEof => write!(f, "unexpected end of bytecode file"),
+            BytecodeError::InvalidUtf8 => write!(f, "bytecode file contains invalid UTF-8"),
+            BytecodeError::InvalidInstruction(ip) => {
+                write!(f, "invalid instruction at bytecode index {}", ip)
+            }
+            BytecodeError::InvalidInternedString(idx) => {
+                write!(f, "interned string {} is not in the bytecode file", idx)
+            }
+        }
+    }
+}
+
+// #region writer
+struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    fn u8(&mut self, n: u8) {
+        self.bytes.push(n);
+    }
+    fn u16(&mut self, n: u16) {
+        self.bytes.extend_from_slice(&n.to_be_bytes());
+    }
+    fn u32(&mut self, n: u32) {
+        self.bytes.extend_from_slice(&n.to_be_bytes());
+    }
+    fn u64(&mut self, n: u64) {
+        self.bytes.extend_from_slice(&n.to_be_bytes());
+    }
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.u32(bytes.len() as u32);
+        self.bytes.extend_from_slice(bytes);
+    }
+    fn str(&mut self, s: &str) {
+        self.bytes(s.as_bytes());
+    }
+}
+// #endregion
+
+// #region reader
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], BytecodeError> {
+        if self.pos + n > self.bytes.len() {
+            return Err(BytecodeError::UnexpectedEof);
+        }
+        let slice = &self.bytes[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(slice)
+    }
+    fn u8(&mut self) -> Result<u8, BytecodeError> {
+        Ok(self.take(1)?[0])
+    }
+    fn u16(&mut self) -> Result<u16, BytecodeError> {
+        let mut buf = [0u8; 2];
+        buf.copy_from_slice(self.take(2)?);
+        Ok(u16::from_be_bytes(buf))
+    }
+    fn u32(&mut self) -> Result<u32, BytecodeError> {
+        let mut buf = [0u8; 4];
+        buf.copy_from_slice(self.take(4)?);
+        Ok(u32::from_be_bytes(buf))
+    }
+    fn u64(&mut self) -> Result<u64, BytecodeError> {
+        let mut buf = [0u8; 8];
+        buf.copy_from_slice(self.take(8)?);
+        Ok(u64::from_be_bytes(buf))
+    }
+    fn bytes(&mut self) -> Result<&'a [u8], BytecodeError> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+    fn string(&mut self) -> Result<String, BytecodeError> {
+        let bytes = self.bytes()?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| BytecodeError::InvalidUtf8)
+    }
+    fn cstring(&mut self) -> Result<String, BytecodeError> {
+        let len = self.bytes[self.pos..]
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or(BytecodeError::UnexpectedEof)?;
+        let bytes = self.take(len)?;
+        self.take(1)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| BytecodeError::InvalidUtf8)
+    }
+    fn strings(&mut self) -> Result<Vec<String>, BytecodeError> {
+        let len = self.u32()? as usize;
+        let mut vec = Vec::with_capacity(len);
+        for _ in 0..len {
+            vec.push(self.string()?);
+        }
+        Ok(vec)
+    }
+}
+// #endregion
+
+/// Serializes the compiler's generated code along with its interned
+/// strings and loaded modules info.
+pub fn serialize(c: &Compiler) -> Vec<u8> {
+    let mut w = Writer { bytes: Vec::new() };
+    // header
+    w.bytes.extend_from_slice(MAGIC);
+    w.u16(FORMAT_VERSION);
+    w.bytes.extend_from_slice(INTERPRETER_VERSION.as_bytes());
+    w.bytes.push(0);
+    // code
+    w.bytes(c.code_as_bytes());
+    // interned strings
+    if let Some(interned_strings) = c.interned_strings.as_ref() {
+        w.u32(interned_strings.len() as u32);
+        for s in interned_strings.iter() {
+            w.str(s);
+        }
+    } else {
+        w.u32(0);
+    }
+    // modules info
+    let modules_info = c.modules_info.borrow();
+    w.u32(modules_info.files.len() as u32);
+    for file in modules_info.files.iter() {
+        w.str(file);
+    }
+    w.u32(modules_info.sources.len() as u32);
+    for source in modules_info.sources.iter() {
+        w.str(source);
+    }
+    w.u32(modules_info.modules_loaded.len() as u32);
+    for path in modules_info.modules_loaded.iter() {
+        w.str(&path.to_string_lossy());
+    }
+    w.u32(modules_info.smap.len() as u32);
+    for smap in modules_info.smap.iter() {
+        w.u64(smap.file.0 as u64);
+        w.u64(smap.file.1 as u64);
+        w.u32(smap.bytecode.0 as u32);
+        w.u32(smap.bytecode.1 as u32);
+        w.u32(smap.fileno as u32);
+    }
+    w.u32(modules_info.symbol.len() as u32);
+    for (ip, symbol) in modules_info.symbol.iter() {
+        w.u32(*ip as u32);
+        w.str(symbol);
+    }
+    w.bytes
+}
+
+/// Deserializes bytecode generated by `serialize` into a compiler
+/// which can then be turned into a virtual machine.
+///
+/// Code with invalid or cut off instructions, or referring to interned
+/// strings that aren't in the file, is rejected.
+pub fn deserialize(bytes: &[u8]) -> Result<Compiler, BytecodeError> {
+    let mut r = Reader { bytes, pos: 0 };
+    // header
+    if r.take(MAGIC.len())
+        .map_err(|_| BytecodeError::InvalidMagic)?
+        != MAGIC
+    {
+        return Err(BytecodeError::InvalidMagic);
+    }
+    let format = r.u16()?;
+    if format != FORMAT_VERSION {
+        return Err(BytecodeError::UnsupportedFormat(format));
+    }
+    let version = r.cstring()?;
+    if version != INTERPRETER_VERSION {
+        return Err(BytecodeError::VersionMismatch(version));
+    }
+    // code
+    let code = r.bytes()?.to_vec();
+    let interned_strings = InternedStringMap::from_vec(r.strings()?);
+    check_code(&code, &interned_strings)?;
+    // modules info
+    let mut modules_info = ModulesInfo::new();
+    modules_info.files = r.strings()?;
+    modules_info.sources = r.strings()?;
+    for path in r.strings()? {
+        modules_info.modules_loaded.insert(PathBuf::from(path));
+    }
+    let nsmap = r.u32()?;
+    for _ in 0..nsmap {
+        let file = (r.u64()? as usize, r.u64()? as usize);
+        let bytecode = (r.u32()? as usize, r.u32()? as usize);
+        let fileno = r.u32()? as usize;
+        modules_info.smap.push(SourceMap {
+            file,
+            bytecode,
+            fileno,
+        });
+    }
+    let nsymbols = r.u32()?;
+    for _ in 0..nsymbols {
+        let ip = r.u32()? as usize;
+        modules_info.symbol.insert(ip, r.string()?);
+    }
+    Ok(Compiler::new_append(
+        code,
+        Rc::new(RefCell::new(modules_info)),
+        interned_strings,
+    ))
+}
+
+// the virtual machine trusts the code, so make sure that every
+// instruction is whole and only refers to interned strings that exist
+fn check_code(code: &[u8], interned_strings: &InternedStringMap) -> Result<(), BytecodeError> {
+    let mut ip = 0;
+    while ip < code.len() {
+        let size =
+            checked_instruction_size(code, ip).ok_or(BytecodeError::InvalidInstruction(ip))?;
+        if code[ip] == VmOpcode::OP_PUSHSTR_INTERNED as u8 {
+            let idx = u16::from_be_bytes([code[ip + 1], code[ip + 2]]);
+            if interned_strings.get(idx).is_none() {
+                return Err(BytecodeError::InvalidInternedString(idx));
+            }
+        }
+        ip += size;
+    }
+    Ok(())
+}
+
+// #region module cache
+/// Key identifying the source file a cached module was compiled from
+#[derive(Debug, PartialEq)]
+struct CacheKey {
+    mtime: (u64, u32),
+    size: u64,
+    hash: u64,
+    optimized: bool,
+}
+
+impl CacheKey {
+    fn read(r: &mut Reader) -> Result<CacheKey, BytecodeError> {
+        if r.take(CACHE_MAGIC.len())? != CACHE_MAGIC {
+            return Err(BytecodeError::InvalidMagic);
+        }
+        Ok(CacheKey {
+            mtime: (r.u64()?, r.u32()?),
+            size: r.u64()?,
+            hash: r.u64()?,
+            optimized: r.u8()? != 0,
+        })
+    }
+}
+
+fn source_mtime(source: &Path) -> Option<(u64, u32)> {
+    let duration = std::fs::metadata(source)
+        .ok()?
+        .modified()
+        .ok()?
+        .duration_since(UNIX_EPOCH)
+        .ok()?;
+    Some((duration.as_secs(), duration.subsec_nanos()))
+}
+
+fn source_hash(s: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    s.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Returns the path of the cache file for a module's source file.
+pub fn cache_path(source: &Path) -> PathBuf {
+    source.with_extension(CACHE_EXTENSION)
+}
+
+/// Reads the cached compiled module for the source file, returning
+/// `None` if there is no cache file, the cache is stale or it wasn't
+/// `optimized` the same way.
+pub fn read_module_cache(source: &Path, optimized: bool) -> Option<Compiler> {
+    let bytes = std::fs::read(cache_path(source)).ok()?;
+    let mut r = Reader {
+        bytes: &bytes,
+        pos: 0,
+    };
+    let key = CacheKey::read(&mut r).ok()?;
+    if key.optimized != optimized {
+        return None;
+    }
+    let size = std::fs::metadata(source).ok()?.len();
+    if key.size != size {
+        return None;
+    }
+    if source_mtime(source) != Some(key.mtime) {
+        // modified but maybe with the same contents
+        let s = std::fs::read_to_string(source).ok()?;
+        if source_hash(&s) != key.hash {
+            return None;
+        }
+    }
+    deserialize(&bytes[r.pos..]).ok()
+}
+
+/// Writes the compiled module to the source file's cache file, `optimized`
+/// telling whether the module's bytecode was optimized.
+///
+/// The module's source code must be the last source in its modules info.
+/// Caching is best-effort, so errors (such as the module residing
+/// in a read-only directory) are ignored.
+pub fn write_module_cache(source: &Path, c: &Compiler, optimized: bool) {
+    let mtime = match source_mtime(source) {
+        Some(mtime) => mtime,
+        None => return,
+    };
+    let mut w = Writer { bytes: Vec::new() };
+    w.bytes.extend_from_slice(CACHE_MAGIC);
+    {
+        let modules_info = c.modules_info.borrow();
+        let s = match modules_info.sources.last() {
+            Some(s) => s,
+            None => return,
+        };
+        w.u64(mtime.0);
+        w.u32(mtime.1);
+        w.u64(s.len() as u64);
+        w.u64(source_hash(s));
+    }
+    w.u8(optimized as u8);
+    w.bytes.extend_from_slice(&serialize(c));
+    // write to a temporary file first so that other
+    // processes never see a partially written cache
+    let path = cache_path(source);
+    let tmp_path = path.with_extension(format!("{}.{}", CACHE_EXTENSION, std::process::id()));
+    if std::fs::write(&tmp_path, &w.bytes).is_ok() && std::fs::rename(&tmp_path, &path).is_err() {
+        std::fs::remove_file(&tmp_path).ok();
+    }
+}
+// #endregion
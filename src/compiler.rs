@@ -18,15 +18,23 @@ use std::cell::RefCell;
 use std::collections::BTreeMap;
 use std::rc::Rc;
 
+use crate::optimizer;
 use crate::vmbindings::interned_string_map::InternedStringMap;
-use crate::vmbindings::vm::{Vm, VmOpcode};
+use crate::vmbindings::vm::{instruction_size, Vm, VmOpcode};
 
 struct Scope {
     vars: Vec<String>,
+    start: usize,
+    // whether the function contains a yield expression
+    yields: bool,
 }
 impl Scope {
-    fn new() -> Scope {
-        Scope { vars: Vec::new() }
+    fn new(start: usize) -> Scope {
+        Scope {
+            vars: Vec::new(),
+            start,
+            yields: false,
+        }
     }
 }
 
@@ -45,6 +53,15 @@ pub struct SourceMap {
     pub fileno: usize,
 }
 
+/// Names of the local variables of a function, indexed by their slot.
+///
+/// These are only used by the debugger and aren't saved in bytecode files.
+#[derive(Clone)]
+pub struct LocalsInfo {
+    pub bytecode: ArrayIndexRange,
+    pub names: Vec<String>,
+}
+
 /// Loaded modules info
 pub struct ModulesInfo {
     pub smap: Vec<SourceMap>,
@@ -52,6 +69,7 @@ pub struct ModulesInfo {
     pub modules_loaded: std::collections::HashSet<std::path::PathBuf>,
     pub symbol: BTreeMap<usize, String>,
     pub sources: Vec<String>,
+    pub locals: Vec<LocalsInfo>,
 }
 
 impl ModulesInfo {
@@ -62,6 +80,23 @@ impl ModulesInfo {
             modules_loaded: std::collections::HashSet::new(),
             symbol: BTreeMap::new(),
             sources: Vec::new(),
+            locals: Vec::new(),
+        }
+    }
+
+    pub fn lookup_smap(&self, bc_idx: usize) -> Option<SourceMap> {
+        // TODO: fix this and maybe use binary search?
+        let mut last_found: Option<SourceMap> = None;
+        for smap in self.smap.iter() {
+            if (smap.bytecode.0..=smap.bytecode.1).contains(&bc_idx) {
+                // this is so that the lookup gets more "specific"
+                last_found = Some((*smap).clone());
+            }
+        }
+        if last_found.is_some() {
+            last_found
+        } else {
+            None
         }
     }
 }
@@ -282,14 +317,32 @@ impl Compiler {
 
     // scopes
     pub fn scope(&mut self) {
-        self.scopes.push(Scope::new());
+        let start = self.clen();
+        self.scopes.push(Scope::new(start));
+    }
+    pub fn set_yields(&mut self) {
+        if let Some(last) = self.scopes.last_mut() {
+            last.yields = true;
+        }
+    }
+    pub fn scope_yields(&self) -> bool {
+        self.scopes.last().map_or(false, |scope| scope.yields)
     }
     pub fn unscope(&mut self) -> u16 {
-        let size = self.scopes.pop().unwrap().vars.len();
+        let scope = self.scopes.pop().unwrap();
+        let size = scope.vars.len();
+        let end = self.clen();
+        self.modules_info.borrow_mut().locals.push(LocalsInfo {
+            bytecode: (scope.start, end),
+            names: scope.vars,
+        });
         size as u16
     }
 
     // loops
+    pub fn is_in_loop(&self) -> bool {
+        !self.loop_stmts.is_empty()
+    }
     pub fn loop_start(&mut self) {
         self.loop_stmts.push(LoopStatement {
             fill_continue: Vec::new(),
@@ -316,21 +369,90 @@ impl Compiler {
         }
     }
 
-    // source map
-    pub fn lookup_smap(&self, bc_idx: usize) -> Option<SourceMap> {
-        // TODO: fix this and maybe use binary search?
-        let mut last_found: Option<SourceMap> = None;
-        let modules_info = self.modules_info.borrow();
-        for smap in modules_info.smap.iter() {
-            if (smap.bytecode.0..=smap.bytecode.1).contains(&bc_idx) {
-                // this is so that the lookup gets more "specific"
-                last_found = Some((*smap).clone());
+    // modules
+    /// Appends code from a separately compiled module, relocating
+    /// its interned strings, source maps and symbols so that they refer
+    /// to this compiler's tables.
+    ///
+    /// Fails without appending any code if the module's strings
+    /// can't be interned.
+    pub fn append_module(&mut self, module: Compiler) -> Result<(), ()> {
+        let mut code = module.code.unwrap();
+        // interned strings
+        if let Some(module_strings) = module.interned_strings.as_ref() {
+            let interned_strings = self.interned_strings.as_mut().unwrap();
+            let mut ip = 0;
+            while ip < code.len() {
+                if code[ip] == VmOpcode::OP_PUSHSTR_INTERNED as u8 {
+                    let idx = u16::from_be_bytes([code[ip + 1], code[ip + 2]]);
+                    let s = module_strings.get(idx).ok_or(())?;
+                    let new_idx = interned_strings.get_or_insert(s.as_ref()).ok_or(())?;
+                    code[ip + 1..ip + 3].copy_from_slice(&new_idx.to_be_bytes());
+                }
+                ip += instruction_size(&code, ip);
             }
         }
-        if last_found.is_some() {
-            last_found
-        } else {
-            None
+        // modules info
+        let base = self.clen();
+        let mut modules_info = self.modules_info.borrow_mut();
+        let module_info = module.modules_info.replace(ModulesInfo::new());
+        let base_fileno = modules_info.files.len();
+        modules_info.files.extend(module_info.files);
+        modules_info.sources.extend(module_info.sources);
+        modules_info
+            .smap
+            .extend(module_info.smap.into_iter().map(|smap| SourceMap {
+                file: smap.file,
+                bytecode: (smap.bytecode.0 + base, smap.bytecode.1 + base),
+                fileno: smap.fileno + base_fileno,
+            }));
+        modules_info.symbol.extend(
+            module_info
+                .symbol
+                .into_iter()
+                .map(|(ip, symbol)| (ip + base, symbol)),
+        );
+        modules_info
+            .locals
+            .extend(module_info.locals.into_iter().map(|locals| LocalsInfo {
+                bytecode: (locals.bytecode.0 + base, locals.bytecode.1 + base),
+                names: locals.names,
+            }));
+        self.code.as_mut().unwrap().extend_from_slice(&code);
+        Ok(())
+    }
+
+    // optimizer
+    /// Optimizes the code emitted from `start` on, moving the source map,
+    /// symbols and locals to their new positions.
+    pub fn optimize(&mut self, start: usize) {
+        let relocation = optimizer::optimize(
+            self.code.as_mut().unwrap(),
+            start,
+            self.interned_strings.as_mut(),
+        );
+        let mut modules_info = self.modules_info.borrow_mut();
+        for smap in modules_info.smap.iter_mut() {
+            smap.bytecode = (
+                relocation.map(smap.bytecode.0),
+                relocation.map(smap.bytecode.1),
+            );
         }
+        // symbols are placed at the last byte of a function's body
+        modules_info.symbol = std::mem::replace(&mut modules_info.symbol, BTreeMap::new())
+            .into_iter()
+            .map(|(ip, symbol)| (relocation.map(ip + 1) - 1, symbol))
+            .collect();
+        for locals in modules_info.locals.iter_mut() {
+            locals.bytecode = (
+                relocation.map(locals.bytecode.0),
+                relocation.map(locals.bytecode.1),
+            );
+        }
+    }
+
+    // source map
+    pub fn lookup_smap(&self, bc_idx: usize) -> Option<SourceMap> {
+        self.modules_info.borrow().lookup_smap(bc_idx)
     }
 }
@@ -0,0 +1,524 @@
+//! Provides an interactive debugger for programs running in the virtual
+//! machine.
+//!
+//! The debugger is attached with `Vm::attach_debugger` and is called before
+//! every instruction. It pauses the program at breakpoints and when stepping,
+//! reading commands from its input until the program is resumed.
+
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashSet};
+use std::io::{BufRead, Write};
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::compiler::{LocalsInfo, ModulesInfo};
+use crate::vmbindings::env::Env;
+use crate::vmbindings::nativeval::NativeValue;
+use crate::vmbindings::vm::{DebugHook, Vm};
+
+const HELP: &str = "commands:
+ b/break [file:]line: pauses the program when it reaches the line
+ d/delete n: deletes breakpoint n
+ s/step: runs until the next line, stepping into function calls
+ n/next: runs until the next line in the current function
+ f/finish: runs until the current function returns
+ c/continue: runs until the next breakpoint
+ bt/backtrace: prints the call stack
+ l/locals [frame]: prints the local variables of a frame in the call stack
+ g/globals: prints the global variables defined by the program
+ p/print name: prints the value of a variable
+ q/quit: exits the program
+an empty line repeats the last command";
+
+/// Line of a source file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Location {
+    fileno: usize,
+    line: usize,
+}
+
+enum Mode {
+    Step,
+    // call stack depth and line the command was run on
+    Next(usize, Location),
+    // call stack depth the command was run in
+    Finish(usize),
+    Continue,
+}
+
+struct Breakpoint {
+    file: Option<String>,
+    line: usize,
+    // resolved once the file is loaded
+    location: Option<Location>,
+}
+
+// stack frame, with the instruction it's running (if known)
+struct Frame {
+    ip: Option<usize>,
+    env: Option<Env>,
+}
+
+/// Interactive debugger for the virtual machine.
+pub struct Debugger {
+    modules_info: Rc<RefCell<ModulesInfo>>,
+    input: Box<dyn BufRead>,
+    // global variables defined by the standard library
+    builtins: HashSet<String>,
+    // source line of every instruction
+    lines: Vec<Option<Location>>,
+    // lines that have code on them
+    code_lines: BTreeSet<Location>,
+    // byte offset of the start of every line in every file
+    line_starts: Vec<Vec<usize>>,
+    // deleted breakpoints are kept as None so that they're numbered consistently
+    breakpoints: Vec<Option<Breakpoint>>,
+    mode: Mode,
+    // line and call stack depth of the last instruction
+    last: Option<(Location, usize)>,
+    last_command: String,
+}
+
+impl Debugger {
+    /// Creates a debugger for the virtual machine, which reads its commands
+    /// from `input`.
+    ///
+    /// The program is paused on its first line.
+    pub fn new(vm: &Vm, input: Box<dyn BufRead>) -> Debugger {
+        Debugger {
+            modules_info: vm.modules_info.clone().unwrap(),
+            input,
+            builtins: vm.global().keys().map(|key| key.to_string()).collect(),
+            lines: Vec::new(),
+            code_lines: BTreeSet::new(),
+            line_starts: Vec::new(),
+            breakpoints: Vec::new(),
+            mode: Mode::Step,
+            last: None,
+            last_command: String::new(),
+        }
+    }
+
+    // #region source lines
+    // maps newly loaded code to its source lines
+    fn update(&mut self, len: usize) {
+        {
+            let modules_info = self.modules_info.borrow();
+            for source in modules_info.sources.iter().skip(self.line_starts.len()) {
+                let starts = std::iter::once(0)
+                    .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+                    .collect();
+                self.line_starts.push(starts);
+            }
+            // source maps of inner nodes come later, overriding their parents
+            let mut nodes: Vec<Option<(Location, usize)>> = vec![None; len];
+            for smap in modules_info.smap.iter() {
+                let starts = match self.line_starts.get(smap.fileno) {
+                    Some(starts) => starts,
+                    None => continue,
+                };
+                let line = match starts.binary_search(&smap.file.0) {
+                    Ok(i) => i + 1,
+                    Err(i) => i,
+                };
+                let location = Location {
+                    fileno: smap.fileno,
+                    line,
+                };
+                let end = smap.bytecode.1.min(len);
+                for node in nodes[smap.bytecode.0.min(end)..end].iter_mut() {
+                    *node = Some((location, smap.bytecode.0));
+                }
+            }
+            // setting up and returning from a function is done by its definition,
+            // which shouldn't be stepped on from inside of the function
+            for function in modules_info.locals.iter() {
+                let end = function.bytecode.1.min(len);
+                for node in nodes[function.bytecode.0.min(end)..end].iter_mut() {
+                    if let Some((_, start)) = node {
+                        if *start < function.bytecode.0 {
+                            *node = None;
+                        }
+                    }
+                }
+            }
+            self.lines = nodes
+                .into_iter()
+                .map(|node| node.map(|(location, _)| location))
+                .collect();
+        }
+        self.code_lines = self.lines.iter().filter_map(|location| *location).collect();
+        for i in 0..self.breakpoints.len() {
+            if let Some(breakpoint) = &self.breakpoints[i] {
+                if breakpoint.location.is_none() {
+                    let location = self
+                        .resolve(
+                            breakpoint.file.as_ref().map(|file| file.as_str()),
+                            breakpoint.line,
+                        )
+                        .unwrap_or(None);
+                    self.breakpoints[i].as_mut().unwrap().location = location;
+                }
+            }
+        }
+    }
+
+    // finds the first line with code at or after the line,
+    // returning None if the file isn't loaded yet
+    fn resolve(&self, file: Option<&str>, line: usize) -> Result<Option<Location>, ()> {
+        let modules_info = self.modules_info.borrow();
+        let fileno = match file {
+            None if !modules_info.files.is_empty() => 0,
+            None => return Ok(None),
+            Some(file) => match modules_info.files.iter().position(|path| {
+                // modules are named by their path in the use statement
+                let path = Path::new(path);
+                path.ends_with(file) || path.with_extension("hana").ends_with(file)
+            }) {
+                Some(fileno) => fileno,
+                None => return Ok(None),
+            },
+        };
+        if fileno >= self.line_starts.len() {
+            return Ok(None);
+        }
+        let from = Location { fileno, line };
+        match self.code_lines.range(from..).next() {
+            Some(location) if location.fileno == fileno => Ok(Some(*location)),
+            _ => Err(()),
+        }
+    }
+
+    fn location(&self, ip: usize) -> Option<Location> {
+        self.lines.get(ip).and_then(|location| *location)
+    }
+
+    fn file_name(&self, fileno: usize) -> String {
+        self.modules_info.borrow().files[fileno].clone()
+    }
+    // #endregion
+
+    // #region call stack
+    fn frames(vm: &Vm) -> Vec<Frame> {
+        let mut frames = Vec::new();
+        let mut ip = Some(vm.ip() as usize);
+        for env in vm.localenv_to_vec() {
+            // functions called from native code don't have a return address,
+            // otherwise it points to the instruction after the call
+            let retip = if env.retip == std::u32::MAX {
+                None
+            } else {
+                Some(env.retip as usize - 1)
+            };
+            frames.push(Frame { ip, env: Some(env) });
+            ip = retip;
+        }
+        frames.push(Frame { ip, env: None });
+        frames
+    }
+
+    // innermost function containing the instruction
+    fn function_at(modules_info: &ModulesInfo, ip: usize) -> Option<LocalsInfo> {
+        modules_info
+            .locals
+            .iter()
+            .filter(|locals| locals.bytecode.0 <= ip && ip < locals.bytecode.1)
+            .min_by_key(|locals| locals.bytecode.1 - locals.bytecode.0)
+            .cloned()
+    }
+
+    fn function_name(modules_info: &ModulesInfo, function: &LocalsInfo) -> Option<String> {
+        // a function's symbol is placed at the end of its body,
+        // after the symbols of the functions nested inside of it
+        let (ip, name) = modules_info
+            .symbol
+            .range(function.bytecode.0..function.bytecode.1)
+            .next_back()?;
+        let nested = modules_info.locals.iter().any(|locals| {
+            locals.bytecode.0 > function.bytecode.0
+                && locals.bytecode.0 <= *ip
+                && *ip < locals.bytecode.1
+        });
+        if nested {
+            // anonymous function
+            None
+        } else {
+            Some(name.clone())
+        }
+    }
+
+    fn describe(&self, ip: Option<usize>) -> String {
+        let ip = match ip {
+            Some(ip) => ip,
+            None => return "[native code]".to_string(),
+        };
+        let location = match self.location(ip) {
+            Some(location) => location,
+            None => return format!("bytecode index {}", ip),
+        };
+        let modules_info = self.modules_info.borrow();
+        let name = Self::function_at(&modules_info, ip)
+            .and_then(|function| Self::function_name(&modules_info, &function));
+        format!(
+            "{}{}:{}",
+            name.map_or(String::new(), |name| name + "@"),
+            modules_info.files[location.fileno],
+            location.line
+        )
+    }
+
+    // names and values of the local variables in the frame and its enclosing
+    // functions
+    fn locals(&self, frame: &Frame) -> Vec<(String, NativeValue)> {
+        let mut locals = Vec::new();
+        let env = match &frame.env {
+            Some(env) => env,
+            None => return locals,
+        };
+        let modules_info = self.modules_info.borrow();
+        let mut function = frame.ip.and_then(|ip| Self::function_at(&modules_info, ip));
+        let mut env: *const Env = env;
+        while !env.is_null() {
+            let slots = unsafe { &(*env).slots };
+            let names = function.as_ref().map_or(&[][..], |f| &f.names[..]);
+            for (i, val) in slots.iter().enumerate() {
+                let name = names
+                    .get(i)
+                    .cloned()
+                    .unwrap_or_else(|| format!("[slot {}]", i));
+                if !locals.iter().any(|(other, _)| *other == name) {
+                    locals.push((name, *val));
+                }
+            }
+            // the enclosing function is the innermost one around this function
+            function = function.and_then(|f| {
+                modules_info
+                    .locals
+                    .iter()
+                    .filter(|locals| {
+                        locals.bytecode.0 < f.bytecode.0 && f.bytecode.1 <= locals.bytecode.1
+                    })
+                    .min_by_key(|locals| locals.bytecode.1 - locals.bytecode.0)
+                    .cloned()
+            });
+            if function.is_none() {
+                break;
+            }
+            env = unsafe { (*env).lexical_parent };
+        }
+        locals
+    }
+
+    fn globals(&self, vm: &Vm) -> Vec<(String, NativeValue)> {
+        let mut globals: Vec<(String, NativeValue)> = vm
+            .global()
+            .iter()
+            .map(|(name, val)| (name.to_string(), *val))
+            .filter(|(name, _)| !self.builtins.contains(name))
+            .collect();
+        globals.sort_by(|a, b| a.0.cmp(&b.0));
+        globals
+    }
+    // #endregion
+
+    // #region commands
+    fn print_source_line(&self, location: Location) {
+        let modules_info = self.modules_info.borrow();
+        let source = &modules_info.sources[location.fileno];
+        let line = source.split('\n').nth(location.line - 1).unwrap_or("");
+        println!("{} | {}", location.line, line);
+    }
+
+    fn print_values(values: Vec<(String, NativeValue)>) {
+        for (name, val) in values {
+            println!("{} = {:?}", name, unsafe { val.unwrap() });
+        }
+    }
+
+    fn add_breakpoint(&mut self, arg: &str) {
+        let (file, line) = match arg.rfind(':') {
+            Some(i) => (Some(arg[..i].to_string()), &arg[i + 1..]),
+            None => (None, arg),
+        };
+        let line = match line.parse::<usize>() {
+            Ok(line) if line > 0 => line,
+            _ => return println!("invalid line number: {}", line),
+        };
+        let n = self.breakpoints.len() + 1;
+        let location = match self.resolve(file.as_ref().map(|file| file.as_str()), line) {
+            Ok(location) => location,
+            Err(_) => {
+                let file = file.unwrap_or_else(|| self.file_name(0));
+                return println!("no code at or after {}:{}", file, line);
+            }
+        };
+        match location {
+            Some(location) => println!(
+                "breakpoint {} at {}:{}",
+                n,
+                self.file_name(location.fileno),
+                location.line
+            ),
+            None => println!(
+                "breakpoint {} at {}:{} (pending until the file is loaded)",
+                n,
+                file.as_ref().unwrap(),
+                line
+            ),
+        }
+        self.breakpoints.push(Some(Breakpoint {
+            file,
+            line,
+            location,
+        }));
+    }
+
+    fn delete_breakpoint(&mut self, arg: &str) {
+        match arg.parse::<usize>() {
+            Ok(n) if n > 0 && n <= self.breakpoints.len() && self.breakpoints[n - 1].is_some() => {
+                self.breakpoints[n - 1] = None;
+                println!("deleted breakpoint {}", n);
+            }
+            _ => println!("no breakpoint {}", arg),
+        }
+    }
+
+    // reads commands until the program is resumed
+    fn pause(&mut self, vm: &mut Vm, location: Location, breakpoint: Option<usize>) {
+        if let Some(n) = breakpoint {
+            print!("breakpoint {}, ", n + 1);
+        }
+        println!("{}", self.describe(Some(vm.ip() as usize)));
+        self.print_source_line(location);
+        let depth = vm.localenv_depth();
+        loop {
+            print!("(haru) ");
+            std::io::stdout().flush().unwrap();
+            let mut command = String::new();
+            match self.input.read_line(&mut command) {
+                Ok(0) | Err(_) => {
+                    println!();
+                    std::process::exit(0);
+                }
+                Ok(_) => {}
+            }
+            let command = if command.trim().is_empty() {
+                self.last_command.clone()
+            } else {
+                command.trim().to_string()
+            };
+            self.last_command = command.clone();
+            let mut words = command.split_whitespace();
+            let arg = words.nth(1);
+            match command.split_whitespace().next().unwrap_or("") {
+                "s" | "step" => {
+                    self.mode = Mode::Step;
+                    return;
+                }
+                "n" | "next" => {
+                    self.mode = Mode::Next(depth, location);
+                    return;
+                }
+                "f" | "finish" => {
+                    if depth == 0 {
+                        println!("the top level can't be finished");
+                    } else {
+                        self.mode = Mode::Finish(depth);
+                        return;
+                    }
+                }
+                "c" | "continue" => {
+                    self.mode = Mode::Continue;
+                    return;
+                }
+                "b" | "break" => match arg {
+                    Some(arg) => self.add_breakpoint(arg),
+                    None => println!("usage: break [file:]line"),
+                },
+                "d" | "delete" => match arg {
+                    Some(arg) => self.delete_breakpoint(arg),
+                    None => println!("usage: delete n"),
+                },
+                "bt" | "backtrace" => {
+                    for (i, frame) in Self::frames(vm).iter().enumerate() {
+                        println!("#{} {}", i, self.describe(frame.ip));
+                    }
+                }
+                "l" | "locals" => {
+                    let frames = Self::frames(vm);
+                    match arg.map_or(Ok(0), |arg| arg.parse::<usize>()) {
+                        Ok(n) if n < frames.len() => {
+                            if frames[n].env.is_none() {
+                                println!("variables at the top level are globals");
+                            } else {
+                                Self::print_values(self.locals(&frames[n]));
+                            }
+                        }
+                        _ => println!("no frame {}", arg.unwrap()),
+                    }
+                }
+                "g" | "globals" => Self::print_values(self.globals(vm)),
+                "p" | "print" => match arg {
+                    Some(name) => {
+                        let frame = Self::frames(vm).swap_remove(0);
+                        let local = self
+                            .locals(&frame)
+                            .into_iter()
+                            .find(|(local, _)| local == name);
+                        let global = || {
+                            self.globals(vm).into_iter().find(|(global, _)| {
+                                global.as_str() == name.trim_start_matches('$')
+                            })
+                        };
+                        match local.or_else(global) {
+                            Some(value) => Self::print_values(vec![value]),
+                            None => println!("no variable named {}", name),
+                        }
+                    }
+                    None => println!("usage: print name"),
+                },
+                "h" | "help" => println!("{}", HELP),
+                "q" | "quit" => std::process::exit(0),
+                command => println!("unknown command {}, try help", command),
+            }
+        }
+    }
+    // #endregion
+}
+
+impl DebugHook for Debugger {
+    fn step(&mut self, vm: &mut Vm) {
+        let len = vm.code.as_ref().unwrap().len();
+        if len != self.lines.len() {
+            self.update(len);
+        }
+        let location = match self.location(vm.ip() as usize) {
+            Some(location) => location,
+            None => return,
+        };
+        let depth = vm.localenv_depth();
+        // only pause when reaching a new line
+        if self.last == Some((location, depth)) {
+            return;
+        }
+        self.last = Some((location, depth));
+        let breakpoint = self
+            .breakpoints
+            .iter()
+            .position(|breakpoint| match breakpoint {
+                Some(breakpoint) => breakpoint.location == Some(location),
+                None => false,
+            });
+        let stop = match self.mode {
+            Mode::Step => true,
+            Mode::Next(from_depth, from) => {
+                depth < from_depth || (depth == from_depth && location != from)
+            }
+            Mode::Finish(from_depth) => depth < from_depth,
+            Mode::Continue => false,
+        };
+        if stop || breakpoint.is_some() {
+            self.pause(vm, location, breakpoint);
+        }
+    }
+}
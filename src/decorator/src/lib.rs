@@ -13,7 +13,7 @@
 //!
 //! The macro should generate a function with the signature:
 //! ```rust,text
-//! pub extern "C" fn succ(vm: *const Vm, nargs: u16) {
+//! pub unsafe extern "C" fn succ(vm: *const Vm, nargs: u16) {
 //!     ...
 //! }
 //! ```
@@ -47,7 +47,7 @@ use syn;
 /// should generate a function like this (semi pseudocode):
 ///
 /// ```rust,text
-/// pub extern "C" fn fopen(cvm : *mut Vm, nargs : u16) {
+/// pub unsafe extern "C" fn fopen(cvm : *mut Vm, nargs : u16) {
 ///     if nargs != [nargs] { [raise vm error] }
 ///     fn fopen() -> Value {
 ///         let Value::String(path) = vm.stack.pop().unwrap() ||
@@ -56,7 +56,7 @@ use syn;
 ///                 panic!("expected mode to be string");
 ///         [body of fopen]
 ///     }
-///     let vm = unsafe { &mut *cvm };
+///     let vm = &mut *cvm;
 ///     let result : Value = #name(vm);
 ///     vm.stack.push(result.wrap());
 /// }
@@ -119,8 +119,8 @@ pub fn hana_function(_args: TokenStream, item: TokenStream) -> TokenStream {
     );
 
     quote!(
-        pub extern "C" fn #name(cvm : *mut Vm, nargs : u16) {
-            let vm = unsafe { &mut *cvm };
+        pub unsafe extern "C" fn #name(cvm : *mut Vm, nargs : u16) {
+            let vm = &mut *cvm;
             if nargs != #arglen {
                 use super::VmError;
                 vm.error = VmError::ERROR_MISMATCH_ARGUMENTS;
@@ -135,7 +135,7 @@ pub fn hana_function(_args: TokenStream, item: TokenStream) -> TokenStream {
             let result = #name(vm);
             match result {
                 Value::PropagateError => (),
-                _ => unsafe{ vm.stack_push_gray(result) },
+                _ => vm.stack_push_gray(result),
             }
         }
     )
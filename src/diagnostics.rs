@@ -0,0 +1,383 @@
+//! Provides error-recovering parsing for reporting every
+//! problem in a source file at once.
+//!
+//! `ast::grammar::start` stops at the first syntax error. Instead, the
+//! recovering parser reports the error, blanks out the offending statement
+//! (or, inside a block, the offending line) and parses again, until the
+//! rest of the file is valid:
+//!
+//! ```
+//! use haru::diagnostics;
+//! let (prog, errors) = diagnostics::parse("x = +\ny = 1\nz = *\n");
+//! assert_eq!(prog.len(), 1);
+//! assert_eq!(errors.len(), 2);
+//! ```
+
+use crate::ast::{ast, grammar};
+use crate::compiler::Compiler;
+use std::collections::HashSet;
+
+/// Maximum number of syntax errors reported for one file
+const MAX_ERRORS: usize = 64;
+
+/// Statements whose bodies are recovered line by line
+const BLOCK_KEYWORDS: [&str; 7] = ["begin", "if", "while", "for", "function", "record", "try"];
+
+/// Names of grammar rules that show up in the parser's expected set
+const RULE_NAMES: [&str; 13] = [
+    "integer literal",
+    "float literal",
+    "string literal",
+    "word",
+    "identifier",
+    "newline",
+    "array literal",
+    "map literal",
+    "expression",
+    "statement",
+    "block statement",
+    "block or then <stmt>",
+    "function arguments",
+];
+
+/// Tokens that start an expression, reported together as "expression"
+const EXPRESSION_TOKENS: [&str; 11] = [
+    "0x",
+    "|",
+    "function",
+    "record",
+    "integer literal",
+    "float literal",
+    "string literal",
+    "array literal",
+    "map literal",
+    "identifier",
+    "function arguments",
+];
+
+/// How serious a problem is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// The program may not do what was intended
+    Warning,
+    /// The program can't be run
+    Error,
+}
+
+/// A problem found in the source code
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    /// Byte range of the source code the problem is in
+    pub span: ast::Span,
+    pub severity: Severity,
+    pub message: String,
+}
+
+/// Parses the program, recovering from syntax errors.
+///
+/// Returns the statements that could be parsed along with
+/// the syntax errors found, in the order of their position.
+pub fn parse(input: &str) -> (Vec<Box<dyn ast::AST>>, Vec<Diagnostic>) {
+    let mut source = input.to_string();
+    let mut errors = Vec::new();
+    for _ in 0..MAX_ERRORS {
+        let err = match grammar::start(&source) {
+            Ok(prog) => {
+                errors.sort_by_key(|err: &Diagnostic| err.span);
+                return (prog, errors);
+            }
+            Err(err) => paren_error(&source, err),
+        };
+        let rest = grammar::start_rest(&source).unwrap_or_else(|_| source.len());
+        let stmt_start = source.len() - rest;
+        errors.push(describe(&source, stmt_start, &err));
+        if !recover(&mut source, stmt_start, err.offset) {
+            break;
+        }
+    }
+    errors.sort_by_key(|err| err.span);
+    (Vec::new(), errors)
+}
+
+// the grammar reports errors inside parentheses at the opening parenthesis,
+// so parse from there again to find where inside of them it actually failed
+fn paren_error(source: &str, err: grammar::ParseError) -> grammar::ParseError {
+    let offset = err.offset.min(source.len());
+    if !source[offset..].starts_with('(') {
+        return err;
+    }
+    match grammar::paren_expr_rest(&source[offset..]) {
+        Err(ref inner) if inner.offset > 0 => {
+            let offset = offset + inner.offset;
+            let (line, column) = crate::ast::pos_to_line(source, offset);
+            // which may be at a nested parenthesis
+            paren_error(
+                source,
+                grammar::ParseError {
+                    line,
+                    column,
+                    offset,
+                    expected: inner.expected.clone(),
+                },
+            )
+        }
+        _ => err,
+    }
+}
+
+/// Emits each statement of the program, collecting code generation errors.
+pub fn codegen(prog: &[Box<dyn ast::AST>]) -> Vec<Diagnostic> {
+    let mut errors = Vec::new();
+    for stmt in prog {
+        let mut c = Compiler::new(true);
+        if let Err(err) = stmt.emit(&mut c) {
+            errors.push(Diagnostic {
+                span: err.span,
+                severity: Severity::Error,
+                message: err.message().to_string(),
+            });
+        }
+    }
+    errors
+}
+
+/// Merges the syntax errors and lint results of a program with its
+/// code generation errors, in the order of their position.
+pub fn merge(
+    prog: &[Box<dyn ast::AST>], syntax_errors: Vec<Diagnostic>, lints: Vec<Diagnostic>,
+) -> Vec<Diagnostic> {
+    let mut problems = syntax_errors;
+    for err in codegen(prog) {
+        // the linter reports these in more detail
+        if !lints.iter().any(|lint| lint.span == err.span) {
+            problems.push(err);
+        }
+    }
+    problems.extend(lints);
+    problems.sort_by_key(|problem| problem.span);
+    problems
+}
+
+// #region recovery
+fn is_id_char(ch: char) -> bool {
+    ch.is_ascii_alphanumeric() || "$_?!".contains(ch)
+}
+
+fn starts_with_keyword(s: &str, keyword: &str) -> bool {
+    s.starts_with(keyword) && !s[keyword.len()..].starts_with(is_id_char)
+}
+
+fn line_bounds(source: &str, pos: usize) -> (usize, usize) {
+    let start = source[..pos].rfind('\n').map_or(0, |i| i + 1);
+    let end = source[pos..].find('\n').map_or(source.len(), |i| pos + i);
+    (start, end)
+}
+
+// replaces the range with whitespace, keeping byte offsets
+// and line breaks intact. Returns false if there was nothing to blank
+fn blank(source: &mut String, start: usize, end: usize) -> bool {
+    let range = &source[start..end];
+    if range.trim().is_empty() {
+        return false;
+    }
+    let blanked: String = range
+        .chars()
+        .map(|ch| {
+            if ch == '\n' {
+                "\n".to_string()
+            } else {
+                " ".repeat(ch.len_utf8())
+            }
+        })
+        .collect();
+    source.replace_range(start..end, &blanked);
+    true
+}
+
+// blanks the code that caused the error at offset so the next parse
+// can get past it. Returns false if the parser can't go any further
+fn recover(source: &mut String, stmt_start: usize, offset: usize) -> bool {
+    let offset = offset.min(source.len());
+    let (line_start, line_end) = line_bounds(source, offset);
+    let in_block = BLOCK_KEYWORDS
+        .iter()
+        .any(|keyword| starts_with_keyword(&source[stmt_start..], keyword));
+    if in_block && line_start > stmt_start && blank(source, line_start, line_end) {
+        return true;
+    }
+    // a statement cut off by the start of the next one
+    if line_start > stmt_start
+        && source[line_start..offset].trim().is_empty()
+        && blank(source, stmt_start, line_start)
+    {
+        return true;
+    }
+    blank(source, stmt_start.min(line_end), line_end)
+}
+// #endregion
+
+// #region messages
+fn found_token(source: &str, offset: usize) -> (String, usize) {
+    let rest = &source[offset..];
+    match rest.chars().next() {
+        None => ("end of file".to_string(), 0),
+        Some('\n') | Some('\r') => ("end of line".to_string(), 1),
+        Some(ch) if is_id_char(ch) => {
+            let len = rest
+                .find(|ch| !is_id_char(ch))
+                .unwrap_or_else(|| rest.len());
+            (format!("`{}`", &rest[..len]), len)
+        }
+        Some(ch) => (format!("`{}`", ch), ch.len_utf8()),
+    }
+}
+
+fn expected_list(expected: &HashSet<&'static str>) -> String {
+    let is_expression = expected.contains("identifier") && expected.contains("integer literal");
+    let mut expected: Vec<String> = expected
+        .iter()
+        .filter(|token| !(is_expression && EXPRESSION_TOKENS.contains(token)))
+        .filter(|&&token| token != "block statement" || !expected.contains("block or then <stmt>"))
+        .chain(if is_expression {
+            Some(&"expression")
+        } else {
+            None
+        })
+        .map(|token| {
+            if RULE_NAMES.contains(token) {
+                token.to_string()
+            } else {
+                format!("`{}`", token)
+            }
+        })
+        .collect();
+    expected.sort();
+    expected.dedup();
+    expected.join(", ")
+}
+
+// finds the last bracket that isn't closed
+fn unclosed_bracket(source: &str, start: usize, end: usize) -> Option<(char, usize)> {
+    let mut brackets = Vec::new();
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    for (i, ch) in source[start..end].char_indices() {
+        match quote {
+            Some(_) if escaped => escaped = false,
+            Some(_) if ch == '\\' => escaped = true,
+            Some(q) if ch == q => quote = None,
+            Some(_) => (),
+            None => match ch {
+                '"' | '\'' => quote = Some(ch),
+                '(' | '[' => brackets.push((ch, start + i)),
+                ')' | ']' => {
+                    brackets.pop();
+                }
+                _ => (),
+            },
+        }
+    }
+    brackets.pop()
+}
+
+// finds the opening quote of a string that is never closed
+fn unterminated_string(source: &str, start: usize, end: usize) -> Option<usize> {
+    let mut quote: Option<(char, usize)> = None;
+    let mut escaped = false;
+    for (i, ch) in source[start..end].char_indices() {
+        match quote {
+            Some(_) if escaped => escaped = false,
+            Some(_) if ch == '\\' => escaped = true,
+            Some((q, _)) if ch == q => quote = None,
+            Some(_) => (),
+            None if ch == '"' || ch == '\'' => quote = Some((ch, start + i)),
+            None if source[start + i..].starts_with("//") => break,
+            None => (),
+        }
+    }
+    let (q, pos) = quote?;
+    if source[end..].contains(q) {
+        None
+    } else {
+        Some(pos)
+    }
+}
+
+fn describe(source: &str, stmt_start: usize, err: &grammar::ParseError) -> Diagnostic {
+    let offset = err.offset.min(source.len());
+    let (line_start, _) = line_bounds(source, offset);
+    let (found, len) = found_token(source, offset);
+
+    let (_, line_end) = line_bounds(source, offset);
+    if let Some(pos) = unterminated_string(source, stmt_start.min(line_start), line_end) {
+        return Diagnostic {
+            span: (pos, pos + 1),
+            severity: Severity::Error,
+            message: "unterminated string literal".to_string(),
+        };
+    }
+
+    // block left open at the end of the file
+    if source[offset..].trim().is_empty() && err.expected.contains("end") {
+        let (line, _) = crate::ast::pos_to_line(source, stmt_start);
+        return Diagnostic {
+            span: (offset, offset),
+            severity: Severity::Error,
+            message: format!(
+                "missing `end`: the statement starting at line {} is never closed",
+                line
+            ),
+        };
+    }
+
+    // missing closing bracket
+    if err.expected.contains(")") || err.expected.contains("]") {
+        if let Some((bracket, pos)) = unclosed_bracket(source, stmt_start.min(offset), offset) {
+            let (line, _) = crate::ast::pos_to_line(source, pos);
+            return Diagnostic {
+                span: (offset, offset + len),
+                severity: Severity::Error,
+                message: format!(
+                    "unexpected {}, `{}` on line {} is never closed",
+                    found, bracket, line
+                ),
+            };
+        }
+    }
+
+    // `then`/`else` that isn't followed by a statement
+    if err.expected.contains("block or then <stmt>")
+        && starts_with_keyword(&source[offset..], "then")
+    {
+        return Diagnostic {
+            span: (offset, offset + len),
+            severity: Severity::Error,
+            message: "expected a statement after `then`".to_string(),
+        };
+    }
+    let before = source[..offset].trim_end();
+    for keyword in ["then", "else"].iter() {
+        if before.ends_with(keyword)
+            && !before[..before.len() - keyword.len()].ends_with(is_id_char)
+            && (err.expected.contains("statement") || err.expected.contains("expression"))
+        {
+            let keyword_start = before.len() - keyword.len();
+            return Diagnostic {
+                span: (keyword_start, keyword_start + keyword.len()),
+                severity: Severity::Error,
+                message: format!("expected a statement after `{}`", keyword),
+            };
+        }
+    }
+
+    Diagnostic {
+        span: (offset, offset + len),
+        severity: Severity::Error,
+        message: format!(
+            "unexpected {}, expected {}",
+            found,
+            expected_list(&err.expected)
+        ),
+    }
+}
+// #endregion
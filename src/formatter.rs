@@ -0,0 +1,732 @@
+//! Provides a pretty-printer for hana source code.
+//!
+//! The formatter prints the abstract syntax tree back out with canonical
+//! indentation, spacing and `begin`/`end` layout, putting the comments
+//! the parser skipped back where they were:
+//!
+//! ```
+//! use haru::formatter;
+//! let formatted = formatter::format("if x==1 then print( x ) // one\n").unwrap();
+//! assert_eq!(formatted, "if x == 1 then print(x) // one\n");
+//! ```
+
+use crate::ast::{ast, grammar};
+
+const INDENT: &str = "    ";
+
+/// Formats the program, returning the first syntax error if it can't be parsed.
+pub fn format(input: &str) -> Result<String, grammar::ParseError> {
+    let prog = grammar::start(input)?;
+    let mut formatter = Formatter {
+        source: input,
+        comments: grammar::comments(input)?,
+        next_comment: 0,
+        out: String::new(),
+        indent: 0,
+        last_pos: 0,
+        block_start: true,
+    };
+    if input.starts_with("#!") {
+        let end = input.find('\n').unwrap_or_else(|| input.len());
+        formatter.out.push_str(&input[..end]);
+        formatter.last_pos = end;
+        formatter.block_start = false;
+    }
+    formatter.stmts(&refs(&prog), input.len());
+    let mut out = formatter.out;
+    if !out.is_empty() {
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+struct Formatter<'a> {
+    source: &'a str,
+    comments: Vec<ast::Span>,
+    // index of the first comment that hasn't been printed
+    next_comment: usize,
+    out: String,
+    indent: usize,
+    // end of the last statement or comment printed
+    last_pos: usize,
+    // whether nothing has been printed in the current block
+    block_start: bool,
+}
+
+fn binop(op: &ast::BinOp) -> (&'static str, u8) {
+    use ast::BinOp::*;
+    match op {
+        Assign => ("=", 1),
+        Adds => ("+=", 1),
+        Subs => ("-=", 1),
+        Muls => ("*=", 1),
+        Divs => ("/=", 1),
+        Mods => ("%=", 1),
+        BitwiseAnd => ("&", 3),
+        BitwiseOr => ("|", 3),
+        BitwiseXor => ("xor", 3),
+        And => ("and", 4),
+        Or => ("or", 4),
+        Eq => ("==", 5),
+        Neq => ("!=", 5),
+        Gt => (">", 5),
+        Lt => ("<", 5),
+        Geq => (">=", 5),
+        Leq => ("<=", 5),
+        Of => ("of", 6),
+        Add => ("+", 7),
+        Sub => ("-", 7),
+        Mul => ("*", 8),
+        Div => ("/", 8),
+        Mod => ("mod", 8),
+    }
+}
+
+// whether the function is written as `|args| ...`
+fn is_pipe_function(def: &ast::FunctionDefinition) -> bool {
+    *def.stmt.span() == def._span
+}
+
+// how tightly the expression binds, lower values need parentheses in more
+// places
+fn precedence(expr: &dyn ast::AST) -> u8 {
+    let any = expr.as_any();
+    if let Some(expr) = any.downcast_ref::<ast::BinExpr>() {
+        binop(&expr.op).1
+    } else if any.is::<ast::DestructureExpr>() {
+        1
+    } else if any.is::<ast::CondExpr>() {
+        2
+    } else if any.is::<ast::CallExpr>()
+        || any.is::<ast::MemExpr>()
+        || any.is::<ast::SliceExpr>()
+        || any.is::<ast::UnaryExpr>()
+    {
+        9
+    } else if any.is::<ast::YieldExpr>() {
+        // so does the value of `yield expr`
+        0
+    } else if let Some(def) = any.downcast_ref::<ast::FunctionDefinition>() {
+        // the body of `|x| expr` extends as far as it can
+        if is_pipe_function(def) && !def.stmt.as_any().is::<ast::BlockStatement>() {
+            0
+        } else {
+            10
+        }
+    } else {
+        10
+    }
+}
+
+// whether an `else` after the statement would be taken as part of it
+fn ends_with_open_if(stmt: &dyn ast::AST) -> bool {
+    let any = stmt.as_any();
+    if let Some(stmt) = any.downcast_ref::<ast::IfStatement>() {
+        match &stmt.alt {
+            Some(alt) => ends_with_open_if(alt.as_ref()),
+            None => true,
+        }
+    } else if let Some(stmt) = any.downcast_ref::<ast::WhileStatement>() {
+        ends_with_open_if(stmt.then.as_ref())
+    } else if let Some(stmt) = any.downcast_ref::<ast::ForStatement>() {
+        ends_with_open_if(stmt.stmt.as_ref())
+    } else if let Some(stmt) = any.downcast_ref::<ast::ForInStatement>() {
+        ends_with_open_if(stmt.stmt.as_ref())
+    } else if let Some(stmt) = any.downcast_ref::<ast::FunctionStatement>() {
+        ends_with_open_if(stmt.def().stmt.as_ref())
+    } else {
+        false
+    }
+}
+
+fn refs(stmts: &[Box<dyn ast::AST>]) -> Vec<&dyn ast::AST> {
+    stmts.iter().map(|stmt| stmt.as_ref()).collect()
+}
+
+fn quote(s: &str) -> String {
+    let mut quoted = String::from("\"");
+    for ch in s.chars() {
+        match ch {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            ch => quoted.push(ch),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+impl<'a> Formatter<'a> {
+    // #region layout
+    fn content_end(&self, span: ast::Span) -> usize {
+        span.0 + self.source[span.0..span.1].trim_end().len()
+    }
+
+    // end of the code in the span, before any comments after it
+    fn code_end(&self, span: ast::Span) -> usize {
+        let mut end = span.1;
+        loop {
+            end = self.content_end((span.0, end));
+            match self.comments.iter().find(|comment| comment.1 == end) {
+                Some(comment) if comment.0 >= span.0 => end = comment.0,
+                _ => return end,
+            }
+        }
+    }
+
+    // whether nothing but whitespace is before pos on its line
+    fn starts_line(&self, pos: usize) -> bool {
+        let before = self.source[..pos].trim_end_matches(|ch| ch == ' ' || ch == '\t');
+        before.is_empty() || before.ends_with('\n')
+    }
+
+    fn end_of_line(&self, pos: usize) -> usize {
+        self.source[pos..]
+            .find('\n')
+            .map_or(self.source.len(), |i| pos + i)
+    }
+
+    // finds the closing keyword of the block in the span,
+    // which may be followed by comments
+    fn closing_keyword(&self, span: ast::Span, keyword: &str) -> usize {
+        let mut end = span.1;
+        loop {
+            end = self.content_end((span.0, end));
+            match self.comments[self.next_comment..]
+                .iter()
+                .find(|comment| comment.1 == end)
+            {
+                Some(comment) => end = comment.0,
+                None => return end - keyword.len(),
+            }
+        }
+    }
+
+    fn newline(&mut self) {
+        self.out.push('\n');
+        for _ in 0..self.indent {
+            self.out.push_str(INDENT);
+        }
+    }
+
+    // starts the line of a statement or comment, keeping one
+    // blank line before it if there was any in the source
+    fn line(&mut self, pos: usize) {
+        if !self.out.is_empty() {
+            if !self.block_start
+                && pos > self.last_pos
+                && self.source[self.last_pos..pos].matches('\n').count() > 1
+            {
+                self.out.push('\n');
+            }
+            self.newline();
+        }
+        self.block_start = false;
+    }
+
+    fn comment(&mut self) -> ast::Span {
+        let comment = self.comments[self.next_comment];
+        self.next_comment += 1;
+        self.last_pos = self.last_pos.max(comment.1);
+        comment
+    }
+
+    // appends the comments before pos that follow code on their line
+    fn trailing_comments(&mut self, pos: usize) {
+        while self.next_comment < self.comments.len()
+            && self.comments[self.next_comment].0 < pos
+            && !self.starts_line(self.comments[self.next_comment].0)
+        {
+            let comment = self.comment();
+            self.out.push(' ');
+            self.out.push_str(&self.source[comment.0..comment.1]);
+        }
+    }
+
+    // prints the comments before pos, each on its own line
+    // unless it was on the line of the previous statement
+    fn leading_comments(&mut self, pos: usize) {
+        while self.next_comment < self.comments.len() && self.comments[self.next_comment].0 < pos {
+            let start = self.comments[self.next_comment].0;
+            if self.block_start || self.out.is_empty() || self.starts_line(start) {
+                self.line(start);
+                let comment = self.comment();
+                self.out.push_str(&self.source[comment.0..comment.1]);
+            } else {
+                self.trailing_comments(start + 1);
+            }
+        }
+    }
+
+    fn stmts(&mut self, stmts: &[&dyn ast::AST], end: usize) {
+        for &stmt in stmts {
+            self.leading_comments(stmt.span().0);
+            self.line(stmt.span().0);
+            self.stmt_line(stmt);
+        }
+        self.leading_comments(end);
+    }
+
+    // prints a statement that ends its line
+    fn stmt_line(&mut self, stmt: &dyn ast::AST) {
+        self.stmt(stmt);
+        self.trailing_comments(stmt.span().1);
+        self.last_pos = self.last_pos.max(self.code_end(*stmt.span()));
+    }
+
+    // prints the statements of a block after its header, indented,
+    // leaving the output on the line of the closing keyword
+    fn body(&mut self, header_end: usize, stmts: &[&dyn ast::AST], end: usize) {
+        let first = stmts.first().map_or(end, |stmt| stmt.span().0);
+        self.trailing_comments(self.end_of_line(header_end).min(first));
+        self.indent += 1;
+        self.block_start = true;
+        self.stmts(stmts, end);
+        self.indent -= 1;
+        self.newline();
+    }
+
+    fn block(&mut self, block: &ast::BlockStatement) {
+        self.out.push_str("begin");
+        let end = self.closing_keyword(block._span, "end");
+        self.body(block._span.0, &refs(&block.stmts), end);
+        self.out.push_str("end");
+        self.last_pos = self.last_pos.max(block._span.1);
+    }
+
+    // prints the body of a control flow statement
+    fn then(&mut self, stmt: &dyn ast::AST, force_block: bool) {
+        if let Some(block) = stmt.as_any().downcast_ref::<ast::BlockStatement>() {
+            self.out.push(' ');
+            self.block(block);
+        } else if force_block {
+            self.out.push_str(" begin");
+            self.body(stmt.span().0, &[stmt], stmt.span().1);
+            self.out.push_str("end");
+        } else {
+            self.out.push_str(" then ");
+            self.stmt_line(stmt);
+        }
+    }
+    // #endregion
+
+    // #region statements
+    fn stmt(&mut self, stmt: &dyn ast::AST) {
+        let any = stmt.as_any();
+        if let Some(stmt) = any.downcast_ref::<ast::BlockStatement>() {
+            self.block(stmt);
+        } else if let Some(stmt) = any.downcast_ref::<ast::ExprStatement>() {
+            self.expr(stmt.expr.as_ref());
+        } else if let Some(stmt) = any.downcast_ref::<ast::IfStatement>() {
+            self.out.push_str("if ");
+            self.expr(stmt.expr.as_ref());
+            let force_block = stmt.alt.is_some() && ends_with_open_if(stmt.then.as_ref());
+            self.then(stmt.then.as_ref(), force_block);
+            if let Some(alt) = &stmt.alt {
+                self.leading_comments(alt.span().0);
+                self.newline();
+                self.out.push_str("else ");
+                self.stmt_line(alt.as_ref());
+            }
+        } else if let Some(stmt) = any.downcast_ref::<ast::WhileStatement>() {
+            self.out.push_str("while ");
+            self.expr(stmt.expr.as_ref());
+            self.then(stmt.then.as_ref(), false);
+        } else if let Some(stmt) = any.downcast_ref::<ast::ForStatement>() {
+            self.out.push_str("for ");
+            self.out.push_str(&stmt.id);
+            self.out.push_str(" = ");
+            self.expr(stmt.from.as_ref());
+            self.out
+                .push_str(if stmt.is_up { " to " } else { " downto " });
+            self.expr(stmt.to.as_ref());
+            // the default step isn't in the source
+            if stmt.step.span().0 != std::usize::MAX {
+                self.out.push_str(" step ");
+                self.expr(stmt.step.as_ref());
+            }
+            self.then(stmt.stmt.as_ref(), false);
+        } else if let Some(stmt) = any.downcast_ref::<ast::ForInStatement>() {
+            self.out.push_str("for ");
+            self.pattern(&stmt.pattern);
+            self.out.push_str(" in ");
+            self.expr(stmt.expr.as_ref());
+            self.then(stmt.stmt.as_ref(), false);
+        } else if any.is::<ast::ContinueStatement>() {
+            self.out.push_str("continue");
+        } else if any.is::<ast::BreakStatement>() {
+            self.out.push_str("break");
+        } else if let Some(stmt) = any.downcast_ref::<ast::ReturnStatement>() {
+            self.out.push_str("return");
+            if let Some(expr) = &stmt.expr {
+                self.out.push(' ');
+                self.expr(expr.as_ref());
+            }
+        } else if let Some(stmt) = any.downcast_ref::<ast::RaiseStatement>() {
+            self.out.push_str("raise ");
+            self.expr(stmt.expr.as_ref());
+        } else if let Some(stmt) = any.downcast_ref::<ast::FunctionStatement>() {
+            let def = stmt.def();
+            self.out.push_str("function ");
+            self.function_header(def);
+            if def.stmt.as_any().is::<ast::BlockStatement>() {
+                self.then(def.stmt.as_ref(), false);
+            } else {
+                self.out.push(' ');
+                self.stmt_line(def.stmt.as_ref());
+            }
+        } else if let Some(stmt) = any.downcast_ref::<ast::RecordStatement>() {
+            self.record(stmt.def());
+        } else if let Some(stmt) = any.downcast_ref::<ast::TryStatement>() {
+            self.try_stmt(stmt);
+        } else if let Some(stmt) = any.downcast_ref::<ast::UseStatement>() {
+            self.out.push_str("use ");
+            self.out.push_str(&quote(&stmt.path));
+            if let Some(namespace) = &stmt.namespace {
+                self.out.push_str(" as ");
+                self.out.push_str(namespace);
+            }
+        } else {
+            self.expr(stmt);
+        }
+    }
+
+    fn try_stmt(&mut self, stmt: &ast::TryStatement) {
+        self.out.push_str("try");
+        let end = self.closing_keyword(stmt._span, "end");
+        let body_end = stmt.cases.first().map_or(end, |case| case._span.0);
+        self.body(stmt._span.0, &refs(&stmt.stmts), body_end);
+        for (i, case) in stmt.cases.iter().enumerate() {
+            self.out.push_str("case ");
+            self.expr(case.etype.as_ref());
+            if let Some(id) = &case.id {
+                self.out.push_str(" as ");
+                self.expr(id.as_ref());
+            }
+            let case_end = stmt.cases.get(i + 1).map_or(end, |case| case._span.0);
+            self.body(case._span.0, &refs(&case.stmts), case_end);
+        }
+        self.out.push_str("end");
+    }
+
+    fn record(&mut self, def: &ast::RecordDefinition) {
+        self.out.push_str("record");
+        if let Some(id) = &def.id {
+            self.out.push(' ');
+            self.out.push_str(id);
+        }
+        let end = self.closing_keyword(def._span, "end");
+        self.body(def._span.0, &refs(&def.stmts), end);
+        self.out.push_str("end");
+    }
+
+    fn function_header(&mut self, def: &ast::FunctionDefinition) {
+        if let Some(id) = &def.id {
+            self.out.push_str(id);
+        }
+        self.out.push('(');
+        self.args(def);
+        self.out.push(')');
+    }
+
+    fn args(&mut self, def: &ast::FunctionDefinition) {
+        let nrequired = def.nrequired();
+        for (i, arg) in def.args.iter().enumerate() {
+            if i > 0 {
+                self.out.push_str(", ");
+            }
+            if def.rest && i == def.args.len() - 1 {
+                self.out.push('*');
+            }
+            match def.patterns.iter().find(|(pos, _)| *pos == i) {
+                Some((_, pattern)) => self.pattern(pattern),
+                None => self.out.push_str(arg),
+            }
+            if let Some(default) = i.checked_sub(nrequired).and_then(|i| def.defaults.get(i)) {
+                self.out.push_str(" = ");
+                self.expr(default.as_ref());
+            }
+        }
+    }
+    // #endregion
+
+    // #region expressions
+    fn expr(&mut self, expr: &dyn ast::AST) {
+        let any = expr.as_any();
+        if any.is::<ast::IntLiteral>()
+            || any.is::<ast::BigIntLiteral>()
+            || any.is::<ast::FloatLiteral>()
+            || any.is::<ast::StrLiteral>()
+            || any.is::<ast::StrInterpolation>()
+        {
+            // literals are kept as they are written
+            let span = *expr.span();
+            self.out.push_str(&self.source[span.0..span.1]);
+        } else if let Some(expr) = any.downcast_ref::<ast::Identifier>() {
+            self.out.push_str(&expr.val);
+        } else if let Some(expr) = any.downcast_ref::<ast::ArrayExpr>() {
+            self.out.push('[');
+            self.list(&expr.exprs);
+            self.out.push(']');
+        } else if let Some(expr) = any.downcast_ref::<ast::MapExpr>() {
+            self.map(expr);
+        } else if let Some(expr) = any.downcast_ref::<ast::UnaryExpr>() {
+            self.out.push_str(match expr.op {
+                ast::UnaryOp::Not => "not ",
+                ast::UnaryOp::Neg => "-",
+            });
+            // only a value can be negated without parentheses
+            self.operand(expr.val.as_ref(), precedence(expr.val.as_ref()) < 10);
+        } else if let Some(expr) = any.downcast_ref::<ast::CondExpr>() {
+            self.operand(expr.cond.as_ref(), precedence(expr.cond.as_ref()) < 3);
+            self.out.push_str(" ? ");
+            self.operand(expr.then.as_ref(), precedence(expr.then.as_ref()) < 3);
+            self.out.push_str(" : ");
+            self.operand(expr.alt.as_ref(), precedence(expr.alt.as_ref()) < 3);
+        } else if let Some(expr) = any.downcast_ref::<ast::BinExpr>() {
+            let (op, prec) = binop(&expr.op);
+            let right = precedence(expr.right.as_ref());
+            self.operand(
+                expr.left.as_ref(),
+                precedence(expr.left.as_ref()) < prec.max(2),
+            );
+            self.out.push(' ');
+            self.out.push_str(op);
+            self.out.push(' ');
+            if prec == 1 {
+                // the right side of an assignment is any expression but another assignment
+                self.operand(expr.right.as_ref(), right == 1);
+            } else {
+                self.operand(expr.right.as_ref(), right <= prec);
+            }
+        } else if let Some(expr) = any.downcast_ref::<ast::MemExpr>() {
+            self.postfix_operand(expr.left.as_ref());
+            match expr.right.as_any().downcast_ref::<ast::Identifier>() {
+                Some(id) if !expr.is_expr => {
+                    self.out
+                        .push_str(if expr.is_namespace { "::" } else { "." });
+                    self.out.push_str(&id.val);
+                }
+                _ => {
+                    self.out.push('[');
+                    self.expr(expr.right.as_ref());
+                    self.out.push(']');
+                }
+            }
+        } else if let Some(expr) = any.downcast_ref::<ast::SliceExpr>() {
+            self.postfix_operand(expr.left.as_ref());
+            self.out.push('[');
+            if let Some(from) = &expr.from {
+                self.expr(from.as_ref());
+            }
+            self.out.push(':');
+            if let Some(to) = &expr.to {
+                self.expr(to.as_ref());
+            }
+            if let Some(step) = &expr.step {
+                self.out.push(':');
+                self.expr(step.as_ref());
+            }
+            self.out.push(']');
+        } else if let Some(expr) = any.downcast_ref::<ast::YieldExpr>() {
+            self.out.push_str("yield");
+            if let Some(expr) = &expr.expr {
+                self.out.push(' ');
+                self.expr(expr.as_ref());
+            }
+        } else if let Some(expr) = any.downcast_ref::<ast::CallExpr>() {
+            self.postfix_operand(expr.callee.as_ref());
+            self.out.push('(');
+            self.list(&expr.args);
+            for (i, (name, arg)) in expr.kwargs.iter().enumerate() {
+                if i > 0 || !expr.args.is_empty() {
+                    self.out.push_str(", ");
+                }
+                self.out.push_str(name);
+                self.out.push_str(": ");
+                self.expr(arg.as_ref());
+            }
+            self.out.push(')');
+        } else if let Some(def) = any.downcast_ref::<ast::FunctionDefinition>() {
+            self.function(def);
+        } else if let Some(def) = any.downcast_ref::<ast::RecordDefinition>() {
+            self.record(def);
+        } else if let Some(expr) = any.downcast_ref::<ast::MatchExpr>() {
+            self.match_expr(expr);
+        } else if let Some(expr) = any.downcast_ref::<ast::DestructureExpr>() {
+            self.pattern(&expr.pattern);
+            self.out.push_str(" = ");
+            self.expr(expr.expr.as_ref());
+        }
+    }
+
+    fn match_expr(&mut self, expr: &ast::MatchExpr) {
+        self.out.push_str("match ");
+        self.expr(expr.expr.as_ref());
+        let end = self.closing_keyword(expr._span, "end");
+        let first = expr.cases.first().map_or(end, |case| case._span.0);
+        self.trailing_comments(self.end_of_line(expr.expr.span().1).min(first));
+        self.indent += 1;
+        self.block_start = true;
+        for (i, case) in expr.cases.iter().enumerate() {
+            self.leading_comments(case._span.0);
+            self.line(case._span.0);
+            self.out.push_str("case ");
+            self.pattern(&case.pattern);
+            if let Some(guard) = &case.guard {
+                self.out.push_str(" if ");
+                self.expr(guard.as_ref());
+            }
+            match &case.body {
+                ast::MatchBody::Expr(expr) => {
+                    self.out.push_str(" then ");
+                    self.expr(expr.as_ref());
+                    self.trailing_comments(self.end_of_line(expr.span().1));
+                    self.last_pos = self.last_pos.max(expr.span().1);
+                }
+                ast::MatchBody::Stmts(stmts) => {
+                    // like the body of a block, without the closing keyword
+                    let case_end = expr.cases.get(i + 1).map_or(end, |case| case._span.0);
+                    let first = stmts.first().map_or(case_end, |stmt| stmt.span().0);
+                    self.trailing_comments(self.end_of_line(case._span.0).min(first));
+                    self.indent += 1;
+                    self.block_start = true;
+                    self.stmts(&refs(stmts), case_end);
+                    self.indent -= 1;
+                    self.block_start = false;
+                }
+            }
+        }
+        self.leading_comments(end);
+        self.indent -= 1;
+        self.newline();
+        self.out.push_str("end");
+    }
+
+    fn pattern(&mut self, pattern: &ast::Pattern) {
+        match pattern {
+            ast::Pattern::Wildcard => self.out.push('_'),
+            ast::Pattern::Bind(id, _) => self.out.push_str(id),
+            ast::Pattern::Literal(literal) => self.expr(literal.as_ref()),
+            ast::Pattern::Of(record, fields) => {
+                self.out.push_str("of ");
+                self.expr(record.as_ref());
+                if !fields.is_empty() {
+                    self.out.push(' ');
+                    self.fields_pattern(fields);
+                }
+            }
+            ast::Pattern::Array(elems, rest) => {
+                self.out.push('[');
+                for i in 0..=elems.len() {
+                    if let Some((pos, rest)) = rest {
+                        if *pos == i {
+                            if i > 0 {
+                                self.out.push_str(", ");
+                            }
+                            self.out.push('*');
+                            self.pattern(rest.as_ref());
+                        }
+                    }
+                    if let Some(elem) = elems.get(i) {
+                        if i > 0 || rest.as_ref().map_or(false, |rest| rest.0 == 0) {
+                            self.out.push_str(", ");
+                        }
+                        self.pattern(elem);
+                    }
+                }
+                self.out.push(']');
+            }
+            ast::Pattern::Record(fields) => self.fields_pattern(fields),
+        }
+    }
+
+    fn fields_pattern(&mut self, fields: &[(String, ast::Pattern)]) {
+        self.out.push('{');
+        for (i, (key, pattern)) in fields.iter().enumerate() {
+            if i > 0 {
+                self.out.push_str(", ");
+            }
+            self.out.push_str(key);
+            match pattern {
+                ast::Pattern::Bind(id, _) if id == key => {}
+                pattern => {
+                    self.out.push_str(": ");
+                    self.pattern(pattern);
+                }
+            }
+        }
+        self.out.push('}');
+    }
+
+    fn operand(&mut self, expr: &dyn ast::AST, parenthesize: bool) {
+        if parenthesize {
+            self.out.push('(');
+            self.expr(expr);
+            self.out.push(')');
+        } else {
+            self.expr(expr);
+        }
+    }
+
+    // prints the left side of a member or call expression
+    fn postfix_operand(&mut self, expr: &dyn ast::AST) {
+        let any = expr.as_any();
+        let parenthesize = precedence(expr) < 9
+            || any.is::<ast::FunctionDefinition>()
+            || any.is::<ast::RecordDefinition>()
+            || any.is::<ast::MatchExpr>();
+        self.operand(expr, parenthesize);
+    }
+
+    fn list(&mut self, exprs: &[Box<dyn ast::AST>]) {
+        for (i, expr) in exprs.iter().enumerate() {
+            if i > 0 {
+                self.out.push_str(", ");
+            }
+            self.expr(expr.as_ref());
+        }
+    }
+
+    fn map(&mut self, expr: &ast::MapExpr) {
+        self.out.push('{');
+        for (i, (key, val)) in expr.entries.iter().enumerate() {
+            if i > 0 {
+                self.out.push_str(", ");
+            }
+            // keys can't be assignments
+            self.operand(key.as_ref(), precedence(key.as_ref()) < 2);
+            self.out.push_str(" => ");
+            self.expr(val.as_ref());
+        }
+        self.out.push('}');
+    }
+
+    fn function(&mut self, def: &ast::FunctionDefinition) {
+        if is_pipe_function(def) {
+            self.out.push('|');
+            self.args(def);
+            self.out.push_str("| ");
+            if let Some(block) = def.stmt.as_any().downcast_ref::<ast::BlockStatement>() {
+                self.out.push('{');
+                let end = self.closing_keyword(def._span, "}");
+                self.body(def._span.0, &refs(&block.stmts), end);
+                self.out.push('}');
+            } else if let Some(stmt) = def.stmt.as_any().downcast_ref::<ast::ReturnStatement>() {
+                self.expr(stmt.expr.as_ref().unwrap().as_ref());
+            }
+            return;
+        }
+        self.out.push_str("function");
+        if def.id.is_some() {
+            self.out.push(' ');
+        }
+        self.function_header(def);
+        // statements other than blocks end with a newline
+        self.then(def.stmt.as_ref(), true);
+    }
+    // #endregion
+}
@@ -5,8 +5,8 @@ use crate::vmbindings::nativeval::NativeValue;
 use crate::vmbindings::value::Value;
 use crate::vmbindings::vm::Vm;
 
-pub extern "C" fn constructor(cvm: *mut Vm, nargs: u16) {
-    let vm = unsafe { &mut *cvm };
+pub unsafe extern "C" fn constructor(cvm: *mut Vm, nargs: u16) {
+    let vm = &mut *cvm;
     if nargs == 0 {
         vm.stack.push(Value::Array(vm.malloc(Vec::new())).wrap());
         return;
@@ -63,9 +63,9 @@ fn value_cmp(left: &NativeValue, right: &NativeValue) -> Ordering {
     let right = right.clone();
 
     match unsafe { value_gt(left, right).unwrap() } {
-        Value::Int(1) => Ordering::Greater,
+        Value::True => Ordering::Greater,
         _ => match unsafe { value_lt(left, right).unwrap() } {
-            Value::Int(1) => Ordering::Less,
+            Value::True => Ordering::Less,
             _ => Ordering::Equal,
         },
     }
@@ -146,7 +146,7 @@ fn index(array: Value::Array, elem: Value::Any) -> Value {
     let array = array.as_ref();
     for i in 0..(array.len() - 1) {
         match unsafe { value_eq(array[i], elem.wrap()).unwrap() } {
-            Value::Int(1) => return Value::Int(i as i64),
+            Value::True => return Value::Int(i as i64),
             _ => (),
         }
     }
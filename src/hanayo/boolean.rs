@@ -0,0 +1,8 @@
+//! Provides Bool record for handling booleans
+use crate::vmbindings::value::Value;
+use crate::vmbindings::vm::Vm;
+
+#[hana_function()]
+fn constructor(val: Value::Any) -> Value {
+    Value::bool(val.is_true(vm))
+}
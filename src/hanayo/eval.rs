@@ -24,6 +24,9 @@ fn eval(s: Value::Str) -> Value {
                 return Value::False;
             }
         }
+        if vm.optimize {
+            c.optimize(target_ip as usize);
+        }
         c.cpushop(VmOpcode::OP_HALT);
         //panic!("{:?}", c.interned_strings);
         vm.interned_strings = c.interned_strings.take();
@@ -95,9 +95,9 @@ fn write(file: Value::Record, buf: Value::Str) -> Value {
     let file = file.as_mut();
     if let Some(field) = file.native_field.as_mut() {
         let file = field.downcast_mut::<File>().unwrap();
-        Value::Int(file.write_all(buf.as_ref().as_bytes()).is_ok() as i64)
+        Value::bool(file.write_all(buf.as_ref().as_bytes()).is_ok())
     } else {
-        Value::Int(0)
+        Value::False
     }
 }
 
@@ -10,6 +10,7 @@ use crate::vmbindings::vmerror::VmError;
 fn constructor(val: Value::Any) -> Value {
     match val {
         Value::Int(n) => Value::Float(n as f64),
+        Value::BigInt(n) => Value::Float(n.as_ref().to_f64()),
         Value::Float(n) => Value::Float(n),
         Value::Str(s) => match f64::from_str(s.as_ref()) {
             Ok(n) => Value::Float(n),
@@ -0,0 +1,19 @@
+//! Provides Generator record for resuming generators
+use crate::vmbindings::value::Value;
+use crate::vmbindings::vm::Vm;
+
+#[hana_function()]
+fn next(gen: Value::Record) -> Value {
+    match vm.call_generator(gen, Value::Nil.wrap()) {
+        Some(val) => unsafe { val.unwrap() },
+        None => Value::PropagateError,
+    }
+}
+
+#[hana_function()]
+fn send(gen: Value::Record, val: Value::Any) -> Value {
+    match vm.call_generator(gen, val.wrap()) {
+        Some(val) => unsafe { val.unwrap() },
+        None => Value::PropagateError,
+    }
+}
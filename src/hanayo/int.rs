@@ -1,18 +1,19 @@
 //! Provides Int record for handling integers
+use crate::vmbindings::bigint::BigInt;
 use crate::vmbindings::record::Record;
 use crate::vmbindings::value::Value;
 use crate::vmbindings::vm::Vm;
 use crate::vmbindings::vmerror::VmError;
-use std::str::FromStr;
 
 #[hana_function()]
 fn constructor(val: Value::Any) -> Value {
     match val {
         Value::Int(n) => Value::Int(n),
+        Value::BigInt(n) => Value::BigInt(n),
         Value::Float(n) => Value::Int(n as i64),
-        Value::Str(s) => match i64::from_str(s.as_ref()) {
-            Ok(n) => Value::Int(n),
-            Err(_) => {
+        Value::Str(s) => match BigInt::from_str_radix(s.as_ref(), 10) {
+            Some(n) => Value::from_bigint(n, vm),
+            None => {
                 hana_raise!(vm, {
                     let rec = vm.malloc(Record::new());
                     rec.as_mut().insert(
@@ -62,6 +63,19 @@ fn chr(i: Value::Int) -> Value {
 }
 
 #[hana_function()]
-fn hex(i: Value::Int) -> Value {
-    Value::Str(vm.malloc(format!("0x{:x}", i).into()))
+fn hex(i: Value::Any) -> Value {
+    let s = match i {
+        Value::Int(n) if n < 0 => format!("-0x{:x}", -i128::from(n)),
+        Value::Int(n) => format!("0x{:x}", n),
+        Value::BigInt(n) => {
+            let n = n.as_ref();
+            if n.is_negative() {
+                format!("-0x{:x}", -n)
+            } else {
+                format!("0x{:x}", n)
+            }
+        }
+        _ => panic!("expected argument i to be type Int"),
+    };
+    Value::Str(vm.malloc(s.into()))
 }
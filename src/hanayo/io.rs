@@ -4,10 +4,10 @@ use std::io::Write;
 use crate::vmbindings::value::Value;
 use crate::vmbindings::vm::Vm;
 
-pub extern "C" fn print(cvm: *mut Vm, nargs: u16) {
-    let vm = unsafe { &mut *cvm };
+pub unsafe extern "C" fn print(cvm: *mut Vm, nargs: u16) {
+    let vm = &mut *cvm;
     for _ in 0..nargs {
-        let val = unsafe { vm.stack.pop().unwrap().unwrap() };
+        let val = vm.stack.pop().unwrap().unwrap();
         std::print!("{}", val);
     }
     std::io::stdout().flush().unwrap();
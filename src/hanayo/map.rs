@@ -0,0 +1,99 @@
+//! Provides Map record for handling maps
+use crate::vmbindings::map::{Map, MapKey};
+use crate::vmbindings::record::Record;
+use crate::vmbindings::value::Value;
+use crate::vmbindings::vm::Vm;
+use crate::vmbindings::vmerror::VmError;
+
+fn map_of(rec: &mut Record) -> &mut Map {
+    let field = rec.native_field.as_mut().unwrap();
+    field.downcast_mut::<Map>().unwrap()
+}
+
+#[hana_function()]
+fn constructor() -> Value {
+    Value::Record(vm.new_map())
+}
+
+#[hana_function()]
+fn get(map: Value::Record, key: Value::Any) -> Value {
+    let map = map_of(map.as_mut());
+    match MapKey::from_value(&key.wrap()).and_then(|key| map.get(&key).cloned()) {
+        Some(val) => unsafe { val.unwrap() },
+        None => Value::Nil,
+    }
+}
+
+#[hana_function()]
+fn set(map: Value::Record, key: Value::Any, val: Value::Any) -> Value {
+    if let Some(key) = MapKey::from_value(&key.wrap()) {
+        map_of(map.as_mut()).insert(key, val.wrap());
+        return val;
+    }
+    hana_raise!(vm, {
+        let rec = vm.malloc(Record::new());
+        rec.as_mut().insert(
+            "prototype",
+            Value::Record(vm.stdlib.as_ref().unwrap().invalid_argument_error.clone()).wrap(),
+        );
+        rec.as_mut().insert(
+            "why",
+            Value::Str(vm.malloc("Map key must be hashable".to_string().into())).wrap(),
+        );
+        rec.as_mut().insert("where", Value::Int(1).wrap());
+        Value::Record(rec)
+    });
+}
+
+#[hana_function()]
+fn delete(map: Value::Record, key: Value::Any) -> Value {
+    let map = map_of(map.as_mut());
+    match MapKey::from_value(&key.wrap()).and_then(|key| map.remove(&key)) {
+        Some(_) => Value::True,
+        None => Value::False,
+    }
+}
+
+#[hana_function()]
+fn has(map: Value::Record, key: Value::Any) -> Value {
+    let map = map_of(map.as_mut());
+    Value::bool(MapKey::from_value(&key.wrap()).map_or(false, |key| map.contains_key(&key)))
+}
+
+#[hana_function()]
+fn keys(map: Value::Record) -> Value {
+    let map = map_of(map.as_mut());
+    let array = vm.malloc(Vec::with_capacity(map.len()));
+    for (key, _) in map.iter() {
+        array.as_mut().push(key.to_value(vm).wrap());
+    }
+    Value::Array(array)
+}
+
+#[hana_function()]
+fn values(map: Value::Record) -> Value {
+    let map = map_of(map.as_mut());
+    let array = vm.malloc(Vec::with_capacity(map.len()));
+    for (_, val) in map.iter() {
+        array.as_mut().push(val.clone());
+    }
+    Value::Array(array)
+}
+
+#[hana_function()]
+fn items(map: Value::Record) -> Value {
+    let map = map_of(map.as_mut());
+    let array = vm.malloc(Vec::with_capacity(map.len()));
+    for (key, val) in map.iter() {
+        let item = vm.malloc(Vec::with_capacity(2));
+        item.as_mut().push(key.to_value(vm).wrap());
+        item.as_mut().push(val.clone());
+        array.as_mut().push(Value::Array(item).wrap());
+    }
+    Value::Array(array)
+}
+
+#[hana_function()]
+fn length(map: Value::Record) -> Value {
+    Value::Int(map_of(map.as_mut()).len() as i64)
+}
@@ -2,6 +2,7 @@
 
 use crate::vmbindings::gc::Gc;
 use crate::vmbindings::record::Record;
+use crate::vmbindings::string::HaruString;
 use crate::vmbindings::value::*;
 use crate::vmbindings::vm::Vm;
 use crate::vmbindings::vmerror::VmError;
@@ -25,6 +26,7 @@ pub mod dir;
 pub mod env;
 pub mod eval;
 pub mod file;
+pub mod generator;
 pub mod io;
 pub mod math;
 pub mod proc;
@@ -40,9 +42,12 @@ cfg_if! {
 }
 
 pub mod array;
+pub mod boolean;
 pub mod float;
 pub mod int;
+pub mod map;
 pub mod record;
+pub mod set;
 pub mod string;
 
 /// Standard library context
@@ -52,11 +57,19 @@ pub struct HanayoCtx {
     pub cmd_rec: Gc<Record>,
     pub proc_rec: Gc<Record>,
     pub time_rec: Gc<Record>,
+    pub generator_rec: Gc<Record>,
+    pub map_rec: Gc<Record>,
+    pub set_rec: Gc<Record>,
 
     // errors
     pub invalid_argument_error: Gc<Record>,
     pub io_error: Gc<Record>,
+    pub import_error: Gc<Record>,
+    pub stack_overflow_error: Gc<Record>,
     pub utf8_decoding_error: Gc<Record>,
+
+    // names of global variables defined by the standard library
+    pub globals: Vec<HaruString>,
 }
 
 /// Initialises hanayo for the virtual machine
@@ -73,8 +86,8 @@ pub fn init(vm: &mut Vm) {
     }
     // constants
     set_var!("nil", Value::Nil);
-    set_var!("true", Value::Int(1));
-    set_var!("false", Value::Int(0));
+    set_var!("true", Value::True);
+    set_var!("false", Value::False);
     set_var!("inf", Value::Float(std::f64::INFINITY));
     set_var!("nan", Value::Float(std::f64::NAN));
 
@@ -140,6 +153,19 @@ pub fn init(vm: &mut Vm) {
     }
     // #endregion
 
+    // #region bool
+    {
+        let boolean = vm.malloc(Record::new());
+        set_obj_var!(
+            boolean,
+            "constructor",
+            Value::NativeFn(boolean::constructor)
+        );
+        vm.dbool = Some(boolean.clone());
+        set_var!("Bool", Value::Record(boolean));
+    }
+    // #endregion
+
     // #region float
     {
         let float = vm.malloc(Record::new());
@@ -160,6 +186,35 @@ pub fn init(vm: &mut Vm) {
     }
     // #endregion
 
+    // #region map
+    let map = vm.malloc(Record::new());
+    set_obj_var!(map, "constructor", Value::NativeFn(map::constructor));
+    set_obj_var!(map, "get", Value::NativeFn(map::get));
+    set_obj_var!(map, "set", Value::NativeFn(map::set));
+    set_obj_var!(map, "delete", Value::NativeFn(map::delete));
+    set_obj_var!(map, "has?", Value::NativeFn(map::has));
+    set_obj_var!(map, "keys", Value::NativeFn(map::keys));
+    set_obj_var!(map, "values", Value::NativeFn(map::values));
+    set_obj_var!(map, "items", Value::NativeFn(map::items));
+    set_obj_var!(map, "length", Value::NativeFn(map::length));
+    set_var!("Map", Value::Record(map.clone()));
+    // #endregion
+
+    // #region set
+    let set = vm.malloc(Record::new());
+    set_obj_var!(set, "constructor", Value::NativeFn(set::constructor));
+    set_obj_var!(set, "add", Value::NativeFn(set::add));
+    set_obj_var!(set, "delete", Value::NativeFn(set::delete));
+    set_obj_var!(set, "has?", Value::NativeFn(set::has));
+    set_obj_var!(set, "length", Value::NativeFn(set::length));
+    set_obj_var!(set, "union", Value::NativeFn(set::union));
+    set_obj_var!(set, "intersection", Value::NativeFn(set::intersection));
+    set_obj_var!(set, "difference", Value::NativeFn(set::difference));
+    set_obj_var!(set, "subset?", Value::NativeFn(set::subset));
+    set_obj_var!(set, "superset?", Value::NativeFn(set::superset));
+    set_var!("Set", Value::Record(set.clone()));
+    // #endregion
+
     // #region files
     let file = vm.malloc(Record::new());
     set_obj_var!(file, "constructor", Value::NativeFn(file::constructor));
@@ -232,6 +287,13 @@ pub fn init(vm: &mut Vm) {
     set_var!("Time", Value::Record(time.clone()));
     // #endregion
 
+    // #region generator
+    let generator = vm.malloc(Record::new());
+    set_obj_var!(generator, "next", Value::NativeFn(generator::next));
+    set_obj_var!(generator, "send", Value::NativeFn(generator::send));
+    set_var!("Generator", Value::Record(generator.clone()));
+    // #endregion
+
     cffi_load(vm);
 
     // #region errors
@@ -256,6 +318,27 @@ pub fn init(vm: &mut Vm) {
     );
     set_var!("IOError", Value::Record(io_error.clone()));
 
+    // ImportError
+    let import_error = vm.malloc(Record::new());
+    set_obj_var!(
+        import_error,
+        "what",
+        Value::Str(vm.malloc("Import error".to_string().into()))
+    );
+    set_var!("ImportError", Value::Record(import_error.clone()));
+
+    // StackOverflowError
+    let stack_overflow_error = vm.malloc(Record::new());
+    set_obj_var!(
+        stack_overflow_error,
+        "what",
+        Value::Str(vm.malloc("Stack overflow error".to_string().into()))
+    );
+    set_var!(
+        "StackOverflowError",
+        Value::Record(stack_overflow_error.clone())
+    );
+
     // UTF8DecodingError
     let utf8_decoding_error = vm.malloc(Record::new());
     set_obj_var!(
@@ -269,16 +352,24 @@ pub fn init(vm: &mut Vm) {
     );
     // #endregion
 
+    let globals = vm.global().keys().cloned().collect();
     vm.stdlib = Some(HanayoCtx {
         file_rec: file,
         dir_rec: dir,
         cmd_rec: cmd,
         proc_rec: proc,
         time_rec: time,
+        generator_rec: generator,
+        map_rec: map,
+        set_rec: set,
 
         // errors
         invalid_argument_error,
         io_error,
+        import_error,
+        stack_overflow_error,
         utf8_decoding_error,
+
+        globals,
     });
 }
@@ -121,7 +121,7 @@ fn kill(process: Value::Record) -> Value {
     let field = process.as_mut().native_field.as_mut().unwrap();
     let p = field.downcast_mut::<Child>().unwrap();
     match p.kill() {
-        Ok(()) => Value::Int(1),
-        Err(_) => Value::Int(0),
+        Ok(()) => Value::True,
+        Err(_) => Value::False,
     }
 }
@@ -0,0 +1,116 @@
+//! Provides Set record for handling sets
+use crate::vmbindings::map::MapKey;
+use crate::vmbindings::record::Record;
+use crate::vmbindings::set::Set;
+use crate::vmbindings::value::Value;
+use crate::vmbindings::vm::Vm;
+use crate::vmbindings::vmerror::VmError;
+
+fn set_of(rec: &Record) -> &Set {
+    let field = rec.native_field.as_ref().unwrap();
+    field.downcast_ref::<Set>().unwrap()
+}
+
+fn set_of_mut(rec: &mut Record) -> &mut Set {
+    let field = rec.native_field.as_mut().unwrap();
+    field.downcast_mut::<Set>().unwrap()
+}
+
+fn unhashable_error(vm: &Vm) -> Value {
+    let rec = vm.malloc(Record::new());
+    rec.as_mut().insert(
+        "prototype",
+        Value::Record(vm.stdlib.as_ref().unwrap().invalid_argument_error.clone()).wrap(),
+    );
+    rec.as_mut().insert(
+        "why",
+        Value::Str(vm.malloc("Set element must be hashable".to_string().into())).wrap(),
+    );
+    rec.as_mut().insert("where", Value::Int(1).wrap());
+    Value::Record(rec)
+}
+
+pub unsafe extern "C" fn constructor(cvm: *mut Vm, nargs: u16) {
+    let vm = &mut *cvm;
+    match nargs {
+        0 => {
+            let set = vm.new_set(Set::new());
+            vm.stack.push(Value::Record(set).wrap());
+        }
+        1 => from_array(cvm, nargs),
+        _ => {
+            vm.error = VmError::ERROR_MISMATCH_ARGUMENTS;
+            vm.error_expected = 1;
+        }
+    }
+}
+
+#[hana_function()]
+fn from_array(elements: Value::Array) -> Value {
+    let mut set = Set::new();
+    for elem in elements.as_ref().iter() {
+        match MapKey::from_value(elem) {
+            Some(elem) => {
+                set.insert(elem);
+            }
+            None => {
+                hana_raise!(vm, unhashable_error(vm));
+            }
+        }
+    }
+    Value::Record(vm.new_set(set))
+}
+
+#[hana_function()]
+fn add(set: Value::Record, elem: Value::Any) -> Value {
+    if let Some(elem) = MapKey::from_value(&elem.wrap()) {
+        return Value::bool(set_of_mut(set.as_mut()).insert(elem));
+    }
+    hana_raise!(vm, unhashable_error(vm));
+}
+
+#[hana_function()]
+fn delete(set: Value::Record, elem: Value::Any) -> Value {
+    let set = set_of_mut(set.as_mut());
+    Value::bool(MapKey::from_value(&elem.wrap()).map_or(false, |elem| set.remove(&elem)))
+}
+
+#[hana_function()]
+fn has(set: Value::Record, elem: Value::Any) -> Value {
+    let set = set_of(set.as_ref());
+    Value::bool(MapKey::from_value(&elem.wrap()).map_or(false, |elem| set.contains(&elem)))
+}
+
+#[hana_function()]
+fn length(set: Value::Record) -> Value {
+    Value::Int(set_of(set.as_ref()).len() as i64)
+}
+
+// set algebra
+#[hana_function()]
+fn union(left: Value::Record, right: Value::Record) -> Value {
+    let set = set_of(left.as_ref()).union(set_of(right.as_ref()));
+    Value::Record(vm.new_set(set))
+}
+
+#[hana_function()]
+fn intersection(left: Value::Record, right: Value::Record) -> Value {
+    let set = set_of(left.as_ref()).intersection(set_of(right.as_ref()));
+    Value::Record(vm.new_set(set))
+}
+
+#[hana_function()]
+fn difference(left: Value::Record, right: Value::Record) -> Value {
+    let set = set_of(left.as_ref()).difference(set_of(right.as_ref()));
+    Value::Record(vm.new_set(set))
+}
+
+#[hana_function()]
+fn subset(left: Value::Record, right: Value::Record) -> Value {
+    Value::bool(set_of(left.as_ref()).is_subset(set_of(right.as_ref())))
+}
+
+#[hana_function()]
+fn superset(left: Value::Record, right: Value::Record) -> Value {
+    Value::bool(set_of(right.as_ref()).is_subset(set_of(left.as_ref())))
+}
@@ -6,14 +6,14 @@ use crate::vmbindings::vmerror::VmError;
 use std::borrow::Borrow;
 use unicode_segmentation::UnicodeSegmentation;
 
-pub extern "C" fn constructor(cvm: *mut Vm, nargs: u16) {
-    let vm = unsafe { &mut *cvm };
+pub unsafe extern "C" fn constructor(cvm: *mut Vm, nargs: u16) {
+    let vm = &mut *cvm;
     if nargs == 0 {
         vm.stack
             .push(Value::Str(vm.malloc(String::new().into())).wrap());
         return;
     } else if nargs == 1 {
-        let arg = unsafe { vm.stack.pop().unwrap().unwrap() };
+        let arg = vm.stack.pop().unwrap().unwrap();
         vm.stack
             .push(Value::Str(vm.malloc(format!("{}", arg).to_string().into())).wrap());
     } else {
@@ -36,12 +36,12 @@ fn bytesize(s: Value::Str) -> Value {
 #[hana_function()]
 fn startswith(s: Value::Str, left: Value::Str) -> Value {
     let s = s.as_ref().borrow() as &String;
-    Value::Int(s.starts_with(left.as_ref().borrow() as &String) as i64)
+    Value::bool(s.starts_with(left.as_ref().borrow() as &String))
 }
 #[hana_function()]
 fn endswith(s: Value::Str, left: Value::Str) -> Value {
     let s = s.as_ref().borrow() as &String;
-    Value::Int(s.ends_with(left.as_ref().borrow() as &String) as i64)
+    Value::bool(s.ends_with(left.as_ref().borrow() as &String))
 }
 
 // basic manip
@@ -13,6 +13,12 @@ extern crate cfg_if;
 extern crate num_derive;
 
 pub mod ast;
+pub mod bytecode;
 pub mod compiler;
+pub mod debugger;
+pub mod diagnostics;
+pub mod formatter;
 pub mod hanayo;
+pub mod lint;
+pub mod optimizer;
 pub mod vmbindings;
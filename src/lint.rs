@@ -0,0 +1,665 @@
+//! Provides a static analysis pass over the abstract syntax tree.
+//!
+//! The linter walks the program the same way the compiler resolves
+//! variables, and reports suspicious code without running it:
+//!
+//! ```
+//! use haru::{ast, lint};
+//! let prog = ast::grammar::start("function f(a) begin\nb = 1\nreturn a\nend\n").unwrap();
+//! let warnings = lint::lint(&prog, "[cmdline]", &[]);
+//! assert_eq!(warnings[0].message, "unused local variable `b`");
+//! ```
+//!
+//! Along the way, it resolves every variable to where it's defined,
+//! which editors use for going to definitions.
+
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+use crate::ast::{ast, grammar};
+use crate::diagnostics::{Diagnostic, Severity};
+use crate::vmbindings::vm::resolve_module;
+
+/// What a variable holds when it's defined
+#[derive(Debug, Clone, PartialEq)]
+pub enum DefinitionKind {
+    Variable,
+    Argument,
+    /// Function with its argument names
+    Function(Vec<String>),
+    Record,
+}
+
+/// Where a variable is defined
+#[derive(Debug, Clone, PartialEq)]
+pub struct Definition {
+    pub name: String,
+    /// Span of the statement or expression defining the variable
+    pub span: ast::Span,
+    pub kind: DefinitionKind,
+}
+
+/// A use of a variable
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reference {
+    pub span: ast::Span,
+    /// Index of the variable's definition in `Analysis::definitions`
+    pub definition: usize,
+}
+
+/// Result of analysing a program
+pub struct Analysis {
+    pub diagnostics: Vec<Diagnostic>,
+    /// Variables defined in the program, excluding those from modules
+    pub definitions: Vec<Definition>,
+    pub references: Vec<Reference>,
+}
+
+struct Local {
+    name: String,
+    span: ast::Span,
+    used: bool,
+    definition: usize,
+}
+
+// variables declared in a function's body
+struct Scope {
+    args: Vec<String>,
+    arg_definitions: Vec<usize>,
+    locals: Vec<Local>,
+    loops: usize,
+}
+
+impl Scope {
+    fn new(args: Vec<String>, arg_definitions: Vec<usize>) -> Scope {
+        Scope {
+            args,
+            arg_definitions,
+            locals: Vec::new(),
+            loops: 0,
+        }
+    }
+
+    fn has(&self, name: &str) -> bool {
+        self.args.iter().any(|arg| arg == name)
+            || self.locals.iter().any(|local| local.name == name)
+    }
+}
+
+struct Linter {
+    diagnostics: Vec<Diagnostic>,
+    scopes: Vec<Scope>,
+    toplevel_loops: usize,
+    // globals assigned anywhere in the program or its modules
+    globals: HashSet<String>,
+    // definitions of the globals assigned in this file
+    global_definitions: HashMap<String, usize>,
+    // global variables read, checked once every assignment is known
+    global_reads: Vec<(String, ast::Span)>,
+    // whether some module couldn't be read, so globals are unknown
+    unknown_globals: bool,
+    // modules already loaded
+    modules: HashSet<PathBuf>,
+    file: String,
+    definitions: Vec<Definition>,
+    references: Vec<Reference>,
+}
+
+/// Lints the program in the file `file`.
+///
+/// `builtins` are global variables defined before the program is run,
+/// such as the ones from the standard library.
+pub fn lint(prog: &[Box<dyn ast::AST>], file: &str, builtins: &[String]) -> Vec<Diagnostic> {
+    analyze(prog, file, builtins).diagnostics
+}
+
+/// Lints the program in the file `file`, also resolving its variables.
+pub fn analyze(prog: &[Box<dyn ast::AST>], file: &str, builtins: &[String]) -> Analysis {
+    let mut linter = Linter::new(file.to_string(), builtins.iter().cloned().collect());
+    linter.stmts(prog);
+    // only the first read of each undefined global is reported
+    let mut reported = HashSet::new();
+    for (name, span) in std::mem::replace(&mut linter.global_reads, Vec::new()) {
+        if let Some(&definition) = linter.global_definitions.get(&name) {
+            linter.references.push(Reference { span, definition });
+        } else if !linter.unknown_globals
+            && !linter.globals.contains(&name)
+            && reported.insert(name.clone())
+        {
+            linter.warn(span, format!("undefined global variable `{}`", name));
+        }
+    }
+    let mut diagnostics = linter.diagnostics;
+    diagnostics.sort_by_key(|diagnostic| diagnostic.span);
+    Analysis {
+        diagnostics,
+        definitions: linter.definitions,
+        references: linter.references,
+    }
+}
+
+impl Linter {
+    fn new(file: String, globals: HashSet<String>) -> Linter {
+        Linter {
+            diagnostics: Vec::new(),
+            scopes: Vec::new(),
+            toplevel_loops: 0,
+            globals,
+            global_definitions: HashMap::new(),
+            global_reads: Vec::new(),
+            unknown_globals: false,
+            modules: HashSet::new(),
+            file,
+            definitions: Vec::new(),
+            references: Vec::new(),
+        }
+    }
+
+    fn warn(&mut self, span: ast::Span, message: String) {
+        self.diagnostics.push(Diagnostic {
+            span,
+            severity: Severity::Warning,
+            message,
+        });
+    }
+
+    fn error(&mut self, span: ast::Span, message: String) {
+        self.diagnostics.push(Diagnostic {
+            span,
+            severity: Severity::Error,
+            message,
+        });
+    }
+
+    // #region variables
+    fn read(&mut self, name: &str, span: ast::Span) {
+        if name.starts_with('$') {
+            self.global_reads.push((name[1..].to_string(), span));
+            return;
+        }
+        for scope in self.scopes.iter_mut().rev() {
+            if let Some(i) = scope.args.iter().position(|arg| arg == name) {
+                let definition = scope.arg_definitions[i];
+                self.references.push(Reference { span, definition });
+                return;
+            }
+            if let Some(local) = scope.locals.iter_mut().find(|local| local.name == name) {
+                local.used = true;
+                let definition = local.definition;
+                self.references.push(Reference { span, definition });
+                return;
+            }
+        }
+        self.global_reads.push((name.to_string(), span));
+    }
+
+    fn define(&mut self, name: &str, span: ast::Span, kind: DefinitionKind) -> usize {
+        self.definitions.push(Definition {
+            name: name.to_string(),
+            span,
+            kind,
+        });
+        self.definitions.len() - 1
+    }
+
+    // refers to an existing definition unless span is the definition itself
+    fn refer(&mut self, span: ast::Span, definition: usize) -> usize {
+        if self.definitions[definition].span != span {
+            self.references.push(Reference { span, definition });
+        }
+        definition
+    }
+
+    fn assign_global(&mut self, name: &str, span: ast::Span) -> usize {
+        self.globals.insert(name.to_string());
+        if let Some(&definition) = self.global_definitions.get(name) {
+            return self.refer(span, definition);
+        }
+        let definition = self.define(name, span, DefinitionKind::Variable);
+        self.global_definitions.insert(name.to_string(), definition);
+        definition
+    }
+
+    // assigns a variable, returning the index of its definition
+    fn assign(&mut self, name: &str, span: ast::Span) -> usize {
+        if name.starts_with('$') {
+            return self.assign_global(&name[1..], span);
+        }
+        let len = self.scopes.len();
+        if len == 0 {
+            return self.assign_global(name, span);
+        }
+        let scope = &self.scopes[len - 1];
+        if let Some(i) = scope.args.iter().position(|arg| arg == name) {
+            let definition = scope.arg_definitions[i];
+            return self.refer(span, definition);
+        }
+        if let Some(local) = scope.locals.iter().find(|local| local.name == name) {
+            let definition = local.definition;
+            return self.refer(span, definition);
+        }
+        if self.scopes[..len - 1]
+            .iter()
+            .any(|scope| scope.args.iter().any(|arg| arg == name))
+        {
+            self.warn(
+                span,
+                format!(
+                    "assignment to `{}` shadows an argument of the enclosing function",
+                    name
+                ),
+            );
+        }
+        let definition = self.define(name, span, DefinitionKind::Variable);
+        self.scopes[len - 1].locals.push(Local {
+            name: name.to_string(),
+            span,
+            used: name.starts_with('_'),
+            definition,
+        });
+        definition
+    }
+
+    // assigns a variable, setting what it holds if this defines it
+    fn assign_kind(&mut self, name: &str, span: ast::Span, kind: DefinitionKind) {
+        let definition = self.assign(name, span);
+        if self.definitions[definition].span == span {
+            self.definitions[definition].kind = kind;
+        }
+    }
+
+    // assigns a variable that isn't expected to be used
+    fn declare(&mut self, name: &str, span: ast::Span) {
+        self.assign(name, span);
+        if let Some(scope) = self.scopes.last_mut() {
+            if let Some(local) = scope.locals.iter_mut().find(|local| local.name == name) {
+                local.used = true;
+            }
+        }
+    }
+    // #endregion
+
+    // #region statements
+    fn stmts(&mut self, stmts: &[Box<dyn ast::AST>]) {
+        let mut terminated = false;
+        for stmt in stmts {
+            if terminated {
+                self.warn(*stmt.span(), "unreachable code".to_string());
+                terminated = false;
+            }
+            if self.stmt(stmt.as_ref()) {
+                terminated = true;
+            }
+        }
+    }
+
+    // lints the statement, returning whether control never goes past it
+    fn stmt(&mut self, stmt: &dyn ast::AST) -> bool {
+        let any = stmt.as_any();
+        if let Some(stmt) = any.downcast_ref::<ast::BlockStatement>() {
+            self.stmts(&stmt.stmts);
+        } else if let Some(stmt) = any.downcast_ref::<ast::ExprStatement>() {
+            self.expr(stmt.expr.as_ref());
+        } else if let Some(stmt) = any.downcast_ref::<ast::IfStatement>() {
+            self.expr(stmt.expr.as_ref());
+            let then = self.stmt(stmt.then.as_ref());
+            if let Some(alt) = &stmt.alt {
+                return self.stmt(alt.as_ref()) && then;
+            }
+        } else if let Some(stmt) = any.downcast_ref::<ast::WhileStatement>() {
+            self.expr(stmt.expr.as_ref());
+            self.in_loop(|linter| linter.stmt(stmt.then.as_ref()));
+        } else if let Some(stmt) = any.downcast_ref::<ast::ForStatement>() {
+            self.expr(stmt.from.as_ref());
+            self.expr(stmt.to.as_ref());
+            self.expr(stmt.step.as_ref());
+            self.declare(&stmt.id, stmt._span);
+            self.in_loop(|linter| linter.stmt(stmt.stmt.as_ref()));
+        } else if let Some(stmt) = any.downcast_ref::<ast::ForInStatement>() {
+            self.expr(stmt.expr.as_ref());
+            self.declare_pattern(&stmt.pattern);
+            self.in_loop(|linter| linter.stmt(stmt.stmt.as_ref()));
+        } else if let Some(stmt) = any.downcast_ref::<ast::ContinueStatement>() {
+            self.loop_control(stmt._span, "continue");
+            return true;
+        } else if let Some(stmt) = any.downcast_ref::<ast::BreakStatement>() {
+            self.loop_control(stmt._span, "break");
+            return true;
+        } else if let Some(stmt) = any.downcast_ref::<ast::ReturnStatement>() {
+            if self.scopes.is_empty() {
+                self.error(
+                    stmt._span,
+                    "return statement outside of a function".to_string(),
+                );
+            }
+            if let Some(expr) = &stmt.expr {
+                self.expr(expr.as_ref());
+            }
+            return true;
+        } else if let Some(stmt) = any.downcast_ref::<ast::RaiseStatement>() {
+            self.expr(stmt.expr.as_ref());
+            return true;
+        } else if let Some(stmt) = any.downcast_ref::<ast::FunctionStatement>() {
+            let def = stmt.def();
+            let kind = DefinitionKind::Function(def.args.clone());
+            self.assign_kind(def.id.as_ref().unwrap(), stmt._span, kind);
+            self.function(def);
+        } else if let Some(stmt) = any.downcast_ref::<ast::RecordStatement>() {
+            let def = stmt.def();
+            self.assign_kind(def.id.as_ref().unwrap(), stmt._span, DefinitionKind::Record);
+            self.record(def);
+        } else if let Some(stmt) = any.downcast_ref::<ast::TryStatement>() {
+            self.stmts(&stmt.stmts);
+            for case in &stmt.cases {
+                self.expr(case.etype.as_ref());
+                if let Some(id) = &case.id {
+                    if let Some(id) = id.as_any().downcast_ref::<ast::Identifier>() {
+                        self.declare(&id.val, id._span);
+                    }
+                }
+                self.stmts(&case.stmts);
+            }
+        } else if let Some(stmt) = any.downcast_ref::<ast::UseStatement>() {
+            if let Some(namespace) = &stmt.namespace {
+                self.declare(namespace, stmt._span);
+            } else {
+                self.use_module(&stmt.path, stmt._span);
+            }
+        } else {
+            self.expr(stmt);
+        }
+        false
+    }
+
+    fn in_loop<F: FnOnce(&mut Linter) -> bool>(&mut self, f: F) {
+        match self.scopes.last_mut() {
+            Some(scope) => scope.loops += 1,
+            None => self.toplevel_loops += 1,
+        }
+        f(self);
+        match self.scopes.last_mut() {
+            Some(scope) => scope.loops -= 1,
+            None => self.toplevel_loops -= 1,
+        }
+    }
+
+    fn loop_control(&mut self, span: ast::Span, keyword: &str) {
+        let loops = match self.scopes.last() {
+            Some(scope) => scope.loops,
+            None => self.toplevel_loops,
+        };
+        if loops == 0 {
+            self.error(span, format!("{} statement outside of a loop", keyword));
+        }
+    }
+
+    // collects the global variables of a module imported with `use`
+    fn use_module(&mut self, path: &str, span: ast::Span) {
+        let pathobj = match resolve_module(path, &self.file) {
+            Ok(pathobj) => pathobj,
+            Err(_) => {
+                self.unknown_globals = true;
+                self.warn(span, format!("can't find module `{}`", path));
+                return;
+            }
+        };
+        if !self.modules.insert(pathobj.clone()) {
+            return;
+        }
+        let prog = match std::fs::read_to_string(&pathobj)
+            .ok()
+            .and_then(|s| grammar::start(&s).ok())
+        {
+            Some(prog) => prog,
+            None => {
+                self.unknown_globals = true;
+                return;
+            }
+        };
+        let mut module = Linter::new(pathobj.to_string_lossy().into_owned(), HashSet::new());
+        module.modules = std::mem::replace(&mut self.modules, HashSet::new());
+        module.stmts(&prog);
+        self.modules = module.modules;
+        self.globals.extend(module.globals);
+        self.unknown_globals |= module.unknown_globals;
+    }
+    // #endregion
+
+    // #region expressions
+    fn expr(&mut self, expr: &dyn ast::AST) {
+        let any = expr.as_any();
+        if let Some(expr) = any.downcast_ref::<ast::Identifier>() {
+            self.read(&expr.val, expr._span);
+        } else if let Some(expr) = any.downcast_ref::<ast::ArrayExpr>() {
+            for expr in &expr.exprs {
+                self.expr(expr.as_ref());
+            }
+        } else if let Some(expr) = any.downcast_ref::<ast::MapExpr>() {
+            self.map(expr);
+        } else if let Some(expr) = any.downcast_ref::<ast::StrInterpolation>() {
+            for part in &expr.parts {
+                self.expr(part.as_ref());
+            }
+        } else if let Some(expr) = any.downcast_ref::<ast::UnaryExpr>() {
+            self.expr(expr.val.as_ref());
+        } else if let Some(expr) = any.downcast_ref::<ast::CondExpr>() {
+            self.expr(expr.cond.as_ref());
+            self.expr(expr.then.as_ref());
+            self.expr(expr.alt.as_ref());
+        } else if let Some(expr) = any.downcast_ref::<ast::BinExpr>() {
+            self.binexpr(expr);
+        } else if let Some(expr) = any.downcast_ref::<ast::MemExpr>() {
+            self.expr(expr.left.as_ref());
+            if expr.is_expr {
+                self.expr(expr.right.as_ref());
+            }
+        } else if let Some(expr) = any.downcast_ref::<ast::SliceExpr>() {
+            self.expr(expr.left.as_ref());
+            for bound in &[&expr.from, &expr.to, &expr.step] {
+                if let Some(bound) = bound {
+                    self.expr(bound.as_ref());
+                }
+            }
+        } else if let Some(expr) = any.downcast_ref::<ast::YieldExpr>() {
+            self.yield_expr(expr);
+        } else if let Some(expr) = any.downcast_ref::<ast::CallExpr>() {
+            self.expr(expr.callee.as_ref());
+            for arg in &expr.args {
+                self.expr(arg.as_ref());
+            }
+            for (_, arg) in &expr.kwargs {
+                self.expr(arg.as_ref());
+            }
+        } else if let Some(def) = any.downcast_ref::<ast::FunctionDefinition>() {
+            self.function(def);
+        } else if let Some(def) = any.downcast_ref::<ast::RecordDefinition>() {
+            self.record(def);
+        } else if let Some(expr) = any.downcast_ref::<ast::MatchExpr>() {
+            self.expr(expr.expr.as_ref());
+            for case in &expr.cases {
+                self.pattern(&case.pattern);
+                if let Some(guard) = &case.guard {
+                    self.expr(guard.as_ref());
+                }
+                match &case.body {
+                    ast::MatchBody::Expr(expr) => self.expr(expr.as_ref()),
+                    ast::MatchBody::Stmts(stmts) => self.stmts(stmts),
+                }
+            }
+        } else if let Some(expr) = any.downcast_ref::<ast::DestructureExpr>() {
+            self.expr(expr.expr.as_ref());
+            self.pattern(&expr.pattern);
+        }
+    }
+
+    fn yield_expr(&mut self, expr: &ast::YieldExpr) {
+        if self.scopes.is_empty() {
+            self.error(
+                expr._span,
+                "yield expression outside of a function".to_string(),
+            );
+        }
+        if let Some(expr) = &expr.expr {
+            self.expr(expr.as_ref());
+        }
+    }
+
+    fn pattern(&mut self, pattern: &ast::Pattern) {
+        match pattern {
+            ast::Pattern::Wildcard | ast::Pattern::Literal(_) => {}
+            ast::Pattern::Bind(id, span) => {
+                self.assign(id, *span);
+            }
+            ast::Pattern::Of(record, fields) => {
+                self.expr(record.as_ref());
+                for (_, pattern) in fields {
+                    self.pattern(pattern);
+                }
+            }
+            ast::Pattern::Array(elems, rest) => {
+                for elem in elems {
+                    self.pattern(elem);
+                }
+                if let Some((_, rest)) = rest {
+                    self.pattern(rest);
+                }
+            }
+            ast::Pattern::Record(fields) => {
+                for (_, pattern) in fields {
+                    self.pattern(pattern);
+                }
+            }
+        }
+    }
+
+    // declares the variables bound by a pattern, like loop variables
+    fn declare_pattern(&mut self, pattern: &ast::Pattern) {
+        match pattern {
+            ast::Pattern::Bind(id, span) => self.declare(id, *span),
+            ast::Pattern::Array(elems, rest) => {
+                for elem in elems {
+                    self.declare_pattern(elem);
+                }
+                if let Some((_, rest)) = rest {
+                    self.declare_pattern(rest);
+                }
+            }
+            ast::Pattern::Record(fields) => {
+                for (_, pattern) in fields {
+                    self.declare_pattern(pattern);
+                }
+            }
+            pattern => self.pattern(pattern),
+        }
+    }
+
+    fn map(&mut self, expr: &ast::MapExpr) {
+        for (key, val) in &expr.entries {
+            self.expr(key.as_ref());
+            self.expr(val.as_ref());
+        }
+    }
+
+    fn binexpr(&mut self, expr: &ast::BinExpr) {
+        if expr.op != ast::BinOp::Assign {
+            self.expr(expr.left.as_ref());
+            self.expr(expr.right.as_ref());
+            return;
+        }
+        let any = expr.left.as_any();
+        if let Some(id) = any.downcast_ref::<ast::Identifier>() {
+            self.expr(expr.right.as_ref());
+            let right = expr.right.as_any();
+            let kind = if let Some(def) = right.downcast_ref::<ast::FunctionDefinition>() {
+                DefinitionKind::Function(def.args.clone())
+            } else if right.is::<ast::RecordDefinition>() {
+                DefinitionKind::Record
+            } else {
+                DefinitionKind::Variable
+            };
+            self.assign_kind(&id.val, id._span, kind);
+        } else if let Some(call) = any.downcast_ref::<ast::CallExpr>() {
+            // function definition in the form of f(x) = expr
+            let id = match call.callee.as_any().downcast_ref::<ast::Identifier>() {
+                Some(id) => id,
+                None => return,
+            };
+            let mut args = Vec::new();
+            for arg in &call.args {
+                match arg.as_any().downcast_ref::<ast::Identifier>() {
+                    Some(arg) => args.push(arg.val.clone()),
+                    None => return,
+                }
+            }
+            if id.val != "_" {
+                self.assign_kind(&id.val, id._span, DefinitionKind::Function(args.clone()));
+            }
+            self.function_body(&args, expr._span, |linter| {
+                linter.expr(expr.right.as_ref());
+            });
+        } else {
+            self.expr(expr.left.as_ref());
+            self.expr(expr.right.as_ref());
+        }
+    }
+
+    fn function(&mut self, def: &ast::FunctionDefinition) {
+        self.function_body(&def.args, def._span, |linter| {
+            for default in &def.defaults {
+                linter.expr(default.as_ref());
+            }
+            for (_, pattern) in &def.patterns {
+                linter.pattern(pattern);
+            }
+            linter.stmt(def.stmt.as_ref());
+        });
+    }
+
+    fn function_body<F: FnOnce(&mut Linter)>(&mut self, args: &[String], span: ast::Span, f: F) {
+        for (i, arg) in args.iter().enumerate() {
+            if args[..i].contains(arg) {
+                self.warn(span, format!("duplicate argument `{}`", arg));
+            } else if self.scopes.iter().any(|scope| scope.has(arg)) {
+                self.warn(
+                    span,
+                    format!(
+                        "argument `{}` shadows a variable of the enclosing function",
+                        arg
+                    ),
+                );
+            }
+        }
+        let arg_definitions = args
+            .iter()
+            .map(|arg| self.define(arg, span, DefinitionKind::Argument))
+            .collect();
+        self.scopes.push(Scope::new(args.to_vec(), arg_definitions));
+        f(self);
+        let scope = self.scopes.pop().unwrap();
+        for local in scope.locals {
+            if !local.used {
+                self.warn(
+                    local.span,
+                    format!("unused local variable `{}`", local.name),
+                );
+            }
+        }
+    }
+
+    fn record(&mut self, def: &ast::RecordDefinition) {
+        // record bodies define keys rather than variables
+        for stmt in &def.stmts {
+            let any = stmt.as_any();
+            if let Some(stmt) = any.downcast_ref::<ast::FunctionStatement>() {
+                self.function(stmt.def());
+            } else if let Some(stmt) = any.downcast_ref::<ast::RecordStatement>() {
+                self.record(stmt.def());
+            } else if let Some(stmt) = any.downcast_ref::<ast::ExprStatement>() {
+                if let Some(expr) = stmt.expr.as_any().downcast_ref::<ast::BinExpr>() {
+                    self.expr(expr.right.as_ref());
+                }
+            }
+        }
+    }
+    // #endregion
+}
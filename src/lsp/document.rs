@@ -0,0 +1,314 @@
+//! Analysis of an open source file.
+
+use haru::ast::ast;
+use haru::diagnostics::{self, Diagnostic, Severity};
+use haru::lint::{self, Analysis, Definition, DefinitionKind};
+
+use crate::json::Json;
+
+/// A global variable from the standard library
+pub struct Builtin {
+    pub name: String,
+    pub type_name: String,
+}
+
+/// A source file opened in the editor
+pub struct Document {
+    pub text: String,
+    prog: Vec<Box<dyn ast::AST>>,
+    analysis: Analysis,
+    diagnostics: Vec<Diagnostic>,
+}
+
+// #region positions
+fn is_id_char(ch: char) -> bool {
+    ch.is_ascii_alphanumeric() || "$_?!".contains(ch)
+}
+
+// converts a byte offset into an LSP position, which counts UTF-16 code units
+fn position(text: &str, offset: usize) -> Json {
+    let offset = offset.min(text.len());
+    let before = &text[..offset];
+    let line = before.matches('\n').count();
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    let character: usize = before[line_start..].chars().map(char::len_utf16).sum();
+    json_object! {
+        "line" => line,
+        "character" => character,
+    }
+}
+
+fn range(text: &str, span: ast::Span) -> Json {
+    // statements' spans include the newlines after them
+    let end = span
+        .0
+        .max(span.0 + text[span.0..span.1.min(text.len())].trim_end().len());
+    json_object! {
+        "start" => position(text, span.0),
+        "end" => position(text, end),
+    }
+}
+
+// converts an LSP position into a byte offset
+fn offset(text: &str, position: &Json) -> Option<usize> {
+    let line = position.get("line").as_usize()?;
+    let character = position.get("character").as_usize()?;
+    let mut line_start = 0;
+    for _ in 0..line {
+        line_start += text[line_start..].find('\n')? + 1;
+    }
+    let mut units = 0;
+    for (i, ch) in text[line_start..].char_indices() {
+        if units >= character || ch == '\n' {
+            return Some(line_start + i);
+        }
+        units += ch.len_utf16();
+    }
+    Some(text.len())
+}
+
+// finds the span of the word `name` in the span
+fn name_span(text: &str, span: ast::Span, name: &str) -> ast::Span {
+    let end = span.1.min(text.len());
+    let mut start = span.0;
+    while let Some(i) = text[start..end].find(name) {
+        let pos = start + i;
+        let after = pos + name.len();
+        if !text[..pos].ends_with(is_id_char) && !text[after..].starts_with(is_id_char) {
+            return (pos, after);
+        }
+        start = after;
+    }
+    span
+}
+// #endregion
+
+impl Document {
+    pub fn new(text: String, file: &str, builtins: &[Builtin]) -> Document {
+        let names: Vec<String> = builtins
+            .iter()
+            .map(|builtin| builtin.name.clone())
+            .collect();
+        let (prog, syntax_errors) = diagnostics::parse(&text);
+        let analysis = lint::analyze(&prog, file, &names);
+        let diagnostics = diagnostics::merge(&prog, syntax_errors, analysis.diagnostics.clone());
+        Document {
+            text,
+            prog,
+            analysis,
+            diagnostics,
+        }
+    }
+
+    pub fn diagnostics(&self) -> Json {
+        Json::Array(
+            self.diagnostics
+                .iter()
+                .map(|diagnostic| {
+                    json_object! {
+                        "range" => range(&self.text, diagnostic.span),
+                        "severity" => match diagnostic.severity {
+                            Severity::Error => 1usize,
+                            Severity::Warning => 2usize,
+                        },
+                        "source" => "haru",
+                        "message" => diagnostic.message.as_str(),
+                    }
+                })
+                .collect(),
+        )
+    }
+
+    // #region symbols
+    pub fn symbols(&self) -> Json {
+        Json::Array(self.stmts_symbols(&self.prog))
+    }
+
+    fn stmts_symbols(&self, stmts: &[Box<dyn ast::AST>]) -> Vec<Json> {
+        let mut symbols = Vec::new();
+        for stmt in stmts {
+            self.stmt_symbols(stmt.as_ref(), &mut symbols);
+        }
+        symbols
+    }
+
+    fn stmt_symbols(&self, stmt: &dyn ast::AST, symbols: &mut Vec<Json>) {
+        let any = stmt.as_any();
+        if let Some(stmt) = any.downcast_ref::<ast::FunctionStatement>() {
+            let def = stmt.def();
+            let mut children = Vec::new();
+            self.stmt_symbols(def.stmt.as_ref(), &mut children);
+            symbols.push(self.symbol(def.id.as_ref().unwrap(), 12, stmt._span, children));
+        } else if let Some(stmt) = any.downcast_ref::<ast::RecordStatement>() {
+            let def = stmt.def();
+            let children = self.stmts_symbols(&def.stmts);
+            symbols.push(self.symbol(def.id.as_ref().unwrap(), 23, stmt._span, children));
+        } else if let Some(stmt) = any.downcast_ref::<ast::BlockStatement>() {
+            symbols.extend(self.stmts_symbols(&stmt.stmts));
+        } else if let Some(stmt) = any.downcast_ref::<ast::IfStatement>() {
+            self.stmt_symbols(stmt.then.as_ref(), symbols);
+            if let Some(alt) = &stmt.alt {
+                self.stmt_symbols(alt.as_ref(), symbols);
+            }
+        } else if let Some(stmt) = any.downcast_ref::<ast::WhileStatement>() {
+            self.stmt_symbols(stmt.then.as_ref(), symbols);
+        } else if let Some(stmt) = any.downcast_ref::<ast::ForStatement>() {
+            self.stmt_symbols(stmt.stmt.as_ref(), symbols);
+        } else if let Some(stmt) = any.downcast_ref::<ast::ForInStatement>() {
+            self.stmt_symbols(stmt.stmt.as_ref(), symbols);
+        } else if let Some(stmt) = any.downcast_ref::<ast::TryStatement>() {
+            symbols.extend(self.stmts_symbols(&stmt.stmts));
+            for case in &stmt.cases {
+                symbols.extend(self.stmts_symbols(&case.stmts));
+            }
+        }
+    }
+
+    fn symbol(&self, name: &str, kind: usize, span: ast::Span, children: Vec<Json>) -> Json {
+        json_object! {
+            "name" => name,
+            "kind" => kind,
+            "range" => range(&self.text, span),
+            "selectionRange" => range(&self.text, name_span(&self.text, span, name)),
+            "children" => children,
+        }
+    }
+    // #endregion
+
+    // #region navigation
+    // finds the definition of the variable at the offset
+    fn definition_at(&self, offset: usize) -> Option<(ast::Span, &Definition)> {
+        let definitions = &self.analysis.definitions;
+        if let Some(reference) = self
+            .analysis
+            .references
+            .iter()
+            .find(|reference| reference.span.0 <= offset && offset <= reference.span.1)
+        {
+            return Some((reference.span, &definitions[reference.definition]));
+        }
+        definitions
+            .iter()
+            .map(|definition| (self.definition_span(definition), definition))
+            .find(|(span, _)| span.0 <= offset && offset <= span.1)
+    }
+
+    fn definition_span(&self, definition: &Definition) -> ast::Span {
+        let mut span = definition.span;
+        if definition.kind == DefinitionKind::Argument {
+            // arguments are defined by their function
+            if let Some(i) = self.text[span.0..span.1].find('(') {
+                span.0 += i;
+            }
+        }
+        name_span(&self.text, span, definition.name.trim_start_matches('$'))
+    }
+
+    fn word_at(&self, offset: usize) -> Option<(ast::Span, &str)> {
+        let offset = offset.min(self.text.len());
+        let start = self.text[..offset]
+            .rfind(|ch| !is_id_char(ch))
+            .map_or(0, |i| i + 1);
+        let end = self.text[offset..]
+            .find(|ch| !is_id_char(ch))
+            .map_or(self.text.len(), |i| offset + i);
+        if start == end {
+            None
+        } else {
+            Some(((start, end), &self.text[start..end]))
+        }
+    }
+
+    pub fn definition(&self, uri: &str, position: &Json) -> Json {
+        let offset = match offset(&self.text, position) {
+            Some(offset) => offset,
+            None => return Json::Null,
+        };
+        match self.definition_at(offset) {
+            Some((_, definition)) => json_object! {
+                "uri" => uri,
+                "range" => range(&self.text, self.definition_span(definition)),
+            },
+            None => Json::Null,
+        }
+    }
+
+    pub fn hover(&self, position: &Json, builtins: &[Builtin]) -> Json {
+        let offset = match offset(&self.text, position) {
+            Some(offset) => offset,
+            None => return Json::Null,
+        };
+        let (span, contents) = if let Some((span, definition)) = self.definition_at(offset) {
+            let name = &definition.name;
+            let contents = match &definition.kind {
+                DefinitionKind::Function(args) => format!(
+                    "```hana\nfunction {}({})\n```\ntakes {} argument{}",
+                    name,
+                    args.join(", "),
+                    args.len(),
+                    if args.len() == 1 { "" } else { "s" }
+                ),
+                DefinitionKind::Record => format!("```hana\nrecord {}\n```", name),
+                DefinitionKind::Argument => format!("argument `{}`", name),
+                DefinitionKind::Variable => format!("variable `{}`", name),
+            };
+            (span, contents)
+        } else if let Some((span, word)) = self.word_at(offset) {
+            match builtins.iter().find(|builtin| builtin.name == word) {
+                Some(builtin) => (
+                    span,
+                    format!(
+                        "`{}`: {} from the standard library",
+                        word, builtin.type_name
+                    ),
+                ),
+                None => return Json::Null,
+            }
+        } else {
+            return Json::Null;
+        };
+        json_object! {
+            "contents" => json_object! {
+                "kind" => "markdown",
+                "value" => contents,
+            },
+            "range" => range(&self.text, span),
+        }
+    }
+
+    pub fn completion(&self, builtins: &[Builtin]) -> Json {
+        let mut items = Vec::new();
+        let mut names = Vec::new();
+        for builtin in builtins {
+            let kind: usize = match builtin.type_name.as_str() {
+                "Function" => 3,
+                "Record" => 7,
+                _ => 6,
+            };
+            names.push(builtin.name.as_str());
+            items.push(json_object! {
+                "label" => builtin.name.as_str(),
+                "kind" => kind,
+                "detail" => "standard library",
+            });
+        }
+        for definition in &self.analysis.definitions {
+            let name = definition.name.as_str();
+            if definition.kind == DefinitionKind::Argument || names.contains(&name) {
+                continue;
+            }
+            let kind: usize = match definition.kind {
+                DefinitionKind::Function(_) => 3,
+                DefinitionKind::Record => 7,
+                _ => 6,
+            };
+            names.push(name);
+            items.push(json_object! {
+                "label" => name,
+                "kind" => kind,
+            });
+        }
+        Json::Array(items)
+    }
+    // #endregion
+}
@@ -0,0 +1,285 @@
+//! Minimal JSON values for encoding and decoding LSP messages.
+
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// Gets the value of a key in an object, or `Json::Null` if there is none.
+    pub fn get(&self, key: &str) -> &Json {
+        static NULL: Json = Json::Null;
+        if let Json::Object(pairs) = self {
+            if let Some((_, val)) = pairs.iter().find(|(k, _)| k == key) {
+                return val;
+            }
+        }
+        &NULL
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_usize(&self) -> Option<usize> {
+        match self {
+            Json::Number(n) if *n >= 0.0 => Some(*n as usize),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&Vec<Json>> {
+        match self {
+            Json::Array(vals) => Some(vals),
+            _ => None,
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        *self == Json::Null
+    }
+
+    /// Parses a JSON document.
+    pub fn parse(s: &str) -> Option<Json> {
+        let mut parser = Parser {
+            chars: s.chars().peekable(),
+        };
+        let val = parser.value()?;
+        parser.whitespace();
+        if parser.chars.next().is_some() {
+            return None;
+        }
+        Some(val)
+    }
+}
+
+/// Creates a `Json::Object` from key-value pairs.
+macro_rules! json_object {
+    ($($key:expr => $val:expr),* $(,)*) => {
+        Json::Object(vec![$(($key.to_string(), Json::from($val))),*])
+    };
+}
+
+impl From<bool> for Json {
+    fn from(b: bool) -> Json {
+        Json::Bool(b)
+    }
+}
+impl From<usize> for Json {
+    fn from(n: usize) -> Json {
+        Json::Number(n as f64)
+    }
+}
+impl From<i64> for Json {
+    fn from(n: i64) -> Json {
+        Json::Number(n as f64)
+    }
+}
+impl From<&str> for Json {
+    fn from(s: &str) -> Json {
+        Json::String(s.to_string())
+    }
+}
+impl From<String> for Json {
+    fn from(s: String) -> Json {
+        Json::String(s)
+    }
+}
+impl From<Vec<Json>> for Json {
+    fn from(vals: Vec<Json>) -> Json {
+        Json::Array(vals)
+    }
+}
+impl<T: Into<Json>> From<Option<T>> for Json {
+    fn from(val: Option<T>) -> Json {
+        val.map_or(Json::Null, |val| val.into())
+    }
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Number(n) => {
+                if n.fract() == 0.0 && n.abs() < 1e15 {
+                    write!(f, "{}", *n as i64)
+                } else {
+                    write!(f, "{}", n)
+                }
+            }
+            Json::String(s) => write_string(f, s),
+            Json::Array(vals) => {
+                write!(f, "[")?;
+                for (i, val) in vals.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", val)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(pairs) => {
+                write!(f, "{{")?;
+                for (i, (key, val)) in pairs.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", val)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for ch in s.chars() {
+        match ch {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            ch if (ch as u32) < 0x20 => write!(f, "\\u{:04x}", ch as u32)?,
+            ch => write!(f, "{}", ch)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+struct Parser<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+}
+
+impl<'a> Parser<'a> {
+    fn whitespace(&mut self) {
+        while let Some(ch) = self.chars.peek() {
+            if !ch.is_whitespace() {
+                break;
+            }
+            self.chars.next();
+        }
+    }
+
+    fn expect(&mut self, word: &str) -> Option<()> {
+        for ch in word.chars() {
+            if self.chars.next()? != ch {
+                return None;
+            }
+        }
+        Some(())
+    }
+
+    fn value(&mut self) -> Option<Json> {
+        self.whitespace();
+        match *self.chars.peek()? {
+            'n' => self.expect("null").map(|_| Json::Null),
+            't' => self.expect("true").map(|_| Json::Bool(true)),
+            'f' => self.expect("false").map(|_| Json::Bool(false)),
+            '"' => self.string().map(Json::String),
+            '[' => {
+                self.chars.next();
+                let mut vals = Vec::new();
+                self.whitespace();
+                if self.chars.peek() == Some(&']') {
+                    self.chars.next();
+                    return Some(Json::Array(vals));
+                }
+                loop {
+                    vals.push(self.value()?);
+                    self.whitespace();
+                    match self.chars.next()? {
+                        ',' => continue,
+                        ']' => return Some(Json::Array(vals)),
+                        _ => return None,
+                    }
+                }
+            }
+            '{' => {
+                self.chars.next();
+                let mut pairs = Vec::new();
+                self.whitespace();
+                if self.chars.peek() == Some(&'}') {
+                    self.chars.next();
+                    return Some(Json::Object(pairs));
+                }
+                loop {
+                    self.whitespace();
+                    let key = self.string()?;
+                    self.whitespace();
+                    self.expect(":")?;
+                    pairs.push((key, self.value()?));
+                    self.whitespace();
+                    match self.chars.next()? {
+                        ',' => continue,
+                        '}' => return Some(Json::Object(pairs)),
+                        _ => return None,
+                    }
+                }
+            }
+            _ => self.number(),
+        }
+    }
+
+    fn number(&mut self) -> Option<Json> {
+        let mut s = String::new();
+        while let Some(&ch) = self.chars.peek() {
+            if !(ch.is_ascii_digit() || "+-.eE".contains(ch)) {
+                break;
+            }
+            s.push(ch);
+            self.chars.next();
+        }
+        s.parse::<f64>().ok().map(Json::Number)
+    }
+
+    fn hex4(&mut self) -> Option<u32> {
+        let mut n = 0;
+        for _ in 0..4 {
+            n = n * 16 + self.chars.next()?.to_digit(16)?;
+        }
+        Some(n)
+    }
+
+    fn string(&mut self) -> Option<String> {
+        self.expect("\"")?;
+        let mut s = String::new();
+        loop {
+            match self.chars.next()? {
+                '"' => return Some(s),
+                '\\' => match self.chars.next()? {
+                    'n' => s.push('\n'),
+                    'r' => s.push('\r'),
+                    't' => s.push('\t'),
+                    'b' => s.push('\u{8}'),
+                    'f' => s.push('\u{c}'),
+                    'u' => {
+                        let mut n = self.hex4()?;
+                        // surrogate pair
+                        if (0xd800..0xdc00).contains(&n) {
+                            self.expect("\\u")?;
+                            let low = self.hex4()?;
+                            n = 0x10000 + ((n - 0xd800) << 10) + (low.checked_sub(0xdc00)?);
+                        }
+                        s.push(std::char::from_u32(n)?);
+                    }
+                    ch => s.push(ch),
+                },
+                ch => s.push(ch),
+            }
+        }
+    }
+}
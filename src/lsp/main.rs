@@ -0,0 +1,259 @@
+//! Language server for hana, speaking the language server protocol over stdio.
+
+extern crate haru;
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+#[macro_use]
+mod json;
+mod document;
+use document::{Builtin, Document};
+use json::Json;
+
+use haru::hanayo;
+use haru::vmbindings::vm::Vm;
+
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+
+struct Server {
+    documents: HashMap<String, Document>,
+    builtins: Vec<Builtin>,
+    shutdown: bool,
+}
+
+// #region transport
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        let mut header = line.splitn(2, ':');
+        if header
+            .next()
+            .unwrap()
+            .eq_ignore_ascii_case("content-length")
+        {
+            length = header
+                .next()
+                .and_then(|len| len.trim().parse::<usize>().ok());
+        }
+    }
+    let length = match length {
+        Some(length) => length,
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "missing Content-Length",
+            ))
+        }
+    };
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    String::from_utf8(body)
+        .map(Some)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+fn send(message: Json) {
+    let body = message.to_string();
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    write!(stdout, "Content-Length: {}\r\n\r\n{}", body.len(), body).unwrap();
+    stdout.flush().unwrap();
+}
+
+fn respond(id: Json, result: Json) {
+    send(json_object! {
+        "jsonrpc" => "2.0",
+        "id" => id,
+        "result" => result,
+    });
+}
+
+fn respond_error(id: Json, code: i64, message: &str) {
+    send(json_object! {
+        "jsonrpc" => "2.0",
+        "id" => id,
+        "error" => json_object! {
+            "code" => code,
+            "message" => message,
+        },
+    });
+}
+// #endregion
+
+// converts a file:// URI into a path, used for resolving imported modules
+fn uri_to_path(uri: &str) -> String {
+    if !uri.starts_with("file://") {
+        return "[lsp]".to_string();
+    }
+    let bytes = uri["file://".len()..].as_bytes();
+    let mut path = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            if let Ok(byte) =
+                u8::from_str_radix(&String::from_utf8_lossy(&bytes[i + 1..i + 3]), 16)
+            {
+                path.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        path.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&path).into_owned()
+}
+
+impl Server {
+    fn new() -> Server {
+        let mut vm = Vm::new(None, None, None);
+        hanayo::init(&mut vm);
+        let mut builtins: Vec<Builtin> = vm
+            .global()
+            .iter()
+            .map(|(name, val)| Builtin {
+                name: name.to_string(),
+                type_name: unsafe { val.unwrap() }.type_name().to_string(),
+            })
+            .collect();
+        builtins.sort_by(|a, b| a.name.cmp(&b.name));
+        Server {
+            documents: HashMap::new(),
+            builtins,
+            shutdown: false,
+        }
+    }
+
+    fn open(&mut self, uri: &str, text: String) {
+        let document = Document::new(text, &uri_to_path(uri), &self.builtins);
+        send(json_object! {
+            "jsonrpc" => "2.0",
+            "method" => "textDocument/publishDiagnostics",
+            "params" => json_object! {
+                "uri" => uri,
+                "diagnostics" => document.diagnostics(),
+            },
+        });
+        self.documents.insert(uri.to_string(), document);
+    }
+
+    fn close(&mut self, uri: &str) {
+        self.documents.remove(uri);
+        send(json_object! {
+            "jsonrpc" => "2.0",
+            "method" => "textDocument/publishDiagnostics",
+            "params" => json_object! {
+                "uri" => uri,
+                "diagnostics" => Vec::new(),
+            },
+        });
+    }
+
+    fn notification(&mut self, method: &str, params: &Json) {
+        let uri = params.get("textDocument").get("uri").as_str().unwrap_or("");
+        match method {
+            "textDocument/didOpen" => {
+                let text = params
+                    .get("textDocument")
+                    .get("text")
+                    .as_str()
+                    .unwrap_or("");
+                self.open(uri, text.to_string());
+            }
+            "textDocument/didChange" => {
+                // the full text is synced on every change
+                let changes = params.get("contentChanges").as_array();
+                if let Some(text) = changes.and_then(|changes| changes.last()) {
+                    let text = text.get("text").as_str().unwrap_or("");
+                    self.open(uri, text.to_string());
+                }
+            }
+            "textDocument/didClose" => self.close(uri),
+            "exit" => std::process::exit(if self.shutdown { 0 } else { 1 }),
+            _ => (),
+        }
+    }
+
+    fn request(&mut self, method: &str, params: &Json) -> Option<Json> {
+        let uri = params.get("textDocument").get("uri").as_str().unwrap_or("");
+        let document = self.documents.get(uri);
+        let position = params.get("position");
+        Some(match method {
+            "initialize" => json_object! {
+                "capabilities" => json_object! {
+                    "textDocumentSync" => 1usize,
+                    "documentSymbolProvider" => true,
+                    "definitionProvider" => true,
+                    "hoverProvider" => true,
+                    "completionProvider" => json_object! {},
+                },
+                "serverInfo" => json_object! {
+                    "name" => "haru-lsp",
+                    "version" => env!("CARGO_PKG_VERSION"),
+                },
+            },
+            "shutdown" => {
+                self.shutdown = true;
+                Json::Null
+            }
+            "textDocument/documentSymbol" => document.map_or(Json::Null, |doc| doc.symbols()),
+            "textDocument/definition" => {
+                document.map_or(Json::Null, |doc| doc.definition(uri, position))
+            }
+            "textDocument/hover" => {
+                document.map_or(Json::Null, |doc| doc.hover(position, &self.builtins))
+            }
+            "textDocument/completion" => {
+                document.map_or(Json::Null, |doc| doc.completion(&self.builtins))
+            }
+            _ => return None,
+        })
+    }
+}
+
+fn main() {
+    let mut server = Server::new();
+    let stdin = io::stdin();
+    let mut stdin = stdin.lock();
+    loop {
+        let body = match read_message(&mut stdin) {
+            Ok(Some(body)) => body,
+            Ok(None) => break,
+            Err(err) => {
+                eprintln!("haru-lsp: {}", err);
+                std::process::exit(1);
+            }
+        };
+        let message = match Json::parse(&body) {
+            Some(message) => message,
+            None => {
+                respond_error(Json::Null, PARSE_ERROR, "invalid JSON");
+                continue;
+            }
+        };
+        let method = message.get("method").as_str().unwrap_or("");
+        let params = message.get("params");
+        let id = message.get("id");
+        if id.is_null() {
+            server.notification(method, params);
+        } else if let Some(result) = server.request(method, params) {
+            respond(id.clone(), result);
+        } else {
+            respond_error(
+                id.clone(),
+                METHOD_NOT_FOUND,
+                &format!("unknown method {}", method),
+            );
+        }
+    }
+}
@@ -24,17 +24,31 @@ use ansi_term::Color as ac;
 use rustyline::error::ReadlineError;
 use rustyline::Editor;
 
+mod bytecode;
 mod compiler;
+mod debugger;
 #[macro_use]
 mod ast;
+mod diagnostics;
+mod formatter;
+mod lint;
+mod optimizer;
 mod vmbindings;
-use vmbindings::vm::{Vm, VmOpcode};
+use vmbindings::gc::{self, GcMode};
+use vmbindings::vm::{self, Vm, VmOpcode};
 use vmbindings::vmerror::VmError;
 mod hanayo;
 
 fn print_error(
-    s: &String, lineno: usize, col: usize, _lineno_end: usize, col_end: usize, etype: &str,
+    s: &String, lineno: usize, col: usize, lineno_end: usize, col_end: usize, etype: &str,
     message: &String,
+) {
+    print_message(s, lineno, col, lineno_end, col_end, etype, ac::Red, message);
+}
+
+fn print_message(
+    s: &String, lineno: usize, col: usize, _lineno_end: usize, col_end: usize, etype: &str,
+    color: ac, message: &String,
 ) {
     let line = s.split("\n").nth(lineno - 1).unwrap();
     let lineno_info = format!("{} | ", lineno);
@@ -51,11 +65,18 @@ fn print_error(
             " ".repeat(lineno_info_len + col - 1)
                 + &"^".repeat(if col_end > col { col_end - col } else { 1 })
         ),
-        ac::Red.bold().paint(etype.to_string()),
+        color.bold().paint(etype.to_string()),
         message
     );
 }
 
+fn print_codegen_error(s: &String, filename: &str, err: &ast::ast::CodeGenError) {
+    let (line, col) = ast::pos_to_line(s, err.span.0);
+    let (line_end, col_end) = ast::pos_to_line(s, err.span.1);
+    let message = format!("{} at {}:{}:{}", err, filename, line, col);
+    print_error(s, line, col, line_end, col_end, "compiler error:", &message);
+}
+
 // command/file
 enum ProcessArg<'a> {
     Command(&'a str),
@@ -102,6 +123,12 @@ fn process(arg: ProcessArg, flag: ParserFlag) {
             s
         }
     };
+    // only report problems if asked
+    if flag.check {
+        let filename = c.modules_info.borrow().files.last().unwrap().clone();
+        std::process::exit(check(&s, &filename));
+    }
+
     let prog = ast::grammar::start(&s).unwrap_or_else(|err| {
         print_error(
             &s,
@@ -127,23 +154,185 @@ fn process(arg: ProcessArg, flag: ParserFlag) {
     // emit bytecode
     for stmt in prog {
         if let Err(e) = stmt.emit(&mut c) {
-            // TODO: better error message
-            eprintln!("{:?}", e);
-            return;
+            let filename = c.modules_info.borrow().files.last().unwrap().clone();
+            print_codegen_error(&s, &filename, &e);
+            std::process::exit(1);
         }
     }
+    if flag.optimize {
+        c.optimize(0);
+    }
     c.cpushop(VmOpcode::OP_HALT);
+    c.modules_info.borrow_mut().sources.push(s);
 
     // dump bytecode if asked
     if flag.dump_bytecode {
-        io::stdout().write(c.code_as_bytes()).unwrap();
+        io::stdout().write_all(&bytecode::serialize(&c)).unwrap();
         return;
     }
 
     // execute!
-    c.modules_info.borrow_mut().sources.push(s);
+    execute(c, &flag);
+}
+
+// reports every syntax error, code generation error and lint warning
+// in the source, returning the exit code
+fn check(s: &String, filename: &str) -> i32 {
+    use diagnostics::Severity;
+
+    let builtins: Vec<String> = {
+        let mut vm = Vm::new(None, None, None);
+        hanayo::init(&mut vm);
+        vm.global().keys().map(|key| key.to_string()).collect()
+    };
+    let (prog, syntax_errors) = diagnostics::parse(s);
+    let lints = lint::lint(&prog, filename, &builtins);
+    let problems = diagnostics::merge(&prog, syntax_errors, lints);
+
+    let mut nerrors = 0;
+    let mut nwarnings = 0;
+    for problem in problems.iter() {
+        let (line, col) = ast::pos_to_line(s, problem.span.0);
+        let (line_end, col_end) = ast::pos_to_line(s, problem.span.1);
+        let message = format!("{} at {}:{}:{}", problem.message, filename, line, col);
+        if problem.severity == Severity::Error {
+            print_message(s, line, col, line_end, col_end, "error:", ac::Red, &message);
+            nerrors += 1;
+        } else {
+            print_message(s, line, col, line_end, col_end, "warning:", ac::Yellow, &message);
+            nwarnings += 1;
+        }
+    }
+    if nerrors + nwarnings > 0 {
+        eprintln!(
+            "\n{} {} error{}, {} warning{} found",
+            ac::Red.bold().paint("check:"),
+            nerrors,
+            if nerrors == 1 { "" } else { "s" },
+            nwarnings,
+            if nwarnings == 1 { "" } else { "s" }
+        );
+    }
+    if nerrors > 0 {
+        1
+    } else if nwarnings > 0 {
+        2
+    } else {
+        0
+    }
+}
+
+// formats the files in place, or only reports the ones that
+// aren't formatted if check is set, returning the exit code
+fn fmt(args: Vec<String>) -> i32 {
+    let mut check = false;
+    let mut files = Vec::new();
+    for arg in args {
+        if arg == "--check" {
+            check = true;
+        } else if arg != "-" && arg.starts_with('-') {
+            eprintln!("fmt: invalid argument {}", arg);
+            return 1;
+        } else {
+            files.push(arg);
+        }
+    }
+    if files.is_empty() {
+        files.push("-".to_string());
+    }
+
+    let mut code = 0;
+    for filename in files.iter() {
+        let s = if filename == "-" {
+            let mut s = String::new();
+            io::stdin().read_to_string(&mut s).map(|_| s)
+        } else {
+            std::fs::read_to_string(filename)
+        };
+        let s = match s {
+            Ok(s) => s,
+            Err(err) => {
+                eprintln!("error reading {}: {}", filename, err);
+                code = 1;
+                continue;
+            }
+        };
+        let formatted = match formatter::format(&s) {
+            Ok(formatted) => formatted,
+            Err(err) => {
+                let (_, errors) = diagnostics::parse(&s);
+                // fall back to the formatter's error if recovery finds none
+                let (span, message) = match errors.first() {
+                    Some(diagnostic) => (diagnostic.span, diagnostic.message.clone()),
+                    None => {
+                        let expected: Vec<String> =
+                            err.expected.iter().map(|x| x.to_string()).collect();
+                        (
+                            (err.offset, err.offset),
+                            format!("expected {}", expected.join(", ")),
+                        )
+                    }
+                };
+                let (line, col) = ast::pos_to_line(&s, span.0);
+                let (line_end, col_end) = ast::pos_to_line(&s, span.1);
+                let message = format!("{} at {}:{}:{}", message, filename, line, col);
+                print_error(&s, line, col, line_end, col_end, "parser error:", &message);
+                code = 1;
+                continue;
+            }
+        };
+        if check {
+            if formatted != s {
+                println!("{} is not formatted", filename);
+                code = 1;
+            }
+        } else if filename == "-" {
+            print!("{}", formatted);
+        } else if formatted != s {
+            if let Err(err) = std::fs::write(filename, formatted) {
+                eprintln!("error writing {}: {}", filename, err);
+                code = 1;
+            }
+        }
+    }
+    code
+}
+
+fn process_bytecode(filename: &str, flag: ParserFlag) {
+    let mut bytes = Vec::new();
+    if filename == "-" {
+        io::stdin().read_to_end(&mut bytes).unwrap_or_else(|err| {
+            println!("error reading from stdin: {}", err);
+            std::process::exit(1);
+        });
+    } else {
+        let mut file = std::fs::File::open(&filename).unwrap_or_else(|err| {
+            println!("error opening file: {}", err);
+            std::process::exit(1);
+        });
+        file.read_to_end(&mut bytes).unwrap_or_else(|err| {
+            println!("error reading file: {}", err);
+            std::process::exit(1);
+        });
+    }
+    let c = bytecode::deserialize(&bytes).unwrap_or_else(|err| {
+        eprintln!("{} {}", ac::Red.bold().paint("bytecode error:"), err);
+        std::process::exit(1);
+    });
+    execute(c, &flag);
+}
+
+fn execute(mut c: compiler::Compiler, flag: &ParserFlag) {
     let mut vm = c.into_vm();
+    vm.module_cache = flag.module_cache;
+    vm.optimize = flag.optimize;
+    vm.set_max_stack_depth(flag.max_stack_depth);
+    vm.set_gc_mode(flag.gc_mode);
     hanayo::init(&mut vm);
+    if flag.debug {
+        let debugger = debugger::Debugger::new(&vm, Box::new(io::BufReader::new(io::stdin())));
+        vm.attach_debugger(Box::new(debugger));
+    }
     vm.gc_enable();
     vm.execute();
     handle_error(&vm, &c);
@@ -178,28 +367,20 @@ fn handle_error(vm: &Vm, c: &compiler::Compiler) -> bool {
         if let Some(hint) = unsafe { vm.error.hint(vm) } {
             eprintln!("{} {}", ac::Red.bold().paint("hint:"), hint);
         }
-        let envs = vm.localenv_to_vec();
-        if envs.len() > 0 {
+        let backtrace = vm.backtrace();
+        if !backtrace.is_empty() {
             eprintln!("{}", ac::Red.bold().paint("backtrace:"));
-            for env in envs {
-                let ip = env.retip as usize;
-                if let Some(smap) = c.lookup_smap(ip) {
-                    let modules_info = c.modules_info.borrow();
-                    let src = &modules_info.sources[smap.fileno];
-                    let (line, col) = ast::pos_to_line(&src, smap.file.0);
-                    eprintln!(
-                        " from {}{}:{}:{}",
-                        if let Some(sym) = modules_info.symbol.get(&ip) {
-                            sym.clone() + "@"
-                        } else {
-                            "".to_string()
-                        },
-                        modules_info.files[smap.fileno],
-                        line,
-                        col
-                    );
-                } else {
-                    eprintln!(" from bytecode index {}", ip);
+            // repeated frames of recursive calls are collapsed
+            let mut frames = backtrace.iter().peekable();
+            while let Some(frame) = frames.next() {
+                eprintln!(" from {}", frame);
+                let mut repeated = 0;
+                while frames.peek() == Some(&frame) {
+                    frames.next();
+                    repeated += 1;
+                }
+                if repeated > 0 {
+                    eprintln!(" ... (repeated {} more times)", repeated);
                 }
             }
         }
@@ -219,6 +400,10 @@ fn repl(flag: ParserFlag) {
         modules_info.sources.push(String::new());
     }
     let mut vm = Vm::new(None, Some(c.modules_info.clone()), None);
+    vm.module_cache = flag.module_cache;
+    vm.optimize = flag.optimize;
+    vm.set_max_stack_depth(flag.max_stack_depth);
+    vm.set_gc_mode(flag.gc_mode);
     hanayo::init(&mut vm);
     loop {
         let readline = rl.readline(">> ");
@@ -261,12 +446,15 @@ fn repl(flag: ParserFlag) {
                             match gencode(&mut c) {
                                 Ok(pop_print_) => {
                                     pop_print = pop_print_;
+                                    if flag.optimize {
+                                        c.optimize(0);
+                                    }
                                     c.cpushop(VmOpcode::OP_HALT);
                                     vm.code = Some(c.take_code());
                                     vm.execute();
                                 }
                                 Err(e) => {
-                                    eprintln!("{:?}", e);
+                                    print_codegen_error(&s, "[repl]", &e);
                                     continue;
                                 }
                             }
@@ -280,13 +468,16 @@ fn repl(flag: ParserFlag) {
                                     if c.clen() as u32 == len {
                                         continue;
                                     }
+                                    if flag.optimize {
+                                        c.optimize(len as usize);
+                                    }
                                     c.cpushop(VmOpcode::OP_HALT);
                                     vm.code = Some(c.take_code());
                                     vm.jmp(len);
                                     vm.execute();
                                 }
                                 Err(e) => {
-                                    eprintln!("{:?}", e);
+                                    print_codegen_error(&s, "[repl]", &e);
                                     continue;
                                 }
                             }
@@ -329,14 +520,30 @@ fn repl(flag: ParserFlag) {
 fn help(program: &str) {
     println!(
         "usage: {} [options] [-c cmd | file | -]
+       {} fmt [--check] [files...]
 options:
  -c cmd : execute program passed in as string
  -d/--dump-vmcode: dumps vm bytecode to stdout
                    (only works in interpreter mode)
  -b/--bytecode: runs file as bytecode
  -a/--print-ast: prints ast and without run
- -v/--version: version",
-        program
+ --check: reports errors and warnings without running
+          (exits with 1 on errors, 2 on warnings)
+ --no-module-cache: don't cache compiled modules
+ --no-optimize: runs the bytecode as emitted, without optimizing it
+ --max-stack-depth=N: raises a StackOverflowError past N nested calls
+                      (defaults to {})
+ --gc=MODE: collects garbage in a single pause (stop-the-world, the default),
+            or a little on every allocation (incremental)
+ --debug: runs the program in the debugger
+          (type help at its prompt for a list of commands)
+ -v/--version: version
+fmt: formats the files in place, or stdin to stdout
+ --check: lists unformatted files without changing them
+          (exits with 1 if there are any)",
+        program,
+        program,
+        vm::DEFAULT_MAX_STACK_DEPTH
     )
 }
 
@@ -356,16 +563,34 @@ the License, or (at your option) any later version.",
 // parser flags
 struct ParserFlag {
     pub dump_bytecode: bool,
+    pub run_bytecode: bool,
     pub print_ast: bool,
+    pub module_cache: bool,
+    pub optimize: bool,
+    pub max_stack_depth: usize,
+    pub gc_mode: GcMode,
+    pub check: bool,
+    pub debug: bool,
 }
 
 fn main() {
-    let mut args = std::env::args();
+    let mut args = std::env::args().peekable();
     let program = args.next().unwrap();
+    if args.peek().map(|arg| arg.as_str()) == Some("fmt") {
+        args.next();
+        std::process::exit(fmt(args.collect()));
+    }
 
     let mut flags = ParserFlag {
         dump_bytecode: false,
+        run_bytecode: false,
         print_ast: false,
+        module_cache: true,
+        optimize: true,
+        max_stack_depth: vm::DEFAULT_MAX_STACK_DEPTH,
+        gc_mode: GcMode::default(),
+        check: false,
+        debug: false,
     };
     let mut cmd = false;
     for arg in args {
@@ -380,12 +605,54 @@ fn main() {
                 "-d" | "--dump-vmcode" => {
                     flags.dump_bytecode = true;
                 }
+                "-b" | "--bytecode" => {
+                    flags.run_bytecode = true;
+                }
                 "-a" | "--print-ast" => {
                     flags.print_ast = true;
                 }
+                "--check" => {
+                    flags.check = true;
+                }
+                "--no-module-cache" => {
+                    flags.module_cache = false;
+                }
+                "--no-optimize" => {
+                    flags.optimize = false;
+                }
+                "--debug" => {
+                    // modules are compiled from source for their variable names
+                    flags.debug = true;
+                    flags.module_cache = false;
+                    // and stepped through as they were written
+                    flags.optimize = false;
+                }
                 "-c" => {
                     cmd = true;
                 }
+                _ if arg.starts_with("--max-stack-depth=") => {
+                    match arg["--max-stack-depth=".len()..].parse() {
+                        Ok(depth) if depth > 0 && depth <= vm::MAX_STACK_DEPTH_LIMIT => {
+                            flags.max_stack_depth = depth
+                        }
+                        _ => {
+                            println!(
+                                "{}: invalid stack depth (must be between 1 and {})",
+                                program,
+                                vm::MAX_STACK_DEPTH_LIMIT
+                            );
+                            return;
+                        }
+                    }
+                }
+                "--gc=stop-the-world" => {
+                    flags.gc_mode = GcMode::StopTheWorld;
+                }
+                "--gc=incremental" => {
+                    flags.gc_mode = GcMode::Incremental {
+                        budget: gc::DEFAULT_GC_BUDGET,
+                    };
+                }
                 _ => {
                     println!("{}: invalid argument", program);
                     return;
@@ -393,6 +660,8 @@ fn main() {
             }
         } else if cmd {
             return process(ProcessArg::Command(&arg), flags);
+        } else if flags.run_bytecode {
+            return process_bytecode(&arg, flags);
         } else {
             return process(ProcessArg::File(&arg), flags);
         }
@@ -0,0 +1,758 @@
+//! Provides an optimization pass over emitted bytecode.
+//!
+//! The pass is run over a range of code after it has been emitted and
+//! before it is executed. It repeats these steps until the code stops changing:
+//!
+//! * jump threading: jumps to unconditional jumps go straight to their final
+//!   target, and unconditional jumps to a return are replaced by the return
+//! * dead code elimination: code after unconditional jumps and returns is
+//!   removed up to the next instruction that can be jumped to
+//! * constant folding of arithmetic on number literals and of string
+//!   concatenation
+//! * peephole rewrites of naive sequences, such as
+//!   `SET_LOCAL n; POP; GET_LOCAL n` into `SET_LOCAL n`
+//!   or `NOT; JNCOND` into `JCOND`
+//! * fusing `SET_LOCAL`/`SET_GLOBAL` followed by `POP` into superinstructions
+//!
+//! Instructions are only removed or shrunk and never moved past each other,
+//! so every jump label still fits in its operand after the pass. The returned
+//! [`Relocation`] maps positions in the old code to the optimized code.
+//!
+//! ```
+//! use haru::ast;
+//! use haru::compiler::Compiler;
+//! use haru::vmbindings::vm::VmOpcode;
+//! let mut c = Compiler::new(true);
+//! for stmt in ast::grammar::start("y = 1 + 2\n").unwrap() {
+//!     stmt.emit(&mut c).unwrap();
+//! }
+//! c.optimize(0);
+//! c.cpushop(VmOpcode::OP_HALT);
+//! ```
+
+use num_traits::cast::FromPrimitive;
+
+use crate::vmbindings::interned_string_map::InternedStringMap;
+use crate::vmbindings::vm::{instruction_size, VmOpcode};
+
+// the steps are repeated at most this many times
+const MAX_PASSES: usize = 16;
+// maximum number of jumps followed when threading a jump
+const MAX_THREADING: usize = 8;
+
+/// Encoding of an instruction's jump label
+#[derive(Clone, Copy, PartialEq)]
+enum Label {
+    // relative to the position of the label
+    U8,
+    I16,
+    U16,
+    // absolute position
+    U32,
+}
+
+// offset and encoding of the jump label of the instruction
+fn label_of(op: VmOpcode) -> Option<(usize, Label)> {
+    match op {
+        VmOpcode::OP_JMP
+        | VmOpcode::OP_JCOND
+        | VmOpcode::OP_JNCOND
+        | VmOpcode::OP_JCOND_NO_POP
+        | VmOpcode::OP_JNCOND_NO_POP
+        | VmOpcode::OP_MATCH_EQ
+        | VmOpcode::OP_MATCH_ARRAY
+        | VmOpcode::OP_MATCH_MEMBER => Some((1, Label::I16)),
+        VmOpcode::OP_FOR_IN | VmOpcode::OP_EXFRAME_RET => Some((1, Label::U16)),
+        // jumps past the variable assignment if done in place
+        VmOpcode::OP_IADD | VmOpcode::OP_IMUL => Some((1, Label::U8)),
+        // [nargs] [end label] ...
+        VmOpcode::OP_DEF_FUNCTION_PUSH => Some((3, Label::U16)),
+        // [slot] [label]
+        VmOpcode::OP_JARG => Some((3, Label::I16)),
+        VmOpcode::OP_JMP_LONG => Some((1, Label::U32)),
+        _ => None,
+    }
+}
+
+// whether execution never continues to the next instruction
+fn is_terminator(op: VmOpcode) -> bool {
+    match op {
+        VmOpcode::OP_HALT
+        | VmOpcode::OP_JMP
+        | VmOpcode::OP_JMP_LONG
+        | VmOpcode::OP_RET
+        | VmOpcode::OP_RETCALL
+        | VmOpcode::OP_EXFRAME_RET => true,
+        _ => false,
+    }
+}
+
+// whether the instruction only pushes a value, so it can be removed if popped
+fn is_pure_push(op: VmOpcode) -> bool {
+    match op {
+        VmOpcode::OP_PUSH8
+        | VmOpcode::OP_PUSH16
+        | VmOpcode::OP_PUSH32
+        | VmOpcode::OP_PUSH64
+        | VmOpcode::OP_PUSH_NIL
+        | VmOpcode::OP_PUSHSTR
+        | VmOpcode::OP_PUSHSTR_INTERNED
+        | VmOpcode::OP_PUSHF64
+        | VmOpcode::OP_GET_LOCAL
+        | VmOpcode::OP_GET_LOCAL_UP
+        | VmOpcode::OP_DUP => true,
+        _ => false,
+    }
+}
+
+fn read_be(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0, |n, &byte| n << 8 | u64::from(byte))
+}
+
+// value of a literal pushed by the bytecode
+#[derive(Clone)]
+enum Constant {
+    Int(i64),
+    Float(f64),
+    Str(String),
+}
+
+// result of an arithmetic operator on constant operands, or None
+// if it is left to be done (or raise an error) at runtime
+fn fold(op: VmOpcode, left: &Constant, right: &Constant) -> Option<Constant> {
+    match (left, right) {
+        (Constant::Int(l), Constant::Int(r)) => match op {
+            // overflowing results are promoted to big integers at runtime
+            VmOpcode::OP_ADD => l.checked_add(*r).map(Constant::Int),
+            VmOpcode::OP_SUB => l.checked_sub(*r).map(Constant::Int),
+            VmOpcode::OP_MUL => l.checked_mul(*r).map(Constant::Int),
+            VmOpcode::OP_DIV => Some(Constant::Float(*l as f64 / *r as f64)),
+            VmOpcode::OP_MOD if *r == -1 => Some(Constant::Int(0)),
+            VmOpcode::OP_MOD if *r != 0 => Some(Constant::Int(l % r)),
+            VmOpcode::OP_BITWISE_AND => Some(Constant::Int(l & r)),
+            VmOpcode::OP_BITWISE_OR => Some(Constant::Int(l | r)),
+            VmOpcode::OP_BITWISE_XOR => Some(Constant::Int(l ^ r)),
+            _ => None,
+        },
+        (Constant::Float(l), Constant::Float(r)) => match op {
+            VmOpcode::OP_ADD => Some(Constant::Float(l + r)),
+            VmOpcode::OP_SUB => Some(Constant::Float(l - r)),
+            VmOpcode::OP_MUL => Some(Constant::Float(l * r)),
+            VmOpcode::OP_DIV => Some(Constant::Float(l / r)),
+            _ => None,
+        },
+        (Constant::Str(l), Constant::Str(r)) if op == VmOpcode::OP_ADD => {
+            Some(Constant::Str(l.clone() + r))
+        }
+        _ => None,
+    }
+}
+
+fn push_int(n: u64) -> Vec<u8> {
+    let bytes = n.to_be_bytes();
+    match n {
+        0..=0xff => vec![VmOpcode::OP_PUSH8 as u8, n as u8],
+        0x100..=0xffff => vec![VmOpcode::OP_PUSH16 as u8, bytes[6], bytes[7]],
+        0x10000..=0xffff_ffff => {
+            let mut code = vec![VmOpcode::OP_PUSH32 as u8];
+            code.extend_from_slice(&bytes[4..]);
+            code
+        }
+        _ => {
+            let mut code = vec![VmOpcode::OP_PUSH64 as u8];
+            code.extend_from_slice(&bytes);
+            code
+        }
+    }
+}
+
+struct Instruction {
+    // position in the unoptimized code
+    pos: usize,
+    code: Vec<u8>,
+    // position of the jump target in the unoptimized code
+    target: Option<usize>,
+    live: bool,
+}
+
+impl Instruction {
+    fn op(&self) -> VmOpcode {
+        VmOpcode::from_u8(self.code[0]).unwrap()
+    }
+}
+
+/// Mapping of positions in the unoptimized code to the optimized code
+pub struct Relocation {
+    start: usize,
+    end: usize,
+    new_end: usize,
+    // old position, new position and new size of each instruction
+    instructions: Vec<(usize, usize, usize)>,
+}
+
+impl Relocation {
+    fn identity(start: usize, end: usize) -> Relocation {
+        Relocation {
+            start,
+            end,
+            new_end: end,
+            instructions: Vec::new(),
+        }
+    }
+
+    /// Maps the position to the optimized code. Positions of removed
+    /// instructions are mapped to the next instruction left.
+    pub fn map(&self, pos: usize) -> usize {
+        if pos < self.start || self.instructions.is_empty() {
+            pos
+        } else if pos >= self.end {
+            pos - self.end + self.new_end
+        } else {
+            match self
+                .instructions
+                .binary_search_by_key(&pos, |&(old, _, _)| old)
+            {
+                Ok(i) => self.instructions[i].1,
+                Err(i) => {
+                    // inside of the instruction
+                    let (old, new, size) = self.instructions[i - 1];
+                    new + (pos - old).min(size.saturating_sub(1))
+                }
+            }
+        }
+    }
+}
+
+struct Optimizer<'a> {
+    start: usize,
+    instructions: Vec<Instruction>,
+    // index of the instruction at each position from start to the end (inclusive)
+    index: Vec<usize>,
+    // whether each instruction can be jumped to, so that it must be kept
+    // as an instruction boundary
+    targets: Vec<bool>,
+    interned_strings: Option<&'a mut InternedStringMap>,
+}
+
+impl<'a> Optimizer<'a> {
+    fn new(
+        code: &[u8], start: usize, interned_strings: Option<&'a mut InternedStringMap>,
+    ) -> Option<Optimizer<'a>> {
+        let mut instructions = Vec::new();
+        let mut index = vec![std::usize::MAX; code.len() - start + 1];
+        let mut pos = start;
+        while pos < code.len() {
+            let op = VmOpcode::from_u8(code[pos])?;
+            let size = instruction_size(code, pos);
+            let target = if let Some((at, label)) = label_of(op) {
+                let at = pos + at;
+                let target = match label {
+                    Label::U8 => at + code[at] as usize,
+                    Label::I16 => {
+                        (at as isize + read_be(&code[at..at + 2]) as i16 as isize) as usize
+                    }
+                    Label::U16 => at + read_be(&code[at..at + 2]) as usize,
+                    Label::U32 => read_be(&code[at..at + 4]) as usize,
+                };
+                // jumps out of the code can't be relocated
+                if target < start || target > code.len() {
+                    return None;
+                }
+                Some(target)
+            } else {
+                None
+            };
+            index[pos - start] = instructions.len();
+            instructions.push(Instruction {
+                pos,
+                code: code[pos..pos + size].to_vec(),
+                target,
+                live: true,
+            });
+            pos += size;
+        }
+        index[code.len() - start] = instructions.len();
+        // every jump must land on an instruction
+        if instructions
+            .iter()
+            .filter_map(|instr| instr.target)
+            .any(|target| index[target - start] == std::usize::MAX)
+        {
+            return None;
+        }
+        Some(Optimizer {
+            start,
+            instructions,
+            index,
+            targets: Vec::new(),
+            interned_strings,
+        })
+    }
+
+    // #region instructions
+    // index of the first instruction left from i on
+    fn next_live(&self, mut i: usize) -> usize {
+        while i < self.instructions.len() && !self.instructions[i].live {
+            i += 1;
+        }
+        i
+    }
+
+    // index of the instruction the jump lands on
+    fn target_index(&self, target: usize) -> usize {
+        self.next_live(self.index[target - self.start])
+    }
+
+    // opcode of the next instruction left after i if it can't be jumped to
+    fn next_op(&self, i: usize) -> Option<(usize, VmOpcode)> {
+        let j = self.next_live(i + 1);
+        if j < self.instructions.len() && !self.targets[j] {
+            Some((j, self.instructions[j].op()))
+        } else {
+            None
+        }
+    }
+
+    fn remove(&mut self, i: usize) {
+        self.instructions[i].live = false;
+    }
+
+    fn find_targets(&mut self) {
+        let mut targets = vec![false; self.instructions.len() + 1];
+        targets[self.next_live(0)] = true;
+        for (i, instr) in self.instructions.iter().enumerate() {
+            if !instr.live {
+                continue;
+            }
+            if let Some(target) = instr.target {
+                targets[self.target_index(target)] = true;
+            }
+            if instr.op() == VmOpcode::OP_DEF_FUNCTION_PUSH {
+                // the function's body is entered by calls
+                targets[self.next_live(i + 1)] = true;
+            }
+        }
+        self.targets = targets;
+    }
+
+    // replaces the instructions left in first..=last with the new ones,
+    // returning false if they would take up more space
+    fn replace(&mut self, first: usize, last: usize, code: Vec<Vec<u8>>) -> bool {
+        let slots: Vec<usize> = (first..=last)
+            .filter(|&i| self.instructions[i].live)
+            .collect();
+        let old_size: usize = slots.iter().map(|&i| self.instructions[i].code.len()).sum();
+        let new_size: usize = code.iter().map(|code| code.len()).sum();
+        if new_size > old_size || code.len() > slots.len() {
+            return false;
+        }
+        for (n, &i) in slots.iter().enumerate() {
+            if let Some(code) = code.get(n) {
+                self.instructions[i].code = code.clone();
+                self.instructions[i].target = None;
+            } else {
+                self.remove(i);
+            }
+        }
+        true
+    }
+    // #endregion
+
+    // #region jumps
+    // whether the jump label of the instruction at i can point to the target
+    fn label_fits(&self, i: usize, target: usize) -> bool {
+        let at = self.instructions[i].pos + 1;
+        let offset = target as isize - at as isize;
+        offset >= std::i16::MIN as isize && offset <= std::i16::MAX as isize
+    }
+
+    fn thread_jumps(&mut self) -> bool {
+        let mut changed = false;
+        for i in 0..self.instructions.len() {
+            if !self.instructions[i].live {
+                continue;
+            }
+            let op = self.instructions[i].op();
+            match op {
+                VmOpcode::OP_JMP
+                | VmOpcode::OP_JCOND
+                | VmOpcode::OP_JNCOND
+                | VmOpcode::OP_JCOND_NO_POP
+                | VmOpcode::OP_JNCOND_NO_POP => {}
+                _ => continue,
+            }
+            for _ in 0..MAX_THREADING {
+                let target = self.instructions[i].target.unwrap();
+                let j = self.target_index(target);
+                if j == self.instructions.len() || j == i {
+                    break;
+                }
+                let next = match (op, self.instructions[j].op()) {
+                    (_, VmOpcode::OP_JMP) => self.instructions[j].target.unwrap(),
+                    // the kept condition jumps the same way again
+                    (VmOpcode::OP_JCOND_NO_POP, VmOpcode::OP_JCOND_NO_POP)
+                    | (VmOpcode::OP_JNCOND_NO_POP, VmOpcode::OP_JNCOND_NO_POP) => {
+                        self.instructions[j].target.unwrap()
+                    }
+                    (VmOpcode::OP_JMP, VmOpcode::OP_RET) => {
+                        self.instructions[i].code = vec![VmOpcode::OP_RET as u8];
+                        self.instructions[i].target = None;
+                        changed = true;
+                        break;
+                    }
+                    _ => break,
+                };
+                if next == target || !self.label_fits(i, next) {
+                    break;
+                }
+                self.instructions[i].target = Some(next);
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    // removes jumps to the next instruction
+    fn remove_empty_jumps(&mut self) -> bool {
+        let mut changed = false;
+        for i in 0..self.instructions.len() {
+            if !self.instructions[i].live {
+                continue;
+            }
+            let target = match self.instructions[i].target {
+                Some(target) => self.target_index(target),
+                None => continue,
+            };
+            if target != self.next_live(i + 1) {
+                continue;
+            }
+            match self.instructions[i].op() {
+                VmOpcode::OP_JMP | VmOpcode::OP_JCOND_NO_POP | VmOpcode::OP_JNCOND_NO_POP => {
+                    self.remove(i);
+                }
+                VmOpcode::OP_JCOND | VmOpcode::OP_JNCOND => {
+                    // the condition is still popped
+                    self.instructions[i].code = vec![VmOpcode::OP_POP as u8];
+                    self.instructions[i].target = None;
+                }
+                _ => continue,
+            }
+            changed = true;
+        }
+        changed
+    }
+
+    fn remove_dead_code(&mut self) -> bool {
+        let mut changed = false;
+        let mut dead = false;
+        for i in 0..self.instructions.len() {
+            if !self.instructions[i].live {
+                continue;
+            }
+            if self.targets[i] {
+                dead = false;
+            }
+            if dead {
+                self.remove(i);
+                changed = true;
+            } else {
+                dead = is_terminator(self.instructions[i].op());
+            }
+        }
+        changed
+    }
+    // #endregion
+
+    // #region constant folding
+    // the constant pushed by the instruction at i, and the index of
+    // the last instruction pushing it
+    fn constant(&self, i: usize) -> Option<(Constant, usize)> {
+        let code = &self.instructions[i].code;
+        let constant = match self.instructions[i].op() {
+            VmOpcode::OP_PUSH8
+            | VmOpcode::OP_PUSH16
+            | VmOpcode::OP_PUSH32
+            | VmOpcode::OP_PUSH64 => Constant::Int(read_be(&code[1..]) as i64),
+            VmOpcode::OP_PUSHF64 => {
+                let mut bytes = [0; 8];
+                bytes.copy_from_slice(&code[1..]);
+                Constant::Float(f64::from_bits(u64::from_ne_bytes(bytes)))
+            }
+            VmOpcode::OP_PUSHSTR => {
+                let s = std::str::from_utf8(&code[1..code.len() - 1]).ok()?;
+                Constant::Str(s.to_string())
+            }
+            VmOpcode::OP_PUSHSTR_INTERNED => {
+                let idx = read_be(&code[1..]) as u16;
+                let s = self.interned_strings.as_ref()?.get(idx)?;
+                Constant::Str(s.as_str().to_string())
+            }
+            _ => return None,
+        };
+        // negative literals are negated at runtime
+        if let Some((j, VmOpcode::OP_NEGATE)) = self.next_op(i) {
+            match constant {
+                Constant::Int(n) if n != std::i64::MIN => return Some((Constant::Int(-n), j)),
+                Constant::Float(n) => return Some((Constant::Float(-n), j)),
+                _ => {}
+            }
+        }
+        Some((constant, i))
+    }
+
+    // instructions pushing the constant, or None if it can't be pushed
+    fn emit_constant(&mut self, constant: Constant) -> Option<Vec<Vec<u8>>> {
+        match constant {
+            Constant::Int(n) if n >= 0 => Some(vec![push_int(n as u64)]),
+            Constant::Int(n) => {
+                let abs = push_int(n.wrapping_neg() as u64);
+                if n != std::i64::MIN && abs.len() < 8 {
+                    Some(vec![abs, vec![VmOpcode::OP_NEGATE as u8]])
+                } else {
+                    Some(vec![push_int(n as u64)])
+                }
+            }
+            Constant::Float(n) => {
+                let mut code = vec![VmOpcode::OP_PUSHF64 as u8];
+                code.extend_from_slice(&n.to_bits().to_ne_bytes());
+                Some(vec![code])
+            }
+            Constant::Str(s) => {
+                if let Some(interned_strings) = self.interned_strings.as_mut() {
+                    if let Some(idx) = interned_strings.get_or_insert(&s) {
+                        let mut code = vec![VmOpcode::OP_PUSHSTR_INTERNED as u8];
+                        code.extend_from_slice(&idx.to_be_bytes());
+                        return Some(vec![code]);
+                    }
+                }
+                if s.contains('\0') {
+                    return None;
+                }
+                let mut code = vec![VmOpcode::OP_PUSHSTR as u8];
+                code.extend_from_slice(s.as_bytes());
+                code.push(0);
+                Some(vec![code])
+            }
+        }
+    }
+
+    fn fold_constants(&mut self, i: usize) -> bool {
+        let (left, left_end) = match self.constant(i) {
+            Some(constant) => constant,
+            None => return false,
+        };
+        // [left] [right] [binary operator]
+        let right = self.next_op(left_end).and_then(|(j, _)| self.constant(j));
+        if let Some((right, right_end)) = right {
+            if let Some((k, op)) = self.next_op(right_end) {
+                if let Some(code) =
+                    fold(op, &left, &right).and_then(|result| self.emit_constant(result))
+                {
+                    if self.replace(i, k, code) {
+                        return true;
+                    }
+                }
+            }
+        }
+        // [strings...] [str_concat n]
+        if let Constant::Str(_) = left {
+            if self.fold_concat(i) {
+                return true;
+            }
+        }
+        // float literals are negated in place
+        if let Constant::Float(_) = left {
+            if left_end != i {
+                let code = self.emit_constant(left).unwrap();
+                return self.replace(i, left_end, code);
+            }
+        }
+        false
+    }
+
+    fn fold_concat(&mut self, i: usize) -> bool {
+        let mut s = String::new();
+        let mut count = 0;
+        let mut last = i;
+        let mut next = Some(i);
+        while let Some((Constant::Str(part), end)) = next.and_then(|j| self.constant(j)) {
+            s += &part;
+            count += 1;
+            last = end;
+            next = self.next_op(end).map(|(j, _)| j);
+        }
+        match self.next_op(last) {
+            Some((k, VmOpcode::OP_STR_CONCAT))
+                if read_be(&self.instructions[k].code[1..]) == count =>
+            {
+                match self.emit_constant(Constant::Str(s)) {
+                    Some(code) => self.replace(i, k, code),
+                    None => false,
+                }
+            }
+            _ => false,
+        }
+    }
+    // #endregion
+
+    // #region peephole
+    fn peephole(&mut self, i: usize) -> bool {
+        let op = self.instructions[i].op();
+        let (j, next) = match self.next_op(i) {
+            Some(next) => next,
+            None => return false,
+        };
+        match (op, next) {
+            // not x; jncond => x; jcond
+            (VmOpcode::OP_NOT, VmOpcode::OP_JCOND) | (VmOpcode::OP_NOT, VmOpcode::OP_JNCOND) => {
+                let target = self.instructions[j].target.unwrap();
+                if !self.label_fits(i, target) {
+                    return false;
+                }
+                let inverted = if next == VmOpcode::OP_JCOND {
+                    VmOpcode::OP_JNCOND
+                } else {
+                    VmOpcode::OP_JCOND
+                };
+                self.instructions[i].code = vec![inverted as u8, 0, 0];
+                self.instructions[i].target = Some(target);
+                self.remove(j);
+                true
+            }
+            (VmOpcode::OP_SET_LOCAL, VmOpcode::OP_POP)
+            | (VmOpcode::OP_SET_GLOBAL, VmOpcode::OP_POP) => {
+                let (get, fused) = if op == VmOpcode::OP_SET_LOCAL {
+                    (VmOpcode::OP_GET_LOCAL, VmOpcode::OP_SET_LOCAL_POP)
+                } else {
+                    (VmOpcode::OP_GET_GLOBAL, VmOpcode::OP_SET_GLOBAL_POP)
+                };
+                match self.next_op(j) {
+                    // the set value is still on the stack
+                    Some((k, next))
+                        if next == get
+                            && self.instructions[k].code[1..]
+                                == self.instructions[i].code[1..] =>
+                    {
+                        self.remove(j);
+                        self.remove(k);
+                    }
+                    _ => {
+                        self.instructions[i].code[0] = fused as u8;
+                        self.remove(j);
+                    }
+                }
+                true
+            }
+            (_, VmOpcode::OP_POP) if is_pure_push(op) => {
+                self.remove(i);
+                self.remove(j);
+                true
+            }
+            _ => false,
+        }
+    }
+    // #endregion
+
+    fn run(&mut self) {
+        for _ in 0..MAX_PASSES {
+            let mut changed = false;
+            self.find_targets();
+            changed |= self.thread_jumps();
+            self.find_targets();
+            changed |= self.remove_empty_jumps();
+            self.find_targets();
+            changed |= self.remove_dead_code();
+            self.find_targets();
+            let mut i = self.next_live(0);
+            while i < self.instructions.len() {
+                if self.fold_constants(i) || self.peephole(i) {
+                    changed = true;
+                }
+                i = self.next_live(i + 1);
+            }
+            if !changed {
+                break;
+            }
+        }
+    }
+
+    // writes the optimized code back with jump labels pointing to their new
+    // positions
+    fn finish(self, code: &mut Vec<u8>) -> Relocation {
+        let end = code.len();
+        let mut positions = Vec::with_capacity(self.instructions.len() + 1);
+        let mut pos = self.start;
+        for instr in self.instructions.iter() {
+            positions.push(pos);
+            if instr.live {
+                pos += instr.code.len();
+            }
+        }
+        positions.push(pos);
+
+        code.truncate(self.start);
+        for (i, instr) in self.instructions.iter().enumerate() {
+            if !instr.live {
+                continue;
+            }
+            let mut bytes = instr.code.clone();
+            if let Some(target) = instr.target {
+                let target = positions[self.index[target - self.start]];
+                let (at, label) = label_of(instr.op()).unwrap();
+                let offset = target as isize - (positions[i] + at) as isize;
+                match label {
+                    Label::U8 => {
+                        assert!(offset >= 0 && offset <= std::u8::MAX as isize);
+                        bytes[at] = offset as u8;
+                    }
+                    Label::I16 => {
+                        assert!(
+                            offset >= std::i16::MIN as isize && offset <= std::i16::MAX as isize
+                        );
+                        bytes[at..at + 2].copy_from_slice(&(offset as i16).to_be_bytes());
+                    }
+                    Label::U16 => {
+                        assert!(offset >= 0 && offset <= std::u16::MAX as isize);
+                        bytes[at..at + 2].copy_from_slice(&(offset as u16).to_be_bytes());
+                    }
+                    Label::U32 => {
+                        bytes[at..at + 4].copy_from_slice(&(target as u32).to_be_bytes());
+                    }
+                }
+            }
+            code.extend_from_slice(&bytes);
+        }
+
+        Relocation {
+            start: self.start,
+            end,
+            new_end: code.len(),
+            instructions: self
+                .instructions
+                .iter()
+                .enumerate()
+                .map(|(i, instr)| {
+                    let size = if instr.live { instr.code.len() } else { 0 };
+                    (instr.pos, positions[i], size)
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Optimizes the code from `start` to its end in place.
+///
+/// Strings created by folding string concatenation are interned in
+/// `interned_strings` if possible. The code is left untouched if it
+/// can't be decoded or jumps out of the optimized range.
+pub fn optimize(
+    code: &mut Vec<u8>, start: usize, interned_strings: Option<&mut InternedStringMap>,
+) -> Relocation {
+    match Optimizer::new(code, start, interned_strings) {
+        Some(mut optimizer) => {
+            optimizer.run();
+            optimizer.finish(code)
+        }
+        None => Relocation::identity(start, code.len()),
+    }
+}
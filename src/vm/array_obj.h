@@ -4,6 +4,7 @@ extern "C" {
 #endif
 
 #include <stdbool.h>
+#include <stdint.h>
 #include "array.h"
 
 typedef array(struct value) array_obj;
@@ -11,6 +12,8 @@ struct vm;
 array_obj *array_obj_malloc(const struct vm *vm);
 array_obj *array_obj_malloc_n(size_t n, const struct vm *vm);
 array_obj *array_obj_repeat(array_obj *array, size_t times, const struct vm *vm);
+array_obj *array_obj_slice(const array_obj *array, int64_t start, int64_t step, size_t n, const struct vm *vm);
+bool array_obj_splice(array_obj *array, int64_t start, int64_t step, size_t n, const array_obj *src);
 
 #ifdef __cplusplus
 }
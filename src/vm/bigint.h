@@ -0,0 +1,19 @@
+#pragma once
+#include <stdbool.h>
+
+struct value;
+struct vm;
+// arithmetic on integers promoted to big integers, the results are
+// demoted back to 64-bit integers whenever they fit
+struct value bigint_add(const struct value left, const struct value right, const struct vm *);
+struct value bigint_sub(const struct value left, const struct value right, const struct vm *);
+struct value bigint_mul(const struct value left, const struct value right, const struct vm *);
+struct value bigint_mod(const struct value left, const struct value right, const struct vm *);
+struct value bigint_bitwise_and(const struct value left, const struct value right, const struct vm *);
+struct value bigint_bitwise_or (const struct value left, const struct value right, const struct vm *);
+struct value bigint_bitwise_xor(const struct value left, const struct value right, const struct vm *);
+struct value bigint_negate(const struct value val, const struct vm *);
+struct value bigint_from_str(const char *str, const struct vm *);
+int bigint_cmp(const struct value left, const struct value right);
+double bigint_to_float(const struct value val);
+bool bigint_is_positive(const struct value val);
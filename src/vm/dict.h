@@ -18,6 +18,9 @@ void dict_set(struct dict *, const char *, struct value);
 const struct value *dict_get_str(const struct dict *, struct string *);
 void dict_set_str(struct dict *, struct string *, struct value);
 bool dict_is_prototype_of(const struct dict *child, const struct dict *parent);
+bool dict_is_generator(const struct dict *);
+bool dict_is_map(const struct dict *);
+bool dict_is_set(const struct dict *);
 
 #ifdef __cplusplus
 }
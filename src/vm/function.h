@@ -9,12 +9,20 @@ struct env;
 struct function {
     uint32_t ip;
     uint16_t nargs;
+    // number of args that have no default value
+    uint16_t nrequired;
+    // whether the last arg collects the remaining positional args
+    bool rest;
+    // position of the args' names in the bytecode
+    uint32_t params;
     // ... (additional rust properties)
 };
 struct vm;
 
 struct function *function_malloc(uint32_t addr, uint16_t nargs, struct env *env, const struct vm *vm);
 void function_set_bound_var(struct function *, uint16_t n, struct value val);
+bool function_bind_args(const struct function *, uint16_t nargs,
+                        const char *kwnames, uint16_t nkwargs, struct vm *vm);
 
 #ifdef __cplusplus
 }
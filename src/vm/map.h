@@ -0,0 +1,15 @@
+#pragma once
+#include <stdbool.h>
+#include "array_obj.h"
+
+struct value;
+struct dict;
+struct vm;
+// maps are records whose native field holds a hash map of hashable keys,
+// map_get returns NULL if the key is missing or unhashable and
+// map_set returns false if the key is unhashable
+struct dict *map_malloc(const struct vm *);
+const struct value *map_get(const struct dict *, const struct value key);
+const struct value *map_get_str(const struct dict *, const char *key);
+bool map_set(struct dict *, const struct value key, const struct value val);
+array_obj *map_keys(const struct dict *, const struct vm *);
@@ -0,0 +1,9 @@
+#pragma once
+#include <stdbool.h>
+#include "array_obj.h"
+
+struct dict;
+struct vm;
+// sets are records whose native field holds a hash set of hashable values
+array_obj *set_elements(const struct dict *, const struct vm *);
+bool set_eq(const struct dict *left, const struct dict *right);
@@ -4,9 +4,11 @@
 #include <stdbool.h>
 
 struct string;
+struct value;
 struct vm;
 struct string *string_malloc(const char *str, const struct vm *);
 struct string *string_append(const struct string *left, const struct string *right, const struct vm *);
+struct string *string_concat(const struct value *values, size_t n, const struct vm *);
 void string_append_in_place(struct string *left, const struct string *right);
 struct string *string_repeat(const struct string *left, int64_t n, const struct vm *);
 void string_repeat_in_place(struct string *left, int64_t n);
@@ -14,4 +16,5 @@ bool string_is_empty(const struct string *str);
 int string_cmp(const struct string *left, const struct string *right);
 struct string *string_at(const struct string *str, int64_t n, const struct vm *);
 array_obj *string_chars(const struct string *str, const struct vm*);
-size_t string_len(struct string *str);
\ No newline at end of file
+size_t string_len(struct string *str);
+struct string *string_slice(const struct string *str, int64_t start, int64_t step, size_t n, const struct vm *);
\ No newline at end of file
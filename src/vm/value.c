@@ -7,6 +7,9 @@
 #include "hmap.h"
 #include "array_obj.h"
 #include "function.h"
+#include "bigint.h"
+#include "dict.h"
+#include "set.h"
 
 // non-primitives
 struct value value_int(int64_t n) {
@@ -15,6 +18,12 @@ struct value value_int(int64_t n) {
         .as.integer = n,
     };
 }
+struct value value_bool(bool b) {
+    return (struct value){
+        .type = TYPE_BOOL,
+        .as.integer = b,
+    };
+}
 struct value value_float(double n) {
     return (struct value){
         .type = TYPE_FLOAT,
@@ -45,15 +54,22 @@ struct value value_interpreter_error() {
 }
 
 // arith
-#define arith_op(name, op, custom)                                                                      \
+// integer arithmetic is promoted to big integers when it overflows
+#define arith_op(name, op, checked, custom)                                                             \
     struct value value_##name(const struct value left, const struct value right, const struct vm *vm) { \
         switch (left.type) {                                                                            \
             case TYPE_INT: {                                                                            \
                 switch (right.type) {                                                                   \
                     case TYPE_FLOAT:                                                                    \
                         return value_float((double)value_get_int(left) op right.as.floatp);             \
-                    case TYPE_INT:                                                                      \
-                        return value_int(value_get_int(left) op value_get_int(right));                  \
+                    case TYPE_INT: {                                                                    \
+                        int64_t result;                                                                 \
+                        if (checked(value_get_int(left), value_get_int(right), &result))                \
+                            return bigint_##name(left, right, vm);                                      \
+                        return value_int(result);                                                       \
+                    }                                                                                   \
+                    case TYPE_BIGINT:                                                                   \
+                        return bigint_##name(left, right, vm);                                          \
                     default:                                                                            \
                         return value_interpreter_error();                                               \
                 }                                                                                       \
@@ -64,6 +80,19 @@ struct value value_interpreter_error() {
                         return value_int(left.as.floatp op(double) value_get_int(right));               \
                     case TYPE_FLOAT:                                                                    \
                         return value_float(left.as.floatp op right.as.floatp);                          \
+                    case TYPE_BIGINT:                                                                   \
+                        return value_float(left.as.floatp op bigint_to_float(right));                   \
+                    default:                                                                            \
+                        return value_interpreter_error();                                               \
+                }                                                                                       \
+            }                                                                                           \
+            case TYPE_BIGINT: {                                                                         \
+                switch (right.type) {                                                                   \
+                    case TYPE_INT:                                                                      \
+                    case TYPE_BIGINT:                                                                   \
+                        return bigint_##name(left, right, vm);                                          \
+                    case TYPE_FLOAT:                                                                    \
+                        return value_float(bigint_to_float(left) op right.as.floatp);                   \
                     default:                                                                            \
                         return value_interpreter_error();                                               \
                 }                                                                                       \
@@ -72,7 +101,7 @@ struct value value_interpreter_error() {
         }                                                                                               \
         return value_interpreter_error();                                                               \
     }
-arith_op(add, +,
+arith_op(add, +, __builtin_add_overflow,
     case TYPE_STR: {
         if(right.type != TYPE_STR) {
             return value_interpreter_error();
@@ -80,8 +109,8 @@ arith_op(add, +,
         return value_pointer(TYPE_STR, string_append(value_get_pointer(left), value_get_pointer(right), vm));
     }
 )
-arith_op(sub, -,)
-arith_op(mul, *,
+arith_op(sub, -, __builtin_sub_overflow,)
+arith_op(mul, *, __builtin_mul_overflow,
     case TYPE_STR: {
         if(right.type == TYPE_INT) {
             if(value_get_int(right) == 0) {
@@ -99,52 +128,54 @@ arith_op(mul, *,
     }
 )
 
-struct value value_div(const struct value left, const struct value right, const struct vm *_) {
-    switch(left.type) {
-    case TYPE_INT: {
-        switch(right.type) {
-            case TYPE_FLOAT:
-                return value_float((double)value_get_int(left) / right.as.floatp);
-            case TYPE_INT:
-                return value_float((double)value_get_int(left) / (double)value_get_int(right));
-        }
-        return value_interpreter_error();
-    }
-    case TYPE_FLOAT: {
-        switch(right.type) {
-            case TYPE_INT:
-                return value_float(left.as.floatp / (double)value_get_int(right));
-            case TYPE_FLOAT:
-                return value_float(left.as.floatp / right.as.floatp);
-            }
-        return value_interpreter_error();
-    }
-    default: return value_interpreter_error(); }
-}
-struct value value_mod(const struct value left, const struct value right, const struct vm *_) {
-    if(left.type == TYPE_INT && right.type == TYPE_INT) {
-        return value_int(value_get_int(left) % value_get_int(right));
-    } else
-        return value_interpreter_error();
+static inline bool value_is_integer(const struct value val) {
+    return val.type == TYPE_INT || val.type == TYPE_BIGINT;
 }
-struct value value_bitwise_and(const struct value left, const struct value right, const struct vm *_) {
-    if (left.type == TYPE_INT && right.type == TYPE_INT) {
-        return value_int(value_get_int(left) & value_get_int(right));
-    } else
-        return value_interpreter_error();
+static bool value_get_number(const struct value val, double *out) {
+    switch (val.type) {
+        case TYPE_INT:
+            *out = (double)value_get_int(val);
+            return true;
+        case TYPE_FLOAT:
+            *out = val.as.floatp;
+            return true;
+        case TYPE_BIGINT:
+            *out = bigint_to_float(val);
+            return true;
+        default:
+            return false;
+    }
 }
-struct value value_bitwise_or(const struct value left, const struct value right, const struct vm *_) {
-    if (left.type == TYPE_INT && right.type == TYPE_INT) {
-        return value_int(value_get_int(left) | value_get_int(right));
+
+struct value value_div(const struct value left, const struct value right, const struct vm *_) {
+    double l, r;
+    if (value_get_number(left, &l) && value_get_number(right, &r)) {
+        return value_float(l / r);
     } else
         return value_interpreter_error();
 }
-struct value value_bitwise_xor(const struct value left, const struct value right, const struct vm *_) {
+struct value value_mod(const struct value left, const struct value right, const struct vm *vm) {
     if (left.type == TYPE_INT && right.type == TYPE_INT) {
-        return value_int(value_get_int(left) ^ value_get_int(right));
+        // INT64_MIN % -1 overflows
+        if (value_get_int(right) == -1) return value_int(0);
+        return value_int(value_get_int(left) % value_get_int(right));
+    } else if (value_is_integer(left) && value_is_integer(right)) {
+        return bigint_mod(left, right, vm);
     } else
         return value_interpreter_error();
 }
+#define bitwise_op(name, op)                                                                            \
+    struct value value_##name(const struct value left, const struct value right, const struct vm *vm) { \
+        if (left.type == TYPE_INT && right.type == TYPE_INT) {                                          \
+            return value_int(value_get_int(left) op value_get_int(right));                              \
+        } else if (value_is_integer(left) && value_is_integer(right)) {                                 \
+            return bigint_##name(left, right, vm);                                                      \
+        } else                                                                                          \
+            return value_interpreter_error();                                                           \
+    }
+bitwise_op(bitwise_and, &)
+bitwise_op(bitwise_or, |)
+bitwise_op(bitwise_xor, ^)
 
 // in place
 // returns 1 if it CAN do it in place
@@ -179,46 +210,112 @@ int value_imul(struct value left, const struct value right) {
 }
 
 // comparison
+// records are only equal to themselves, sets are equal if they have the same elements
+static bool dict_eq(const struct value left, const struct value right) {
+    if (right.type == TYPE_DICT && dict_is_set(value_get_pointer(left)) &&
+        dict_is_set(value_get_pointer(right))) {
+        return set_eq(value_get_pointer(left), value_get_pointer(right));
+    }
+    return value_get_int(left) == value_get_int(right);
+}
+#define cmp_op(name, op, number_bool, custom)                                                           \
+    struct value value_##name(const struct value left, const struct value right, const struct vm *vm) { \
+        switch (left.type) {                                                                            \
+            case TYPE_INT: {                                                                            \
+                switch (right.type) {                                                                   \
+                    case TYPE_FLOAT:                                                                    \
+                        return value_bool((double)value_get_int(left) op right.as.floatp);              \
+                    case TYPE_INT:                                                                      \
+                        return value_bool(value_get_int(left) op value_get_int(right));                 \
+                    case TYPE_BIGINT:                                                                   \
+                        return value_bool(bigint_cmp(left, right) op 0);                                \
+                    case TYPE_BOOL:                                                                     \
+                        return number_bool;                                                             \
+                    default:                                                                            \
+                        return value_interpreter_error();                                               \
+                }                                                                                       \
+            }                                                                                           \
+            case TYPE_FLOAT: {                                                                          \
+                switch (right.type) {                                                                   \
+                    case TYPE_INT:                                                                      \
+                        return value_bool(left.as.floatp op(double) value_get_int(right));              \
+                    case TYPE_FLOAT:                                                                    \
+                        return value_bool(left.as.floatp op right.as.floatp);                           \
+                    case TYPE_BIGINT:                                                                   \
+                        return value_bool(left.as.floatp op bigint_to_float(right));                    \
+                    case TYPE_BOOL:                                                                     \
+                        return number_bool;                                                             \
+                    default:                                                                            \
+                        return value_interpreter_error();                                               \
+                }                                                                                       \
+            }                                                                                           \
+            case TYPE_BIGINT: {                                                                         \
+                switch (right.type) {                                                                   \
+                    case TYPE_INT:                                                                      \
+                    case TYPE_BIGINT:                                                                   \
+                        return value_bool(bigint_cmp(left, right) op 0);                                \
+                    case TYPE_FLOAT:                                                                    \
+                        return value_bool(bigint_to_float(left) op right.as.floatp);                    \
+                    case TYPE_BOOL:                                                                     \
+                        return number_bool;                                                             \
+                    default:                                                                            \
+                        return value_interpreter_error();                                               \
+                }                                                                                       \
+            }                                                                                           \
+                custom                                                                                  \
+        }                                                                                               \
+        return value_interpreter_error();                                                               \
+    }
 #define strcmp_op(cond) \
     case TYPE_STR: \
-        return value_int(right.type == TYPE_STR && string_cmp(value_get_pointer(left), value_get_pointer(right)) cond); \
+        return value_bool(right.type == TYPE_STR && string_cmp(value_get_pointer(left), value_get_pointer(right)) cond); \
         break;
-arith_op(eq, ==,
+cmp_op(eq, ==, value_bool(false),
     strcmp_op(== 0)
     case TYPE_NATIVE_FN:
     case TYPE_FN:
+        return value_bool(value_get_int(left) == value_get_int(right));
     case TYPE_DICT:
-        return value_int(value_get_int(left) == value_get_int(right));
+        return value_bool(dict_eq(left, right));
+    case TYPE_BOOL:
+        return value_bool(right.type == TYPE_BOOL && value_get_int(left) == value_get_int(right));
     case TYPE_NIL:
-        return value_int(right.type == TYPE_NIL);
+        return value_bool(right.type == TYPE_NIL);
 )
-arith_op(neq, !=,
+cmp_op(neq, !=, value_bool(true),
     strcmp_op(!= 0)
     case TYPE_NATIVE_FN:
     case TYPE_FN:
+        return value_bool(value_get_int(left) != value_get_int(right));
     case TYPE_DICT:
-        return value_int(value_get_int(left) != value_get_int(right));
+        return value_bool(!dict_eq(left, right));
+    case TYPE_BOOL:
+        return value_bool(right.type != TYPE_BOOL || value_get_int(left) != value_get_int(right));
     case TYPE_NIL:
-        return value_int(right.type != TYPE_NIL);
+        return value_bool(right.type != TYPE_NIL);
 )
-arith_op(lt, <,
+cmp_op(lt, <, value_interpreter_error(),
     strcmp_op(< 0)
 )
-arith_op(leq, <=,
+cmp_op(leq, <=, value_interpreter_error(),
     strcmp_op(<= 0)
 )
-arith_op(gt, >,
+cmp_op(gt, >, value_interpreter_error(),
     strcmp_op(> 0)
 )
-arith_op(geq, >=,
+cmp_op(geq, >=, value_interpreter_error(),
     strcmp_op(>= 0)
 )
 
 // boolean
 bool value_is_true(const struct value val) {
     switch (val.type) {
+        case TYPE_BOOL:
+            return value_get_int(val) != 0;
         case TYPE_INT:
             return value_get_int(val) > 0;
+        case TYPE_BIGINT:
+            return bigint_is_positive(val);
         case TYPE_FLOAT:
             return val.as.floatp > 0;
         case TYPE_STR:
@@ -233,7 +330,10 @@ struct dict *value_get_prototype(const struct vm *vm, const struct value val) {
         case TYPE_STR:
             return vm->dstr;
         case TYPE_INT:
+        case TYPE_BIGINT:
             return vm->dint;
+        case TYPE_BOOL:
+            return vm->dbool;
         case TYPE_FLOAT:
             return vm->dfloat;
         case TYPE_ARRAY:
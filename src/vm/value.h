@@ -17,8 +17,11 @@ struct string;
 #define TYPE_STR 5
 #define TYPE_DICT 6
 #define TYPE_ARRAY 7
+#define TYPE_BOOL 8
+#define TYPE_BIGINT 9
 #define TYPE_INTERPRETER_ERROR 127
 #define TYPE_INTERPRETER_ITERATOR 128
+#define TYPE_INTERPRETER_UNSET 129
 
 typedef void (*value_fn)(struct vm *vm, uint16_t nargs);
 struct __attribute__((packed)) value {
@@ -33,6 +36,7 @@ struct __attribute__((packed)) value {
 struct env;
 
 struct value value_int(int64_t);
+struct value value_bool(bool);
 struct value value_float(double);
 struct value value_str(const char*, const struct vm*);
 struct value value_function(uint32_t ip, uint16_t nargs, struct env *env, const struct vm *);
@@ -6,6 +6,9 @@
 #include "dict.h"
 #include "array_obj.h"
 #include "function.h"
+#include "bigint.h"
+#include "map.h"
+#include "set.h"
 #include "exception_frame.h"
 
 #include <assert.h>
@@ -22,6 +25,42 @@
 #endif
 #define FATAL(...) fprintf(stderr, __VA_ARGS__)
 
+// resolves the bounds and step of a slice of a value of the given length
+// into the start, step and number of elements it has, clamping the bounds
+// and counting negative ones from the end
+static enum vm_error slice_resolve(const struct value from, const struct value to,
+                                   const struct value step, const int64_t length,
+                                   int64_t *pstart, int64_t *pstep, size_t *pn) {
+    if ((from.type != TYPE_NIL && from.type != TYPE_INT) ||
+        (to.type != TYPE_NIL && to.type != TYPE_INT) ||
+        (step.type != TYPE_NIL && step.type != TYPE_INT)) {
+        return ERROR_KEY_NON_INT;
+    }
+    const int64_t s = step.type == TYPE_NIL ? 1 : value_get_int(step);
+    if (s == 0) {
+        return ERROR_SLICE_ZERO_STEP;
+    }
+    // a negative step goes from the end down to the start
+    const int64_t lower = s > 0 ? 0 : -1, upper = s > 0 ? length : length - 1;
+#define BOUND(val, default)                                      \
+    ((val).type == TYPE_NIL ? (default)                          \
+     : value_get_int(val) < 0                                    \
+         ? (value_get_int(val) + length < lower ? lower          \
+                                                : value_get_int(val) + length) \
+         : (value_get_int(val) > upper ? upper : value_get_int(val)))
+    const int64_t start = BOUND(from, s > 0 ? lower : upper);
+    const int64_t stop = BOUND(to, s > 0 ? upper : lower);
+#undef BOUND
+    *pstart = start;
+    *pstep = s;
+    if (s > 0) {
+        *pn = stop > start ? (size_t)((stop - start + s - 1) / s) : 0;
+    } else {
+        *pn = start > stop ? (size_t)((start - stop - s - 1) / -s) : 0;
+    }
+    return ERROR_NO_ERROR;
+}
+
 void vm_execute(struct vm *vm) {
 #define ERROR(code, unwind)           \
     do {                              \
@@ -42,6 +81,8 @@ void vm_execute(struct vm *vm) {
 #define dispatch()                                   \
     do {                                             \
         debug_assert(vm->ip <= vm->code.length);     \
+        if (vm->debug_hook != NULL)                  \
+            vm->debug_hook(vm);                      \
         goto *dispatch_table[vm->code.data[vm->ip]]; \
     } while (0)
 #else
@@ -49,6 +90,8 @@ void vm_execute(struct vm *vm) {
     do {                                             \
         vm_print_stack(vm);                          \
         debug_assert(vm->ip <= vm->code.length);     \
+        if (vm->debug_hook != NULL)                  \
+            vm->debug_hook(vm);                      \
         goto *dispatch_table[vm->code.data[vm->ip]]; \
     } while (0)
 #endif
@@ -92,7 +135,31 @@ void vm_execute(struct vm *vm) {
         // iterators
         X(OP_FOR_IN), X(OP_SWAP),
         // modules
-        X(OP_USE)};
+        X(OP_USE), X(OP_USE_AS),
+        // match expressions
+        X(OP_DUP),
+        X(OP_MATCH_EQ), X(OP_MATCH_ARRAY), X(OP_MATCH_INDEX), X(OP_MATCH_REST),
+        X(OP_MATCH_MEMBER),
+        // strings
+        X(OP_STR_CONCAT),
+        // function arguments
+        X(OP_CALL_KW), X(OP_JARG),
+        // destructuring
+        X(OP_MATCH_FAIL),
+        // slices
+        X(OP_SLICE_GET), X(OP_SLICE_SET),
+        // generators
+        X(OP_GENERATOR_NEW), X(OP_YIELD),
+        // big integers
+        X(OP_PUSH_BIGINT),
+        // maps
+        X(OP_MAP_LOAD), X(OP_MEMBER_INDEX_GET), X(OP_MEMBER_INDEX_GET_NO_POP),
+        // superinstructions
+        X(OP_SET_LOCAL_POP), X(OP_SET_GLOBAL_POP),
+        // quickened
+        X(OP_ADD_INT), X(OP_ADD_FLOAT), X(OP_SUB_INT), X(OP_SUB_FLOAT), X(OP_MUL_INT), X(OP_MUL_FLOAT),
+        X(OP_LT_INT), X(OP_LT_FLOAT), X(OP_LEQ_INT), X(OP_LEQ_FLOAT),
+        X(OP_GT_INT), X(OP_GT_FLOAT), X(OP_GEQ_INT), X(OP_GEQ_FLOAT)};
 
 #undef X
 
@@ -196,7 +263,7 @@ void vm_execute(struct vm *vm) {
         vm->ip++;
         struct value val = array_top(vm->stack);
         array_pop(vm->stack);
-        array_push(vm->stack, value_int(!value_is_true(val)));
+        array_push(vm->stack, value_bool(!value_is_true(val)));
         dispatch();
     }
     // pops top of the stack, performs unary negation and pushes the result
@@ -206,7 +273,15 @@ void vm_execute(struct vm *vm) {
         array_pop(vm->stack);
         switch (val.type) {
             case TYPE_INT: {
-                array_push(vm->stack, value_int(-value_get_int(val)));
+                // -INT64_MIN overflows
+                if (value_get_int(val) == INT64_MIN)
+                    array_push(vm->stack, bigint_negate(val, vm));
+                else
+                    array_push(vm->stack, value_int(-value_get_int(val)));
+                break;
+            }
+            case TYPE_BIGINT: {
+                array_push(vm->stack, bigint_negate(val, vm));
                 break;
             }
             case TYPE_FLOAT: {
@@ -237,9 +312,32 @@ void vm_execute(struct vm *vm) {
         array_push(vm->stack, result);                             \
         dispatch();                                                \
     }
-    binop(OP_ADD, value_add)
-    binop(OP_SUB, value_sub)
-    binop(OP_MUL, value_mul)
+    // these rewrite themselves into an op specialized for their operands'
+    // types if both are ints or floats (see the quickened ops below)
+#define binop_quicken(optype, fn)                                     \
+    doop(optype) : {                                                  \
+        vm->ip++;                                                     \
+        LOG(#optype "\n");                                            \
+        debug_assert(vm->stack.length >= 2);                          \
+                                                                      \
+        struct value right = vm->stack.data[vm->stack.length - 1];    \
+        struct value left = vm->stack.data[vm->stack.length - 2];     \
+                                                                      \
+        struct value result = fn(left, right, vm);                    \
+        if (result.type == TYPE_INTERPRETER_ERROR) {                  \
+            ERROR(ERROR_##optype, 1);                                 \
+        }                                                             \
+        if (left.type == TYPE_INT && right.type == TYPE_INT)          \
+            vm->code.data[vm->ip - 1] = optype##_INT;                 \
+        else if (left.type == TYPE_FLOAT && right.type == TYPE_FLOAT) \
+            vm->code.data[vm->ip - 1] = optype##_FLOAT;               \
+        vm->stack.length -= 2;                                        \
+        array_push(vm->stack, result);                                \
+        dispatch();                                                   \
+    }
+    binop_quicken(OP_ADD, value_add)
+    binop_quicken(OP_SUB, value_sub)
+    binop_quicken(OP_MUL, value_mul)
     binop(OP_DIV, value_div)
     binop(OP_MOD, value_mod)
 
@@ -279,10 +377,10 @@ void vm_execute(struct vm *vm) {
     binop_inplace(OP_IMUL, ERROR_OP_MUL, value_imul, value_mul)
 
     // comparison
-    binop(OP_LT,  value_lt)
-    binop(OP_LEQ, value_leq)
-    binop(OP_GT,  value_gt)
-    binop(OP_GEQ, value_geq)
+    binop_quicken(OP_LT,  value_lt)
+    binop_quicken(OP_LEQ, value_leq)
+    binop_quicken(OP_GT,  value_gt)
+    binop_quicken(OP_GEQ, value_geq)
     binop(OP_EQ,  value_eq)
     binop(OP_NEQ, value_neq)
 
@@ -301,14 +399,12 @@ void vm_execute(struct vm *vm) {
             const struct dict *rhs = value_get_pointer(right);
             if (left.type == TYPE_DICT) {
                 if (rhs == vm->drec) {
-                    array_push(vm->stack, value_int(1));
+                    array_push(vm->stack, value_bool(true));
                 } else {
-                    array_push(vm->stack, value_int(dict_is_prototype_of(value_get_pointer(left), rhs)));
+                    array_push(vm->stack, value_bool(dict_is_prototype_of(value_get_pointer(left), rhs)));
                 }
-            } else if (value_get_prototype(vm, left) == rhs) {
-                array_push(vm->stack, value_int(1));
             } else {
-                array_push(vm->stack, value_int(0));
+                array_push(vm->stack, value_bool(value_get_prototype(vm, left) == rhs));
             }
         } else {
             ERROR(ERROR_EXPECTED_RECORD_OF_EXPR, 1);
@@ -400,20 +496,35 @@ void vm_execute(struct vm *vm) {
         dispatch();
     }
 
-    // pushes a function with [name], that begins at the next instruction pointer
+    // pushes a function that begins after the names of its arguments
     // to the stack and jumps to the [end address]
     doop(OP_DEF_FUNCTION_PUSH): {
-        // [opcode][end address]
+        // [opcode][nargs][end address][nrequired][rest][name1][name2]...
         vm->ip++;
         const uint16_t nargs = (uint16_t)(vm->code.data[vm->ip + 0] << 8 |
                                           vm->code.data[vm->ip + 1]);
         vm->ip += (uint32_t)sizeof(nargs);
+        const uint32_t end = vm->ip;
         const uint16_t pos = (uint16_t)(vm->code.data[vm->ip + 0] << 8 |
                                         vm->code.data[vm->ip + 1]);
-        LOG("DEF_FUNCTION_PUSH %d %d\n", pos, nargs);
-        array_push(vm->stack, value_function(vm->ip + (uint32_t)sizeof(pos), nargs, vm->localenv, vm));
+        vm->ip += (uint32_t)sizeof(pos);
+        const uint16_t nrequired = (uint16_t)(vm->code.data[vm->ip + 0] << 8 |
+                                              vm->code.data[vm->ip + 1]);
+        vm->ip += (uint32_t)sizeof(nrequired);
+        const bool rest = vm->code.data[vm->ip++] != 0;
+        const uint32_t params = vm->ip;
+        for (uint16_t i = 0; i < nargs; i++) {
+            vm->ip += (uint32_t)strlen((const char *)&vm->code.data[vm->ip]) + 1;
+        }
+        LOG("DEF_FUNCTION_PUSH %d %d %d %d\n", pos, nargs, nrequired, rest);
+        struct value val = value_function(vm->ip, nargs, vm->localenv, vm);
+        struct function *ifn = value_get_pointer(val);
+        ifn->nrequired = nrequired;
+        ifn->rest = rest;
+        ifn->params = params;
+        array_push(vm->stack, val);
 
-        vm->ip += pos;
+        vm->ip = end + pos;
         dispatch();
     }
 
@@ -468,6 +579,18 @@ void vm_execute(struct vm *vm) {
         }
         dispatch();
     }
+// once an exception has been caught, continues in its handler if it was
+// caught at the current native call depth, otherwise returns to the
+// native function it was raised in
+#define HANDLE_CAUGHT_EXCEPTION()                                                             \
+    do {                                                                                      \
+        if (vm->exframe_fallthrough != NULL) {                                                \
+            if (exframe_native_stack_depth(vm->exframe_fallthrough) != vm->native_call_depth) \
+                return;                                                                       \
+            vm->exframe_fallthrough = NULL;                                                   \
+            dispatch();                                                                       \
+        }                                                                                     \
+    } while (0)
     // pops a function/record constructor on top of the stack,
     // sets up necessary environment and calls it.
 #define CALL_NATIVE(expr)                                                                     \
@@ -475,12 +598,32 @@ void vm_execute(struct vm *vm) {
         vm->native_call_depth++;                                                              \
         expr(vm, nargs);                                                                      \
         vm->native_call_depth--;                                                              \
-        if ((vm->exframe_fallthrough != NULL &&                                               \
-             exframe_native_stack_depth(vm->exframe_fallthrough) != vm->native_call_depth) || \
-            vm->error != ERROR_NO_ERROR)                                                      \
+        if (vm->error != ERROR_NO_ERROR)                                                      \
             return;                                                                           \
+        HANDLE_CAUGHT_EXCEPTION();                                                            \
     } while (0)
-#define JMP_INTERPRETED_FN_(POP, UNWIND, END_IF_NATIVE)                                  \
+// the call stack is full and a StackOverflowError has been raised,
+// continues in its handler if it was caught
+#define STACK_OVERFLOW(UNWIND)                                                                \
+    do {                                                                                      \
+        if (vm->error != ERROR_NO_ERROR) {                                                    \
+            vm->ip -= (uint32_t)(UNWIND);                                                     \
+            return;                                                                           \
+        }                                                                                     \
+        HANDLE_CAUGHT_EXCEPTION();                                                            \
+    } while (0)
+// leaves one value per argument of the function on the stack, the positional
+// arguments given are on top of the stack followed by the keyword arguments
+#define BIND_ARGS(NARGS, UNWIND, KWNAMES, NKWARGS)                                   \
+    do {                                                                             \
+        if ((NKWARGS) != 0 || (NARGS) != ifn->nargs || ifn->nrequired != ifn->nargs) { \
+            if (!function_bind_args(ifn, NARGS, KWNAMES, NKWARGS, vm)) {             \
+                vm->ip -= (uint32_t)(UNWIND);                                        \
+                return;                                                              \
+            }                                                                        \
+        }                                                                            \
+    } while (0)
+#define JMP_INTERPRETED_FN_(POP, UNWIND, KWNAMES, NKWARGS, END_IF_NATIVE)               \
     do {                                                                                 \
         if (val.type == TYPE_DICT) {                                                     \
             do {                                                                         \
@@ -493,6 +636,9 @@ void vm_execute(struct vm *vm) {
             const struct value ctor = *pctor;                                            \
             switch (ctor.type) {                                                         \
                 case TYPE_NATIVE_FN: {                                                   \
+                    if ((NKWARGS) != 0) {                                                \
+                        ERROR(ERROR_UNEXPECTED_KEYWORD_ARGUMENT, UNWIND);                \
+                    }                                                                    \
                     CALL_NATIVE(((value_fn)value_get_pointer(ctor)));                    \
                     do {                                                                 \
                         END_IF_NATIVE                                                    \
@@ -501,12 +647,10 @@ void vm_execute(struct vm *vm) {
                 }                                                                        \
                 case TYPE_FN: {                                                          \
                     ifn = value_get_pointer(ctor);                                       \
-                    if (nargs + 1 != ifn->nargs) {                                       \
-                        ERROR_EXPECT(ERROR_MISMATCH_ARGUMENTS, ifn->nargs, UNWIND);      \
-                    }                                                                    \
                     struct value new_val = value_dict(vm);                               \
                     dict_set(value_get_pointer(new_val), "prototype", val);              \
                     array_push(vm->stack, new_val);                                      \
+                    BIND_ARGS(nargs + 1, UNWIND, KWNAMES, NKWARGS);                      \
                     break;                                                               \
                 }                                                                        \
                 default:                                                                 \
@@ -518,46 +662,58 @@ void vm_execute(struct vm *vm) {
             } while (0);                                                                 \
             ifn = value_get_pointer(val);                                                \
             LOG("= %d %d\n", ifn->nargs, nargs);                                         \
-            if (nargs != ifn->nargs) {                                                   \
-                ERROR_EXPECT(ERROR_MISMATCH_ARGUMENTS, ifn->nargs, UNWIND);              \
-            }                                                                            \
+            BIND_ARGS(nargs, UNWIND, KWNAMES, NKWARGS);                                  \
         }                                                                                \
     } while (0)
-#define JMP_INTERPRETED_FN(UNWIND, END_IF_NATIVE) JMP_INTERPRETED_FN_(array_pop(vm->stack);, UNWIND, END_IF_NATIVE)
-#define JMP_INTERPRETED_FN_NO_POP(UNWIND, END_IF_NATIVE) JMP_INTERPRETED_FN_(do{}while(0);, UNWIND, END_IF_NATIVE)
-    doop(OP_CALL) : {
+#define JMP_INTERPRETED_FN(UNWIND, END_IF_NATIVE) JMP_INTERPRETED_FN_(array_pop(vm->stack);, UNWIND, NULL, 0, END_IF_NATIVE)
+#define JMP_INTERPRETED_FN_NO_POP(UNWIND, END_IF_NATIVE) JMP_INTERPRETED_FN_(do{}while(0);, UNWIND, NULL, 0, END_IF_NATIVE)
+    doop(OP_CALL):
+    doop(OP_CALL_KW): {
         // argument: [arg2][arg1]
-        vm->ip++;
+        // with keyword arguments: [kwarg2][kwarg1][arg2][arg1]
+        const uint32_t start = vm->ip;
+        const enum vm_opcode op = vm->code.data[vm->ip++];
         struct value val = array_top(vm->stack);
         const uint16_t nargs = (uint16_t)(vm->code.data[vm->ip+0] << 8 |
                                           vm->code.data[vm->ip+1]);
         vm->ip += (uint32_t)sizeof(nargs);
-        debug_assert(vm->stack.length >= nargs);
-        LOG("call %d\n", nargs);
+        uint16_t nkwargs = 0;
+        const char *kwnames = NULL;
+        if (op == OP_CALL_KW) {
+            // [nkwargs][name1][name2]...
+            nkwargs = (uint16_t)(vm->code.data[vm->ip+0] << 8 |
+                                 vm->code.data[vm->ip+1]);
+            vm->ip += (uint32_t)sizeof(nkwargs);
+            kwnames = (const char *)&vm->code.data[vm->ip];
+            for (uint16_t i = 0; i < nkwargs; i++) {
+                vm->ip += (uint32_t)strlen((const char *)&vm->code.data[vm->ip]) + 1;
+            }
+        }
+        const uint32_t size = vm->ip - start;
+        debug_assert(vm->stack.length >= nargs + nkwargs);
+        LOG("call %d %d\n", nargs, nkwargs);
         switch(val.type) {
         case TYPE_NATIVE_FN: {
+            if (nkwargs != 0) {
+                ERROR(ERROR_UNEXPECTED_KEYWORD_ARGUMENT, size);
+            }
             array_pop(vm->stack);
             CALL_NATIVE(((value_fn)(value_get_pointer(val))));
             break; }
         case TYPE_FN:
         case TYPE_DICT: {
             struct function *ifn;
-            JMP_INTERPRETED_FN(1 + sizeof(nargs), {
-                if (vm->exframe_fallthrough != NULL) {
-                    if (exframe_native_stack_depth(vm->exframe_fallthrough) == vm->native_call_depth) {
-                        dispatch();
-                    } else {
-                        return;
-                    }
-                }
+            JMP_INTERPRETED_FN_(array_pop(vm->stack);, size, kwnames, nkwargs, {
                 dispatch();
             });
 
             // caller
-            vm_enter_env(vm, ifn);
+            if (vm_enter_env(vm, ifn) == NULL) {
+                STACK_OVERFLOW(size);
+            }
             break; }
         default: {
-            ERROR(ERROR_EXPECTED_CALLABLE, 1 + sizeof(nargs)); }
+            ERROR(ERROR_EXPECTED_CALLABLE, size); }
         }
         dispatch();
     }
@@ -577,12 +733,21 @@ void vm_execute(struct vm *vm) {
         vm->ip++;
         assert(0);
     }
+    // x["key"] is x.key unless x is a map, in which case it gets the key's entry
+    doop(OP_MEMBER_INDEX_GET):
+    doop(OP_MEMBER_INDEX_GET_NO_POP):
     doop(OP_MEMBER_GET):
     doop(OP_MEMBER_GET_NO_POP): {
-        const enum vm_opcode op = vm->code.data[vm->ip];
+        enum vm_opcode op = vm->code.data[vm->ip];
+        const bool indexed = op == OP_MEMBER_INDEX_GET || op == OP_MEMBER_INDEX_GET_NO_POP;
+        if (op == OP_MEMBER_INDEX_GET) {
+            op = OP_MEMBER_GET;
+        }
+        const uint32_t ip = vm->ip;
         vm->ip++;
         const char *key = (const char *)&vm->code.data[vm->ip]; // must be null terminated
-        vm->ip += (uint32_t)strlen(key) + 1;
+        const size_t len = strlen(key);
+        vm->ip += (uint32_t)len + 1;
         LOG(op == OP_MEMBER_GET ? "MEMBER_GET %s\n" : "MEMBER_GET_NO_POP %s\n", key);
 
         const struct value val = array_top(vm->stack);
@@ -603,13 +768,22 @@ void vm_execute(struct vm *vm) {
             if(op == OP_MEMBER_GET) {
                 array_pop(vm->stack);
             }
+            if (indexed && dict_is_map(dict)) {
+                const struct value *result = map_get_str(dict, key);
+                if (result == NULL) {
+                    ERROR(ERROR_UNKNOWN_KEY, len+1);
+                }
+                array_push(vm->stack, *result);
+                dispatch();
+            }
         }
 
-        const struct value *result = dict_get(dict, key);
+        // cached by instruction, see inline_cache.rs
+        const struct value *result = vm_member_get(vm, dict, key, len, ip);
         if(result != NULL) {
             array_push(vm->stack, *result);
         } else {
-            ERROR(ERROR_UNKNOWN_KEY, strlen(key)+1);
+            ERROR(ERROR_UNKNOWN_KEY, len+1);
         }
         dispatch();
     }
@@ -626,6 +800,7 @@ void vm_execute(struct vm *vm) {
         array_pop(vm->stack);
 
         struct value val = array_top(vm->stack);
+        gc_write_barrier(value_get_pointer(dval));
         dict_set(value_get_pointer(dval), key, val);
         dispatch();
     }
@@ -677,8 +852,12 @@ void vm_execute(struct vm *vm) {
                 if (index.type != TYPE_INT) {
                     ERROR(ERROR_KEY_NON_INT, 1);
                 }
-                const int32_t i = (int32_t)value_get_int(index);
-                if (!(i >= 0 && i < (int32_t)array->length)) {
+                int64_t i = value_get_int(index);
+                // negative indices count from the end
+                if (i < 0) {
+                    i += (int64_t)array->length;
+                }
+                if (!(i >= 0 && i < (int64_t)array->length)) {
                     ERROR_EXPECT(ERROR_UNBOUNDED_ACCESS, 1, array->length);
                 }
                 array_push(vm->stack, array->data[i]);
@@ -688,8 +867,11 @@ void vm_execute(struct vm *vm) {
                 if (index.type != TYPE_INT) {
                     ERROR(ERROR_KEY_NON_INT, 1);
                 }
-                const int32_t i = (int32_t)value_get_int(index);
-                struct string *s = string_at(value_get_pointer(dval), i, vm);
+                int64_t i = value_get_int(index);
+                if (i < 0) {
+                    i += (int64_t)string_len(value_get_pointer(dval));
+                }
+                struct string *s = i < 0 ? NULL : string_at(value_get_pointer(dval), i, vm);
                 if (s == NULL) {
                     ERROR_EXPECT(ERROR_UNBOUNDED_ACCESS, 1, string_len(value_get_pointer(dval)));
                 }
@@ -697,6 +879,14 @@ void vm_execute(struct vm *vm) {
                 break;
             }
             case TYPE_DICT: {
+                if (dict_is_map(value_get_pointer(dval))) {
+                    const struct value *val = map_get(value_get_pointer(dval), index);
+                    if (val == NULL) {
+                        ERROR(ERROR_UNKNOWN_KEY, 1);
+                    }
+                    array_push(vm->stack, *val);
+                    break;
+                }
                 if (index.type != TYPE_STR) {
                     ERROR(ERROR_RECORD_KEY_NON_STRING, 1);
                 }
@@ -729,15 +919,26 @@ void vm_execute(struct vm *vm) {
                 if (index.type != TYPE_INT) {
                     ERROR(ERROR_KEY_NON_INT, 1);
                 }
-                const int64_t i = value_get_int(index);
+                int64_t i = value_get_int(index);
                 array_obj *array = value_get_pointer(dval);
+                if (i < 0) {
+                    i += (int64_t)array->length;
+                }
                 if (!(i >= 0 && i < (int64_t)array->length)) {
-                    ERROR_EXPECT(ERROR_UNBOUNDED_ACCESS, array->length, 1);
+                    ERROR_EXPECT(ERROR_UNBOUNDED_ACCESS, 1, array->length);
                 }
+                gc_write_barrier(array);
                 array->data[i] = val;
                 break;
             }
             case TYPE_DICT: {
+                gc_write_barrier(value_get_pointer(dval));
+                if (dict_is_map(value_get_pointer(dval))) {
+                    if (!map_set(value_get_pointer(dval), index, val)) {
+                        ERROR(ERROR_MAP_KEY_UNHASHABLE, 1);
+                    }
+                    break;
+                }
                 if (index.type != TYPE_STR) {
                     ERROR(ERROR_RECORD_KEY_NON_STRING, 1);
                 }
@@ -799,16 +1000,9 @@ void vm_execute(struct vm *vm) {
         LOG("RAISE\n");
         if(!vm_raise(vm)) {
             vm->error = ERROR_UNHANDLED_EXCEPTION;
-            if (vm->exframe_fallthrough != NULL || vm->native_call_depth != 0) {
-                LOG("falling through pls wait (%ld)\n", vm->native_call_depth);
-                return;
-            }
-            return;
-        }
-        if (vm->exframe_fallthrough != NULL || vm->native_call_depth != 0) {
-            LOG("falling through pls wait (%ld)\n", vm->native_call_depth);
             return;
         }
+        HANDLE_CAUGHT_EXCEPTION();
         dispatch();
     }
     doop(OP_EXFRAME_RET): {
@@ -877,22 +1071,35 @@ void vm_execute(struct vm *vm) {
             case TYPE_DICT: {                                                                               \
                 struct function *ifn;                                                                       \
                 JMP_INTERPRETED_FN_NO_POP(1 + sizeof(pos), {                                                \
-                    if (vm->exframe_fallthrough != NULL) {                                                  \
-                        if (exframe_native_stack_depth(vm->exframe_fallthrough) == vm->native_call_depth) { \
-                            dispatch();                                                                     \
-                        } else {                                                                            \
-                            return;                                                                         \
-                        }                                                                                   \
-                    }                                                                                       \
                     dispatch();                                                                             \
                 });                                                                                         \
-                vm_enter_env(vm, ifn);                                                                      \
+                if (vm_enter_env(vm, ifn) == NULL) {                                                        \
+                    STACK_OVERFLOW(1 + sizeof(pos));                                                        \
+                }                                                                                           \
                 dispatch();                                                                                 \
             }                                                                                               \
             default:                                                                                        \
                 ERROR(ERROR_EXPECTED_CALLABLE, 1 + sizeof(pos));                                            \
         }                                                                                                   \
     } while (0);
+// generators are resumed in the current stack frame, returning
+// to the loop's body when they yield and past the loop when they return
+#define RESUME_GENERATOR                                       \
+    do {                                                       \
+        const uint32_t end = vm->ip + pos;                     \
+        vm->ip += (uint32_t)sizeof(pos);                       \
+        if (vm_resume_generator(vm, dict, end)) {              \
+            HANDLE_CAUGHT_EXCEPTION();                         \
+            dispatch();                                        \
+        }                                                      \
+        if (vm->error != ERROR_NO_ERROR) {                     \
+            ERROR(vm->error, 1 + sizeof(pos));                 \
+        }                                                      \
+        array_pop(vm->stack); /* iterator */                   \
+        array_pop(vm->stack); /* generator */                  \
+        vm->ip = end;                                          \
+        dispatch();                                            \
+    } while (0);
         vm->ip++;
         const uint16_t pos = (uint16_t)(vm->code.data[vm->ip + 0] << 8 |
                                         vm->code.data[vm->ip + 1]);
@@ -922,8 +1129,24 @@ void vm_execute(struct vm *vm) {
             }
             case TYPE_DICT: {
                 struct dict *dict = value_get_pointer(top);
-                const struct value *pval = dict_get(dict, "next");
+                if (dict_is_map(dict) || dict_is_set(dict)) {
+                    // maps and sets are iterated over a copy of their keys
+                    array_obj *keys = dict_is_map(dict) ? map_keys(dict, vm) : set_elements(dict, vm);
+                    array_pop(vm->stack);
+                    if (keys->length == 0) {  // skip empty
+                        vm->ip += pos;
+                        dispatch();
+                    }
+                    array_push(vm->stack, value_pointer(TYPE_ARRAY, keys));
+                    array_push(vm->stack, value_pointer(TYPE_INTERPRETER_ITERATOR, (void *)1));
+                    array_push(vm->stack, keys->data[0]);
+                    break;
+                }
                 array_push(vm->stack, value_pointer(TYPE_INTERPRETER_ITERATOR, (void *)0));
+                if (dict_is_generator(dict)) {
+                    RESUME_GENERATOR
+                }
+                const struct value *pval = dict_get(dict, "next");
                 vm->ip += (uint32_t)sizeof(pos);
                 array_push(vm->stack, top);  // pass arg
                 CALL_DICT_ITERATOR_FN
@@ -958,13 +1181,16 @@ void vm_execute(struct vm *vm) {
                     }
                     case TYPE_DICT: {
                         LOG("CONTINUE\n");
-                        const struct dict *dict = value_get_pointer(iterator);
+                        struct dict *dict = value_get_pointer(iterator);
                         if (dict_get(dict, "stopped") != NULL) {
                             array_pop(vm->stack); /* iterator */
                             array_pop(vm->stack); /* record */
                             vm->ip += pos;
                             dispatch();
                         }
+                        if (dict_is_generator(dict)) {
+                            RESUME_GENERATOR
+                        }
 
                         const struct value *pval = dict_get(dict, "next");
                         vm->ip += (uint32_t)sizeof(pos);
@@ -985,6 +1211,7 @@ void vm_execute(struct vm *vm) {
         vm->ip += (uint32_t)sizeof(pos);
         dispatch();
 #undef CALL_DICT_ITERATOR_FN
+#undef RESUME_GENERATOR
     }
 
     doop(OP_SWAP): {
@@ -1004,8 +1231,381 @@ void vm_execute(struct vm *vm) {
         LOG("USE %s\n", str);
         vm->ip += (uint32_t)strlen(str) + 1;
         vm_load_module(vm, str);
+        if (vm->error != ERROR_NO_ERROR) {
+            // unhandled ImportError
+            return;
+        }
+        HANDLE_CAUGHT_EXCEPTION();
+        dispatch();
+    }
+    // pushes a record of the module's exports
+    doop(OP_USE_AS): {
+        vm->ip++;
+        char *str = (char *)&vm->code.data[vm->ip]; // must be null terminated
+        LOG("USE_AS %s\n", str);
+        vm->ip += (uint32_t)strlen(str) + 1;
+        vm_load_module_as(vm, str);
+        if (vm->error != ERROR_NO_ERROR) {
+            // unhandled ImportError or error inside of module
+            return;
+        }
+        HANDLE_CAUGHT_EXCEPTION();
+        dispatch();
+    }
+
+    // match expressions
+    // the value being matched stays on top of the stack while it's tested,
+    // the tests jump to the label after the opcode if it doesn't match
+    doop(OP_DUP): {
+        vm->ip++;
+        LOG("DUP\n");
+        debug_assert(vm->stack.length >= 1);
+        array_push(vm->stack, array_top(vm->stack));
+        dispatch();
+    }
+    doop(OP_MATCH_EQ): { // match_eq [16-bit position]
+        vm->ip++;
+        const int16_t pos = (int16_t)(vm->code.data[vm->ip + 0] << 8 |
+                                      vm->code.data[vm->ip + 1]);
+        LOG("MATCH_EQ %d\n", pos);
+        debug_assert(vm->stack.length >= 2);
+        const struct value literal = array_top(vm->stack);
+        array_pop(vm->stack);
+        // values that can't be compared with the literal don't match it
+        const struct value result = value_eq(array_top(vm->stack), literal, vm);
+        if (result.type != TYPE_INTERPRETER_ERROR && value_is_true(result)) {
+            vm->ip += (uint32_t)sizeof(pos);
+        } else {
+            vm->ip += pos;
+        }
+        dispatch();
+    }
+    doop(OP_MATCH_ARRAY): { // match_array [16-bit position] [16-bit length] [8-bit has rest]
+        vm->ip++;
+        const int16_t pos = (int16_t)(vm->code.data[vm->ip + 0] << 8 |
+                                      vm->code.data[vm->ip + 1]);
+        const uint16_t length = (uint16_t)(vm->code.data[vm->ip + 2] << 8 |
+                                           vm->code.data[vm->ip + 3]);
+        const bool has_rest = vm->code.data[vm->ip + 4] != 0;
+        LOG("MATCH_ARRAY %d %d %d\n", pos, length, has_rest);
+        const struct value val = array_top(vm->stack);
+        if (val.type == TYPE_ARRAY) {
+            const array_obj *array = value_get_pointer(val);
+            if (has_rest ? array->length >= length : array->length == length) {
+                vm->ip += 5;
+                dispatch();
+            }
+        }
+        vm->ip += pos;
+        dispatch();
+    }
+    doop(OP_MATCH_INDEX): { // match_index [16-bit signed index]
+        // pushes the element of the array at the index, counting
+        // from the end if it's negative
+        vm->ip++;
+        const int16_t index = (int16_t)(vm->code.data[vm->ip + 0] << 8 |
+                                        vm->code.data[vm->ip + 1]);
+        vm->ip += (uint32_t)sizeof(index);
+        LOG("MATCH_INDEX %d\n", index);
+        const array_obj *array = value_get_pointer(array_top(vm->stack));
+        const size_t i = index < 0 ? array->length - (size_t)(-index) : (size_t)index;
+        debug_assert(i < array->length);
+        array_push(vm->stack, array->data[i]);
+        dispatch();
+    }
+    doop(OP_MATCH_REST): { // match_rest [16-bit start] [16-bit elements after]
+        // pushes a new array of the elements between the ones matched
+        vm->ip++;
+        const uint16_t start = (uint16_t)(vm->code.data[vm->ip + 0] << 8 |
+                                          vm->code.data[vm->ip + 1]);
+        const uint16_t after = (uint16_t)(vm->code.data[vm->ip + 2] << 8 |
+                                          vm->code.data[vm->ip + 3]);
+        vm->ip += 4;
+        LOG("MATCH_REST %d %d\n", start, after);
+        const array_obj *array = value_get_pointer(array_top(vm->stack));
+        const size_t length = array->length - start - after;
+        struct value rval = value_array_n(length, vm);
+        array_obj *rest = value_get_pointer(rval);
+        rest->length = length;
+        for (size_t i = 0; i < length; i++) {
+            rest->data[i] = array->data[start + i];
+        }
+        array_push(vm->stack, rval);
+        dispatch();
+    }
+    doop(OP_MATCH_MEMBER): { // match_member [16-bit position] [key]
+        // pushes the value of the record's key
+        vm->ip++;
+        const int16_t pos = (int16_t)(vm->code.data[vm->ip + 0] << 8 |
+                                      vm->code.data[vm->ip + 1]);
+        const char *key = (const char *)&vm->code.data[vm->ip + 2]; // must be null terminated
+        LOG("MATCH_MEMBER %d %s\n", pos, key);
+        const struct value val = array_top(vm->stack);
+        if (val.type == TYPE_DICT) {
+            const struct value *result = dict_get(value_get_pointer(val), key);
+            if (result != NULL) {
+                vm->ip += 2 + (uint32_t)strlen(key) + 1;
+                array_push(vm->stack, *result);
+                dispatch();
+            }
+        }
+        vm->ip += pos;
+        dispatch();
+    }
+
+    // strings
+    doop(OP_STR_CONCAT): { // str_concat [16-bit count]
+        // pops the values, pushing a new string of them converted
+        // to strings and joined together
+        vm->ip++;
+        const uint16_t n = (uint16_t)(vm->code.data[vm->ip + 0] << 8 |
+                                      vm->code.data[vm->ip + 1]);
+        vm->ip += (uint32_t)sizeof(n);
+        LOG("STR_CONCAT %d\n", n);
+        debug_assert(vm->stack.length >= n);
+        struct string *s = string_concat(&vm->stack.data[vm->stack.length - n], n, vm);
+        vm->stack.length -= n;
+        array_push(vm->stack, value_pointer(TYPE_STR, s));
+        dispatch();
+    }
+
+    // function arguments
+    doop(OP_JARG): { // jarg [16-bit slot] [16-bit position]
+        // skips the code computing an argument's default value
+        // if the argument was given
+        vm->ip++;
+        const uint16_t n = (uint16_t)(vm->code.data[vm->ip + 0] << 8 |
+                                      vm->code.data[vm->ip + 1]);
+        vm->ip += (uint32_t)sizeof(n);
+        const int16_t pos = (int16_t)(vm->code.data[vm->ip + 0] << 8 |
+                                      vm->code.data[vm->ip + 1]);
+        LOG("JARG %d %d\n", n, pos);
+        if (env_get(vm->localenv, n).type != TYPE_INTERPRETER_UNSET) {
+            vm->ip += pos;
+        } else {
+            vm->ip += (uint32_t)sizeof(pos);
+        }
+        dispatch();
+    }
+
+    // destructuring
+    doop(OP_MATCH_FAIL): {
+        // the value on top of the stack doesn't match the pattern
+        // it's being destructured with
+        LOG("MATCH_FAIL\n");
+        ERROR(ERROR_PATTERN_MISMATCH, 0);
+    }
+
+    // slices
+    doop(OP_SLICE_GET): {
+        // stack: [value][from][to][step], missing bounds are nil
+        vm->ip++;
+        LOG("SLICE_GET\n");
+        const struct value step = array_top(vm->stack);
+        array_pop(vm->stack);
+        const struct value to = array_top(vm->stack);
+        array_pop(vm->stack);
+        const struct value from = array_top(vm->stack);
+        array_pop(vm->stack);
+        const struct value dval = array_top(vm->stack);
+        array_pop(vm->stack);
+
+        int64_t start, s;
+        size_t n;
+        enum vm_error error;
+        switch (dval.type) {
+            case TYPE_ARRAY: {
+                const array_obj *array = value_get_pointer(dval);
+                if ((error = slice_resolve(from, to, step, (int64_t)array->length, &start, &s, &n)) != ERROR_NO_ERROR) {
+                    ERROR(error, 1);
+                }
+                array_push(vm->stack, value_pointer(TYPE_ARRAY, array_obj_slice(array, start, s, n, vm)));
+                break;
+            }
+            case TYPE_STR: {
+                struct string *str = value_get_pointer(dval);
+                if ((error = slice_resolve(from, to, step, (int64_t)string_len(str), &start, &s, &n)) != ERROR_NO_ERROR) {
+                    ERROR(error, 1);
+                }
+                array_push(vm->stack, value_pointer(TYPE_STR, string_slice(str, start, s, n, vm)));
+                break;
+            }
+            default:
+                ERROR(ERROR_CANNOT_ACCESS_NON_RECORD, 1);
+        }
+        dispatch();
+    }
+    doop(OP_SLICE_SET): {
+        // stack: [assigned value][array][from][to][step]
+        vm->ip++;
+        LOG("SLICE_SET\n");
+        const struct value step = array_top(vm->stack);
+        array_pop(vm->stack);
+        const struct value to = array_top(vm->stack);
+        array_pop(vm->stack);
+        const struct value from = array_top(vm->stack);
+        array_pop(vm->stack);
+        const struct value dval = array_top(vm->stack);
+        array_pop(vm->stack);
+
+        const struct value val = array_top(vm->stack);
+        if (dval.type != TYPE_ARRAY) {
+            ERROR(ERROR_EXPECTED_RECORD_ARRAY, 1);
+        }
+        array_obj *array = value_get_pointer(dval);
+        int64_t start, s;
+        size_t n;
+        const enum vm_error error = slice_resolve(from, to, step, (int64_t)array->length, &start, &s, &n);
+        if (error != ERROR_NO_ERROR) {
+            ERROR(error, 1);
+        }
+        gc_write_barrier(array);
+        if (val.type != TYPE_ARRAY || !array_obj_splice(array, start, s, n, value_get_pointer(val))) {
+            ERROR_EXPECT(ERROR_SLICE_ASSIGN_MISMATCH, 1, n);
+        }
+        dispatch();
+    }
+
+    // generators
+    // replaces OP_ENV_NEW in functions that yield, the stack frame is
+    // suspended into a generator record which is returned to the caller
+    doop(OP_GENERATOR_NEW): {
+        vm->ip++;
+        const uint16_t n = (uint16_t)(vm->code.data[vm->ip + 0] << 8 |
+                                      vm->code.data[vm->ip + 1]);
+        vm->ip += (uint32_t)sizeof(n);
+        LOG("GENERATOR_NEW %d\n", n);
+        env_init(vm->localenv, n, vm);
+        if (vm_generator_new(vm)) {
+            LOG("return from vm_call\n");
+            return;
+        }
+        dispatch();
+    }
+    doop(OP_YIELD): {
+        vm->ip++;
+        LOG("YIELD\n");
+        if (vm_yield(vm)) {
+            LOG("return from vm_call\n");
+            return;
+        }
+        dispatch();
+    }
+
+    // push integer literals too large for 64 bits on to the stack
+    doop(OP_PUSH_BIGINT): {
+        vm->ip++;
+        char *str = (char *)&vm->code.data[vm->ip]; // must be null terminated
+        vm->ip += (uint32_t)strlen(str) + 1;
+        LOG("PUSH_BIGINT %s\n", str);
+        array_push(vm->stack, bigint_from_str(str, vm));
         dispatch();
     }
+    // maps
+    doop(OP_MAP_LOAD): {
+        // stack: [key][value]...[length]
+        vm->ip++;
+
+        const size_t length = (size_t)value_get_int(array_top(vm->stack));
+        array_pop(vm->stack);
+        LOG("MAP_LOAD %ld\n", length);
+
+        struct dict *map = map_malloc(vm);
+        // entries are inserted in the order they were written
+        const struct value *entries = &vm->stack.data[vm->stack.length - 2 * length];
+        for (size_t i = 0; i < length; i++) {
+            if (!map_set(map, entries[2 * i], entries[2 * i + 1])) {
+                ERROR(ERROR_MAP_KEY_UNHASHABLE, 1);
+            }
+        }
+        vm->stack.length -= 2 * length;
+
+        array_push(vm->stack, value_pointer(TYPE_DICT, map));
+        dispatch();
+    }
+
+    // superinstructions
+    // these are fused by the bytecode optimizer
+    doop(OP_SET_LOCAL_POP): { // set_local + pop
+        vm->ip++;
+        const uint16_t n = (uint16_t)(vm->code.data[vm->ip + 0] << 8 |
+                                      vm->code.data[vm->ip + 1]);
+        vm->ip += (uint32_t)sizeof(n);
+        LOG("SET LOCAL POP %d\n", n);
+        env_set(vm->localenv, n, array_top(vm->stack));
+        array_pop(vm->stack);
+        dispatch();
+    }
+    doop(OP_SET_GLOBAL_POP): { // set_global + pop
+        vm->ip++;
+        char *key = (char *)&vm->code.data[vm->ip]; // must be null terminated
+        vm->ip += (uint32_t)strlen(key) + 1;
+        LOG("SET GLOBAL POP %s\n", key);
+        hmap_set(vm->globalenv, key, array_top(vm->stack));
+        array_pop(vm->stack);
+        dispatch();
+    }
+
+    // quickened ops
+    // these skip checking the operands' types on the generic op. When the
+    // operands aren't of the expected types (or an int op overflows), they
+    // are deoptimized back into the generic op, which handles them instead
+#define deoptimize(generic)              \
+    do {                                 \
+        vm->code.data[vm->ip] = generic; \
+        goto doop(generic);              \
+    } while (0)
+#define quickened_int_arith(optype, generic, checked)                    \
+    doop(optype) : {                                                     \
+        LOG(#optype "\n");                                               \
+        debug_assert(vm->stack.length >= 2);                             \
+                                                                         \
+        struct value right = vm->stack.data[vm->stack.length - 1];       \
+        struct value left = vm->stack.data[vm->stack.length - 2];        \
+        int64_t result;                                                  \
+        if (left.type != TYPE_INT || right.type != TYPE_INT ||           \
+            checked(value_get_int(left), value_get_int(right), &result)) \
+            deoptimize(generic);                                         \
+        vm->ip++;                                                        \
+        vm->stack.length--;                                              \
+        vm->stack.data[vm->stack.length - 1] = value_int(result);        \
+        dispatch();                                                      \
+    }
+#define quickened_op(optype, generic, vtype, result)               \
+    doop(optype) : {                                               \
+        LOG(#optype "\n");                                         \
+        debug_assert(vm->stack.length >= 2);                       \
+                                                                   \
+        struct value right = vm->stack.data[vm->stack.length - 1]; \
+        struct value left = vm->stack.data[vm->stack.length - 2];  \
+        if (left.type != vtype || right.type != vtype)             \
+            deoptimize(generic);                                   \
+        vm->ip++;                                                  \
+        vm->stack.length--;                                        \
+        vm->stack.data[vm->stack.length - 1] = result;             \
+        dispatch();                                                \
+    }
+#define quickened_float_arith(optype, generic, op) \
+    quickened_op(optype, generic, TYPE_FLOAT,      \
+                 value_float(value_get_float(left) op value_get_float(right)))
+#define quickened_cmp(optype, generic, vtype, get, op) \
+    quickened_op(optype, generic, vtype, value_bool(get(left) op get(right)))
+
+    quickened_int_arith(OP_ADD_INT, OP_ADD, __builtin_add_overflow)
+    quickened_int_arith(OP_SUB_INT, OP_SUB, __builtin_sub_overflow)
+    quickened_int_arith(OP_MUL_INT, OP_MUL, __builtin_mul_overflow)
+    quickened_float_arith(OP_ADD_FLOAT, OP_ADD, +)
+    quickened_float_arith(OP_SUB_FLOAT, OP_SUB, -)
+    quickened_float_arith(OP_MUL_FLOAT, OP_MUL, *)
+
+    quickened_cmp(OP_LT_INT,    OP_LT,  TYPE_INT,   value_get_int,   <)
+    quickened_cmp(OP_LT_FLOAT,  OP_LT,  TYPE_FLOAT, value_get_float, <)
+    quickened_cmp(OP_LEQ_INT,   OP_LEQ, TYPE_INT,   value_get_int,   <=)
+    quickened_cmp(OP_LEQ_FLOAT, OP_LEQ, TYPE_FLOAT, value_get_float, <=)
+    quickened_cmp(OP_GT_INT,    OP_GT,  TYPE_INT,   value_get_int,   >)
+    quickened_cmp(OP_GT_FLOAT,  OP_GT,  TYPE_FLOAT, value_get_float, >)
+    quickened_cmp(OP_GEQ_INT,   OP_GEQ, TYPE_INT,   value_get_int,   >=)
+    quickened_cmp(OP_GEQ_FLOAT, OP_GEQ, TYPE_FLOAT, value_get_float, >=)
 }
 
 struct value vm_call(struct vm *vm, const struct value fn, const a_value *args) {
@@ -1049,21 +1649,28 @@ struct value vm_call(struct vm *vm, const struct value fn, const a_value *args)
         return value_interpreter_error();
     }
 
-    if((uint32_t)args->length != ifn->nargs) {
-        vm->ip = ifn->ip;
-        vm->error = ERROR_MISMATCH_ARGUMENTS;
-        return value_interpreter_error();
+    // setup stack
+    LOG("%ld\n", args->length);
+    for (size_t i = (size_t)args->length; i-- > 0;) {
+        array_push(vm->stack, args->data[i]);
+    }
+    if (nargs != ifn->nargs || ifn->nrequired != ifn->nargs) {
+        if (!function_bind_args(ifn, nargs, NULL, 0, vm)) {
+            vm->stack.length -= nargs;
+            vm->ip = ifn->ip;
+            return value_interpreter_error();
+        }
     }
 
     const uint32_t last = vm->ip;
     // setup env
     struct env *oldenv = vm->localenv;
+    struct hmap *oldglobalenv = vm->globalenv;
     vm->ip = (uint32_t)-1;
     struct env *curenv = vm_enter_env(vm, ifn);
-    // setup stack/ip
-    LOG("%ld\n", args->length);
-    for (size_t i = (size_t)args->length; i-- > 0;) {
-        array_push(vm->stack, args->data[i]);
+    if (curenv == NULL) { // StackOverflowError
+        if (vm->error) vm->ip = last;
+        return value_interpreter_error();
     }
     // call it
     vm_execute(vm);
@@ -1079,6 +1686,7 @@ struct value vm_call(struct vm *vm, const struct value fn, const a_value *args)
     LOG("vm_call complete\n");
     env_free(curenv);
     vm->localenv = oldenv;
+    vm->globalenv = oldglobalenv;
     vm->ip = last;
 
     const struct value val = array_top(vm->stack);
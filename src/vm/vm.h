@@ -47,7 +47,30 @@ enum vm_opcode {
     // iterators
     OP_FOR_IN, OP_SWAP,
     // modules
-    OP_USE,
+    OP_USE, OP_USE_AS,
+    // match expressions
+    OP_DUP,
+    OP_MATCH_EQ, OP_MATCH_ARRAY, OP_MATCH_INDEX, OP_MATCH_REST, OP_MATCH_MEMBER,
+    // strings
+    OP_STR_CONCAT,
+    // function arguments
+    OP_CALL_KW, OP_JARG,
+    // destructuring
+    OP_MATCH_FAIL,
+    // slices
+    OP_SLICE_GET, OP_SLICE_SET,
+    // generators
+    OP_GENERATOR_NEW, OP_YIELD,
+    // big integers
+    OP_PUSH_BIGINT,
+    // maps
+    OP_MAP_LOAD, OP_MEMBER_INDEX_GET, OP_MEMBER_INDEX_GET_NO_POP,
+    // superinstructions
+    OP_SET_LOCAL_POP, OP_SET_GLOBAL_POP,
+    // quickened
+    OP_ADD_INT, OP_ADD_FLOAT, OP_SUB_INT, OP_SUB_FLOAT, OP_MUL_INT, OP_MUL_FLOAT,
+    OP_LT_INT, OP_LT_FLOAT, OP_LEQ_INT, OP_LEQ_FLOAT,
+    OP_GT_INT, OP_GT_FLOAT, OP_GEQ_INT, OP_GEQ_FLOAT,
 };
 
 enum vm_error {
@@ -82,7 +105,15 @@ enum vm_error {
     ERROR_UNHANDLED_EXCEPTION,
     ERROR_EXPECTED_ITERABLE,
     ERROR_EXPECTED_RECORD_OF_EXPR,
-    ERROR_UNKNOWN_KEY
+    ERROR_UNKNOWN_KEY,
+    ERROR_UNEXPECTED_KEYWORD_ARGUMENT,
+    ERROR_TOO_FEW_ARGUMENTS,
+    ERROR_TOO_MANY_ARGUMENTS,
+    ERROR_PATTERN_MISMATCH,
+    ERROR_SLICE_ZERO_STEP,
+    ERROR_SLICE_ASSIGN_MISMATCH,
+    ERROR_GENERATOR_RUNNING,
+    ERROR_MAP_KEY_UNHASHABLE
 };
 
 typedef array(uint8_t) a_uint8;
@@ -90,6 +121,8 @@ typedef array(struct value) a_value;
 struct exframe;
 typedef array(struct exframe) a_exframe;
 struct hmap;
+struct vm;
+typedef void (*vm_debug_hook)(struct vm *);
 
 struct vm {
     uint32_t ip;
@@ -98,12 +131,15 @@ struct vm {
     a_exframe exframes;
     a_uint8 code;
     a_value stack;
-    struct dict *dstr, *dint, *dfloat, *darray, *drec;
+    struct dict *dstr, *dint, *dfloat, *darray, *drec, *dbool;
     enum vm_error error;
     uint32_t error_expected;
 
     struct exframe *exframe_fallthrough;
     size_t native_call_depth;
+
+    // called before each instruction while a debugger is attached
+    vm_debug_hook debug_hook;
 };
 
 void vm_execute(struct vm*);
@@ -121,10 +157,20 @@ struct env *vm_enter_env(struct vm *, struct function *);
 struct env *vm_enter_env_tail(struct vm *, struct function *);
 bool vm_leave_env(struct vm *);
 
+bool vm_generator_new(struct vm *);
+bool vm_yield(struct vm *);
+bool vm_resume_generator(struct vm *, struct dict *, uint32_t exit_ip);
+
+const struct value *vm_member_get(struct vm *, const struct dict *, const char *key, size_t len, uint32_t ip);
+
 void vm_load_module(struct vm*, const char*);
+void vm_load_module_as(struct vm*, const char*);
 
 void vm_print_stack(const struct vm*);
 
+// must be called before storing a value into a garbage collected object
+void gc_write_barrier(void *);
+
 #ifdef __cplusplus
 }
 #endif
@@ -0,0 +1,460 @@
+//! Provides the arbitrary-precision integers that integer
+//! arithmetic is promoted to when it overflows
+
+use super::gc::{GcNode, GcTraceable};
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Add, BitAnd, BitOr, BitXor, Mul, Neg, Sub};
+
+/// Arbitrary-precision integer
+///
+/// The magnitude is stored as little-endian 32-bit limbs without
+/// any leading zero limb, so zero has no limbs and is never negative.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BigInt {
+    negative: bool,
+    mag: Vec<u32>,
+}
+
+// #region magnitude arithmetic
+fn trim(mag: &mut Vec<u32>) {
+    while mag.last() == Some(&0) {
+        mag.pop();
+    }
+}
+
+fn cmp_mag(left: &[u32], right: &[u32]) -> Ordering {
+    if left.len() != right.len() {
+        return left.len().cmp(&right.len());
+    }
+    for (l, r) in left.iter().rev().zip(right.iter().rev()) {
+        if l != r {
+            return l.cmp(r);
+        }
+    }
+    Ordering::Equal
+}
+
+fn add_mag(left: &[u32], right: &[u32]) -> Vec<u32> {
+    let (long, short) = if left.len() >= right.len() {
+        (left, right)
+    } else {
+        (right, left)
+    };
+    let mut result = Vec::with_capacity(long.len() + 1);
+    let mut carry = 0u64;
+    for (i, l) in long.iter().enumerate() {
+        let sum = u64::from(*l) + u64::from(*short.get(i).unwrap_or(&0)) + carry;
+        result.push(sum as u32);
+        carry = sum >> 32;
+    }
+    if carry != 0 {
+        result.push(carry as u32);
+    }
+    result
+}
+
+// left must be greater than or equal to right
+fn sub_mag(left: &[u32], right: &[u32]) -> Vec<u32> {
+    let mut result = Vec::with_capacity(left.len());
+    let mut borrow = 0i64;
+    for (i, l) in left.iter().enumerate() {
+        let mut diff = i64::from(*l) - i64::from(*right.get(i).unwrap_or(&0)) - borrow;
+        borrow = if diff < 0 {
+            diff += 1 << 32;
+            1
+        } else {
+            0
+        };
+        result.push(diff as u32);
+    }
+    trim(&mut result);
+    result
+}
+
+fn mul_mag(left: &[u32], right: &[u32]) -> Vec<u32> {
+    if left.is_empty() || right.is_empty() {
+        return Vec::new();
+    }
+    let mut result = vec![0u32; left.len() + right.len()];
+    for (i, l) in left.iter().enumerate() {
+        let mut carry = 0u64;
+        for (j, r) in right.iter().enumerate() {
+            let prod = u64::from(*l) * u64::from(*r) + u64::from(result[i + j]) + carry;
+            result[i + j] = prod as u32;
+            carry = prod >> 32;
+        }
+        result[i + right.len()] = carry as u32;
+    }
+    trim(&mut result);
+    result
+}
+
+// multiplies the magnitude by a small factor and adds a small term in place
+fn mul_add_small(mag: &mut Vec<u32>, factor: u32, term: u32) {
+    let mut carry = u64::from(term);
+    for limb in mag.iter_mut() {
+        let prod = u64::from(*limb) * u64::from(factor) + carry;
+        *limb = prod as u32;
+        carry = prod >> 32;
+    }
+    if carry != 0 {
+        mag.push(carry as u32);
+    }
+}
+
+fn divrem_small(mag: &[u32], divisor: u32) -> (Vec<u32>, u32) {
+    let mut quot = vec![0u32; mag.len()];
+    let mut rem = 0u64;
+    for (i, limb) in mag.iter().enumerate().rev() {
+        let cur = (rem << 32) | u64::from(*limb);
+        quot[i] = (cur / u64::from(divisor)) as u32;
+        rem = cur % u64::from(divisor);
+    }
+    trim(&mut quot);
+    (quot, rem as u32)
+}
+
+// long division (Knuth's algorithm D), divisor must be at least 2 limbs long
+fn divrem_mag(left: &[u32], right: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    if cmp_mag(left, right) == Ordering::Less {
+        return (Vec::new(), left.to_vec());
+    }
+    // normalize so that the divisor's top limb has its high bit set
+    let shift = right.last().unwrap().leading_zeros();
+    let shl = |mag: &[u32], extra: usize| {
+        let mut result = Vec::with_capacity(mag.len() + extra);
+        let mut carry = 0u32;
+        for limb in mag {
+            result.push((*limb << shift) | carry);
+            carry = if shift == 0 { 0 } else { *limb >> (32 - shift) };
+        }
+        result.push(carry);
+        result.resize(mag.len() + extra, 0);
+        result
+    };
+    let v = shl(right, 0);
+    let mut u = shl(left, 1);
+    let n = right.len();
+    let m = left.len() - n;
+    let base = 1u64 << 32;
+    let mut quot = vec![0u32; m + 1];
+    for j in (0..=m).rev() {
+        let top = (u64::from(u[j + n]) << 32) | u64::from(u[j + n - 1]);
+        let mut qhat = top / u64::from(v[n - 1]);
+        let mut rhat = top % u64::from(v[n - 1]);
+        while qhat >= base || qhat * u64::from(v[n - 2]) > ((rhat << 32) | u64::from(u[j + n - 2]))
+        {
+            qhat -= 1;
+            rhat += u64::from(v[n - 1]);
+            if rhat >= base {
+                break;
+            }
+        }
+        // multiply and subtract
+        let mut borrow = 0i64;
+        let mut carry = 0u64;
+        for i in 0..n {
+            let prod = qhat * u64::from(v[i]) + carry;
+            carry = prod >> 32;
+            let diff = i64::from(u[i + j]) - (prod & 0xffff_ffff) as i64 - borrow;
+            u[i + j] = diff as u32;
+            borrow = if diff < 0 { 1 } else { 0 };
+        }
+        let diff = i64::from(u[j + n]) - carry as i64 - borrow;
+        u[j + n] = diff as u32;
+        if diff < 0 {
+            // qhat was one too large, add the divisor back
+            qhat -= 1;
+            let mut carry = 0u64;
+            for i in 0..n {
+                let sum = u64::from(u[i + j]) + u64::from(v[i]) + carry;
+                u[i + j] = sum as u32;
+                carry = sum >> 32;
+            }
+            u[j + n] = u[j + n].wrapping_add(carry as u32);
+        }
+        quot[j] = qhat as u32;
+    }
+    trim(&mut quot);
+    // unnormalize the remainder
+    let mut rem: Vec<u32> = (0..n)
+        .map(|i| {
+            if shift == 0 {
+                u[i]
+            } else {
+                (u[i] >> shift) | (u[i + 1] << (32 - shift))
+            }
+        })
+        .collect();
+    trim(&mut rem);
+    (quot, rem)
+}
+
+// two's complement representation of the number in `len` limbs
+fn to_twos(val: &BigInt, len: usize) -> Vec<u32> {
+    let mut limbs = val.mag.clone();
+    limbs.resize(len, 0);
+    if val.negative {
+        // !(|x| - 1) == -x
+        for limb in limbs.iter_mut() {
+            let (diff, borrow) = limb.overflowing_sub(1);
+            *limb = diff;
+            if !borrow {
+                break;
+            }
+        }
+        for limb in limbs.iter_mut() {
+            *limb = !*limb;
+        }
+    }
+    limbs
+}
+
+fn from_twos(mut limbs: Vec<u32>) -> BigInt {
+    let negative = limbs.last().map_or(false, |limb| *limb >> 31 == 1);
+    if negative {
+        // -x == !x + 1
+        for limb in limbs.iter_mut() {
+            *limb = !*limb;
+        }
+        mul_add_small(&mut limbs, 1, 1);
+    }
+    BigInt::from_parts(negative, limbs)
+}
+// #endregion
+
+impl BigInt {
+    fn from_parts(negative: bool, mut mag: Vec<u32>) -> BigInt {
+        trim(&mut mag);
+        BigInt {
+            negative: negative && !mag.is_empty(),
+            mag,
+        }
+    }
+
+    /// Parses a string of digits in the given radix, optionally prefixed by
+    /// `-`.
+    pub fn from_str_radix(s: &str, radix: u32) -> Option<BigInt> {
+        let (negative, digits) = if s.starts_with('-') {
+            (true, &s[1..])
+        } else {
+            (false, s)
+        };
+        if digits.is_empty() {
+            return None;
+        }
+        let mut mag = Vec::new();
+        for ch in digits.chars() {
+            mul_add_small(&mut mag, radix, ch.to_digit(radix)?);
+        }
+        Some(BigInt::from_parts(negative, mag))
+    }
+
+    /// Converts the number into a 64-bit integer if it fits in one.
+    pub fn to_i64(&self) -> Option<i64> {
+        if self.mag.len() > 2 {
+            return None;
+        }
+        let abs = self
+            .mag
+            .iter()
+            .rev()
+            .fold(0u64, |acc, limb| (acc << 32) | u64::from(*limb));
+        if self.negative {
+            if abs <= 1 << 63 {
+                Some((-i128::from(abs)) as i64)
+            } else {
+                None
+            }
+        } else if abs <= std::i64::MAX as u64 {
+            Some(abs as i64)
+        } else {
+            None
+        }
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        let abs = self
+            .mag
+            .iter()
+            .rev()
+            .fold(0.0, |acc, limb| acc * 4_294_967_296.0 + f64::from(*limb));
+        if self.negative {
+            -abs
+        } else {
+            abs
+        }
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.mag.is_empty()
+    }
+
+    /// Divides the number, truncating the quotient towards zero.
+    /// The remainder has the same sign as the dividend.
+    /// Returns `None` if the divisor is zero.
+    pub fn div_rem(&self, other: &BigInt) -> Option<(BigInt, BigInt)> {
+        let (quot, rem) = match other.mag.len() {
+            0 => return None,
+            1 => {
+                let (quot, rem) = divrem_small(&self.mag, other.mag[0]);
+                (quot, vec![rem])
+            }
+            _ => divrem_mag(&self.mag, &other.mag),
+        };
+        Some((
+            BigInt::from_parts(self.negative != other.negative, quot),
+            BigInt::from_parts(self.negative, rem),
+        ))
+    }
+
+    fn bitwise(&self, other: &BigInt, op: fn(u32, u32) -> u32) -> BigInt {
+        // one extra limb for the sign
+        let len = self.mag.len().max(other.mag.len()) + 1;
+        let left = to_twos(self, len);
+        let right = to_twos(other, len);
+        from_twos(
+            left.iter()
+                .zip(right.iter())
+                .map(|(l, r)| op(*l, *r))
+                .collect(),
+        )
+    }
+}
+
+impl From<i64> for BigInt {
+    fn from(n: i64) -> BigInt {
+        let abs = i128::from(n).abs() as u64;
+        BigInt::from_parts(n < 0, vec![abs as u32, (abs >> 32) as u32])
+    }
+}
+
+// #region operators
+impl<'a> Neg for &'a BigInt {
+    type Output = BigInt;
+    fn neg(self) -> BigInt {
+        BigInt::from_parts(!self.negative, self.mag.clone())
+    }
+}
+
+impl<'a> Add for &'a BigInt {
+    type Output = BigInt;
+    fn add(self, other: &BigInt) -> BigInt {
+        if self.negative == other.negative {
+            return BigInt::from_parts(self.negative, add_mag(&self.mag, &other.mag));
+        }
+        match cmp_mag(&self.mag, &other.mag) {
+            Ordering::Less => BigInt::from_parts(other.negative, sub_mag(&other.mag, &self.mag)),
+            _ => BigInt::from_parts(self.negative, sub_mag(&self.mag, &other.mag)),
+        }
+    }
+}
+
+impl<'a> Sub for &'a BigInt {
+    type Output = BigInt;
+    fn sub(self, other: &BigInt) -> BigInt {
+        self + &-other
+    }
+}
+
+impl<'a> Mul for &'a BigInt {
+    type Output = BigInt;
+    fn mul(self, other: &BigInt) -> BigInt {
+        BigInt::from_parts(
+            self.negative != other.negative,
+            mul_mag(&self.mag, &other.mag),
+        )
+    }
+}
+
+impl<'a> BitAnd for &'a BigInt {
+    type Output = BigInt;
+    fn bitand(self, other: &BigInt) -> BigInt {
+        self.bitwise(other, |l, r| l & r)
+    }
+}
+
+impl<'a> BitOr for &'a BigInt {
+    type Output = BigInt;
+    fn bitor(self, other: &BigInt) -> BigInt {
+        self.bitwise(other, |l, r| l | r)
+    }
+}
+
+impl<'a> BitXor for &'a BigInt {
+    type Output = BigInt;
+    fn bitxor(self, other: &BigInt) -> BigInt {
+        self.bitwise(other, |l, r| l ^ r)
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &BigInt) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => cmp_mag(&self.mag, &other.mag),
+            (true, true) => cmp_mag(&other.mag, &self.mag),
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &BigInt) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+// #endregion
+
+// #region formatting
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.mag.is_empty() {
+            return write!(f, "0");
+        }
+        // split into base 10^9 chunks, least significant first
+        let mut chunks = Vec::new();
+        let mut mag = self.mag.clone();
+        while !mag.is_empty() {
+            let (quot, rem) = divrem_small(&mag, 1_000_000_000);
+            chunks.push(rem);
+            mag = quot;
+        }
+        if self.negative {
+            write!(f, "-")?;
+        }
+        write!(f, "{}", chunks.pop().unwrap())?;
+        for chunk in chunks.iter().rev() {
+            write!(f, "{:09}", chunk)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::LowerHex for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.negative {
+            write!(f, "-")?;
+        }
+        match self.mag.split_last() {
+            None => write!(f, "0"),
+            Some((last, rest)) => {
+                write!(f, "{:x}", last)?;
+                for limb in rest.iter().rev() {
+                    write!(f, "{:08x}", limb)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+// #endregion
+
+impl GcTraceable for BigInt {
+    unsafe fn trace(&self, _manager: &mut Vec<*mut GcNode>) {}
+}
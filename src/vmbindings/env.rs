@@ -1,7 +1,12 @@
 //! Provides the stack frame for the virtual machine
 
+use super::function::Function;
+use super::gc::Gc;
+use super::hmap::HaruHashMap;
 use super::nativeval::NativeValue;
+use super::record::Record;
 use super::value::Value;
+use std::ptr::{null, null_mut};
 
 #[repr(C)]
 #[derive(Clone)]
@@ -23,6 +28,18 @@ pub struct Env {
     ///
     /// This is used for getting values on the previous stack frame.
     pub lexical_parent: *const Env,
+
+    /// Global environment of the caller
+    ///
+    /// This is restored on OP_RET.
+    pub globalenv: *mut HaruHashMap,
+
+    /// Function the stack frame was entered for
+    pub callee: *const Function,
+
+    /// Generator record, if the frame is running the body of
+    /// a function that yields
+    pub generator: Option<Gc<Record>>,
 }
 
 impl Env {
@@ -32,6 +49,9 @@ impl Env {
             nargs: nargs,
             lexical_parent: lexical_parent,
             retip: retip,
+            globalenv: null_mut(),
+            callee: null(),
+            generator: None,
         }
     }
 
@@ -41,6 +61,9 @@ impl Env {
             nargs: 0,
             lexical_parent: other.lexical_parent,
             retip: std::u32::MAX,
+            globalenv: null_mut(),
+            callee: null(),
+            generator: None,
         }
     }
 
@@ -4,6 +4,7 @@ use std::ptr::NonNull;
 
 use super::env::Env;
 use super::function::Function;
+use super::hmap::HaruHashMap;
 use super::record::Record;
 use super::value::Value;
 use super::vm::Vm;
@@ -18,17 +19,21 @@ pub struct ExFrame {
     pub unwind_stack: usize,
     /// How many native functions to return until we can call this?
     pub unwind_native_call_depth: usize,
+    /// The global environment to restore
+    pub unwind_globalenv: *mut HaruHashMap,
 }
 
 impl ExFrame {
     pub fn new(
         unwind_env: Option<NonNull<Env>>, unwind_stack: usize, unwind_native_call_depth: usize,
+        unwind_globalenv: *mut HaruHashMap,
     ) -> ExFrame {
         ExFrame {
             handlers: BTreeMap::new(),
             unwind_env,
             unwind_stack,
             unwind_native_call_depth,
+            unwind_globalenv,
         }
     }
 
@@ -1,18 +1,22 @@
 //! Foreign C bindings for the virtual machine
 
+use super::bigint::BigInt;
 use super::env::Env;
 use super::exframe::ExFrame;
 use super::function::Function;
-use super::gc::Gc;
+use super::gc::{write_barrier, Gc};
+use super::generator::Generator;
 use super::hmap::HaruHashMap;
-use super::nativeval::NativeValue;
+use super::map::{Map, MapKey};
+use super::nativeval::{NativeValue, NativeValueType};
 use super::record::Record;
+use super::set::Set;
 use super::string::HaruString;
 use super::value::Value;
 use super::vm::Vm;
 
 use std::alloc::{alloc_zeroed, realloc, Layout};
-use std::borrow::Borrow;
+use std::borrow::{Borrow, Cow};
 use std::ffi::CStr;
 use std::ptr::{null, null_mut, NonNull};
 use unicode_segmentation::UnicodeSegmentation;
@@ -35,6 +39,10 @@ mod foreignc {
         let layout = Layout::from_size_align(nelems * size, 2).unwrap();
         realloc(ptr, layout, new_size)
     }
+    #[no_mangle]
+    unsafe extern "C" fn gc_write_barrier(ptr: *mut libc::c_void) {
+        write_barrier(ptr)
+    }
 
     // #endregion
 
@@ -119,6 +127,110 @@ mod foreignc {
         left.is_prototype_of(right)
     }
 
+    #[no_mangle]
+    unsafe extern "C" fn dict_is_generator(selfptr: *const Record) -> bool {
+        let rec = &*selfptr;
+        rec.native_field.as_ref().map_or(false, |field| field.is::<Generator>())
+    }
+
+    #[no_mangle]
+    unsafe extern "C" fn dict_is_map(selfptr: *const Record) -> bool {
+        let rec = &*selfptr;
+        rec.native_field.as_ref().map_or(false, |field| field.is::<Map>())
+    }
+
+    #[no_mangle]
+    unsafe extern "C" fn dict_is_set(selfptr: *const Record) -> bool {
+        let rec = &*selfptr;
+        rec.native_field.as_ref().map_or(false, |field| field.is::<Set>())
+    }
+
+    #[no_mangle]
+    unsafe extern "C" fn vm_member_get(
+        cvm: *mut Vm, cr: *const Record, ckey: *const libc::c_char, len: usize, ip: u32,
+    ) -> *const NativeValue {
+        let vm = &mut *cvm;
+        let key = std::slice::from_raw_parts(ckey as *const u8, len);
+        vm.member_get(&*cr, key, ip)
+    }
+    // #endregion
+
+    // #region map
+    #[no_mangle]
+    unsafe extern "C" fn map_malloc(vm: *const Vm) -> *mut Record {
+        (&*vm).new_map().into_raw()
+    }
+
+    #[no_mangle]
+    unsafe extern "C" fn map_get(selfptr: *const Record, key: NativeValue) -> *const NativeValue {
+        let field = (*selfptr).native_field.as_ref().unwrap();
+        let map = field.downcast_ref::<Map>().unwrap();
+        match MapKey::from_value(&key).and_then(|key| map.get(&key)) {
+            Some(val) => val,
+            None => null(),
+        }
+    }
+
+    #[no_mangle]
+    unsafe extern "C" fn map_get_str(
+        selfptr: *const Record, ckey: *const libc::c_char,
+    ) -> *const NativeValue {
+        let field = (*selfptr).native_field.as_ref().unwrap();
+        let map = field.downcast_ref::<Map>().unwrap();
+        let key = MapKey::Str(CStr::from_ptr(ckey).to_string_lossy().into_owned());
+        match map.get(&key) {
+            Some(val) => val,
+            None => null(),
+        }
+    }
+
+    #[no_mangle]
+    unsafe extern "C" fn map_set(selfptr: *mut Record, key: NativeValue, val: NativeValue) -> bool {
+        let field = (*selfptr).native_field.as_mut().unwrap();
+        let map = field.downcast_mut::<Map>().unwrap();
+        match MapKey::from_value(&key) {
+            Some(key) => {
+                map.insert(key, val);
+                true
+            }
+            None => false,
+        }
+    }
+
+    #[no_mangle]
+    unsafe extern "C" fn map_keys(selfptr: *const Record, vm: *const Vm) -> *mut Vec<NativeValue> {
+        let vm = &*vm;
+        let field = (*selfptr).native_field.as_ref().unwrap();
+        let map = field.downcast_ref::<Map>().unwrap();
+        let keys = vm.malloc(Vec::with_capacity(map.len()));
+        for (key, _) in map.iter() {
+            keys.as_mut().push(key.to_value(vm).wrap());
+        }
+        keys.into_raw()
+    }
+    // #endregion
+
+    // #region set
+    unsafe fn set_of<'a>(selfptr: *const Record) -> &'a Set {
+        let field = (*selfptr).native_field.as_ref().unwrap();
+        field.downcast_ref::<Set>().unwrap()
+    }
+
+    #[no_mangle]
+    unsafe extern "C" fn set_elements(selfptr: *const Record, vm: *const Vm) -> *mut Vec<NativeValue> {
+        let vm = &*vm;
+        let set = set_of(selfptr);
+        let elements = vm.malloc(Vec::with_capacity(set.len()));
+        for elem in set.iter() {
+            elements.as_mut().push(elem.to_value(vm).wrap());
+        }
+        elements.into_raw()
+    }
+
+    #[no_mangle]
+    unsafe extern "C" fn set_eq(left: *const Record, right: *const Record) -> bool {
+        set_of(left) == set_of(right)
+    }
     // #endregion
 
     // #region string
@@ -139,6 +251,21 @@ mod foreignc {
         (&*vm).malloc(newleft.into()).into_raw()
     }
 
+    #[no_mangle]
+    unsafe extern "C" fn string_concat(
+        values: *const NativeValue, n: usize, vm: *const Vm,
+    ) -> *mut HaruString {
+        use std::fmt::Write;
+        let mut s = String::new();
+        for value in std::slice::from_raw_parts(values, n) {
+            match value.unwrap() {
+                Value::Str(p) => s.push_str(p.as_ref().borrow() as &String),
+                value => write!(s, "{}", value).unwrap(),
+            }
+        }
+        (&*vm).malloc(s.into()).into_raw()
+    }
+
     #[no_mangle]
     unsafe extern "C" fn string_repeat(
         cleft: *const HaruString, n: i64, vm: *const Vm,
@@ -217,6 +344,18 @@ mod foreignc {
         let s = &*s;
         s.graphemes(true).count()
     }
+
+    #[no_mangle]
+    unsafe extern "C" fn string_slice(
+        s: *const HaruString, start: i64, step: i64, n: usize, vm: *const Vm,
+    ) -> *mut HaruString {
+        let s: &'static HaruString = &*s;
+        let graphemes: Vec<&str> = s.graphemes(true).collect();
+        let slice: String = (0..n as i64)
+            .map(|i| graphemes[(start + i * step) as usize])
+            .collect();
+        (&*vm).malloc(slice.into()).into_raw()
+    }
     // #endregion
 
     // #region function
@@ -224,7 +363,9 @@ mod foreignc {
     unsafe extern "C" fn function_malloc(
         addr: u32, nargs: u16, env: *const Env, vm: *const Vm,
     ) -> *mut Function {
-        (&*vm).malloc(Function::new(addr, nargs, env)).into_raw()
+        let vm = &*vm;
+        vm.malloc(Function::new(addr, nargs, env, vm.globalenv_ptr()))
+            .into_raw()
     }
 
     #[no_mangle]
@@ -232,6 +373,31 @@ mod foreignc {
         let fun = &mut *fun;
         fun.bound.set(slot, val)
     }
+
+    #[no_mangle]
+    unsafe extern "C" fn function_bind_args(
+        fun: *const Function, nargs: u16, kwnames: *const libc::c_char, nkwargs: u16,
+        cvm: *mut Vm,
+    ) -> bool {
+        let fun = &*fun;
+        let vm = &mut *cvm;
+        let mut kwname = kwnames;
+        let kwnames: Vec<&[u8]> = (0..nkwargs)
+            .map(|_| {
+                let name = CStr::from_ptr(kwname).to_bytes();
+                kwname = kwname.add(name.len() + 1);
+                name
+            })
+            .collect();
+        match fun.bind_args(vm, nargs, &kwnames) {
+            Ok(()) => true,
+            Err((error, expected)) => {
+                vm.error = error;
+                vm.error_expected = expected;
+                false
+            }
+        }
+    }
     // #endregion
 
     // #region array
@@ -256,6 +422,38 @@ mod foreignc {
         }
         (&*vm).malloc(result).into_raw()
     }
+    #[no_mangle]
+    unsafe extern "C" fn array_obj_slice(
+        carray: *const Vec<NativeValue>, start: i64, step: i64, n: usize, vm: *const Vm,
+    ) -> *mut Vec<NativeValue> {
+        let array = &*carray;
+        let result: Vec<NativeValue> = (0..n as i64)
+            .map(|i| array[(start + i * step) as usize])
+            .collect();
+        (&*vm).malloc(result).into_raw()
+    }
+    #[no_mangle]
+    unsafe extern "C" fn array_obj_splice(
+        carray: *mut Vec<NativeValue>, start: i64, step: i64, n: usize,
+        csrc: *const Vec<NativeValue>,
+    ) -> bool {
+        let array = &mut *carray;
+        // the source may be the array itself
+        let src = (&*csrc).clone();
+        if step == 1 {
+            // contiguous slices can be replaced by any number of elements
+            let start = start as usize;
+            array.splice(start..start + n, src);
+            true
+        } else if src.len() == n {
+            for (i, val) in src.into_iter().enumerate() {
+                array[(start + i as i64 * step) as usize] = val;
+            }
+            true
+        } else {
+            false
+        }
+    }
     // #endregion
 
     // #region env
@@ -304,8 +502,11 @@ mod foreignc {
     ) -> Option<NonNull<Env>> {
         let vm = &mut *selfptr;
         let fun = &mut *fun;
-        vm.enter_env(fun);
-        vm.localenv()
+        if vm.enter_env(fun) {
+            vm.localenv()
+        } else {
+            None
+        }
     }
     #[no_mangle]
     unsafe extern "C" fn vm_enter_env_tail(
@@ -320,11 +521,25 @@ mod foreignc {
     #[no_mangle]
     unsafe extern "C" fn vm_leave_env(selfptr: *mut Vm) -> bool {
         let vm = &mut *selfptr;
-        if vm.localenv().unwrap().as_ref().retip == std::u32::MAX {
-            return true;
-        }
-        vm.leave_env();
-        false
+        vm.ret()
+    }
+
+    #[no_mangle]
+    unsafe extern "C" fn vm_generator_new(selfptr: *mut Vm) -> bool {
+        let vm = &mut *selfptr;
+        vm.new_generator()
+    }
+    #[no_mangle]
+    unsafe extern "C" fn vm_yield(selfptr: *mut Vm) -> bool {
+        let vm = &mut *selfptr;
+        vm.yield_generator()
+    }
+    #[no_mangle]
+    unsafe extern "C" fn vm_resume_generator(
+        selfptr: *mut Vm, rec: *mut Record, exit_ip: u32,
+    ) -> bool {
+        let vm = &mut *selfptr;
+        vm.resume_generator(Gc::from_raw(rec), Value::Nil.wrap(), exit_ip)
     }
     // #endregion
 
@@ -368,6 +583,13 @@ mod foreignc {
         let vm = &mut *cvm;
         vm.load_module(&path);
     }
+
+    #[no_mangle]
+    unsafe extern "C" fn vm_load_module_as(cvm: *mut Vm, cpath: *const libc::c_char) {
+        let path = CStr::from_ptr(cpath).to_string_lossy();
+        let vm = &mut *cvm;
+        vm.load_module_as(&path);
+    }
     // #endregion
 
     // #region value
@@ -384,4 +606,73 @@ mod foreignc {
     }
     // #endregion
 
+    // #region bigint
+    // operands are either ints or big integers
+    unsafe fn as_bigint<'a>(val: &NativeValue) -> Cow<'a, BigInt> {
+        match val.unwrap() {
+            Value::Int(n) => Cow::Owned(BigInt::from(n)),
+            Value::BigInt(p) => Cow::Borrowed(&*p.to_raw()),
+            _ => unreachable!(),
+        }
+    }
+
+    macro_rules! bigint_op {
+        ($name:ident, $op:tt) => {
+            #[no_mangle]
+            unsafe extern "C" fn $name(
+                left: NativeValue, right: NativeValue, vm: *const Vm,
+            ) -> NativeValue {
+                let result = as_bigint(&left).as_ref() $op as_bigint(&right).as_ref();
+                Value::from_bigint(result, &*vm).wrap()
+            }
+        };
+    }
+    bigint_op!(bigint_add, +);
+    bigint_op!(bigint_sub, -);
+    bigint_op!(bigint_mul, *);
+    bigint_op!(bigint_bitwise_and, &);
+    bigint_op!(bigint_bitwise_or, |);
+    bigint_op!(bigint_bitwise_xor, ^);
+
+    #[no_mangle]
+    unsafe extern "C" fn bigint_mod(
+        left: NativeValue, right: NativeValue, vm: *const Vm,
+    ) -> NativeValue {
+        match as_bigint(&left).div_rem(&as_bigint(&right)) {
+            Some((_, rem)) => Value::from_bigint(rem, &*vm).wrap(),
+            None => NativeValue {
+                r#type: NativeValueType::TYPE_INTERPRETER_ERROR,
+                data: 0,
+            },
+        }
+    }
+
+    #[no_mangle]
+    unsafe extern "C" fn bigint_negate(val: NativeValue, vm: *const Vm) -> NativeValue {
+        Value::from_bigint(-as_bigint(&val).as_ref(), &*vm).wrap()
+    }
+
+    #[no_mangle]
+    unsafe extern "C" fn bigint_from_str(cstr: *const libc::c_char, vm: *const Vm) -> NativeValue {
+        let s = CStr::from_ptr(cstr).to_string_lossy();
+        Value::from_bigint(BigInt::from_str_radix(&s, 10).unwrap(), &*vm).wrap()
+    }
+
+    #[no_mangle]
+    unsafe extern "C" fn bigint_cmp(left: NativeValue, right: NativeValue) -> i32 {
+        as_bigint(&left).cmp(&as_bigint(&right)) as i32
+    }
+
+    #[no_mangle]
+    unsafe extern "C" fn bigint_to_float(val: NativeValue) -> f64 {
+        as_bigint(&val).to_f64()
+    }
+
+    #[no_mangle]
+    unsafe extern "C" fn bigint_is_positive(val: NativeValue) -> bool {
+        let n = as_bigint(&val);
+        !n.is_negative() && !n.is_zero()
+    }
+    // #endregion
+
 }
@@ -1,6 +1,10 @@
 //! Provides a function value in Hana
 
 use super::env::Env;
+use super::hmap::HaruHashMap;
+use super::nativeval::{NativeValue, NativeValueType};
+use super::vm::Vm;
+use super::vmerror::VmError;
 use crate::vmbindings::gc::{push_gray_body, GcNode, GcTraceable};
 use std::ptr::null_mut;
 
@@ -11,6 +15,13 @@ pub struct Function {
     pub ip: u32,
     /// Number of args the function takes in
     pub nargs: u16,
+    /// Number of args that have no default value
+    pub nrequired: u16,
+    /// Whether the last arg collects the remaining positional args
+    /// into an array
+    pub rest: bool,
+    /// Position of the args' names in the bytecode
+    pub params: u32,
 
     // internal rust properties:
     /// Represents the current local environment
@@ -20,24 +31,109 @@ pub struct Function {
     ///
     /// Wwe use this to implement closures.
     pub bound: Env,
+    /// Global environment of the module the function is declared in
+    pub globalenv: *mut HaruHashMap,
 }
 
 impl Function {
-    pub unsafe fn new(ip: u32, nargs: u16, env: *const Env) -> Function {
+    pub unsafe fn new(
+        ip: u32, nargs: u16, env: *const Env, globalenv: *mut HaruHashMap,
+    ) -> Function {
         Function {
             ip: ip,
             nargs: nargs,
+            nrequired: nargs,
+            rest: false,
+            params: 0,
             bound: if env.is_null() {
                 Env::new(0, null_mut(), nargs)
             } else {
                 Env::copy(&*env)
             },
+            globalenv,
         }
     }
 
     pub unsafe fn get_bound_ptr(&self) -> *const Env {
         &self.bound
     }
+
+    /// Names of the args, which are stored in the bytecode
+    /// after the function's OP_DEF_FUNCTION_PUSH
+    pub fn arg_names<'a>(&self, code: &'a [u8]) -> Vec<&'a [u8]> {
+        let mut start = self.params as usize;
+        (0..self.nargs)
+            .map(|_| {
+                let len = code[start..].iter().position(|&b| b == 0).unwrap();
+                let name = &code[start..start + len];
+                start += len + 1;
+                name
+            })
+            .collect()
+    }
+
+    /// Rearranges the args of a call on top of the stack into one value
+    /// per arg of the function, in the order OP_ENV_NEW expects them.
+    ///
+    /// The positional args are on top of the stack, followed by the values
+    /// of the keyword args named in `kwnames`. Args with a default value
+    /// that weren't given are left unset for OP_JARG. The stack is left
+    /// untouched if the args don't fit the function.
+    pub unsafe fn bind_args(
+        &self, vm: &mut Vm, nargs: u16, kwnames: &[&[u8]],
+    ) -> Result<(), (VmError, u32)> {
+        let npositional = (self.nargs - self.rest as u16) as usize;
+        let nrequired = self.nrequired as usize;
+        let nargs = nargs as usize;
+        let mismatch = |error| {
+            if nrequired == self.nargs as usize {
+                (VmError::ERROR_MISMATCH_ARGUMENTS, u32::from(self.nargs))
+            } else if error == VmError::ERROR_TOO_FEW_ARGUMENTS {
+                (error, u32::from(self.nrequired))
+            } else {
+                (error, npositional as u32)
+            }
+        };
+        if nargs > npositional && !self.rest {
+            return Err(mismatch(VmError::ERROR_TOO_MANY_ARGUMENTS));
+        }
+
+        let unset = NativeValue {
+            data: 0,
+            r#type: NativeValueType::TYPE_INTERPRETER_UNSET,
+        };
+        let mut slots = vec![unset; self.nargs as usize];
+        let len = vm.stack.len();
+        let arg = |i: usize| vm.stack[len - 1 - i];
+        for (i, slot) in slots.iter_mut().take(nargs.min(npositional)).enumerate() {
+            *slot = arg(i);
+        }
+        if !kwnames.is_empty() {
+            let names = self.arg_names(vm.code.as_ref().unwrap());
+            for (i, kwname) in kwnames.iter().enumerate() {
+                match names[..npositional].iter().position(|name| name == kwname) {
+                    Some(slot) if slots[slot].r#type == unset.r#type => {
+                        slots[slot] = arg(nargs + i);
+                    }
+                    _ => return Err((VmError::ERROR_UNEXPECTED_KEYWORD_ARGUMENT, 0)),
+                }
+            }
+        }
+        if slots[..nrequired].iter().any(|slot| slot.r#type == unset.r#type) {
+            return Err(mismatch(VmError::ERROR_TOO_FEW_ARGUMENTS));
+        }
+        if self.rest {
+            let rest: Vec<NativeValue> = (npositional..nargs).map(arg).collect();
+            slots[npositional] = NativeValue {
+                data: vm.malloc(rest).into_raw() as u64,
+                r#type: NativeValueType::TYPE_ARRAY,
+            };
+        }
+
+        vm.stack.truncate(len - nargs - kwnames.len());
+        vm.stack.extend(slots.into_iter().rev());
+        Ok(())
+    }
 }
 
 // gc traceable
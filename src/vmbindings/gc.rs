@@ -1,4 +1,10 @@
 //! Basic implementation of a mark and sweep garbage collector
+//!
+//! The collector is either run in a single pause once enough memory is
+//! allocated, or incrementally, marking and sweeping a bounded number of
+//! nodes on every allocation. Incremental marking traces what was reachable
+//! when marking started: before a node is written to, a write barrier traces
+//! the values it refers to so that they survive being overwritten.
 
 pub use libc::c_void;
 use std::alloc::{alloc_zeroed, dealloc, Layout};
@@ -19,6 +25,8 @@ pub struct GcNode {
     size: usize,
     color: GcNodeColor,
     native_refs: usize,
+    // state of the manager which allocated the node
+    state: *mut GcState,
     // tracer gets called on the marking phase
     tracer: GenericTraceFunction,
     /* finalizer gets called with a pointer to
@@ -39,6 +47,38 @@ type GenericFunction = unsafe fn(*mut c_void);
 // this might be a finalizer or a tracer function
 // TODO maybe replace this with Any
 
+/// Collection strategy of the garbage collector
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GcMode {
+    /// Marks and sweeps the whole heap in a single pause
+    StopTheWorld,
+    /// Marks or sweeps at most `budget` nodes per allocation
+    Incremental { budget: usize },
+}
+
+/// Number of nodes marked or swept per allocation in incremental mode
+pub const DEFAULT_GC_BUDGET: usize = 1024;
+
+impl Default for GcMode {
+    fn default() -> GcMode {
+        GcMode::StopTheWorld
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum GcPhase {
+    Idle,
+    Marking,
+    Sweeping,
+}
+
+// state shared with the nodes, so that nodes can be grayed out
+// through handles and write barriers without access to the manager
+struct GcState {
+    marking: bool,
+    gray_nodes: Vec<*mut GcNode>,
+}
+
 // manager
 const INITIAL_THRESHOLD: usize = 4096;
 const USED_SPACE_RATIO: f64 = 0.7;
@@ -46,9 +86,16 @@ pub struct GcManager {
     first_node: *mut GcNode,
     last_node: *mut GcNode,
     bytes_allocated: usize,
-    gray_nodes: Vec<*mut GcNode>,
+    state: *mut GcState,
     threshold: usize,
     enabled: bool,
+    mode: GcMode,
+    phase: GcPhase,
+    // while sweeping: the last node that was kept, the next node to sweep
+    // and the last node to sweep (nodes allocated after it are kept)
+    sweep_prev: *mut GcNode,
+    sweep_node: *mut GcNode,
+    sweep_last: *mut GcNode,
 }
 
 impl GcManager {
@@ -57,9 +104,17 @@ impl GcManager {
             first_node: null_mut(),
             last_node: null_mut(),
             bytes_allocated: 0,
-            gray_nodes: Vec::new(),
+            state: Box::into_raw(Box::new(GcState {
+                marking: false,
+                gray_nodes: Vec::new(),
+            })),
             threshold: INITIAL_THRESHOLD,
             enabled: false,
+            mode: GcMode::default(),
+            phase: GcPhase::Idle,
+            sweep_prev: null_mut(),
+            sweep_node: null_mut(),
+            sweep_last: null_mut(),
         }
     }
 
@@ -82,16 +137,23 @@ impl GcManager {
             self.last_node = node;
         }
         (*node).native_refs = 1;
+        (*node).state = self.state;
         (*node).tracer = std::mem::transmute(T::trace as *mut c_void);
         (*node).finalizer = finalizer;
         (*node).size = size;
         self.bytes_allocated += (*node).size;
-        // gray out the node
-        // TODO: we currently move the write barrier forward rather than backwards
-        // this probably is less efficient than setting the newly allocated node
-        // to white then resetting its soon-to-be parent to gray (for retracing)
-        (*node).color = GcNodeColor::Gray;
-        self.gray_nodes.push(node);
+        if (*self.state).marking {
+            // nodes allocated while marking only refer to nodes that are
+            // already going to be marked, so they don't need to be traced
+            (*node).color = GcNodeColor::Black;
+        } else {
+            // gray out the node
+            // TODO: we currently move the write barrier forward rather than backwards
+            // this probably is less efficient than setting the newly allocated node
+            // to white then resetting its soon-to-be parent to gray (for retracing)
+            (*node).color = GcNodeColor::Gray;
+            (*self.state).gray_nodes.push(node);
+        }
         // return the body aka (start byte + sizeof(GCNode))
         std::mem::forget(std::mem::replace(&mut *(node.add(1) as *mut T), x));
         node.add(1) as *mut T
@@ -107,7 +169,7 @@ impl GcManager {
     }
 
     pub unsafe fn push_gray_body(&mut self, ptr: *mut c_void) {
-        push_gray_body(&mut self.gray_nodes, ptr)
+        push_gray_body(&mut (*self.state).gray_nodes, ptr)
     }
 
     // state
@@ -118,76 +180,124 @@ impl GcManager {
         self.enabled = false;
     }
 
+    pub fn mode(&self) -> GcMode {
+        self.mode
+    }
+    pub fn set_mode(&mut self, mode: GcMode) {
+        // a collection that is in progress gets finished in the new mode
+        self.mode = mode;
+    }
+
     // gc algorithm
     unsafe fn cycle(&mut self, vm: &Vm, size: usize) -> Option<NonNull<GcNode>> {
-        if !self.enabled || self.bytes_allocated < self.threshold {
+        if !self.enabled {
             return None;
         }
-        // marking phase
-        let gray_nodes = std::mem::replace(&mut self.gray_nodes, Vec::new());
-        for node in gray_nodes.iter() {
-            let body = node.add(1) as *mut c_void;
-            (**node).color = GcNodeColor::Black;
-            ((**node).tracer)(body, std::mem::transmute(&mut self.gray_nodes));
+        if self.phase == GcPhase::Idle {
+            if self.bytes_allocated < self.threshold {
+                return None;
+            }
+            // the nodes grayed out since the last cycle are
+            // traced along with the roots
+            (*self.state).marking = true;
+            vm.trace(&mut (*self.state).gray_nodes);
+            self.phase = GcPhase::Marking;
         }
-        // nothing left to traverse, sweeping phase:
-        if self.gray_nodes.is_empty() {
-            let mut prev: *mut GcNode = null_mut();
-            // sweep
-            let mut node = self.first_node;
-            let mut first_fitting_node: Option<NonNull<GcNode>> = None;
-            while !node.is_null() {
-                let next: *mut GcNode = (*node).next;
-                let mut freed = false;
-                if (*node).native_refs == 0 && (*node).color == GcNodeColor::White {
-                    freed = true;
-                    let body = node.add(1);
-
-                    // remove from ll
-                    if prev.is_null() {
-                        self.first_node = (*node).next;
-                    } else {
-                        (*prev).next = (*node).next;
-                    }
-                    if (*node).next.is_null() {
-                        self.last_node = prev;
-                    }
-                    self.bytes_allocated -= (*node).size;
-
-                    // call finalizer
-                    let finalizer = (*node).finalizer;
-                    finalizer(body as *mut c_void);
-
-                    // if this node fits then record it
-                    if (*node).size == size && first_fitting_node.is_none() {
-                        std::ptr::write_bytes(node as *mut u8, 0, (*node).size);
-                        first_fitting_node = Some(NonNull::new_unchecked(node));
-                    } else { // else just free it
-                        let layout = Layout::from_size_align((*node).size, 2).unwrap();
-                        dealloc(node as *mut u8, layout);
-                    }
-                } else if (*node).native_refs != 0 {
-                    self.gray_nodes.push(node);
+        let mut budget = match self.mode {
+            GcMode::StopTheWorld => std::usize::MAX,
+            GcMode::Incremental { budget } => budget.max(1),
+        };
+        if self.phase == GcPhase::Marking {
+            budget = self.mark(budget);
+        }
+        if self.phase == GcPhase::Sweeping {
+            self.sweep(size, budget)
+        } else {
+            None
+        }
+    }
+
+    // marking phase, returns the unused budget
+    unsafe fn mark(&mut self, mut budget: usize) -> usize {
+        let state = &mut *self.state;
+        while budget > 0 {
+            if let Some(node) = state.gray_nodes.pop() {
+                trace_node(node, &mut state.gray_nodes);
+                budget -= 1;
+            } else {
+                break;
+            }
+        }
+        if !state.gray_nodes.is_empty() {
+            return budget;
+        }
+        // nothing left to traverse, sweeping phase
+        state.marking = false;
+        self.phase = GcPhase::Sweeping;
+        self.sweep_prev = null_mut();
+        self.sweep_node = self.first_node;
+        self.sweep_last = self.last_node;
+        budget
+    }
+
+    // sweeping phase, returns the first freed node that fits the size
+    unsafe fn sweep(&mut self, size: usize, mut budget: usize) -> Option<NonNull<GcNode>> {
+        let mut first_fitting_node: Option<NonNull<GcNode>> = None;
+        while budget > 0 && !self.sweep_node.is_null() {
+            budget -= 1;
+            let node = self.sweep_node;
+            let next: *mut GcNode = (*node).next;
+            if (*node).native_refs == 0 && (*node).color == GcNodeColor::White {
+                let body = node.add(1);
+
+                // remove from ll
+                if self.sweep_prev.is_null() {
+                    self.first_node = (*node).next;
                 } else {
+                    (*self.sweep_prev).next = (*node).next;
+                }
+                if (*node).next.is_null() {
+                    self.last_node = self.sweep_prev;
+                }
+                self.bytes_allocated -= (*node).size;
+
+                // call finalizer
+                let finalizer = (*node).finalizer;
+                finalizer(body as *mut c_void);
+
+                // if this node fits then record it
+                if (*node).size == size && first_fitting_node.is_none() {
+                    std::ptr::write_bytes(node as *mut u8, 0, (*node).size);
+                    first_fitting_node = Some(NonNull::new_unchecked(node));
+                } else { // else just free it
+                    let layout = Layout::from_size_align((*node).size, 2).unwrap();
+                    dealloc(node as *mut u8, layout);
+                }
+            } else {
+                // gray nodes were allocated or grayed out since marking
+                // ended, and are already queued for the next cycle
+                if (*node).color == GcNodeColor::Black {
                     (*node).color = GcNodeColor::White;
                 }
-                if !freed {
-                    prev = node;
+                // nodes referenced from native code are roots
+                if (*node).native_refs != 0 {
+                    push_gray_body(&mut (*self.state).gray_nodes, node.add(1) as *mut c_void);
                 }
-                node = next;
+                self.sweep_prev = node;
             }
-            vm.trace(&mut self.gray_nodes);
+            self.sweep_node = if node == self.sweep_last { null_mut() } else { next };
+        }
 
+        if self.sweep_node.is_null() {
+            self.phase = GcPhase::Idle;
             // we didn't collect enough, grow the ratio
             if ((self.bytes_allocated as f64) / (self.threshold as f64)) > USED_SPACE_RATIO {
                 self.threshold = (self.bytes_allocated as f64 / USED_SPACE_RATIO) as usize;
             }
-
-            // return first fitting node if there is any
-            first_fitting_node
-        } else {
-            None
         }
+
+        // return first fitting node if there is any
+        first_fitting_node
     }
 }
 
@@ -206,6 +316,7 @@ impl std::ops::Drop for GcManager {
                 dealloc(node as *mut u8, layout);
                 node = next;
             }
+            drop(Box::from_raw(self.state));
         }
     }
 }
@@ -220,10 +331,8 @@ impl<T: Sized + GcTraceable> Gc<T> {
     // raw
     pub unsafe fn from_raw(ptr: *mut T) -> Gc<T> {
         //println!("from raw");
-        // manually color it black & increment ref
-        let node: *mut GcNode = (ptr as *mut GcNode).sub(1);
-        (*node).color = GcNodeColor::Black;
-        (*node).native_refs += 1;
+        // manually increment ref (graying out the node)
+        ref_inc(ptr as *mut c_void);
         Gc {
             ptr: NonNull::new(ptr).unwrap(),
         }
@@ -239,7 +348,10 @@ impl<T: Sized + GcTraceable> Gc<T> {
 
     // refs with interior mutability
     pub fn as_mut(&self) -> &mut T {
-        unsafe { &mut *self.ptr.as_ptr() }
+        unsafe {
+            write_barrier(self.ptr.as_ptr() as *mut c_void);
+            &mut *self.ptr.as_ptr()
+        }
     }
 }
 
@@ -302,6 +414,8 @@ pub unsafe fn ref_inc(ptr: *mut c_void) {
     }
     let node: *mut GcNode = (ptr as *mut GcNode).sub(1);
     (*node).native_refs += 1;
+    // nodes referenced from native code are roots
+    push_gray_body(&mut (*(*node).state).gray_nodes, ptr);
 }
 
 pub unsafe fn ref_dec(ptr: *mut c_void) {
@@ -321,3 +435,21 @@ pub unsafe fn push_gray_body(gray_nodes: &mut Vec<*mut GcNode>, ptr: *mut c_void
     (*node).color = GcNodeColor::Gray;
     gray_nodes.push(node);
 }
+
+/// Write barrier, called before storing into the garbage collected object at `ptr`.
+///
+/// While marking, the object is traced if it hasn't been yet so that the
+/// values it refers to are kept alive even if they get overwritten.
+pub unsafe fn write_barrier(ptr: *mut c_void) {
+    let node: *mut GcNode = (ptr as *mut GcNode).sub(1);
+    let state = &mut *(*node).state;
+    if state.marking && (*node).color != GcNodeColor::Black {
+        trace_node(node, &mut state.gray_nodes);
+    }
+}
+
+unsafe fn trace_node(node: *mut GcNode, gray_nodes: &mut Vec<*mut GcNode>) {
+    let body = node.add(1) as *mut c_void;
+    (*node).color = GcNodeColor::Black;
+    ((*node).tracer)(body, std::mem::transmute(gray_nodes));
+}
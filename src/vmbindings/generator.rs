@@ -0,0 +1,76 @@
+//! Provides the generator objects created by functions that yield
+
+use super::env::Env;
+use super::gc::{push_gray_body, GcNode, GcTraceable};
+use super::hmap::HaruHashMap;
+use super::nativeval::NativeValue;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GeneratorState {
+    /// The function hasn't started running its body
+    Created,
+    /// The function is suspended on a yield expression
+    Suspended,
+    /// The function's stack frame is in the call stack
+    Running,
+    /// The function has returned
+    Finished,
+}
+
+/// Suspended stack frame of a function that yields
+///
+/// Generators are stored in the native field of their record,
+/// and are resumed by `for... in` statements or their `next` method.
+pub struct Generator {
+    pub state: GeneratorState,
+    /// Instruction pointer to resume from
+    pub ip: u32,
+    /// The function's stack frame while it isn't running
+    pub env: Option<Env>,
+    /// The function's part of the stack while it isn't running
+    pub stack: Vec<NativeValue>,
+    /// Length of the stack below the function's part while it is running
+    pub stack_base: usize,
+    /// Global environment of the module the function is declared in
+    pub globalenv: *mut HaruHashMap,
+    /// The function itself, which holds the lexical parent of its frame
+    pub function: NativeValue,
+    /// Where to jump to once the function returns when it is consumed
+    /// by a `for... in` statement, `std::u32::MAX` otherwise
+    pub exit_ip: u32,
+}
+
+impl Generator {
+    pub fn new(ip: u32, env: Env, globalenv: *mut HaruHashMap, function: NativeValue) -> Generator {
+        Generator {
+            state: GeneratorState::Created,
+            ip,
+            env: Some(env),
+            stack: Vec::new(),
+            stack_base: 0,
+            globalenv,
+            function,
+            exit_ip: std::u32::MAX,
+        }
+    }
+}
+
+impl GcTraceable for Generator {
+    unsafe fn trace(&self, gray_nodes: &mut Vec<*mut GcNode>) {
+        if let Some(ptr) = self.function.as_gc_pointer() {
+            push_gray_body(gray_nodes, ptr);
+        }
+        if let Some(env) = &self.env {
+            for val in env.slots.iter() {
+                if let Some(ptr) = val.as_gc_pointer() {
+                    push_gray_body(gray_nodes, ptr);
+                }
+            }
+        }
+        for val in self.stack.iter() {
+            if let Some(ptr) = val.as_gc_pointer() {
+                push_gray_body(gray_nodes, ptr);
+            }
+        }
+    }
+}
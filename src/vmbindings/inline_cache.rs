@@ -0,0 +1,138 @@
+//! Provides inline caches for member lookups
+//!
+//! Each `MEMBER_GET` instruction remembers where it last found its key:
+//! the shape of the receiving record and the stamps of the prototypes it
+//! walked through. Lookups on records of the same shape then skip hashing
+//! the key and walking the prototype chain.
+
+use super::nativeval::NativeValue;
+use super::record::{Record, Shape, UNSHAPED};
+use std::ptr::null;
+
+// lookups through deeper prototype chains aren't cached
+const MAX_CHAIN_DEPTH: usize = 4;
+
+struct MemberCache {
+    key: String,
+    shape: Shape,
+    // stamp of the receiver, used instead of its shape if it is unshaped
+    receiver_stamp: u64,
+    // stamps of the prototypes walked through, ending with the holder
+    // of the key (empty if the receiver holds it)
+    chain: Vec<u64>,
+    // the value is found here as long as the holder's stamp hasn't changed
+    slot: *const NativeValue,
+}
+
+impl MemberCache {
+    fn lookup(&self, rec: &Record) -> Option<*const NativeValue> {
+        let same_shape = self.shape != UNSHAPED && rec.shape() == self.shape;
+        if self.chain.is_empty() {
+            if rec.stamp() == self.receiver_stamp {
+                return Some(self.slot);
+            } else if same_shape {
+                // same keys as the cached receiver but in another record
+                return rec.get_own(&self.key).map(|val| val as *const NativeValue);
+            }
+            return None;
+        }
+        if !same_shape && rec.stamp() != self.receiver_stamp {
+            return None;
+        }
+        let mut holder = rec;
+        for &stamp in self.chain.iter() {
+            holder = holder.prototype()?;
+            if holder.stamp() != stamp {
+                return None;
+            }
+        }
+        Some(self.slot)
+    }
+}
+
+/// Inline caches of an instruction stream, indexed by instruction pointer
+pub struct InlineCaches {
+    // index of the cache of each instruction plus one, or 0 if it has none
+    index: Vec<u32>,
+    caches: Vec<MemberCache>,
+}
+
+impl InlineCaches {
+    pub fn new() -> InlineCaches {
+        InlineCaches {
+            index: Vec::new(),
+            caches: Vec::new(),
+        }
+    }
+
+    /// Gets the value of the key in the record or its prototypes, returning
+    /// null if it isn't found. `ip` is the address of the instruction doing
+    /// the lookup.
+    pub fn member_get(&mut self, rec: &Record, key: &[u8], ip: u32) -> *const NativeValue {
+        let ip = ip as usize;
+        if ip < self.index.len() && self.index[ip] != 0 {
+            let cache = &self.caches[self.index[ip] as usize - 1];
+            if cache.key.as_bytes() == key {
+                if let Some(slot) = cache.lookup(rec) {
+                    return slot;
+                }
+            }
+        }
+
+        let key = String::from_utf8_lossy(key).into_owned();
+        if let Some(val) = rec.get_own(&key) {
+            self.fill(
+                ip,
+                MemberCache {
+                    key,
+                    shape: rec.shape(),
+                    receiver_stamp: rec.stamp(),
+                    chain: Vec::new(),
+                    slot: val,
+                },
+            );
+            return val;
+        }
+        let mut chain = Vec::new();
+        let mut holder = rec;
+        while let Some(prototype) = holder.prototype() {
+            holder = prototype;
+            chain.push(holder.stamp());
+            if let Some(val) = holder.get_own(&key) {
+                if chain.len() <= MAX_CHAIN_DEPTH {
+                    self.fill(
+                        ip,
+                        MemberCache {
+                            key,
+                            shape: rec.shape(),
+                            receiver_stamp: rec.stamp(),
+                            chain,
+                            slot: val,
+                        },
+                    );
+                }
+                return val;
+            }
+        }
+        null()
+    }
+
+    // caches are monomorphic, so a miss replaces the previous cache
+    fn fill(&mut self, ip: usize, cache: MemberCache) {
+        if ip >= self.index.len() {
+            self.index.resize(ip + 1, 0);
+        }
+        if self.index[ip] == 0 {
+            self.caches.push(cache);
+            self.index[ip] = self.caches.len() as u32;
+        } else {
+            self.caches[self.index[ip] as usize - 1] = cache;
+        }
+    }
+}
+
+impl Default for InlineCaches {
+    fn default() -> InlineCaches {
+        InlineCaches::new()
+    }
+}
@@ -41,4 +41,24 @@ impl InternedStringMap {
     pub unsafe fn get_unchecked(&self, idx: u16) -> &Rc<String> {
         self.data.get_unchecked(idx as usize)
     }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<Rc<String>> {
+        self.data.iter()
+    }
+
+    /// Creates an interned string map whose indexes match
+    /// the order of the strings in the vector.
+    pub fn from_vec(strings: Vec<String>) -> InternedStringMap {
+        InternedStringMap {
+            data: strings.into_iter().map(Rc::new).collect(),
+        }
+    }
 }
@@ -0,0 +1,164 @@
+//! Provides the hash map stored in Map records
+
+use super::bigint::BigInt;
+use super::gc::{push_gray_body, GcNode, GcTraceable};
+use super::nativeval::NativeValue;
+use super::value::Value;
+use super::vm::Vm;
+use hashbrown::HashMap;
+use std::borrow::Borrow;
+
+/// Hashable key of a map
+///
+/// Keys are snapshots of the values they were created from, so mutating
+/// an array after using it as a key doesn't change the key.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum MapKey {
+    Nil,
+    Bool(bool),
+    Int(i64),
+    BigInt(BigInt),
+    /// Bits of the float, with NaNs normalised (floats with
+    /// integer values are stored as `Int` keys)
+    Float(u64),
+    Str(String),
+    Tuple(Vec<MapKey>),
+}
+
+impl MapKey {
+    /// Creates a key from the value, returning None if it isn't hashable.
+    pub fn from_value(val: &NativeValue) -> Option<MapKey> {
+        match unsafe { val.unwrap() } {
+            Value::Nil => Some(MapKey::Nil),
+            Value::True => Some(MapKey::Bool(true)),
+            Value::False => Some(MapKey::Bool(false)),
+            Value::Int(n) => Some(MapKey::Int(n)),
+            Value::BigInt(n) => Some(MapKey::BigInt(n.as_ref().clone())),
+            // 1.0 == 1, so they must be the same key
+            Value::Float(n)
+                if n.fract() == 0.0
+                    && n >= std::i64::MIN as f64
+                    && n < -(std::i64::MIN as f64) =>
+            {
+                Some(MapKey::Int(n as i64))
+            }
+            Value::Float(n) => Some(MapKey::Float(if n.is_nan() {
+                std::f64::NAN.to_bits()
+            } else {
+                n.to_bits()
+            })),
+            Value::Str(s) => Some(MapKey::Str((s.as_ref().borrow() as &String).clone())),
+            Value::Array(a) => a
+                .as_ref()
+                .iter()
+                .map(MapKey::from_value)
+                .collect::<Option<Vec<MapKey>>>()
+                .map(MapKey::Tuple),
+            _ => None,
+        }
+    }
+
+    /// Creates a new value equal to the one the key was created from.
+    pub fn to_value(&self, vm: &Vm) -> Value {
+        match self {
+            MapKey::Nil => Value::Nil,
+            MapKey::Bool(b) => Value::bool(*b),
+            MapKey::Int(n) => Value::Int(*n),
+            MapKey::BigInt(n) => Value::BigInt(vm.malloc(n.clone())),
+            MapKey::Float(n) => Value::Float(f64::from_bits(*n)),
+            MapKey::Str(s) => Value::Str(vm.malloc(s.clone().into())),
+            MapKey::Tuple(keys) => {
+                let array = vm.malloc(Vec::with_capacity(keys.len()));
+                for key in keys {
+                    array.as_mut().push(key.to_value(vm).wrap());
+                }
+                Value::Array(array)
+            }
+        }
+    }
+}
+
+/// Hash map of hashable keys to values, iterated in insertion order
+pub struct Map {
+    indices: HashMap<MapKey, usize>,
+    // removed entries are left as holes until the map is compacted
+    entries: Vec<Option<(MapKey, NativeValue)>>,
+}
+
+impl Map {
+    pub fn new() -> Map {
+        Map {
+            indices: HashMap::new(),
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    pub fn get(&self, key: &MapKey) -> Option<&NativeValue> {
+        let idx = *self.indices.get(key)?;
+        self.entries[idx].as_ref().map(|(_, val)| val)
+    }
+
+    pub fn contains_key(&self, key: &MapKey) -> bool {
+        self.indices.contains_key(key)
+    }
+
+    /// Inserts the value, keeping the position of the key if it was
+    /// already in the map.
+    pub fn insert(&mut self, key: MapKey, val: NativeValue) {
+        if let Some(&idx) = self.indices.get(&key) {
+            self.entries[idx] = Some((key, val));
+        } else {
+            self.indices.insert(key.clone(), self.entries.len());
+            self.entries.push(Some((key, val)));
+        }
+    }
+
+    pub fn remove(&mut self, key: &MapKey) -> Option<NativeValue> {
+        let idx = self.indices.remove(key)?;
+        let (_, val) = self.entries[idx].take().unwrap();
+        if self.entries.len() > 2 * self.indices.len() + 8 {
+            self.compact();
+        }
+        Some(val)
+    }
+
+    // removes the holes left by removed entries
+    fn compact(&mut self) {
+        self.entries.retain(Option::is_some);
+        for (idx, entry) in self.entries.iter().enumerate() {
+            let (key, _) = entry.as_ref().unwrap();
+            *self.indices.get_mut(key).unwrap() = idx;
+        }
+    }
+
+    /// Iterates over the entries in insertion order
+    pub fn iter(&self) -> impl Iterator<Item = (&MapKey, &NativeValue)> {
+        self.entries
+            .iter()
+            .filter_map(|entry| entry.as_ref().map(|(key, val)| (key, val)))
+    }
+}
+
+impl Default for Map {
+    fn default() -> Map {
+        Map::new()
+    }
+}
+
+impl GcTraceable for Map {
+    unsafe fn trace(&self, gray_nodes: &mut Vec<*mut GcNode>) {
+        for (_, val) in self.iter() {
+            if let Some(ptr) = val.as_gc_pointer() {
+                push_gray_body(gray_nodes, ptr);
+            }
+        }
+    }
+}
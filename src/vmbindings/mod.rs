@@ -1,14 +1,19 @@
 //! Bindings for the virtual machine.
 
+pub mod bigint;
 pub mod env;
 pub mod exframe;
 mod foreignc;
 pub mod function;
+pub mod generator;
 pub mod gc;
 pub mod hmap;
+pub mod inline_cache;
 pub mod interned_string_map;
+pub mod map;
 pub mod nativeval;
 pub mod record;
+pub mod set;
 pub mod string;
 pub mod value;
 pub mod vm;
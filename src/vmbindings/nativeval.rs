@@ -1,6 +1,7 @@
 //! Provides the native value representation
 //! used by the virtual machine
 
+use super::bigint::BigInt;
 use super::function::Function;
 use super::gc::{ref_dec, ref_inc, Gc};
 use super::record::Record;
@@ -20,8 +21,11 @@ pub enum NativeValueType {
     TYPE_STR = 5,
     TYPE_DICT = 6,
     TYPE_ARRAY = 7,
+    TYPE_BOOL = 8,
+    TYPE_BIGINT = 9,
     TYPE_INTERPRETER_ERROR = 127,
     TYPE_INTERPRETER_ITERATOR = 128,
+    TYPE_INTERPRETER_UNSET = 129,
 }
 
 #[repr(C, packed)]
@@ -39,6 +43,7 @@ impl NativeValue {
         #[allow(non_camel_case_types)]
         match &self.r#type {
             NativeValueType::TYPE_NIL => Value::Nil,
+            NativeValueType::TYPE_BOOL => Value::bool(self.data != 0),
             NativeValueType::TYPE_INT => Value::Int(transmute::<u64, i64>(self.data)),
             NativeValueType::TYPE_FLOAT => Value::Float(f64::from_bits(self.data)),
             NativeValueType::TYPE_NATIVE_FN => {
@@ -50,6 +55,7 @@ impl NativeValue {
             NativeValueType::TYPE_ARRAY => {
                 Value::Array(Gc::from_raw(self.data as *mut Vec<NativeValue>))
             }
+            NativeValueType::TYPE_BIGINT => Value::BigInt(Gc::from_raw(self.data as *mut BigInt)),
             _ => Value::Nil,
         }
     }
@@ -60,7 +66,8 @@ impl NativeValue {
             NativeValueType::TYPE_STR
             | NativeValueType::TYPE_FN
             | NativeValueType::TYPE_DICT
-            | NativeValueType::TYPE_ARRAY => {
+            | NativeValueType::TYPE_ARRAY
+            | NativeValueType::TYPE_BIGINT => {
                 if self.data != 0 {
                     Some(self.data as _)
                 } else {
@@ -78,7 +85,8 @@ impl NativeValue {
             NativeValueType::TYPE_STR
             | NativeValueType::TYPE_FN
             | NativeValueType::TYPE_DICT
-            | NativeValueType::TYPE_ARRAY => {
+            | NativeValueType::TYPE_ARRAY
+            | NativeValueType::TYPE_BIGINT => {
                 ref_inc(self.data as *mut libc::c_void);
             }
             _ => {}
@@ -91,7 +99,8 @@ impl NativeValue {
             NativeValueType::TYPE_STR
             | NativeValueType::TYPE_FN
             | NativeValueType::TYPE_DICT
-            | NativeValueType::TYPE_ARRAY => {
+            | NativeValueType::TYPE_ARRAY
+            | NativeValueType::TYPE_BIGINT => {
                 ref_dec(self.data as *mut libc::c_void);
             }
             _ => {}
@@ -1,15 +1,64 @@
 //! Provides a record value in Hana
 
 use super::gc::{push_gray_body, GcNode, GcTraceable};
+use super::generator::Generator;
 use super::hmap::HaruHashMap;
+use super::map::Map;
 use super::nativeval::NativeValue;
 use super::string::HaruString;
 use super::value::Value;
+use hashbrown::hash_map::Entry;
+use hashbrown::HashMap;
 use std::any::Any;
 use std::borrow::Borrow;
 use std::boxed::Box;
+use std::cell::{Cell, RefCell};
 use std::hash::Hash;
 
+/// Identifier of the keys a record has, records which had the
+/// same keys inserted in the same order have the same shape
+pub type Shape = u32;
+
+/// Shape of records that had too many keys inserted to share their shapes
+pub const UNSHAPED: Shape = std::u32::MAX;
+// records with more keys than this are used as dictionaries
+const MAX_SHAPE_KEYS: usize = 64;
+const MAX_SHAPES: usize = 1 << 16;
+
+thread_local! {
+    // shapes of records after inserting a new key, indexed by their previous shape
+    static SHAPE_TRANSITIONS: RefCell<Vec<HashMap<String, Shape>>> = RefCell::new(vec![HashMap::new()]);
+    static LAST_STAMP: Cell<u64> = Cell::new(0);
+}
+
+fn next_shape(shape: Shape, key: &str, nkeys: usize) -> Shape {
+    if shape == UNSHAPED || nkeys > MAX_SHAPE_KEYS {
+        return UNSHAPED;
+    }
+    SHAPE_TRANSITIONS.with(|transitions| {
+        let mut transitions = transitions.borrow_mut();
+        if let Some(&next) = transitions[shape as usize].get(key) {
+            return next;
+        }
+        if transitions.len() == MAX_SHAPES {
+            return UNSHAPED;
+        }
+        let next = transitions.len() as Shape;
+        transitions[shape as usize].insert(key.to_string(), next);
+        transitions.push(HashMap::new());
+        next
+    })
+}
+
+// stamps are never reused, so that they identify both a record
+// and the set of keys it has
+fn next_stamp() -> u64 {
+    LAST_STAMP.with(|stamp| {
+        stamp.set(stamp.get() + 1);
+        stamp.get()
+    })
+}
+
 /// A record value in Hana
 pub struct Record {
     data: HaruHashMap,
@@ -17,6 +66,9 @@ pub struct Record {
     // it says static but it lasts as long as Record, see below!
     /// Dynamic field for use in native functions
     pub native_field: Option<Box<Any>>,
+    shape: Shape,
+    // changes whenever a key is inserted, see inline caches
+    stamp: u64,
 }
 
 impl Record {
@@ -25,6 +77,8 @@ impl Record {
             data: HaruHashMap::new(),
             prototype: None,
             native_field: None,
+            shape: 0,
+            stamp: next_stamp(),
         }
     }
 
@@ -33,6 +87,8 @@ impl Record {
             data: HaruHashMap::with_capacity(n),
             prototype: None,
             native_field: None,
+            shape: 0,
+            stamp: next_stamp(),
         }
     }
 
@@ -64,7 +120,37 @@ impl Record {
                 }
             };
         }
-        self.data.insert(k, v);
+        let nkeys = self.data.len();
+        match self.data.entry(k) {
+            Entry::Occupied(mut entry) => {
+                entry.insert(v);
+            }
+            Entry::Vacant(entry) => {
+                let key: &String = entry.key().borrow();
+                self.shape = next_shape(self.shape, key, nkeys);
+                self.stamp = next_stamp();
+                entry.insert(v);
+            }
+        }
+    }
+
+    /// Gets the value of a key of the record itself, ignoring its prototypes
+    pub fn get_own(&self, k: &str) -> Option<&NativeValue> {
+        self.data.get(k)
+    }
+
+    pub fn prototype(&self) -> Option<&Record> {
+        self.prototype
+    }
+
+    pub fn shape(&self) -> Shape {
+        self.shape
+    }
+
+    /// Returns a stamp that changes whenever a key is inserted into the record,
+    /// which never moves the values of existing keys otherwise.
+    pub fn stamp(&self) -> u64 {
+        self.stamp
     }
 
     pub fn iter(&self) -> hashbrown::hash_map::Iter<HaruString, NativeValue> {
@@ -91,5 +177,14 @@ impl GcTraceable for Record {
                 push_gray_body(gray_nodes, ptr);
             }
         }
+        // suspended generators keep their stack frame in the native field
+        // and maps keep their entries there
+        if let Some(field) = &self.native_field {
+            if let Some(generator) = field.downcast_ref::<Generator>() {
+                generator.trace(gray_nodes);
+            } else if let Some(map) = field.downcast_ref::<Map>() {
+                map.trace(gray_nodes);
+            }
+        }
     }
 }
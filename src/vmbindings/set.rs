@@ -0,0 +1,93 @@
+//! Provides the hash set stored in Set records
+
+use super::map::{Map, MapKey};
+use super::value::Value;
+
+/// Set of hashable values, iterated in insertion order
+pub struct Set {
+    // elements are the keys of a map of nil values
+    elements: Map,
+}
+
+impl Set {
+    pub fn new() -> Set {
+        Set {
+            elements: Map::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.elements.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.elements.is_empty()
+    }
+
+    pub fn contains(&self, elem: &MapKey) -> bool {
+        self.elements.contains_key(elem)
+    }
+
+    /// Inserts the element, returning false if it was already in the set.
+    pub fn insert(&mut self, elem: MapKey) -> bool {
+        if self.contains(&elem) {
+            return false;
+        }
+        self.elements.insert(elem, Value::Nil.wrap());
+        true
+    }
+
+    /// Removes the element, returning false if it wasn't in the set.
+    pub fn remove(&mut self, elem: &MapKey) -> bool {
+        self.elements.remove(elem).is_some()
+    }
+
+    /// Iterates over the elements in insertion order
+    pub fn iter(&self) -> impl Iterator<Item = &MapKey> {
+        self.elements.iter().map(|(elem, _)| elem)
+    }
+
+    /// Elements of either set, in the order of self then other
+    pub fn union(&self, other: &Set) -> Set {
+        let mut set = Set::new();
+        for elem in self.iter().chain(other.iter()) {
+            set.insert(elem.clone());
+        }
+        set
+    }
+
+    /// Elements of self that are in other
+    pub fn intersection(&self, other: &Set) -> Set {
+        let mut set = Set::new();
+        for elem in self.iter().filter(|elem| other.contains(elem)) {
+            set.insert(elem.clone());
+        }
+        set
+    }
+
+    /// Elements of self that aren't in other
+    pub fn difference(&self, other: &Set) -> Set {
+        let mut set = Set::new();
+        for elem in self.iter().filter(|elem| !other.contains(elem)) {
+            set.insert(elem.clone());
+        }
+        set
+    }
+
+    pub fn is_subset(&self, other: &Set) -> bool {
+        self.len() <= other.len() && self.iter().all(|elem| other.contains(elem))
+    }
+}
+
+impl Default for Set {
+    fn default() -> Set {
+        Set::new()
+    }
+}
+
+impl PartialEq for Set {
+    /// Sets are equal if they have the same elements, in any order.
+    fn eq(&self, other: &Set) -> bool {
+        self.len() == other.len() && self.is_subset(other)
+    }
+}
@@ -1,5 +1,6 @@
 //! Provides an abstraction for native values
 
+use super::bigint::BigInt;
 use super::function::Function;
 use super::gc::Gc;
 use super::nativeval::{NativeValue, NativeValueType};
@@ -9,7 +10,7 @@ use super::vm::Vm;
 use std::borrow::Borrow;
 extern crate libc;
 
-pub type NativeFnData = extern "C" fn(*mut Vm, u16);
+pub type NativeFnData = unsafe extern "C" fn(*mut Vm, u16);
 
 #[derive(Clone, PartialEq)]
 #[allow(non_camel_case_types, dead_code)]
@@ -23,6 +24,7 @@ pub enum Value {
     False,
 
     Int(i64),
+    BigInt(Gc<BigInt>),
     Float(f64),
     NativeFn(NativeFnData),
     Fn(Gc<Function>),
@@ -51,17 +53,21 @@ impl Value {
                     data: 0,
                 },
                 Value::True => NativeValue {
-                    r#type: NativeValueType::TYPE_INT,
+                    r#type: NativeValueType::TYPE_BOOL,
                     data: 1,
                 },
                 Value::False => NativeValue {
-                    r#type: NativeValueType::TYPE_INT,
+                    r#type: NativeValueType::TYPE_BOOL,
                     data: 0,
                 },
                 Value::Int(n) => NativeValue {
                     r#type: NativeValueType::TYPE_INT,
                     data: transmute::<i64, u64>(*n),
                 },
+                Value::BigInt(p) => NativeValue {
+                    r#type: NativeValueType::TYPE_BIGINT,
+                    data: transmute::<*const BigInt, u64>(p.to_raw()),
+                },
                 Value::Float(n) => NativeValue {
                     r#type: NativeValueType::TYPE_FLOAT,
                     data: transmute::<f64, u64>(*n),
@@ -96,6 +102,22 @@ impl Value {
         unsafe { value_get_prototype(vm, self.wrap()) }
     }
 
+    /// Wraps the big integer, demoting it to an `Int` if it fits in 64 bits.
+    pub fn from_bigint(n: BigInt, vm: &Vm) -> Value {
+        match n.to_i64() {
+            Some(n) => Value::Int(n),
+            None => Value::BigInt(vm.malloc(n)),
+        }
+    }
+
+    pub fn bool(b: bool) -> Value {
+        if b {
+            Value::True
+        } else {
+            Value::False
+        }
+    }
+
     // bool
     pub fn is_true(&self, vm: *const Vm) -> bool {
         unsafe { value_is_true(self.wrap(), vm) }
@@ -105,7 +127,8 @@ impl Value {
     pub fn type_name(&self) -> &str {
         match self {
             Value::Nil => "nil",
-            Value::Int(_) => "Int",
+            Value::True | Value::False => "Bool",
+            Value::Int(_) | Value::BigInt(_) => "Int",
             Value::Float(_) => "Float",
             Value::NativeFn(_) | Value::Fn(_) => "Function",
             Value::Str(_) => "String",
@@ -123,9 +146,10 @@ impl fmt::Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Value::Nil => write!(f, "[nil]"),
-            Value::True => write!(f, "1"),
-            Value::False => write!(f, "0"),
+            Value::True => write!(f, "true"),
+            Value::False => write!(f, "false"),
             Value::Int(n) => write!(f, "{}", n),
+            Value::BigInt(p) => write!(f, "{}", p.as_ref()),
             Value::Float(n) => write!(f, "{}", n),
             Value::NativeFn(_) => write!(f, "[native fn]"),
             Value::Fn(_) => write!(f, "[fn]"),
@@ -142,7 +166,10 @@ impl fmt::Debug for Value {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Value::Nil => write!(f, "[nil]"),
+            Value::True => write!(f, "true"),
+            Value::False => write!(f, "false"),
             Value::Int(n) => write!(f, "{}", n),
+            Value::BigInt(p) => write!(f, "{}", p.as_ref()),
             Value::Float(n) => write!(f, "{}", n),
             Value::NativeFn(_) => write!(f, "[native fn]"),
             Value::Fn(_) => write!(f, "[fn]"),
@@ -1,9 +1,10 @@
 //! Provides an interface for the virtual machine
 
 use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 use std::mem::ManuallyDrop;
-use std::path::Path;
-use std::ptr::{null_mut, NonNull};
+use std::path::{Path, PathBuf};
+use std::ptr::{null, null_mut, NonNull};
 use std::rc::Rc;
 
 extern crate libc;
@@ -12,10 +13,14 @@ use super::env::Env;
 use super::exframe::ExFrame;
 use super::function::Function;
 use super::gc::*;
+use super::generator::{Generator, GeneratorState};
 use super::hmap::HaruHashMap;
+use super::inline_cache::InlineCaches;
 use super::interned_string_map::InternedStringMap;
+use super::map::Map;
 use super::nativeval::{NativeValue, NativeValueType};
 use super::record::Record;
+use super::set::Set;
 use super::string::HaruString;
 use super::value::Value;
 
@@ -25,7 +30,29 @@ use crate::hanayo::HanayoCtx;
 
 extern crate num_derive;
 
-const CALL_STACK_SIZE: usize = 512;
+/// Default maximum number of stack frames in the call stack.
+pub const DEFAULT_MAX_STACK_DEPTH: usize = 10000;
+/// Largest maximum number of stack frames the call stack can be given.
+pub const MAX_STACK_DEPTH_LIMIT: usize = 1 << 24;
+
+// layout of the pool of stack frames
+fn call_stack_layout(max_stack_depth: usize) -> std::alloc::Layout {
+    std::alloc::Layout::array::<Env>(max_stack_depth).expect("call stack is too large")
+}
+
+// allocates the pool of stack frames, aborting if out of memory
+unsafe fn alloc_call_stack(max_stack_depth: usize, zeroed: bool) -> *mut Env {
+    let layout = call_stack_layout(max_stack_depth);
+    let ptr = if zeroed {
+        std::alloc::alloc_zeroed(layout)
+    } else {
+        std::alloc::alloc(layout)
+    };
+    if ptr.is_null() {
+        std::alloc::handle_alloc_error(layout);
+    }
+    ptr as *mut Env
+}
 
 #[repr(transparent)]
 struct ConstNonNull<T: Sized> {
@@ -50,7 +77,7 @@ impl<T: Sized> ConstNonNull<T> {
 
 //
 #[repr(u8)]
-#[derive(Debug, PartialEq, Clone, FromPrimitive, ToPrimitive)]
+#[derive(Debug, PartialEq, Clone, Copy, FromPrimitive, ToPrimitive)]
 #[allow(non_camel_case_types)]
 pub enum VmOpcode {
     OP_HALT,
@@ -127,6 +154,128 @@ pub enum VmOpcode {
     OP_SWAP,
     // modules
     OP_USE,
+    OP_USE_AS,
+    // match expressions
+    OP_DUP,
+    OP_MATCH_EQ,
+    OP_MATCH_ARRAY,
+    OP_MATCH_INDEX,
+    OP_MATCH_REST,
+    OP_MATCH_MEMBER,
+    // strings
+    OP_STR_CONCAT,
+    // function arguments
+    OP_CALL_KW,
+    OP_JARG,
+    // destructuring
+    OP_MATCH_FAIL,
+    // slices
+    OP_SLICE_GET,
+    OP_SLICE_SET,
+    // generators
+    OP_GENERATOR_NEW,
+    OP_YIELD,
+    // big integers
+    OP_PUSH_BIGINT,
+    // maps
+    OP_MAP_LOAD,
+    OP_MEMBER_INDEX_GET,
+    OP_MEMBER_INDEX_GET_NO_POP,
+    // superinstructions
+    OP_SET_LOCAL_POP,
+    OP_SET_GLOBAL_POP,
+    // quickened
+    OP_ADD_INT,
+    OP_ADD_FLOAT,
+    OP_SUB_INT,
+    OP_SUB_FLOAT,
+    OP_MUL_INT,
+    OP_MUL_FLOAT,
+    OP_LT_INT,
+    OP_LT_FLOAT,
+    OP_LEQ_INT,
+    OP_LEQ_FLOAT,
+    OP_GT_INT,
+    OP_GT_FLOAT,
+    OP_GEQ_INT,
+    OP_GEQ_FLOAT,
+}
+
+/// Returns the size in bytes of the instruction (opcode and its operands)
+/// starting at `code[ip]`.
+pub fn instruction_size(code: &[u8], ip: usize) -> usize {
+    checked_instruction_size(code, ip).expect("invalid instruction")
+}
+
+/// Returns the size in bytes of the instruction starting at `code[ip]`,
+/// or `None` if it isn't a valid instruction or the code ends before it does.
+pub fn checked_instruction_size(code: &[u8], ip: usize) -> Option<usize> {
+    use num_traits::cast::FromPrimitive;
+    let cstr_size = |start: usize| {
+        // null-terminated string operand
+        Some(code.get(start..)?.iter().position(|&b| b == 0)? + 1)
+    };
+    let u16_at = |start: usize| Some(u16::from_be_bytes([*code.get(start)?, *code.get(start + 1)?]));
+    let size = 1 + match VmOpcode::from_u8(*code.get(ip)?)? {
+        VmOpcode::OP_PUSH8 | VmOpcode::OP_IADD | VmOpcode::OP_IMUL => 1,
+        VmOpcode::OP_PUSH16
+        | VmOpcode::OP_PUSHSTR_INTERNED
+        | VmOpcode::OP_ENV_NEW
+        | VmOpcode::OP_GENERATOR_NEW
+        | VmOpcode::OP_SET_LOCAL
+        | VmOpcode::OP_SET_LOCAL_FUNCTION_DEF
+        | VmOpcode::OP_SET_LOCAL_POP
+        | VmOpcode::OP_GET_LOCAL
+        | VmOpcode::OP_JMP
+        | VmOpcode::OP_JCOND
+        | VmOpcode::OP_JNCOND
+        | VmOpcode::OP_JCOND_NO_POP
+        | VmOpcode::OP_JNCOND_NO_POP
+        | VmOpcode::OP_CALL
+        | VmOpcode::OP_EXFRAME_RET
+        | VmOpcode::OP_RETCALL
+        | VmOpcode::OP_FOR_IN
+        | VmOpcode::OP_MATCH_EQ
+        | VmOpcode::OP_MATCH_INDEX
+        | VmOpcode::OP_STR_CONCAT => 2,
+        VmOpcode::OP_PUSH32
+        | VmOpcode::OP_GET_LOCAL_UP
+        | VmOpcode::OP_JMP_LONG
+        | VmOpcode::OP_MATCH_REST
+        | VmOpcode::OP_JARG => 4,
+        VmOpcode::OP_MATCH_ARRAY => 5,
+        VmOpcode::OP_PUSH64 | VmOpcode::OP_PUSHF64 => 8,
+        VmOpcode::OP_PUSHSTR
+        | VmOpcode::OP_PUSH_BIGINT
+        | VmOpcode::OP_SET_GLOBAL
+        | VmOpcode::OP_SET_GLOBAL_POP
+        | VmOpcode::OP_GET_GLOBAL
+        | VmOpcode::OP_MEMBER_GET
+        | VmOpcode::OP_MEMBER_GET_NO_POP
+        | VmOpcode::OP_MEMBER_INDEX_GET
+        | VmOpcode::OP_MEMBER_INDEX_GET_NO_POP
+        | VmOpcode::OP_MEMBER_SET
+        | VmOpcode::OP_USE
+        | VmOpcode::OP_USE_AS => cstr_size(ip + 1)?,
+        // jump label followed by the key
+        VmOpcode::OP_MATCH_MEMBER => 2 + cstr_size(ip + 3)?,
+        // args, end label, required args and rest flag followed by the names
+        VmOpcode::OP_DEF_FUNCTION_PUSH => {
+            let nargs = u16_at(ip + 1)?;
+            (0..nargs).try_fold(7, |size, _| Some(size + cstr_size(ip + 1 + size)?))?
+        }
+        // positional and keyword args followed by the keywords
+        VmOpcode::OP_CALL_KW => {
+            let nkwargs = u16_at(ip + 3)?;
+            (0..nkwargs).try_fold(4, |size, _| Some(size + cstr_size(ip + 1 + size)?))?
+        }
+        _ => 0,
+    };
+    if ip + size <= code.len() {
+        Some(size)
+    } else {
+        None
+    }
 }
 
 #[repr(C)]
@@ -136,9 +285,9 @@ pub struct Vm {
     localenv: Option<NonNull<Env>>,
     // pointer to start of pool of stack frames
     localenv_bp: *mut Env,
-    // global environment, all unscoped variables/variables
+    // current global environment, all unscoped variables/variables
     // starting with '$' should also be stored here without '$'
-    globalenv: Option<Box<HaruHashMap>>,
+    globalenv: *mut HaruHashMap,
     exframes: Option<Vec<ExFrame>>, // exception frame
     pub code: Option<Vec<u8>>,      // where all the code is
     pub stack: Vec<NativeValue>,    // stack
@@ -149,6 +298,7 @@ pub struct Vm {
     pub(crate) dfloat: Option<Gc<Record>>,
     pub(crate) darray: Option<Gc<Record>>,
     pub(crate) drec: Option<Gc<Record>>,
+    pub(crate) dbool: Option<Gc<Record>>,
 
     pub error: VmError,
     pub error_expected: u32,
@@ -157,11 +307,49 @@ pub struct Vm {
     exframe_fallthrough: Option<ConstNonNull<ExFrame>>,
     native_call_depth: usize,
 
+    // called before each instruction while a debugger is attached
+    debug_hook: Option<unsafe extern "C" fn(*mut Vm)>,
+
     // rust-specific fields
     pub interned_strings: Option<InternedStringMap>,
     pub modules_info: Option<Rc<RefCell<ModulesInfo>>>,
     pub(crate) stdlib: Option<HanayoCtx>,
+    // exports of modules imported with `use ... as`
+    // (must be dropped before the gc manager)
+    module_namespaces: HashMap<PathBuf, Gc<Record>>,
+    // modules imported with `use ... as` that are still running
+    modules_loading: HashSet<PathBuf>,
+    // global environments of the main program and modules
+    // imported with `use ... as` (boxed so that pointers stay valid)
+    #[allow(clippy::vec_box)]
+    globalenvs: Vec<Box<HaruHashMap>>,
     gc_manager: Option<RefCell<GcManager>>,
+    // whether imported modules are cached as compiled bytecode
+    pub module_cache: bool,
+    // whether code compiled at runtime is optimized
+    pub optimize: bool,
+    // number of stack frames in the pool of stack frames
+    max_stack_depth: usize,
+    debugger: Option<Box<dyn DebugHook>>,
+    // caches of member lookups, shared by execution contexts
+    member_caches: InlineCaches,
+}
+
+/// Hook for debuggers attached to the virtual machine.
+pub trait DebugHook {
+    /// Called before the instruction at `vm.ip()` is executed.
+    fn step(&mut self, vm: &mut Vm);
+}
+
+unsafe extern "C" fn vm_debug_hook(vm: *mut Vm) {
+    let vm = &mut *vm;
+    // the debugger is taken out so that it can borrow the vm
+    if let Some(mut debugger) = vm.debugger.take() {
+        debugger.step(vm);
+        if vm.debug_hook.is_some() {
+            vm.debugger = Some(debugger);
+        }
+    }
 }
 
 #[link(name = "hana", kind = "static")]
@@ -171,22 +359,23 @@ extern "C" {
     fn vm_call(vm: *mut Vm, fun: NativeValue, args: *const Vec<NativeValue>) -> NativeValue;
 }
 
+fn generator_of(rec: &mut Record) -> &mut Generator {
+    let field = rec.native_field.as_mut().unwrap();
+    field.downcast_mut::<Generator>().unwrap()
+}
+
 impl Vm {
     #[cfg_attr(tarpaulin, skip)]
     pub fn new(
         code: Option<Vec<u8>>, modules_info: Option<Rc<RefCell<ModulesInfo>>>,
         interned_strings: Option<InternedStringMap>,
     ) -> Vm {
+        let mut globalenvs = vec![Box::new(HaruHashMap::new())];
         Vm {
             ip: 0,
             localenv: None,
-            localenv_bp: {
-                use std::alloc::{alloc, Layout};
-                use std::mem::size_of;
-                let layout = Layout::from_size_align(size_of::<Env>() * CALL_STACK_SIZE, 4);
-                unsafe { alloc(layout.unwrap()) as *mut Env }
-            },
-            globalenv: Some(Box::new(HaruHashMap::new())),
+            localenv_bp: unsafe { alloc_call_stack(DEFAULT_MAX_STACK_DEPTH, false) },
+            globalenv: globalenvs[0].as_mut() as *mut HaruHashMap,
             exframes: Some(Vec::with_capacity(2)),
             code,
             stack: Vec::with_capacity(2),
@@ -195,14 +384,24 @@ impl Vm {
             dfloat: None,
             darray: None,
             drec: None,
+            dbool: None,
             error: VmError::ERROR_NO_ERROR,
             error_expected: 0,
             exframe_fallthrough: None,
             native_call_depth: 0,
+            debug_hook: None,
             interned_strings,
             modules_info,
             stdlib: None,
             gc_manager: Some(RefCell::new(GcManager::new())),
+            module_cache: false,
+            optimize: false,
+            max_stack_depth: DEFAULT_MAX_STACK_DEPTH,
+            module_namespaces: HashMap::new(),
+            modules_loading: HashSet::new(),
+            globalenvs,
+            debugger: None,
+            member_caches: InlineCaches::new(),
         }
     }
 
@@ -232,13 +431,15 @@ impl Vm {
 
     // globals
     pub fn global(&self) -> &HaruHashMap {
-        use std::borrow::Borrow;
-        self.globalenv.as_ref().unwrap().borrow()
+        unsafe { &*self.globalenv }
     }
 
     pub fn mut_global(&mut self) -> &mut HaruHashMap {
-        use std::borrow::BorrowMut;
-        self.globalenv.as_mut().unwrap().borrow_mut()
+        unsafe { &mut *self.globalenv }
+    }
+
+    pub(crate) fn globalenv_ptr(&self) -> *mut HaruHashMap {
+        self.globalenv
     }
 
     // gc
@@ -258,6 +459,16 @@ impl Vm {
         self.gc_manager.as_ref().unwrap().borrow_mut().enable()
     }
 
+    pub fn gc_mode(&self) -> GcMode {
+        self.gc_manager.as_ref().unwrap().borrow().mode()
+    }
+
+    /// Sets how the garbage collector collects memory,
+    /// this should be called when the virtual machine is created.
+    pub fn set_gc_mode(&self, mode: GcMode) {
+        self.gc_manager.as_ref().unwrap().borrow_mut().set_mode(mode)
+    }
+
     pub unsafe fn stack_push_gray(&mut self, val: Value) {
         let w = val.wrap();
         if let Some(ptr) = w.as_gc_pointer() {
@@ -271,28 +482,64 @@ impl Vm {
     }
 
     // call stack
-    pub unsafe fn enter_env(&mut self, fun: &'static Function) {
-        if self.localenv.is_none() {
-            self.localenv = NonNull::new(self.localenv_bp);
-        } else {
-            let localenv = self.localenv.unwrap().as_ptr();
-            if localenv.offset_from(self.localenv_bp) > (CALL_STACK_SIZE as isize) {
-                panic!("maximum stack depth exceeded");
-            } else {
-                self.localenv = NonNull::new(localenv.add(1));
-            }
+    /// Maximum number of stack frames in the call stack.
+    pub fn max_stack_depth(&self) -> usize {
+        self.max_stack_depth
+    }
+
+    /// Sets the maximum number of stack frames in the call stack. Calls
+    /// past it raise a `StackOverflowError`.
+    ///
+    /// This reallocates the call stack, so it can't be done while the
+    /// virtual machine is inside of a function. The depth must be between
+    /// 1 and `MAX_STACK_DEPTH_LIMIT`.
+    pub fn set_max_stack_depth(&mut self, max_stack_depth: usize) {
+        assert!(self.localenv.is_none(), "can't resize call stack while it is in use");
+        assert!(max_stack_depth > 0 && max_stack_depth <= MAX_STACK_DEPTH_LIMIT);
+        unsafe {
+            use std::alloc::dealloc;
+            dealloc(self.localenv_bp as *mut u8, call_stack_layout(self.max_stack_depth));
+            self.localenv_bp = alloc_call_stack(max_stack_depth, false);
         }
-        std::ptr::write(
-            self.localenv.unwrap().as_ptr(),
-            Env::new(self.ip, fun.get_bound_ptr(), fun.nargs),
-        );
+        self.max_stack_depth = max_stack_depth;
+    }
+
+    // returns false if the call stack is full
+    unsafe fn push_env(&mut self, env: Env) -> bool {
+        if self.localenv_depth() == self.max_stack_depth {
+            return false;
+        }
+        self.localenv = match self.localenv {
+            Some(localenv) => NonNull::new(localenv.as_ptr().add(1)),
+            None => NonNull::new(self.localenv_bp),
+        };
+        std::ptr::write(self.localenv.unwrap().as_ptr(), env);
+        true
+    }
+
+    /// Enters a new stack frame for calling the function.
+    ///
+    /// If the call stack is full, this raises a `StackOverflowError`
+    /// and returns false.
+    pub unsafe fn enter_env(&mut self, fun: &'static Function) -> bool {
+        let mut env = Env::new(self.ip, fun.get_bound_ptr(), fun.nargs);
+        env.globalenv = self.globalenv;
+        env.callee = fun;
+        if !self.push_env(env) {
+            self.raise_stack_overflow_error();
+            return false;
+        }
+        self.globalenv = fun.globalenv;
         self.ip = fun.ip;
+        true
     }
 
     pub unsafe fn enter_env_tail(&mut self, fun: &'static Function) {
         let env = self.localenv.as_mut().unwrap().as_mut();
         env.nargs = fun.nargs;
         env.lexical_parent = fun.get_bound_ptr();
+        env.callee = fun;
+        self.globalenv = fun.globalenv;
         self.ip = fun.ip;
     }
 
@@ -300,6 +547,9 @@ impl Vm {
         if let Some(localenv) = self.localenv {
             let localenv = localenv.as_ptr();
             self.ip = (*localenv).retip;
+            if !(*localenv).globalenv.is_null() {
+                self.globalenv = (*localenv).globalenv;
+            }
             if localenv == self.localenv_bp {
                 std::ptr::drop_in_place(localenv);
                 self.localenv = None;
@@ -310,6 +560,196 @@ impl Vm {
         }
     }
 
+    /// Returns from the current stack frame like OP_RET, with the return
+    /// value on top of the stack.
+    ///
+    /// This returns true if the frame was entered from native code,
+    /// which leaves the frame itself.
+    pub unsafe fn ret(&mut self) -> bool {
+        let localenv = self.localenv.unwrap().as_ptr();
+        let exit_ip = match (*localenv).generator.take() {
+            Some(rec) => self.finish_generator(rec),
+            None => None,
+        };
+        if (*localenv).retip == std::u32::MAX {
+            return true;
+        }
+        self.leave_env();
+        if let Some(exit_ip) = exit_ip {
+            self.ip = exit_ip;
+        }
+        false
+    }
+
+    // generators
+    /// Moves the current stack frame out of the call stack, leaving behind
+    /// an empty frame that returns to the same place.
+    unsafe fn take_env(&mut self) -> Env {
+        let localenv = self.localenv.unwrap().as_ptr();
+        let mut placeholder = Env::new((*localenv).retip, null(), 0);
+        placeholder.globalenv = (*localenv).globalenv;
+        std::mem::replace(&mut *localenv, placeholder)
+    }
+
+    /// Suspends the current stack frame into a new generator record,
+    /// which is returned to the caller.
+    pub unsafe fn new_generator(&mut self) -> bool {
+        let rec = self.malloc(Record::new());
+        if let Some(stdlib) = &self.stdlib {
+            rec.as_mut().insert(
+                "prototype",
+                Value::Record(stdlib.generator_rec.clone()).wrap(),
+            );
+        }
+        let localenv = self.localenv.unwrap().as_ptr();
+        let function = Value::Fn(Gc::from_raw((*localenv).callee as *mut Function)).wrap();
+        // the generator that tail called the function returns the new one
+        let outer = (*localenv).generator.take();
+        let env = self.take_env();
+        (*localenv).generator = outer;
+        rec.as_mut().native_field = Some(Box::new(Generator::new(
+            self.ip,
+            env,
+            self.globalenv,
+            function,
+        )));
+        self.stack.push(Value::Record(rec).wrap());
+        self.ret()
+    }
+
+    /// Suspends the current stack frame into its generator, returning
+    /// the value on top of the stack to the caller.
+    pub unsafe fn yield_generator(&mut self) -> bool {
+        let val = self.stack.pop().unwrap();
+        let localenv = self.localenv.unwrap().as_ptr();
+        let rec = (*localenv).generator.take().unwrap();
+        let generator = generator_of(rec.as_mut());
+        generator.stack = self.stack.split_off(generator.stack_base);
+        generator.ip = self.ip;
+        generator.env = Some(self.take_env());
+        generator.state = GeneratorState::Suspended;
+        self.stack.push(val);
+        self.ret()
+    }
+
+    /// Resumes the generator, which returns to the current instruction
+    /// pointer once it yields or returns. `val` is the result of the yield
+    /// expression it is suspended on.
+    ///
+    /// If the generator was resumed by a `for... in` statement, it jumps
+    /// to `exit_ip` when it returns instead.
+    ///
+    /// This returns false if the generator has finished or is already running,
+    /// which is an error. If the call stack is full, it raises a
+    /// `StackOverflowError` instead, returning true if the exception was
+    /// handled so that execution continues in its handler.
+    pub unsafe fn resume_generator(
+        &mut self, rec: Gc<Record>, val: NativeValue, exit_ip: u32,
+    ) -> bool {
+        let generator = generator_of(rec.as_mut());
+        match generator.state {
+            GeneratorState::Running => {
+                self.error = VmError::ERROR_GENERATOR_RUNNING;
+                return false;
+            }
+            GeneratorState::Finished => return false,
+            _ => {}
+        }
+        if self.localenv_depth() == self.max_stack_depth {
+            self.raise_stack_overflow_error();
+            return self.error == VmError::ERROR_NO_ERROR;
+        }
+        let mut env = generator.env.take().unwrap();
+        env.retip = self.ip;
+        env.globalenv = self.globalenv;
+        env.generator = Some(rec.clone());
+        self.push_env(env);
+        self.globalenv = generator.globalenv;
+        self.ip = generator.ip;
+        generator.stack_base = self.stack.len();
+        self.stack.append(&mut generator.stack);
+        if generator.state == GeneratorState::Suspended {
+            self.stack.push(val);
+        }
+        generator.exit_ip = exit_ip;
+        generator.state = GeneratorState::Running;
+        true
+    }
+
+    unsafe fn finish_generator(&mut self, rec: Gc<Record>) -> Option<u32> {
+        let generator = generator_of(rec.as_mut());
+        generator.state = GeneratorState::Finished;
+        rec.as_mut().insert("stopped", Value::True.wrap());
+        if generator.exit_ip == std::u32::MAX {
+            None
+        } else {
+            // the return value is dropped along with the
+            // for... in statement's generator and iterator
+            self.stack.truncate(generator.stack_base - 2);
+            Some(generator.exit_ip)
+        }
+    }
+
+    /// Resumes the generator from native code, returning the value
+    /// it yields or returns, or nil if it has already finished.
+    pub fn call_generator(&mut self, rec: Gc<Record>, val: NativeValue) -> Option<NativeValue> {
+        unsafe {
+            let last = self.ip;
+            let oldenv = self.localenv;
+            let oldglobalenv = self.globalenv;
+            self.ip = std::u32::MAX;
+            if !self.resume_generator(rec, val, std::u32::MAX) {
+                self.ip = last;
+                return if self.error == VmError::ERROR_NO_ERROR {
+                    Some(Value::Nil.wrap())
+                } else {
+                    None
+                };
+            }
+            if self.exframe_fallthrough.is_some() {
+                // StackOverflowError caught outside of the native call
+                return None;
+            }
+            let curenv = self.localenv;
+            vm_execute(self);
+            if self.error != VmError::ERROR_NO_ERROR
+                || self.exframe_fallthrough.is_some()
+                || self.localenv != curenv
+            {
+                return None;
+            }
+            std::ptr::drop_in_place(curenv.unwrap().as_ptr());
+            self.localenv = oldenv;
+            self.globalenv = oldglobalenv;
+            self.ip = last;
+            self.stack.pop()
+        }
+    }
+
+    // maps
+    /// Allocates a new empty map record.
+    pub fn new_map(&self) -> Gc<Record> {
+        let rec = self.malloc(Record::new());
+        if let Some(stdlib) = &self.stdlib {
+            rec.as_mut()
+                .insert("prototype", Value::Record(stdlib.map_rec.clone()).wrap());
+        }
+        rec.as_mut().native_field = Some(Box::new(Map::new()));
+        rec
+    }
+
+    // sets
+    /// Allocates a new set record holding the set.
+    pub fn new_set(&self, set: Set) -> Gc<Record> {
+        let rec = self.malloc(Record::new());
+        if let Some(stdlib) = &self.stdlib {
+            rec.as_mut()
+                .insert("prototype", Value::Record(stdlib.set_rec.clone()).wrap());
+        }
+        rec.as_mut().native_field = Some(Box::new(set));
+        rec
+    }
+
     // accessors
     pub fn localenv(&self) -> Option<NonNull<Env>> {
         self.localenv.clone()
@@ -330,6 +770,60 @@ impl Vm {
         }
         vec
     }
+    /// Number of stack frames in the call stack.
+    pub fn localenv_depth(&self) -> usize {
+        match self.localenv {
+            Some(localenv) => unsafe { localenv.as_ptr().offset_from(self.localenv_bp) as usize + 1 },
+            None => 0,
+        }
+    }
+    /// Locations of the calls in the call stack, from the innermost one.
+    ///
+    /// Each location is formatted as `[function@]file:line:column`, or as
+    /// the bytecode index of the call if it has no source map.
+    pub fn backtrace(&self) -> Vec<String> {
+        let modules_info = match self.modules_info.as_ref() {
+            Some(modules_info) => modules_info.borrow(),
+            None => return Vec::new(),
+        };
+        // recursive calls return to the same few places
+        let mut locations: HashMap<u32, String> = HashMap::new();
+        let mut frames = Vec::with_capacity(self.localenv_depth());
+        for env in self.localenv_to_vec() {
+            let ip = env.retip;
+            let location = locations.entry(ip).or_insert_with(|| {
+                if ip == std::u32::MAX {
+                    return "[native code]".to_string();
+                }
+                let ip = ip as usize;
+                let smap = modules_info.lookup_smap(ip);
+                let file = smap.and_then(|smap| {
+                    let src = modules_info.sources.get(smap.fileno)?;
+                    let file = modules_info.files.get(smap.fileno)?;
+                    Some((src, file, smap.file.0))
+                });
+                match file {
+                    Some((src, file, pos)) => {
+                        let (line, col) = crate::ast::pos_to_line(&src, pos);
+                        format!(
+                            "{}{}:{}:{}",
+                            if let Some(sym) = modules_info.symbol.get(&ip) {
+                                sym.clone() + "@"
+                            } else {
+                                "".to_string()
+                            },
+                            file,
+                            line,
+                            col
+                        )
+                    }
+                    None => format!("bytecode index {}", ip),
+                }
+            });
+            frames.push(location.clone());
+        }
+        frames
+    }
 
     // exceptions
     fn exframes(&self) -> &Vec<ExFrame> {
@@ -340,33 +834,98 @@ impl Vm {
     }
     pub fn enter_exframe(&mut self) -> &mut ExFrame {
         let localenv = self.localenv.clone();
-        let len = self.stack.len() - 1;
+        // the handlers and their types are on the stack above a nil
+        let mut len = self.stack.len() - 1;
+        while self.stack[len].r#type != NativeValueType::TYPE_NIL {
+            len -= 2;
+        }
         let native_call_depth = self.native_call_depth;
+        let globalenv = self.globalenv;
         self.mut_exframes()
-            .push(ExFrame::new(localenv, len, native_call_depth));
+            .push(ExFrame::new(localenv, len, native_call_depth, globalenv));
         self.mut_exframes().last_mut().unwrap()
     }
     pub fn leave_exframe(&mut self) {
         self.mut_exframes().pop();
     }
     pub fn raise(&mut self) -> bool {
-        if self.exframes().len() == 0 {
-            return false;
-        }
         let val = unsafe { self.stack.last().unwrap().unwrap() };
-        for exframe in self.exframes.as_ref().unwrap().iter() {
-            if let Some(handler) = exframe.get_handler(self, &val) {
-                self.ip = handler.ip;
-                if handler.nargs == 0 {
-                    self.stack.pop();
-                }
-                if exframe.unwind_native_call_depth != self.native_call_depth {
-                    self.exframe_fallthrough = ConstNonNull::new(exframe);
-                }
-                return true;
-            }
+        // the innermost exception frame with a handler for it catches it
+        let vm: *const Vm = self;
+        let idx = match self
+            .exframes()
+            .iter()
+            .rposition(|exframe| exframe.get_handler(vm, &val).is_some())
+        {
+            Some(idx) => idx,
+            None => return false,
+        };
+        self.mut_exframes().truncate(idx + 1);
+        let exframe = &self.exframes.as_ref().unwrap()[idx];
+        let handler = exframe.get_handler(vm, &val).unwrap();
+        let (ip, nargs) = (handler.ip, handler.nargs);
+        let (unwind_env, unwind_stack) = (exframe.unwind_env, exframe.unwind_stack);
+        self.globalenv = exframe.unwind_globalenv;
+        // cleared once execution reaches the exception frame's native call
+        self.exframe_fallthrough = ConstNonNull::new(exframe);
+        // the handler runs in the stack frame of the try statement
+        unsafe { self.unwind_env(unwind_env) };
+        let val = self.stack.pop().unwrap();
+        self.stack.truncate(unwind_stack);
+        self.ip = ip;
+        if nargs != 0 {
+            self.stack.push(val);
         }
-        false
+        true
+    }
+
+    // drops the stack frames above the given one
+    unsafe fn unwind_env(&mut self, env: Option<NonNull<Env>>) {
+        while self.localenv != env {
+            let localenv = self.localenv.unwrap().as_ptr();
+            std::ptr::drop_in_place(localenv);
+            self.localenv = if localenv == self.localenv_bp {
+                None
+            } else {
+                NonNull::new(localenv.sub(1))
+            };
+        }
+    }
+
+    // raises a StackOverflowError record when a function is called
+    // with a full call stack
+    fn raise_stack_overflow_error(&mut self) {
+        let rec = self.malloc(Record::new());
+        if let Some(stdlib) = self.stdlib.as_ref() {
+            rec.as_mut().insert(
+                "prototype",
+                Value::Record(stdlib.stack_overflow_error.clone()).wrap(),
+            );
+        }
+        let why = format!("maximum stack depth of {} exceeded", self.max_stack_depth);
+        rec.as_mut()
+            .insert("why", Value::Str(self.malloc(why.into())).wrap());
+        rec.as_mut()
+            .insert("depth", Value::Int(self.max_stack_depth as i64).wrap());
+        let backtrace = self.malloc(Vec::new());
+        for frame in self.backtrace() {
+            let frame = Value::Str(self.malloc(frame.into()));
+            backtrace.as_mut().push(frame.wrap());
+        }
+        rec.as_mut()
+            .insert("backtrace", Value::Array(backtrace).wrap());
+        self.stack.push(Value::Record(rec).wrap());
+        if !self.raise() {
+            self.error = VmError::ERROR_UNHANDLED_EXCEPTION;
+        }
+    }
+
+    // member lookups
+    /// Gets the value of the key in the record or its prototypes through
+    /// the inline cache of the instruction at `ip`, returning null if the
+    /// key isn't found.
+    pub fn member_get(&mut self, rec: &Record, key: &[u8], ip: u32) -> *const NativeValue {
+        self.member_caches.member_get(rec, key, ip)
     }
 
     // functions
@@ -409,7 +968,7 @@ impl Vm {
             ip: self.ip,
             localenv: self.localenv.take(),
             localenv_bp: self.localenv_bp,
-            globalenv: None, // shared
+            globalenv: null_mut(), // shared
             exframes: self.exframes.take(),
             code: None, // shared
             stack: std::mem::replace(&mut self.stack, Vec::with_capacity(2)),
@@ -419,24 +978,29 @@ impl Vm {
             dfloat: None,
             darray: None,
             drec: None,
+            dbool: None,
             // shared
             error: VmError::ERROR_NO_ERROR,
             error_expected: 0,
             interned_strings: None,
             exframe_fallthrough: self.exframe_fallthrough.take(),
             native_call_depth: self.native_call_depth,
+            debug_hook: None,
             modules_info: None,
             stdlib: None,
             gc_manager: None,
+            module_cache: self.module_cache,
+            optimize: self.optimize,
+            max_stack_depth: self.max_stack_depth,
+            module_namespaces: HashMap::new(),
+            modules_loading: HashSet::new(),
+            globalenvs: Vec::new(),
+            debugger: None,
+            member_caches: InlineCaches::new(),
         };
         // create new ctx
         self.ip = 0;
-        self.localenv_bp = unsafe {
-            use std::alloc::{alloc_zeroed, Layout};
-            use std::mem::size_of;
-            let layout = Layout::from_size_align(size_of::<Env>() * CALL_STACK_SIZE, 4);
-            alloc_zeroed(layout.unwrap()) as *mut Env
-        };
+        self.localenv_bp = unsafe { alloc_call_stack(self.max_stack_depth, true) };
         self.exframes = Some(Vec::new());
         ManuallyDrop::new(current_ctx)
     }
@@ -446,8 +1010,7 @@ impl Vm {
 
         // drop old
         unsafe {
-            use std::alloc::{dealloc, Layout};
-            use std::mem;
+            use std::alloc::dealloc;
             // stack frames
             if let Some(localenv) = self.localenv {
                 let mut env = self.localenv_bp;
@@ -457,8 +1020,7 @@ impl Vm {
                 }
                 std::ptr::drop_in_place(localenv.as_ptr());
             }
-            let layout = Layout::from_size_align(mem::size_of::<Env>() * CALL_STACK_SIZE, 4);
-            dealloc(self.localenv_bp as *mut u8, layout.unwrap());
+            dealloc(self.localenv_bp as *mut u8, call_stack_layout(self.max_stack_depth));
         }
 
         // fill in
@@ -507,91 +1069,372 @@ impl Vm {
         self.ip = ip;
     }
 
+    // debugger
+    /// Attaches a debugger, which is called before every instruction.
+    pub fn attach_debugger(&mut self, debugger: Box<dyn DebugHook>) {
+        self.debugger = Some(debugger);
+        self.debug_hook = Some(vm_debug_hook);
+    }
+    /// Detaches the debugger, letting the program run at full speed.
+    ///
+    /// If this is called from inside of the debugger's hook, the
+    /// debugger is dropped once the hook returns.
+    pub fn detach_debugger(&mut self) -> Option<Box<dyn DebugHook>> {
+        self.debug_hook = None;
+        self.debugger.take()
+    }
+
     // imports
     pub fn load_module(&mut self, path: &str) {
         // loads module, jumps to the module then jump back to OP_USE
-        use crate::ast;
-        use std::io::Read;
-
         let rc = self.modules_info.clone().unwrap();
 
-        let pathobj = if path.starts_with("./") {
-            let c = rc.borrow_mut();
-            let last_path = c.files.last().unwrap();
-            let curpath = Path::new(&last_path);
-            let mut pathobj = if let Some(parent) = curpath.parent() {
-                parent.join(Path::new(path))
-            } else {
-                Path::new(path).to_path_buf()
-            };
-            if !pathobj.as_path().is_file() && pathobj.extension().is_none() {
-                pathobj.set_extension("hana");
-            }
-            pathobj
-        } else if path.starts_with('/') {
-            let mut pathobj = Path::new(path).to_path_buf();
-            if !pathobj.as_path().is_file() && pathobj.extension().is_none() {
-                pathobj.set_extension("hana");
-            }
-            pathobj
-        } else {
-            use std::env;
-            match env::var_os("HANA_PATH") {
-                Some(parent) => env::split_paths(&parent)
-                    .map(|x| {
-                        let mut pathobj = Path::new(&x).join(path).to_path_buf();
-                        if pathobj.extension().is_none() {
-                            pathobj.set_extension("hana");
-                        }
-                        pathobj
-                    })
-                    .find(|x| x.as_path().is_file())
-                    .unwrap(),
-                None => panic!("HANA_PATH not set!"),
+        let pathobj = match self.resolve_module(path) {
+            Ok(pathobj) => pathobj,
+            Err(err) => return self.raise_import_error(path, err),
+        };
+
+        if rc.borrow().modules_loaded.contains(&pathobj) {
+            return;
+        }
+
+        let module = match self.compile_module(&pathobj, path) {
+            Ok(module) => module,
+            Err(err) => return self.raise_import_error(path, err),
+        };
+        rc.borrow_mut().modules_loaded.insert(pathobj.clone());
+
+        let importer_ip = self.ip;
+        match self.append_module(module, &pathobj, path, |c| {
+            c.cpushop(VmOpcode::OP_JMP_LONG);
+            c.cpush32(importer_ip);
+        }) {
+            Ok(imported_ip) => self.ip = imported_ip,
+            Err(err) => {
+                rc.borrow_mut().modules_loaded.remove(&pathobj);
+                self.raise_import_error(path, err);
             }
+        }
+    }
+
+    pub fn load_module_as(&mut self, path: &str) {
+        // runs the module in its own global environment,
+        // then pushes a record of its global variables
+        let pathobj = match self.resolve_module(path) {
+            Ok(pathobj) => pathobj,
+            Err(err) => return self.raise_import_error(path, err),
         };
 
-        if rc.borrow_mut().modules_loaded.contains(&pathobj) {
+        if let Some(rec) = self.module_namespaces.get(&pathobj) {
+            let rec = Value::Record(rec.clone()).wrap();
+            self.stack.push(rec);
             return;
-        } else {
-            rc.borrow_mut().modules_loaded.insert(pathobj.clone());
+        }
+        if self.modules_loading.contains(&pathobj) {
+            let err = ImportError {
+                path: Some(pathobj),
+                ..ImportError::new("cyclic import".to_string())
+            };
+            return self.raise_import_error(path, err);
         }
 
-        if let Ok(mut file) = std::fs::File::open(pathobj) {
-            let mut s = String::new();
-            file.read_to_string(&mut s).unwrap();
-            let prog = ast::grammar::start(&s).unwrap();
-            rc.borrow_mut().files.push(path.to_string());
-            rc.borrow_mut().sources.push(s);
+        let module = match self.compile_module(&pathobj, path) {
+            Ok(module) => module,
+            Err(err) => return self.raise_import_error(path, err),
+        };
+        let imported_ip = match self.append_module(module, &pathobj, path, |c| {
+            c.cpushop(VmOpcode::OP_HALT);
+        }) {
+            Ok(imported_ip) => imported_ip,
+            Err(err) => return self.raise_import_error(path, err),
+        };
+        self.modules_loading.insert(pathobj.clone());
 
-            let importer_ip = self.ip;
-            let imported_ip = self.code.as_ref().unwrap().len();
-            {
-                let mut c = Compiler::new_append(
-                    self.code.take().unwrap(),
-                    rc,
-                    self.interned_strings.take().unwrap(),
-                );
-                for stmt in prog {
-                    stmt.emit(&mut c).unwrap();
+        // module environment only has the standard library's variables
+        let mut globalenv = HaruHashMap::new();
+        if let Some(stdlib) = self.stdlib.as_ref() {
+            for key in stdlib.globals.iter() {
+                if let Some(val) = self.global().get(key) {
+                    globalenv.insert(key.clone(), *val);
                 }
-                c.cpushop(VmOpcode::OP_JMP_LONG);
-                c.cpush32(importer_ip);
-                self.interned_strings = c.interned_strings.take();
-                self.code = Some(c.into_code());
             }
-            self.ip = imported_ip as u32;
+        }
+        let builtins = globalenv.clone();
+        let mut globalenv = Box::new(globalenv);
+        let importer_env = std::mem::replace(&mut self.globalenv, globalenv.as_mut());
+        self.globalenvs.push(globalenv);
+        let rc = self.modules_info.clone().unwrap();
+        let modules_loaded = std::mem::replace(
+            &mut rc.borrow_mut().modules_loaded,
+            HashSet::new(),
+        );
+
+        let ctx = self.new_exec_ctx();
+        self.jmp(imported_ip);
+        self.execute();
+        // exceptions the module doesn't handle are raised in the importer
+        let exception = if self.error == VmError::ERROR_UNHANDLED_EXCEPTION {
+            self.stack.pop()
         } else {
+            None
+        };
+        self.restore_exec_ctx(ctx);
+        self.modules_loading.remove(&pathobj);
+        if let Some(exception) = exception {
+            self.globalenv = importer_env;
+            rc.borrow_mut().modules_loaded = modules_loaded;
+            self.error = VmError::ERROR_NO_ERROR;
+            self.stack.push(exception);
+            if !self.raise() {
+                self.error = VmError::ERROR_UNHANDLED_EXCEPTION;
+            }
+            return;
+        }
+
+        // allocate while the module's variables are still reachable
+        let rec = self.malloc(Record::new());
+        let module_env = std::mem::replace(&mut self.globalenv, importer_env);
+        rc.borrow_mut().modules_loaded = modules_loaded;
+        if self.error != VmError::ERROR_NO_ERROR {
             return;
         }
+
+        for (key, val) in unsafe { &*module_env }.iter() {
+            if builtins.get(key) != Some(val) {
+                rec.as_mut().insert(key.clone(), *val);
+            }
+        }
+        self.module_namespaces.insert(pathobj, rec.clone());
+        self.stack.push(Value::Record(rec).wrap());
+    }
+
+    // appends the compiled module to the current code, returning
+    // the module's starting instruction pointer
+    fn append_module<F>(
+        &mut self, module: Compiler, pathobj: &Path, path: &str, end: F,
+    ) -> Result<u32, ImportError>
+    where
+        F: FnOnce(&mut Compiler),
+    {
+        let imported_ip = self.code.as_ref().unwrap().len();
+        let mut c = Compiler::new_append(
+            self.code.take().unwrap(),
+            self.modules_info.clone().unwrap(),
+            self.interned_strings.take().unwrap(),
+        );
+        let mut result = Ok(imported_ip as u32);
+        if c.append_module(module).is_err() {
+            // out of interned string slots, compile the module in place
+            let (files, sources, smap, locals) = {
+                let modules_info = c.modules_info.borrow();
+                (
+                    modules_info.files.len(),
+                    modules_info.sources.len(),
+                    modules_info.smap.len(),
+                    modules_info.locals.len(),
+                )
+            };
+            if let Err(err) = compile_source(&mut c, pathobj, path) {
+                // throw away whatever was emitted before the error
+                let mut code = c.take_code();
+                code.truncate(imported_ip);
+                c.receive_code(code);
+                let mut modules_info = c.modules_info.borrow_mut();
+                modules_info.files.truncate(files);
+                modules_info.sources.truncate(sources);
+                modules_info.smap.truncate(smap);
+                modules_info.locals.truncate(locals);
+                modules_info.symbol.split_off(&imported_ip);
+                result = Err(err);
+            }
+        }
+        if result.is_ok() {
+            end(&mut c);
+        }
+        self.interned_strings = c.interned_strings.take();
+        self.code = Some(c.into_code());
+        result
+    }
+
+    // finds the module's source file
+    fn resolve_module(&self, path: &str) -> Result<PathBuf, ImportError> {
+        let rc = self.modules_info.as_ref().unwrap().borrow();
+        let importer = rc.files.last().map_or("", |file| file.as_str());
+        resolve_module(path, importer).map_err(|searched| {
+            if searched.is_empty() {
+                return ImportError::new("HANA_PATH is not set".to_string());
+            }
+            let dirs: Vec<String> = searched
+                .iter()
+                .map(|dir| dir.to_string_lossy().into_owned())
+                .collect();
+            ImportError {
+                searched,
+                ..ImportError::new(format!("module not found in {}", dirs.join(", ")))
+            }
+        })
+    }
+
+    // compiles the module separately so that it can be cached and
+    // later appended to the current code
+    fn compile_module(&self, pathobj: &Path, path: &str) -> Result<Compiler, ImportError> {
+        use crate::bytecode;
+
+        if self.module_cache {
+            if let Some(c) = bytecode::read_module_cache(pathobj, self.optimize) {
+                c.modules_info.borrow_mut().files = vec![path.to_string()];
+                return Ok(c);
+            }
+        }
+
+        let mut c = Compiler::new(true);
+        compile_source(&mut c, pathobj, path)?;
+        if self.optimize {
+            c.optimize(0);
+        }
+        if self.module_cache {
+            bytecode::write_module_cache(pathobj, &c, self.optimize);
+        }
+        Ok(c)
+    }
+
+    // raises an ImportError record from the module loading error
+    fn raise_import_error(&mut self, path: &str, err: ImportError) {
+        let rec = self.malloc(Record::new());
+        if let Some(stdlib) = self.stdlib.as_ref() {
+            rec.as_mut().insert(
+                "prototype",
+                Value::Record(stdlib.import_error.clone()).wrap(),
+            );
+        }
+        rec.as_mut()
+            .insert("why", Value::Str(self.malloc(err.why.into())).wrap());
+        rec.as_mut().insert(
+            "where",
+            Value::Str(self.malloc(path.to_string().into())).wrap(),
+        );
+        if let Some(pathobj) = err.path {
+            rec.as_mut().insert(
+                "path",
+                Value::Str(self.malloc(pathobj.to_string_lossy().into_owned().into())).wrap(),
+            );
+        }
+        let searched = self.malloc(Vec::new());
+        for dir in err.searched {
+            let dir = Value::Str(self.malloc(dir.to_string_lossy().into_owned().into()));
+            searched.as_mut().push(dir.wrap());
+        }
+        rec.as_mut()
+            .insert("searched", Value::Array(searched).wrap());
+        if let Some((line, column)) = err.location {
+            rec.as_mut().insert("line", Value::Int(line as i64).wrap());
+            rec.as_mut()
+                .insert("column", Value::Int(column as i64).wrap());
+        }
+        self.stack.push(Value::Record(rec).wrap());
+        if !self.raise() {
+            self.error = VmError::ERROR_UNHANDLED_EXCEPTION;
+        }
+    }
+}
+
+/// Finds the source file of the module imported as `path` by the file `importer`.
+///
+/// Returns the directories searched if it can't be found, which is
+/// empty if the module is looked up in `HANA_PATH` and it isn't set.
+pub fn resolve_module(path: &str, importer: &str) -> Result<PathBuf, Vec<PathBuf>> {
+    let with_extension = |mut pathobj: PathBuf| {
+        if !pathobj.as_path().is_file() && pathobj.extension().is_none() {
+            pathobj.set_extension("hana");
+        }
+        pathobj
+    };
+    let searched = if path.starts_with("./") {
+        let curpath = Path::new(importer);
+        match curpath.parent() {
+            // files in the current directory have an empty parent
+            Some(parent) if !parent.as_os_str().is_empty() => vec![parent.to_path_buf()],
+            _ => vec![PathBuf::from(".")],
+        }
+    } else if path.starts_with('/') {
+        vec![PathBuf::from("/")]
+    } else {
+        use std::env;
+        match env::var_os("HANA_PATH") {
+            Some(parent) => env::split_paths(&parent).collect(),
+            None => return Err(Vec::new()),
+        }
+    };
+    searched
+        .iter()
+        .map(|parent| with_extension(parent.join(path)))
+        .find(|pathobj| pathobj.as_path().is_file())
+        .ok_or(searched)
+}
+
+// reads, parses and emits the module's source code into the compiler
+fn compile_source(c: &mut Compiler, pathobj: &Path, path: &str) -> Result<(), ImportError> {
+    use crate::ast;
+
+    let s = std::fs::read_to_string(pathobj).map_err(|err| ImportError {
+        path: Some(pathobj.to_path_buf()),
+        ..ImportError::new(format!("unable to read module: {}", err))
+    })?;
+    let prog = ast::grammar::start(&s).map_err(|err| {
+        let expected: Vec<String> = err.expected.iter().map(|x| x.to_string()).collect();
+        ImportError {
+            path: Some(pathobj.to_path_buf()),
+            location: Some((err.line, err.column)),
+            ..ImportError::new(format!(
+                "syntax error at {}:{}:{}: expected {}",
+                path,
+                err.line,
+                err.column,
+                expected.join(", ")
+            ))
+        }
+    })?;
+    c.modules_info.borrow_mut().files.push(path.to_string());
+    for stmt in prog {
+        stmt.emit(c).map_err(|err| {
+            let (line, column) = ast::pos_to_line(&s, err.span.0);
+            ImportError {
+                path: Some(pathobj.to_path_buf()),
+                location: Some((line, column)),
+                ..ImportError::new(format!(
+                    "code generation error at {}:{}:{}: {}",
+                    path, line, column, err
+                ))
+            }
+        })?;
+    }
+    c.modules_info.borrow_mut().sources.push(s);
+    Ok(())
+}
+
+// reason for failing to load a module
+struct ImportError {
+    why: String,
+    path: Option<PathBuf>,
+    searched: Vec<PathBuf>,
+    location: Option<(usize, usize)>,
+}
+
+impl ImportError {
+    fn new(why: String) -> ImportError {
+        ImportError {
+            why,
+            path: None,
+            searched: Vec::new(),
+            location: None,
+        }
     }
 }
 
 impl std::ops::Drop for Vm {
     fn drop(&mut self) {
         unsafe {
-            use std::alloc::{dealloc, Layout};
-            use std::mem;
+            use std::alloc::dealloc;
 
             // stack frames
             if let Some(localenv) = self.localenv {
@@ -602,8 +1445,7 @@ impl std::ops::Drop for Vm {
                 }
                 std::ptr::drop_in_place(localenv.as_ptr());
             }
-            let layout = Layout::from_size_align(mem::size_of::<Env>() * CALL_STACK_SIZE, 4);
-            dealloc(self.localenv_bp as *mut u8, layout.unwrap());
+            dealloc(self.localenv_bp as *mut u8, call_stack_layout(self.max_stack_depth));
         }
     }
 }
@@ -611,9 +1453,11 @@ impl std::ops::Drop for Vm {
 impl GcTraceable for Vm {
 
     unsafe fn trace(&self, vec: &mut Vec<*mut GcNode>) {
-        for (_, val) in self.global().iter() {
-            if let Some(ptr) = val.as_gc_pointer() {
-                push_gray_body(vec, ptr);
+        for globalenv in self.globalenvs.iter() {
+            for (_, val) in globalenv.iter() {
+                if let Some(ptr) = val.as_gc_pointer() {
+                    push_gray_body(vec, ptr);
+                }
             }
         }
         // stack
@@ -39,6 +39,14 @@ pub enum VmError {
     ERROR_EXPECTED_ITERABLE,
     ERROR_EXPECTED_RECORD_OF_EXPR,
     ERROR_UNKNOWN_KEY,
+    ERROR_UNEXPECTED_KEYWORD_ARGUMENT,
+    ERROR_TOO_FEW_ARGUMENTS,
+    ERROR_TOO_MANY_ARGUMENTS,
+    ERROR_PATTERN_MISMATCH,
+    ERROR_SLICE_ZERO_STEP,
+    ERROR_SLICE_ASSIGN_MISMATCH,
+    ERROR_GENERATOR_RUNNING,
+    ERROR_MAP_KEY_UNHASHABLE,
 }
 
 #[cfg_attr(tarpaulin, skip)]
@@ -90,6 +98,24 @@ impl VmError {
                 "Function expects exactly {} arguments",
                 vm.error_expected
             )),
+            VmError::ERROR_TOO_FEW_ARGUMENTS => Some(format!(
+                "Function expects at least {} arguments",
+                vm.error_expected
+            )),
+            VmError::ERROR_TOO_MANY_ARGUMENTS => Some(format!(
+                "Function expects at most {} arguments",
+                vm.error_expected
+            )),
+            VmError::ERROR_PATTERN_MISMATCH => {
+                let top = vm.stack.last().unwrap().unwrap();
+                Some(format!(
+                    "Can't destructure {} with the pattern",
+                    top.type_name()
+                ))
+            }
+            VmError::ERROR_SLICE_ASSIGN_MISMATCH => {
+                Some(format!("The slice has {} elements", vm.error_expected))
+            }
             VmError::ERROR_UNBOUNDED_ACCESS => {
                 Some(format!("Index must be between [0, {})", vm.error_expected))
             }
@@ -174,6 +200,26 @@ impl std::fmt::Display for VmError {
             VmError::ERROR_UNHANDLED_EXCEPTION => write!(f, "Unhandled exception"),
             VmError::ERROR_EXPECTED_ITERABLE => write!(f, "Expected iterable record or array"),
             VmError::ERROR_UNKNOWN_KEY => write!(f, "Unknown key"),
+            VmError::ERROR_UNEXPECTED_KEYWORD_ARGUMENT => write!(
+                f,
+                "Keyword argument is unknown, given twice or not accepted by the function"
+            ),
+            VmError::ERROR_TOO_FEW_ARGUMENTS | VmError::ERROR_TOO_MANY_ARGUMENTS => {
+                write!(f, "Argument mismatch")
+            }
+            VmError::ERROR_PATTERN_MISMATCH => {
+                write!(f, "Value doesn't match the destructuring pattern")
+            }
+            VmError::ERROR_SLICE_ZERO_STEP => write!(f, "Slice step can't be zero"),
+            VmError::ERROR_SLICE_ASSIGN_MISMATCH => write!(
+                f,
+                "Slice can only be assigned an array, of as many elements if it has a step"
+            ),
+            VmError::ERROR_GENERATOR_RUNNING => write!(f, "Generator is already running"),
+            VmError::ERROR_MAP_KEY_UNHASHABLE => write!(
+                f,
+                "Map key must be nil, a boolean, a number, a string or an array of those"
+            ),
             _ => write!(f, "[vmerror]"),
         }
     }
@@ -0,0 +1,229 @@
+mod value_ext;
+use value_ext::*;
+extern crate haru;
+use haru::ast::grammar;
+use haru::bytecode::{self, BytecodeError};
+use haru::compiler;
+use haru::hanayo;
+use haru::vmbindings::interned_string_map::InternedStringMap;
+use haru::vmbindings::vm::{Vm, VmOpcode};
+
+#[cfg(test)]
+pub mod bytecode_tests {
+
+    use super::*;
+
+    macro_rules! compile {
+        ($x:expr) => {{
+            let prog = grammar::start($x).unwrap();
+            let mut c = compiler::Compiler::new(true);
+            c.modules_info.borrow_mut().files.push("[test]".to_string());
+            for stmt in prog {
+                stmt.emit(&mut c).unwrap();
+            }
+            c.cpushop(VmOpcode::OP_HALT);
+            c.modules_info.borrow_mut().sources.push($x.to_string());
+            c
+        }};
+    }
+
+    #[test]
+    fn round_trip() {
+        let c = compile!("y = 'interned'\nfunction f() return 1\n");
+        let bytes = bytecode::serialize(&c);
+        let c2 = bytecode::deserialize(&bytes).unwrap();
+        assert_eq!(c.code_as_bytes(), c2.code_as_bytes());
+        let modules_info = c.modules_info.borrow();
+        let modules_info2 = c2.modules_info.borrow();
+        assert_eq!(modules_info.files, modules_info2.files);
+        assert_eq!(modules_info.sources, modules_info2.sources);
+        assert_eq!(modules_info.symbol, modules_info2.symbol);
+        assert_eq!(modules_info.smap.len(), modules_info2.smap.len());
+        assert_eq!(
+            c2.interned_strings.as_ref().unwrap().get(0).unwrap().as_str(),
+            "interned"
+        );
+    }
+
+    #[test]
+    fn execute_interned() {
+        let c = compile!("y = 'interned'\nz = 'interned' + ' too'\n");
+        let mut c = bytecode::deserialize(&bytecode::serialize(&c)).unwrap();
+        let mut vm: Vm = c.into_vm();
+        hanayo::init(&mut vm);
+        vm.gc_enable();
+        vm.execute();
+        assert_eq!(vm.global().get("y").unwrap().unwraps().string(), "interned");
+        assert_eq!(
+            vm.global().get("z").unwrap().unwraps().string(),
+            "interned too"
+        );
+    }
+
+    #[test]
+    fn smap_lookup() {
+        let c = compile!("y = 1\nz = x\n");
+        let c2 = bytecode::deserialize(&bytecode::serialize(&c)).unwrap();
+        let last = c.code_as_bytes().len() - 2;
+        assert_eq!(
+            c.lookup_smap(last).unwrap().file,
+            c2.lookup_smap(last).unwrap().file
+        );
+    }
+
+    #[test]
+    fn invalid_magic() {
+        assert_eq!(
+            bytecode::deserialize(b"\x00\x01\x02").err(),
+            Some(BytecodeError::InvalidMagic)
+        );
+    }
+
+    #[test]
+    fn truncated() {
+        let c = compile!("y = 1\n");
+        let bytes = bytecode::serialize(&c);
+        assert_eq!(
+            bytecode::deserialize(&bytes[..bytes.len() - 1]).err(),
+            Some(BytecodeError::UnexpectedEof)
+        );
+    }
+
+    #[test]
+    fn invalid_interned_string() {
+        let mut c = compile!("y = 'interned'\n");
+        c.interned_strings = Some(InternedStringMap::new());
+        assert_eq!(
+            bytecode::deserialize(&bytecode::serialize(&c)).err(),
+            Some(BytecodeError::InvalidInternedString(0))
+        );
+    }
+
+    #[test]
+    fn invalid_instruction() {
+        let mut c = compile!("y = 1\n");
+        let ip = c.code_as_bytes().len();
+        c.cpushop(VmOpcode::OP_PUSH32);
+        c.cpush8(0);
+        assert_eq!(
+            bytecode::deserialize(&bytecode::serialize(&c)).err(),
+            Some(BytecodeError::InvalidInstruction(ip))
+        );
+    }
+
+    // #region module cache
+    macro_rules! eval_cached {
+        ($x:expr) => {{
+            let mut c = compile!($x);
+            let mut vm: Vm = c.into_vm();
+            vm.module_cache = true;
+            hanayo::init(&mut vm);
+            vm.gc_enable();
+            vm.execute();
+            vm
+        }};
+    }
+
+    fn module_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(name);
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn module_cache_reuse() {
+        let dir = module_dir("hana_module_cache_reuse");
+        let module = dir.join("module.hana");
+        std::fs::write(&module, "x = 'module'\n").unwrap();
+        let program = format!("use '{}'\ny = x\n", module.to_str().unwrap());
+        let vm: Vm = eval_cached!(&program);
+        assert_eq!(vm.global().get("y").unwrap().unwraps().string(), "module");
+        assert!(dir.join("module.hanac").is_file());
+        assert!(bytecode::read_module_cache(&module, false).is_some());
+        let vm: Vm = eval_cached!(&program);
+        assert_eq!(vm.global().get("y").unwrap().unwraps().string(), "module");
+    }
+
+    #[test]
+    fn module_cache_optimize() {
+        let dir = module_dir("hana_module_cache_optimize");
+        let module = dir.join("module.hana");
+        std::fs::write(&module, "x = 1 + 2\n").unwrap();
+        let program = format!("use '{}'\ny = x\n", module.to_str().unwrap());
+        let vm: Vm = eval_cached!(&program);
+        assert_eq!(vm.global().get("y").unwrap().unwraps().int(), 3);
+        assert!(bytecode::read_module_cache(&module, false).is_some());
+        assert!(bytecode::read_module_cache(&module, true).is_none());
+    }
+
+    #[test]
+    fn module_cache_stale() {
+        let dir = module_dir("hana_module_cache_stale");
+        let module = dir.join("module.hana");
+        std::fs::write(&module, "x = 1\n").unwrap();
+        let program = format!("use '{}'\ny = x\n", module.to_str().unwrap());
+        let vm: Vm = eval_cached!(&program);
+        assert_eq!(vm.global().get("y").unwrap().unwraps().int(), 1);
+        std::fs::write(&module, "x = 20\n").unwrap();
+        let vm: Vm = eval_cached!(&program);
+        assert_eq!(vm.global().get("y").unwrap().unwraps().int(), 20);
+    }
+
+    #[test]
+    fn module_cache_interned() {
+        let dir = module_dir("hana_module_cache_interned");
+        let module = dir.join("module.hana");
+        std::fs::write(&module, "b = 'module'\nc = 'program'\n").unwrap();
+        let program = format!(
+            "a = 'program'\nuse '{}'\nd = 'module'\n",
+            module.to_str().unwrap()
+        );
+        for _ in 0..2 {
+            let vm: Vm = eval_cached!(&program);
+            assert_eq!(vm.global().get("a").unwrap().unwraps().string(), "program");
+            assert_eq!(vm.global().get("b").unwrap().unwraps().string(), "module");
+            assert_eq!(vm.global().get("c").unwrap().unwraps().string(), "program");
+            assert_eq!(vm.global().get("d").unwrap().unwraps().string(), "module");
+        }
+    }
+
+    #[test]
+    fn module_cache_invalid_interned() {
+        let dir = module_dir("hana_module_cache_invalid_interned");
+        let module = dir.join("module.hana");
+        std::fs::write(&module, "x = 'module'\n").unwrap();
+        let mut c = compile!("x = 'module'\n");
+        c.interned_strings = Some(InternedStringMap::new());
+        bytecode::write_module_cache(&module, &c, false);
+        assert!(dir.join("module.hanac").is_file());
+        assert!(bytecode::read_module_cache(&module, false).is_none());
+        let program = format!("use '{}'\ny = x\n", module.to_str().unwrap());
+        let vm: Vm = eval_cached!(&program);
+        assert_eq!(vm.global().get("y").unwrap().unwraps().string(), "module");
+    }
+
+    #[test]
+    fn module_cache_smap() {
+        let dir = module_dir("hana_module_cache_smap");
+        let module = dir.join("module.hana");
+        std::fs::write(&module, "x = 1\ny = undefined_var\n").unwrap();
+        let program = format!("use '{}'\n", module.to_str().unwrap());
+        for _ in 0..2 {
+            let mut c = compile!(&program);
+            let mut vm: Vm = c.into_vm();
+            vm.module_cache = true;
+            hanayo::init(&mut vm);
+            vm.gc_enable();
+            vm.execute();
+            let smap = c.lookup_smap(vm.ip() as usize).unwrap();
+            let modules_info = c.modules_info.borrow();
+            assert_eq!(modules_info.files[smap.fileno], module.to_str().unwrap());
+            assert_eq!(
+                &modules_info.sources[smap.fileno][smap.file.0..smap.file.1],
+                "undefined_var"
+            );
+        }
+    }
+    // #endregion
+}
@@ -0,0 +1,149 @@
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+#[cfg(test)]
+pub mod debugger_tests {
+
+    use super::*;
+
+    const PROGRAM: &str = "function add(a, b) begin
+    c = a + b
+    return c
+end
+x = 1
+y = add(x, 2)
+print(y, '\\n')
+";
+
+    fn program_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(name);
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // runs the program in the debugger with the commands, returning its output
+    fn debug(dir: &PathBuf, program: &str, commands: &str) -> String {
+        let file = dir.join("program.hana");
+        std::fs::write(&file, program).unwrap();
+        // target/debug/deps/debugger-* -> target/debug/haru
+        let mut path = std::env::current_exe().unwrap();
+        path.pop();
+        path.pop();
+        path.push("haru");
+        let mut child = Command::new(path)
+            .arg("--debug")
+            .arg("program.hana")
+            .current_dir(dir)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child
+            .stdin
+            .as_mut()
+            .unwrap()
+            .write_all(commands.as_bytes())
+            .unwrap();
+        let output = child.wait_with_output().unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    #[test]
+    fn breakpoint_backtrace() {
+        let dir = program_dir("hana_debugger_breakpoint");
+        let output = debug(&dir, PROGRAM, "b 2\nc\nbt\nc\n");
+        assert!(output.starts_with("program.hana:1\n1 | function add(a, b) begin\n(haru) "));
+        assert!(output.contains("breakpoint 1 at program.hana:2\n"));
+        assert!(output.contains("breakpoint 1, add@program.hana:2\n2 |     c = a + b\n"));
+        assert!(output.contains("#0 add@program.hana:2\n#1 program.hana:6\n"));
+        assert!(output.ends_with("(haru) 3\n"));
+    }
+
+    #[test]
+    fn breakpoint_next_line_with_code() {
+        let dir = program_dir("hana_debugger_breakpoint_next_line");
+        let output = debug(&dir, PROGRAM, "b 4\nb program.hana:99\nd 1\nd 1\nc\n");
+        assert!(output.contains("breakpoint 1 at program.hana:5\n"));
+        assert!(output.contains("no code at or after program.hana:99\n"));
+        assert!(output.contains("deleted breakpoint 1\n(haru) no breakpoint 1\n"));
+        assert!(!output.contains("breakpoint 1,"));
+    }
+
+    #[test]
+    fn step_next_finish() {
+        let dir = program_dir("hana_debugger_step");
+        let output = debug(&dir, PROGRAM, "n\nn\ns\ns\nfinish\nnext\n\nq\n");
+        let stops: Vec<&str> = output
+            .lines()
+            .filter(|line| line.contains(".hana:"))
+            .map(|line| line.trim_start_matches("(haru) "))
+            .collect();
+        assert_eq!(
+            stops,
+            vec![
+                "program.hana:1",
+                "program.hana:5",
+                "program.hana:6",
+                "add@program.hana:2",
+                "add@program.hana:3",
+                "program.hana:6",
+                "program.hana:7",
+            ]
+        );
+        assert!(output.ends_with("(haru) 3\n"));
+    }
+
+    #[test]
+    fn locals_globals() {
+        let dir = program_dir("hana_debugger_locals");
+        let program = "function outer(n) begin
+    m = n + 1
+    function inner() begin
+        return n * m
+    end
+    return inner()
+end
+print(outer(5), '\\n')
+";
+        let output = debug(
+            &dir,
+            program,
+            "b 4\nc\nlocals\nlocals 1\np n\np outer\np nothing\nglobals\nc\n",
+        );
+        assert!(output.contains("(haru) n = 5\nm = 6\ninner = [fn]\n"));
+        assert!(output.contains("(haru) variables at the top level are globals\n"));
+        assert!(output.contains("(haru) n = 5\n(haru) outer = [fn]\n"));
+        assert!(output.contains("(haru) no variable named nothing\n"));
+        assert!(output.contains("(haru) outer = [fn]\n(haru) 30\n"));
+    }
+
+    #[test]
+    fn module_breakpoint() {
+        let dir = program_dir("hana_debugger_module");
+        std::fs::write(
+            dir.join("module.hana"),
+            "function twice(x) begin\n    return x * 2\nend\n",
+        )
+        .unwrap();
+        let program = "use './module'\nprint(twice(2), '\\n')\n";
+        let output = debug(&dir, program, "b module.hana:2\nc\nbt\nc\n");
+        assert!(
+            output.contains("breakpoint 1 at module.hana:2 (pending until the file is loaded)\n")
+        );
+        assert!(output.contains("breakpoint 1, twice@./module:2\n2 |     return x * 2\n"));
+        assert!(output.contains("#0 twice@./module:2\n#1 program.hana:2\n"));
+        assert!(output.ends_with("(haru) 4\n"));
+    }
+
+    #[test]
+    fn unknown_command() {
+        let dir = program_dir("hana_debugger_unknown_command");
+        let output = debug(&dir, PROGRAM, "frobnicate\nf\nq\n");
+        assert!(output.contains("(haru) unknown command frobnicate, try help\n"));
+        assert!(output.contains("(haru) the top level can't be finished\n"));
+        assert!(!output.contains("3\n"));
+    }
+}
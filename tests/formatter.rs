@@ -0,0 +1,259 @@
+extern crate haru;
+use haru::ast::grammar;
+use haru::compiler;
+use haru::formatter;
+
+#[cfg(test)]
+pub mod formatter_tests {
+
+    use super::*;
+
+    macro_rules! fmt {
+        ($x:expr) => {
+            formatter::format($x).unwrap()
+        };
+    }
+
+    fn code(s: &str) -> Vec<u8> {
+        let prog = grammar::start(s).unwrap();
+        let mut c = compiler::Compiler::new(true);
+        for stmt in prog {
+            stmt.emit(&mut c).unwrap();
+        }
+        c.take_code()
+    }
+
+    // #region layout
+    #[test]
+    fn spacing() {
+        assert_eq!(
+            fmt!("x=a+b*2\nprint( x,y )\nfor i=0 to 10 step 2 then print(i)\n"),
+            "x = a + b * 2\nprint(x, y)\nfor i = 0 to 10 step 2 then print(i)\n"
+        );
+    }
+
+    #[test]
+    fn indentation() {
+        assert_eq!(
+            fmt!(
+                "
+function f(n) begin
+if n then begin
+  while n > 0 begin
+n -= 1
+      end
+    end
+return n
+end
+"
+            ),
+            "function f(n) begin
+    if n begin
+        while n > 0 begin
+            n -= 1
+        end
+    end
+    return n
+end
+"
+        );
+    }
+
+    #[test]
+    fn if_else() {
+        assert_eq!(
+            fmt!("if a then b()\n  else if c begin\nd()\nend\nelse e()\n"),
+            "if a then b()\nelse if c begin\n    d()\nend\nelse e()\n"
+        );
+    }
+
+    #[test]
+    fn blank_lines() {
+        assert_eq!(
+            fmt!("\n\nx = 1\n\n\n\ny = 2\nz = 3\n\n"),
+            "x = 1\n\ny = 2\nz = 3\n"
+        );
+    }
+
+    #[test]
+    fn records() {
+        assert_eq!(
+            fmt!(
+                "
+record Point
+x = 0
+function constructor(self, x) begin
+self.x = x
+return self
+end
+end
+p = record
+y = 1
+end
+"
+            ),
+            "record Point
+    x = 0
+    function constructor(self, x) begin
+        self.x = x
+        return self
+    end
+end
+p = record
+    y = 1
+end
+"
+        );
+    }
+
+    #[test]
+    fn try_case() {
+        assert_eq!(
+            fmt!("try\nraise IOError()\ncase IOError as e\nprint(e)\ncase Int\nprint(1)\nend\n"),
+            "try\n    raise IOError()\ncase IOError as e\n    print(e)\ncase Int\n    print(1)\nend\n"
+        );
+    }
+
+    #[test]
+    fn match_cases() {
+        assert_eq!(
+            fmt!("x = match  v // value\ncase 0  then 'zero'\ncase [a,*rest]if a>1\nprint( rest )\ncase of  P{x:0,y}then y\ncase _\nend\n"),
+            "x = match v // value\n    case 0 then 'zero'\n    case [a, *rest] if a > 1\n        print(rest)\n    case of P {x: 0, y} then y\n    case _\nend\n"
+        );
+    }
+
+    #[test]
+    fn functions() {
+        assert_eq!(
+            fmt!("f = |x,y| x+y\ng = |x| {\nreturn x\n}\nh = function(x) begin\nreturn x\nend\n"),
+            "f = |x, y| x + y\ng = |x| {\n    return x\n}\nh = function(x) begin\n    return x\nend\n"
+        );
+    }
+
+    #[test]
+    fn function_args() {
+        assert_eq!(
+            fmt!("function f(a,b=1,  *c) begin\nend\ng = | *xs |xs\nf(1,b:2)\n"),
+            "function f(a, b = 1, *c) begin\nend\ng = |*xs| xs\nf(1, b: 2)\n"
+        );
+    }
+
+    #[test]
+    fn destructuring() {
+        assert_eq!(
+            fmt!("[a,b]=[b,a]\n{x,y:z}  = p\nfor k,v in pairs then print(k)\nfunction f([a,*b],{c}) begin\nend\n"),
+            "[a, b] = [b, a]\n{x, y: z} = p\nfor [k, v] in pairs then print(k)\nfunction f([a, *b], {c}) begin\nend\n"
+        );
+    }
+    // #endregion
+
+    // #region expressions
+    #[test]
+    fn parentheses() {
+        assert_eq!(
+            fmt!("x = ((a + b)) * (c)\ny = a - (b - c)\nz = -(f(1))\nw = (|x| x)(1)\nv = (a ? b : c) ? d : e\n"),
+            "x = (a + b) * c\ny = a - (b - c)\nz = -(f(1))\nw = (|x| x)(1)\nv = (a ? b : c) ? d : e\n"
+        );
+    }
+
+    #[test]
+    fn members() {
+        assert_eq!(
+            fmt!("print(a . b, a :: b, a['b'], a[1])\nuse 'mod' as m\n"),
+            "print(a.b, a::b, a['b'], a[1])\nuse \"mod\" as m\n"
+        );
+    }
+
+    #[test]
+    fn slices() {
+        assert_eq!(
+            fmt!("print(a[ 1 : -1 ], a[:], f()[::2])\na[1:2]=[]\n"),
+            "print(a[1:-1], a[:], f()[::2])\na[1:2] = []\n"
+        );
+    }
+
+    #[test]
+    fn maps() {
+        assert_eq!(
+            fmt!("m={ }\nm = {1=>2,'a'  =>  [3]}\n"),
+            "m = {}\nm = {1 => 2, 'a' => [3]}\n"
+        );
+    }
+
+    #[test]
+    fn yield_expr() {
+        assert_eq!(
+            fmt!("function f() begin\nx = (yield   1)+1\nyield\nend\n"),
+            "function f() begin\n    x = (yield 1) + 1\n    yield\nend\n"
+        );
+    }
+    // #endregion
+
+    // #region comments
+    #[test]
+    fn comments_trailing() {
+        assert_eq!(
+            fmt!("x = 1   // one\nif x then y = 2 // two\nelse y = 3\n"),
+            "x = 1 // one\nif x then y = 2 // two\nelse y = 3\n"
+        );
+    }
+
+    #[test]
+    fn comments_own_line() {
+        assert_eq!(
+            fmt!(
+                "
+// header
+/* block
+   comment */
+function f() begin // body
+      // first
+  return 1
+      // last
+end
+// footer
+"
+            ),
+            "// header
+/* block
+   comment */
+function f() begin // body
+    // first
+    return 1
+    // last
+end
+// footer
+"
+        );
+    }
+
+    #[test]
+    fn comments_in_strings() {
+        assert_eq!(
+            fmt!("x = \"// not a comment\" // comment\n"),
+            "x = \"// not a comment\" // comment\n"
+        );
+    }
+    // #endregion
+
+    #[test]
+    fn examples() {
+        for dir in ["examples", "tests/interpreted"].iter() {
+            for entry in std::fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                let s = std::fs::read_to_string(&path).unwrap();
+                if grammar::start(&s).is_err() {
+                    continue;
+                }
+                let formatted = fmt!(&s);
+                assert_eq!(fmt!(&formatted), formatted, "{:?} isn't stable", path);
+                assert!(code(&s) == code(&formatted), "{:?} changed", path);
+            }
+        }
+    }
+
+    #[test]
+    fn syntax_error() {
+        assert!(formatter::format("x = +\n").is_err());
+    }
+}
@@ -2,8 +2,11 @@ mod value_ext;
 use value_ext::*;
 extern crate haru;
 use haru::ast::grammar;
+use haru::bytecode;
 use haru::compiler;
 use haru::hanayo;
+use haru::vmbindings::gc::GcMode;
+use haru::vmbindings::interned_string_map::InternedStringMap;
 use haru::vmbindings::value::Value;
 use haru::vmbindings::vm::{Vm, VmOpcode};
 use haru::vmbindings::vmerror::VmError;
@@ -37,7 +40,7 @@ pub mod hanayo_tests {
 y = 1 of Int
 "
         );
-        assert_eq!(vm.global().get("y").unwrap().unwraps().int(), 1);
+        assert_eq!(vm.global().get("y").unwrap().unwraps().bool(), true);
     }
 
     #[test]
@@ -52,7 +55,7 @@ end
 y = X of Y
 "
         );
-        assert_eq!(vm.global().get("y").unwrap().unwraps().int(), 1);
+        assert_eq!(vm.global().get("y").unwrap().unwraps().bool(), true);
     }
 
     #[test]
@@ -70,7 +73,7 @@ end
 y = X of Z
 "
         );
-        assert_eq!(vm.global().get("y").unwrap().unwraps().int(), 1);
+        assert_eq!(vm.global().get("y").unwrap().unwraps().bool(), true);
     }
     #[test]
     fn of_expr_not_parent() {
@@ -89,7 +92,7 @@ end
 y = X of A
 "
         );
-        assert_eq!(vm.global().get("y").unwrap().unwraps().int(), 0);
+        assert_eq!(vm.global().get("y").unwrap().unwraps().bool(), false);
     }
 
     #[test]
@@ -99,7 +102,7 @@ y = X of A
 y = (10).prototype == Int
 "
         );
-        assert_eq!(vm.global().get("y").unwrap().unwraps().int(), 1);
+        assert_eq!(vm.global().get("y").unwrap().unwraps().bool(), true);
     }
 
     #[test]
@@ -187,8 +190,51 @@ y = (16).hex()
         );
         assert_eq!(vm.global().get("y").unwrap().unwraps().string(), "0x10");
     }
+
+    #[test]
+    fn int_bigint() {
+        let vm: Vm = eval!(
+            "
+x = Int('18446744073709551616')
+y = x.hex()
+z = String(-x)
+"
+        );
+        assert_eq!(
+            vm.global().get("y").unwrap().unwraps().string(),
+            "0x10000000000000000"
+        );
+        assert_eq!(
+            vm.global().get("z").unwrap().unwraps().string(),
+            "-18446744073709551616"
+        );
+    }
     // #end
 
+    // #region bool
+    #[test]
+    fn bool_constructor() {
+        let vm: Vm = eval!(
+            "
+y = Bool('a')
+z = Bool(0)
+"
+        );
+        assert_eq!(vm.global().get("y").unwrap().unwraps().bool(), true);
+        assert_eq!(vm.global().get("z").unwrap().unwraps().bool(), false);
+    }
+
+    #[test]
+    fn bool_prototype() {
+        let vm: Vm = eval!(
+            "
+y = (1 == 1) of Bool
+"
+        );
+        assert_eq!(vm.global().get("y").unwrap().unwraps().bool(), true);
+    }
+    // #endregion
+
     // #region float
     #[test]
     fn float_constructor() {
@@ -503,7 +549,7 @@ y = '日本'.bytesize()
 y = 'abc'.startswith?('a')
 "
         );
-        assert_eq!(vm.global().get("y").unwrap().unwraps().int(), 1);
+        assert_eq!(vm.global().get("y").unwrap().unwraps().bool(), true);
     }
 
     #[test]
@@ -513,7 +559,7 @@ y = 'abc'.startswith?('a')
 y = 'abc'.endswith?('bc')
 "
         );
-        assert_eq!(vm.global().get("y").unwrap().unwraps().int(), 1);
+        assert_eq!(vm.global().get("y").unwrap().unwraps().bool(), true);
     }
 
     #[test]
@@ -676,6 +722,196 @@ record x
     b = 10
 end
 y = Record::has_key(x, 'a')
+"
+        );
+        assert_eq!(vm.global().get("y").unwrap().unwraps().bool(), true);
+    }
+    // #endregion
+
+    // #region map
+    #[test]
+    fn map_new() {
+        let vm: Vm = eval!(
+            "
+m = Map()
+m.set(1, 'a')
+m.set([1, 'b'], 'c')
+y = m.get(1)
+z = m.get([1, 'b'])
+w = m.get('missing')
+n = m.length()
+"
+        );
+        assert_eq!(vm.global().get("y").unwrap().unwraps().string(), "a");
+        assert_eq!(vm.global().get("z").unwrap().unwraps().string(), "c");
+        assert_eq!(vm.global().get("w").unwrap().unwraps(), Value::Nil);
+        assert_eq!(vm.global().get("n").unwrap().unwraps().int(), 2);
+    }
+
+    #[test]
+    fn map_has_delete() {
+        let vm: Vm = eval!(
+            "
+m = {'prototype' => 1, nil => 2}
+x = m.has?('prototype')
+y = m.delete(nil)
+z = m.has?(nil)
+w = m.delete(nil)
+"
+        );
+        assert_eq!(vm.global().get("x").unwrap().unwraps().bool(), true);
+        assert_eq!(vm.global().get("y").unwrap().unwraps().bool(), true);
+        assert_eq!(vm.global().get("z").unwrap().unwraps().bool(), false);
+        assert_eq!(vm.global().get("w").unwrap().unwraps().bool(), false);
+    }
+
+    #[test]
+    fn map_keys_values_items() {
+        let vm: Vm = eval!(
+            "
+m = {3 => 'c', 1 => 'a', 2 => 'b'}
+m.delete(1)
+m[1] = 'd'
+x = m.keys()
+y = m.values()
+z = m.items()
+"
+        );
+        let keys: Vec<i64> = vm.global().get("x").unwrap().unwraps().array()
+            .iter()
+            .map(|v| unsafe { v.unwrap() }.int())
+            .collect();
+        assert_eq!(keys, vec![3, 2, 1]);
+        let values: Vec<&String> = vm.global().get("y").unwrap().unwraps().array()
+            .iter()
+            .map(|v| unsafe { v.unwrap() }.string())
+            .collect();
+        assert_eq!(values, vec!["c", "b", "d"]);
+        let items = vm.global().get("z").unwrap().unwraps().array();
+        let item = unsafe { items[0].unwrap() }.array();
+        assert_eq!(unsafe { item[0].unwrap() }.int(), 3);
+        assert_eq!(unsafe { item[1].unwrap() }.string(), "c");
+    }
+
+    #[test]
+    fn map_string_literal_keys() {
+        let vm: Vm = eval!(
+            "
+m = {'keys' => 1, 'a' => 2}
+m['a'] += 1
+m['a key too long to be interned'] = 4
+m['f'] = |self, x| x * 2
+y = m['keys'] + m['a'] + m['a key too long to be interned']
+z = m.keys().length()
+w = m['f'](3)
+"
+        );
+        assert_eq!(vm.global().get("y").unwrap().unwraps().int(), 8);
+        assert_eq!(vm.global().get("z").unwrap().unwraps().int(), 4);
+        assert_eq!(vm.global().get("w").unwrap().unwraps().int(), 6);
+    }
+
+    #[test]
+    fn map_float_keys() {
+        let vm: Vm = eval!(
+            "
+m = {1 => 'a', 0.5 => 'b'}
+m[-0.0] = 'c'
+x = m.get(1.0)
+y = m.get(0.5)
+z = m.get(0)
+n = m.length()
+"
+        );
+        assert_eq!(vm.global().get("x").unwrap().unwraps().string(), "a");
+        assert_eq!(vm.global().get("y").unwrap().unwraps().string(), "b");
+        assert_eq!(vm.global().get("z").unwrap().unwraps().string(), "c");
+        assert_eq!(vm.global().get("n").unwrap().unwraps().int(), 3);
+    }
+
+    #[test]
+    fn map_unhashable_key() {
+        let vm: Vm = eval!(
+            "
+try
+    Map().set(Record(), 1)
+case InvalidArgumentError
+    y = 1
+end
+"
+        );
+        assert_eq!(vm.global().get("y").unwrap().unwraps().int(), 1);
+    }
+    // #endregion
+
+    // #region set
+    #[test]
+    fn set_new() {
+        let vm: Vm = eval!(
+            "
+s = Set([1, 2, 2, 'a'])
+x = s.length()
+y = s.add(3)
+z = s.add(1)
+w = s.has?('a')
+v = s.delete('a')
+u = s.has?('a')
+"
+        );
+        assert_eq!(vm.global().get("x").unwrap().unwraps().int(), 3);
+        assert_eq!(vm.global().get("y").unwrap().unwraps().bool(), true);
+        assert_eq!(vm.global().get("z").unwrap().unwraps().bool(), false);
+        assert_eq!(vm.global().get("w").unwrap().unwraps().bool(), true);
+        assert_eq!(vm.global().get("v").unwrap().unwraps().bool(), true);
+        assert_eq!(vm.global().get("u").unwrap().unwraps().bool(), false);
+    }
+
+    #[test]
+    fn set_algebra() {
+        let vm: Vm = eval!(
+            "
+a = Set([1, 2, 3])
+b = Set([2, 3, 4])
+x = 0
+for e in a.union(b) then x = x * 10 + e
+y = 0
+for e in a.intersection(b) then y = y * 10 + e
+z = 0
+for e in a.difference(b) then z = z * 10 + e
+w = Set([2, 3]).subset?(a)
+v = a.superset?(b)
+"
+        );
+        assert_eq!(vm.global().get("x").unwrap().unwraps().int(), 1234);
+        assert_eq!(vm.global().get("y").unwrap().unwraps().int(), 23);
+        assert_eq!(vm.global().get("z").unwrap().unwraps().int(), 1);
+        assert_eq!(vm.global().get("w").unwrap().unwraps().bool(), true);
+        assert_eq!(vm.global().get("v").unwrap().unwraps().bool(), false);
+    }
+
+    #[test]
+    fn set_eq() {
+        let vm: Vm = eval!(
+            "
+x = Set([1, [2, 'a']]) == Set([[2, 'a'], 1])
+y = Set([1]) == Set([1, 2])
+z = Set() != Set()
+"
+        );
+        assert_eq!(vm.global().get("x").unwrap().unwraps().bool(), true);
+        assert_eq!(vm.global().get("y").unwrap().unwraps().bool(), false);
+        assert_eq!(vm.global().get("z").unwrap().unwraps().bool(), false);
+    }
+
+    #[test]
+    fn set_unhashable() {
+        let vm: Vm = eval!(
+            "
+try
+    Set([1, Record()])
+case InvalidArgumentError
+    y = 1
+end
 "
         );
         assert_eq!(vm.global().get("y").unwrap().unwraps().int(), 1);
@@ -916,6 +1152,499 @@ y = sqrt(4.0)
     }
     // #endregion
 
+    // #region imports
+    #[test]
+    fn use_as() {
+        std::fs::write(
+            "/tmp/hana_use_as.hana",
+            "
+x = 1
+private = Int('2')
+function f() return x + private
+",
+        )
+        .unwrap();
+        let vm: Vm = eval!(
+            "
+x = 5
+use '/tmp/hana_use_as' as m
+y = m::f()
+z = m.x
+"
+        );
+        assert_eq!(vm.global().get("x").unwrap().unwraps().int(), 5);
+        assert_eq!(vm.global().get("y").unwrap().unwraps().int(), 3);
+        assert_eq!(vm.global().get("z").unwrap().unwraps().int(), 1);
+        assert!(vm.global().get("private").is_none());
+        assert!(vm.global().get("f").is_none());
+        let m = vm.global().get("m").unwrap().unwraps();
+        let m = m.record();
+        assert!(m.get("private").is_some());
+        assert!(m.get("Int").is_none());
+    }
+
+    #[test]
+    fn use_as_twice() {
+        std::fs::write("/tmp/hana_use_as_twice.hana", "x = [1]\n").unwrap();
+        let vm: Vm = eval!(
+            "
+use '/tmp/hana_use_as_twice' as a
+use '/tmp/hana_use_as_twice' as b
+y = a == b
+"
+        );
+        assert_eq!(vm.global().get("y").unwrap().unwraps().bool(), true);
+    }
+
+    #[test]
+    fn use_as_nested() {
+        std::fs::write("/tmp/hana_use_as_nested_inner.hana", "x = 10\n").unwrap();
+        std::fs::write(
+            "/tmp/hana_use_as_nested_outer.hana",
+            "use '/tmp/hana_use_as_nested_inner'\ny = x + 1\n",
+        )
+        .unwrap();
+        let vm: Vm = eval!(
+            "
+use '/tmp/hana_use_as_nested_inner'
+use '/tmp/hana_use_as_nested_outer' as m
+z = m.y
+"
+        );
+        assert_eq!(vm.global().get("x").unwrap().unwraps().int(), 10);
+        assert_eq!(vm.global().get("z").unwrap().unwraps().int(), 11);
+    }
+
+    #[test]
+    fn use_as_cyclic() {
+        std::fs::write(
+            "/tmp/hana_use_as_cyclic_a.hana",
+            "use '/tmp/hana_use_as_cyclic_b' as b\n",
+        )
+        .unwrap();
+        std::fs::write(
+            "/tmp/hana_use_as_cyclic_b.hana",
+            "use '/tmp/hana_use_as_cyclic_a' as a\n",
+        )
+        .unwrap();
+        let vm: Vm = eval!(
+            "
+try
+    use '/tmp/hana_use_as_cyclic_a' as a
+case ImportError as e
+    y = e.why
+end
+"
+        );
+        assert_eq!(
+            vm.global().get("y").unwrap().unwraps().string(),
+            "cyclic import"
+        );
+    }
+
+    #[test]
+    fn import_error_not_found() {
+        let vm: Vm = eval!(
+            "
+try
+    use '/tmp/hana_import_error_not_found'
+case ImportError as e
+    y = e.why
+    z = e.where
+end
+"
+        );
+        assert_eq!(
+            vm.global().get("y").unwrap().unwraps().string(),
+            "module not found in /"
+        );
+        assert_eq!(
+            vm.global().get("z").unwrap().unwraps().string(),
+            "/tmp/hana_import_error_not_found"
+        );
+    }
+
+    #[test]
+    fn import_error_syntax() {
+        std::fs::write("/tmp/hana_import_error_syntax.hana", "x = 1\ny = (\n").unwrap();
+        let vm: Vm = eval!(
+            "
+try
+    use '/tmp/hana_import_error_syntax'
+case ImportError as e
+    y = e.line
+    z = e.path
+end
+"
+        );
+        assert_eq!(vm.global().get("y").unwrap().unwraps().int(), 2);
+        assert_eq!(
+            vm.global().get("z").unwrap().unwraps().string(),
+            "/tmp/hana_import_error_syntax.hana"
+        );
+    }
+
+    #[test]
+    fn import_error_codegen() {
+        std::fs::write("/tmp/hana_import_error_codegen.hana", "x = 1\nbegin\nreturn x\nend\n").unwrap();
+        let vm: Vm = eval!(
+            "
+try
+    use '/tmp/hana_import_error_codegen'
+case ImportError as e
+    y = e.line
+    z = e.column
+end
+"
+        );
+        assert_eq!(vm.global().get("y").unwrap().unwraps().int(), 3);
+        assert_eq!(vm.global().get("z").unwrap().unwraps().int(), 1);
+    }
+
+    #[test]
+    fn import_error_retry() {
+        std::fs::remove_file("/tmp/hana_import_error_retry.hana").ok();
+        let vm: Vm = eval!(
+            "
+try
+    use '/tmp/hana_import_error_retry'
+case ImportError
+    File('/tmp/hana_import_error_retry.hana', 'wc').write('y = 10')
+end
+use '/tmp/hana_import_error_retry'
+"
+        );
+        assert_eq!(vm.global().get("y").unwrap().unwraps().int(), 10);
+    }
+
+    #[test]
+    fn import_error_unhandled() {
+        let vm: Vm = eval!(
+            "
+use '/tmp/hana_import_error_unhandled'
+y = 1
+"
+        );
+        assert_eq!(vm.error, VmError::ERROR_UNHANDLED_EXCEPTION);
+        assert!(vm.global().get("y").is_none());
+    }
+
+    #[test]
+    fn import_error_not_found_cwd() {
+        let vm: Vm = eval!(
+            "
+try
+    use './hana_import_error_not_found_cwd'
+case ImportError as e
+    y = e.why
+end
+"
+        );
+        assert_eq!(
+            vm.global().get("y").unwrap().unwraps().string(),
+            "module not found in ."
+        );
+    }
+
+    // imports a module from a program that is out of interned string
+    // slots, so that it's compiled again from `source` rather than
+    // appended from a module cache compiled from `cached`
+    fn import_uncached(path: &str, source: &[u8], cached: &str) -> Vm {
+        let pathobj = std::path::Path::new(path);
+        std::fs::write(pathobj, source).unwrap();
+        let mut module = compiler::Compiler::new(true);
+        for stmt in grammar::start(cached).unwrap() {
+            stmt.emit(&mut module).unwrap();
+        }
+        // the cache is only checked against the source's size and mtime
+        module
+            .modules_info
+            .borrow_mut()
+            .sources
+            .push(" ".repeat(source.len()));
+        bytecode::write_module_cache(pathobj, &module, false);
+
+        let strings = (0..=std::u16::MAX).map(|i| format!("s{}", i)).collect();
+        let mut c = compiler::Compiler::new(true);
+        c.interned_strings = Some(InternedStringMap::from_vec(strings));
+        let prog = grammar::start(&format!(
+            "
+try
+    use '{}'
+case ImportError as e
+    y = e.why
+    z = e.path
+end
+",
+            path
+        ))
+        .unwrap();
+        for stmt in prog {
+            stmt.emit(&mut c).unwrap();
+        }
+        c.cpushop(VmOpcode::OP_HALT);
+        let mut vm = c.into_vm();
+        hanayo::init(&mut vm);
+        vm.module_cache = true;
+        vm.execute();
+        vm
+    }
+
+    #[test]
+    fn import_uncached_source() {
+        let vm = import_uncached(
+            "/tmp/hana_import_uncached_source.hana",
+            b"y = 'source'\n",
+            "y = 'cached'",
+        );
+        assert_eq!(vm.error, VmError::ERROR_NO_ERROR);
+        assert_eq!(vm.global().get("y").unwrap().unwraps().string(), "source");
+    }
+
+    #[test]
+    fn import_error_uncached_syntax() {
+        let vm = import_uncached(
+            "/tmp/hana_import_error_uncached_syntax.hana",
+            b"y = (\n",
+            "y = 'cached'",
+        );
+        assert_eq!(vm.error, VmError::ERROR_NO_ERROR);
+        assert!(vm
+            .global()
+            .get("y")
+            .unwrap()
+            .unwraps()
+            .string()
+            .starts_with("syntax error at "));
+        assert_eq!(
+            vm.global().get("z").unwrap().unwraps().string(),
+            "/tmp/hana_import_error_uncached_syntax.hana"
+        );
+    }
+
+    #[test]
+    fn import_error_uncached_unreadable() {
+        let vm = import_uncached(
+            "/tmp/hana_import_error_uncached_unreadable.hana",
+            b"y = '\xff'\n",
+            "y = 'cached'",
+        );
+        assert_eq!(vm.error, VmError::ERROR_NO_ERROR);
+        assert!(vm
+            .global()
+            .get("y")
+            .unwrap()
+            .unwraps()
+            .string()
+            .starts_with("unable to read module: "));
+        assert_eq!(
+            vm.global().get("z").unwrap().unwraps().string(),
+            "/tmp/hana_import_error_uncached_unreadable.hana"
+        );
+    }
+    // #endregion
+
+    // #region stack overflow
+    #[test]
+    fn stack_overflow_caught() {
+        let vm: Vm = eval!(
+            "
+function f() begin
+    return 1 + f()
+end
+try
+    f()
+case StackOverflowError as e
+    y = e.depth
+    z = e.backtrace.length()
+end
+w = 1 + [1, 2].map(|x| x * 2)[1]
+"
+        );
+        assert_eq!(vm.global().get("y").unwrap().unwraps().int(), 10000);
+        assert_eq!(vm.global().get("z").unwrap().unwraps().int(), 10000);
+        assert_eq!(vm.global().get("w").unwrap().unwraps().int(), 5);
+    }
+
+    #[test]
+    fn stack_overflow_native_call() {
+        let vm: Vm = eval!(
+            "
+function f() begin
+    return 1 + f()
+end
+try
+    [1].map(|x| f())
+case StackOverflowError
+    y = 1
+end
+z = [1, 2].map(|x| x * 2)
+"
+        );
+        assert_eq!(vm.global().get("y").unwrap().unwraps().int(), 1);
+        assert_eq!(vm.global().get("z").unwrap().unwraps().array().len(), 2);
+    }
+
+    #[test]
+    fn stack_overflow_max_depth() {
+        let prog = grammar::start(
+            "
+function f(n) begin
+    if n == 0 then return 0
+    return 1 + f(n - 1)
+end
+y = f(40)
+try
+    f(50)
+case StackOverflowError as e
+    z = e.depth
+end
+",
+        )
+        .unwrap();
+        let mut c = compiler::Compiler::new(true);
+        for stmt in prog {
+            stmt.emit(&mut c);
+        }
+        c.cpushop(VmOpcode::OP_HALT);
+        let mut vm = c.into_vm();
+        vm.set_max_stack_depth(50);
+        hanayo::init(&mut vm);
+        vm.gc_enable();
+        vm.execute();
+        assert_eq!(vm.global().get("y").unwrap().unwraps().int(), 40);
+        assert_eq!(vm.global().get("z").unwrap().unwraps().int(), 50);
+    }
+    // #endregion
+
+    // #region gc
+    // moves old values between records that are kept alive, while
+    // allocating enough for collections to happen in between
+    const GC_PROGRAM: &str = "
+record Node
+    function constructor(self, v) begin
+        self.v = v
+        self.next = nil
+        return self
+    end
+end
+left = []
+right = []
+for i = 0 to 300 begin
+    n = Node(i)
+    n.next = Node(i)
+    left.push(n)
+    right.push(Node(0))
+end
+m = Map()
+for r = 0 to 30 begin
+    for j = 0 to 300 begin
+        right[j].next = left[j].next
+        left[j].next = nil
+        m[j] = [Node(r), 'x' + String(r)]
+        tmp = left[j]
+        left[j] = right[j]
+        right[j] = tmp
+    end
+end
+y = 0
+for n in left then y += n.next.v
+z = 0
+for k in m.keys() then z += m[k][0].v + Int(m[k][1].copy(1, 10))
+";
+
+    fn eval_with_gc(mode: GcMode) -> Vm {
+        let prog = grammar::start(GC_PROGRAM).unwrap();
+        let mut c = compiler::Compiler::new(true);
+        for stmt in prog {
+            stmt.emit(&mut c);
+        }
+        c.cpushop(VmOpcode::OP_HALT);
+        let mut vm = c.into_vm();
+        vm.set_gc_mode(mode);
+        hanayo::init(&mut vm);
+        vm.gc_enable();
+        vm.execute();
+        vm
+    }
+
+    #[test]
+    fn gc_default_mode() {
+        let vm: Vm = eval!("y = 1");
+        assert_eq!(vm.gc_mode(), GcMode::StopTheWorld);
+    }
+
+    #[test]
+    fn gc_stop_the_world() {
+        let vm = eval_with_gc(GcMode::StopTheWorld);
+        assert_eq!(vm.gc_mode(), GcMode::StopTheWorld);
+        assert_eq!(vm.global().get("y").unwrap().unwraps().int(), 44850);
+        assert_eq!(vm.global().get("z").unwrap().unwraps().int(), 17400);
+    }
+
+    #[test]
+    fn gc_incremental() {
+        let vm = eval_with_gc(GcMode::Incremental { budget: 16 });
+        assert_eq!(vm.global().get("y").unwrap().unwraps().int(), 44850);
+        assert_eq!(vm.global().get("z").unwrap().unwraps().int(), 17400);
+    }
+    // #endregion
+
+    // #region generator
+    #[test]
+    fn generator_next() {
+        let vm: Vm = eval!(
+            "
+function g() begin
+    yield 'a'
+    yield 'b'
+    return 'c'
+end
+gen = g()
+y = [gen.next(), gen.next(), gen.next(), gen.next()]
+z = gen.stopped
+"
+        );
+        let arr = vm.global().get("y").unwrap().unwraps().array();
+        assert_eq!(arr[0].unwraps().string(), "a");
+        assert_eq!(arr[1].unwraps().string(), "b");
+        assert_eq!(arr[2].unwraps().string(), "c");
+        assert_eq!(arr[3].unwraps(), Value::Nil);
+        assert_eq!(vm.global().get("z").unwrap().unwraps(), Value::True);
+    }
+
+    #[test]
+    fn generator_send() {
+        let vm: Vm = eval!(
+            "
+function sum() begin
+    total = 0
+    while true then total += yield total
+end
+gen = sum()
+gen.next()
+gen.send(2)
+y = gen.send(3)
+"
+        );
+        assert_eq!(vm.global().get("y").unwrap().unwraps(), Value::Int(5));
+    }
+
+    #[test]
+    fn generator_running() {
+        let vm: Vm = eval!(
+            "
+function g() begin
+    yield gen.next()
+end
+gen = g()
+gen.next()
+"
+        );
+        assert_eq!(vm.error, VmError::ERROR_GENERATOR_RUNNING);
+    }
+    // #endregion
+
     // #region other
     #[test]
     fn eval() {
@@ -935,6 +1664,26 @@ eval('y = "a"')
         );
         assert_eq!(vm.global().get("y").unwrap().unwraps().string(), "a");
     }
+    #[test]
+    fn example_sieve() {
+        // collect what the example prints instead of printing it
+        let source = format!(
+            "out = []\nfunction print(x, sep=nil) begin\nout.push(x)\nend\n{}",
+            include_str!("../examples/sieve.hana")
+        );
+        let vm: Vm = eval!(&source);
+        let primes: Vec<i64> = vm.global().get("out").unwrap().unwraps().array()
+            .iter()
+            .filter_map(|v| match unsafe { v.unwrap() } {
+                Value::Int(n) => Some(n),
+                _ => None,
+            })
+            .collect();
+        // there are 1229 primes below 10000
+        assert_eq!(primes.len(), 1229);
+        assert_eq!(&primes[..10], &[2, 3, 5, 7, 11, 13, 17, 19, 23, 29]);
+        assert_eq!(&primes[1224..], &[9931, 9941, 9949, 9967, 9973]);
+    }
     // #endregion
 
 }
@@ -4,7 +4,7 @@ extern crate haru;
 use haru::ast::grammar;
 use haru::compiler;
 use haru::vmbindings::value::Value;
-use haru::vmbindings::vm::{Vm, VmOpcode};
+use haru::vmbindings::vm::{instruction_size, Vm, VmOpcode};
 use haru::vmbindings::vmerror::VmError;
 
 #[cfg(test)]
@@ -90,31 +90,55 @@ pub mod interpreter_tests {
     #[test]
     fn cmp_gt() {
         let vm: Vm = eval!("y = 1 > 0");
-        assert_eq!(vm.global().get("y").unwrap().unwraps(), Value::Int(1));
+        assert_eq!(vm.global().get("y").unwrap().unwraps(), Value::True);
     }
 
     #[test]
     fn cmp_lt() {
         let vm: Vm = eval!("y = 1 < 0");
-        assert_eq!(vm.global().get("y").unwrap().unwraps(), Value::Int(0));
+        assert_eq!(vm.global().get("y").unwrap().unwraps(), Value::False);
     }
 
     #[test]
     fn cmp_gte() {
         let vm: Vm = eval!("y = 0 >= 0");
-        assert_eq!(vm.global().get("y").unwrap().unwraps(), Value::Int(1));
+        assert_eq!(vm.global().get("y").unwrap().unwraps(), Value::True);
     }
 
     #[test]
     fn cmp_lte() {
         let vm: Vm = eval!("y = 0 <= 0");
-        assert_eq!(vm.global().get("y").unwrap().unwraps(), Value::Int(1));
+        assert_eq!(vm.global().get("y").unwrap().unwraps(), Value::True);
     }
 
     #[test]
     fn cmp_eq() {
         let vm: Vm = eval!("y = 0 == 0");
-        assert_eq!(vm.global().get("y").unwrap().unwraps(), Value::Int(1));
+        assert_eq!(vm.global().get("y").unwrap().unwraps(), Value::True);
+    }
+
+    #[test]
+    fn cmp_eq_bool() {
+        let vm: Vm = eval!("y = (1 < 0) == not 1");
+        assert_eq!(vm.global().get("y").unwrap().unwraps(), Value::True);
+    }
+
+    #[test]
+    fn cmp_eq_bool_int() {
+        let vm: Vm = eval!(
+            "
+t = 0 < 1
+f = 1 < 0
+a = t == 1
+b = 1 == t
+c = 1.0 != f
+d = f != 1.0
+"
+        );
+        assert_eq!(vm.global().get("a").unwrap().unwraps(), Value::False);
+        assert_eq!(vm.global().get("b").unwrap().unwraps(), Value::False);
+        assert_eq!(vm.global().get("c").unwrap().unwraps(), Value::True);
+        assert_eq!(vm.global().get("d").unwrap().unwraps(), Value::True);
     }
 
     #[test]
@@ -220,6 +244,123 @@ x *= 3
             &"aaa".to_string()
         );
     }
+
+    #[test]
+    fn int_overflow_promotes() {
+        let vm: Vm = eval!(
+            "
+x = 9223372036854775807 + 1
+y = x * x
+z = y - y
+"
+        );
+        assert_eq!(
+            format!("{}", vm.global().get("x").unwrap().unwraps()),
+            "9223372036854775808"
+        );
+        assert_eq!(
+            format!("{}", vm.global().get("y").unwrap().unwraps()),
+            "85070591730234615865843651857942052864"
+        );
+        // results are demoted back when they fit in 64 bits
+        assert_eq!(vm.global().get("z").unwrap().unwraps(), Value::Int(0));
+    }
+
+    #[test]
+    fn bigint_literal() {
+        let vm: Vm = eval!(
+            "
+x = 123456789012345678901234567890
+y = x mod 97
+z = -9223372036854775808
+w = x > 1 and (x & 0xff) == 210
+"
+        );
+        assert_eq!(
+            format!("{}", vm.global().get("x").unwrap().unwraps()),
+            "123456789012345678901234567890"
+        );
+        assert_eq!(vm.global().get("y").unwrap().unwraps(), Value::Int(52));
+        assert_eq!(
+            vm.global().get("z").unwrap().unwraps(),
+            Value::Int(std::i64::MIN)
+        );
+        assert_eq!(vm.global().get("w").unwrap().unwraps(), Value::True);
+    }
+    // #endregion
+
+    // #region quickening
+    #[test]
+    fn quickened_arith_deoptimize() {
+        let vm: Vm = eval!(
+            "
+function add(a, b) begin
+    return a + b
+end
+x = add(1, 2)
+y = add(1.5, 2.0)
+z = add('a', 'b')
+w = add(9223372036854775807, 1)
+v = add(3, 4)
+"
+        );
+        assert_eq!(vm.global().get("x").unwrap().unwraps(), Value::Int(3));
+        assert_eq!(vm.global().get("y").unwrap().unwraps(), Value::Float(3.5));
+        assert_eq!(vm.global().get("z").unwrap().unwraps().string(), "ab");
+        assert_eq!(
+            format!("{}", vm.global().get("w").unwrap().unwraps()),
+            "9223372036854775808"
+        );
+        assert_eq!(vm.global().get("v").unwrap().unwraps(), Value::Int(7));
+        // quickened again by the last call
+        let code = vm.code.as_ref().unwrap();
+        let mut ip = 0;
+        let mut quickened = false;
+        while ip < code.len() {
+            quickened |= code[ip] == VmOpcode::OP_ADD_INT as u8;
+            ip += instruction_size(code, ip);
+        }
+        assert!(quickened);
+    }
+
+    #[test]
+    fn quickened_cmp_deoptimize() {
+        let vm: Vm = eval!(
+            "
+function lt(a, b) begin
+    return a < b
+end
+x = lt(1, 2)
+y = lt(2.5, 1.0)
+z = lt('a', 'b')
+w = lt(1, 2.5)
+v = lt(3, 2)
+"
+        );
+        assert_eq!(vm.global().get("x").unwrap().unwraps(), Value::True);
+        assert_eq!(vm.global().get("y").unwrap().unwraps(), Value::False);
+        assert_eq!(vm.global().get("z").unwrap().unwraps(), Value::True);
+        assert_eq!(vm.global().get("w").unwrap().unwraps(), Value::True);
+        assert_eq!(vm.global().get("v").unwrap().unwraps(), Value::False);
+    }
+
+    #[test]
+    fn quickened_loop_overflow() {
+        let vm: Vm = eval!(
+            "
+x = 1
+i = 0
+while i < 70 begin
+    x = x * 2
+    i = i + 1
+end
+"
+        );
+        assert_eq!(
+            format!("{}", vm.global().get("x").unwrap().unwraps()),
+            "1180591620717411303424"
+        );
+    }
     // #endregion
 
     // #region if statement
@@ -514,6 +655,88 @@ z = y()
         );
         assert_eq!(vm.global().get("z").unwrap().unwraps(), Value::Int(0));
     }
+    #[test]
+    fn function_default_args() {
+        let vm: Vm = eval!(
+            "
+function f(a, b = a * 2, c = 1) begin
+    return a + b * 10 + c * 100
+end
+x = f(1)
+y = f(1, 3)
+z = f(1, c: 3)
+"
+        );
+        assert_eq!(vm.global().get("x").unwrap().unwraps(), Value::Int(121));
+        assert_eq!(vm.global().get("y").unwrap().unwraps(), Value::Int(131));
+        assert_eq!(vm.global().get("z").unwrap().unwraps(), Value::Int(321));
+    }
+    #[test]
+    fn function_rest_args() {
+        let vm: Vm = eval!(
+            "
+function f(a, *rest) begin
+    return rest
+end
+x = match f(1)
+    case [] then 0
+end
+y = f(1, 2, 3)[1]
+z = (|*xs| xs)(1, 2)[0]
+"
+        );
+        assert_eq!(vm.global().get("x").unwrap().unwraps(), Value::Int(0));
+        assert_eq!(vm.global().get("y").unwrap().unwraps(), Value::Int(3));
+        assert_eq!(vm.global().get("z").unwrap().unwraps(), Value::Int(1));
+    }
+    #[test]
+    fn function_keyword_args() {
+        let vm: Vm = eval!(
+            "
+function f(a, b) begin
+    return a - b
+end
+record R
+    function constructor(self, x = 0, y = 0) begin
+        self.x = x
+        self.y = y
+        return self
+    end
+    function scaled(self, by = 1) begin
+        return (self.x + self.y) * by
+    end
+end
+x = f(b: 1, a: 10)
+r = R(y: 5)
+y = r.scaled(by: 2)
+"
+        );
+        assert_eq!(vm.global().get("x").unwrap().unwraps(), Value::Int(9));
+        assert_eq!(vm.global().get("y").unwrap().unwraps(), Value::Int(10));
+    }
+    #[test]
+    fn function_too_few_args() {
+        let vm: Vm = eval!(
+            "
+function f(a, b = 1) begin
+end
+f()
+"
+        );
+        assert_eq!(vm.error, VmError::ERROR_TOO_FEW_ARGUMENTS);
+        assert_eq!(vm.error_expected, 1);
+    }
+    #[test]
+    fn function_unknown_keyword_arg() {
+        let vm: Vm = eval!(
+            "
+function f(a) begin
+end
+f(1, a: 2)
+"
+        );
+        assert_eq!(vm.error, VmError::ERROR_UNEXPECTED_KEYWORD_ARGUMENT);
+    }
     /* // TODO: this test won't work because vm halts
         #[test]
         fn function_call_from_native() {
@@ -568,6 +791,336 @@ end
         );
         assert_eq!(vm.global().get("y").unwrap().unwraps(), Value::Int(10));
     }
+
+    #[test]
+    fn try_stmt_raise_in_function() {
+        let vm: Vm = eval!(
+            "
+record A
+    function constructor(self) begin
+        return self
+    end
+end
+function f(n) begin
+    if n == 0 then raise A()
+    return 1 + f(n - 1)
+end
+function g() begin
+    x = 5
+    try
+        f(10)
+    case A
+        x += 1
+    end
+    return x
+end
+y = 1 + g()
+"
+        );
+        assert_eq!(vm.global().get("y").unwrap().unwraps(), Value::Int(7));
+        assert!(vm.localenv().is_none());
+    }
+
+    #[test]
+    fn try_stmt_nested_innermost() {
+        let vm: Vm = eval!(
+            "
+record A
+    function constructor(self) begin
+        return self
+    end
+end
+y = 0
+try
+    try
+        raise A()
+    case A
+        y = 1
+    end
+    raise A()
+case A
+    y += 10
+end
+"
+        );
+        assert_eq!(vm.global().get("y").unwrap().unwraps(), Value::Int(11));
+    }
+
+    #[test]
+    fn stack_overflow_unhandled() {
+        let vm: Vm = eval!(
+            "
+function f() begin
+    return 1 + f()
+end
+f()
+"
+        );
+        assert_eq!(vm.error, VmError::ERROR_UNHANDLED_EXCEPTION);
+        assert_eq!(vm.localenv_depth(), vm.max_stack_depth());
+    }
+    // #endregion
+
+    // #region match expression
+    #[test]
+    fn match_literal() {
+        let vm: Vm = eval!(
+            "
+function f(x) begin
+    return match x
+        case 0 then 'zero'
+        case -1 then 'minus one'
+        case 1.5 then 'float'
+        case 'a' then 'string'
+        case _ then 'other'
+    end
+end
+y = [f(0), f(-1), f(1.5), f('a'), f('b'), f(2)]
+"
+        );
+        let arr = vm.global().get("y").unwrap().unwraps().array();
+        let strings: Vec<&String> = arr.iter().map(|x| x.unwraps().string()).collect();
+        assert_eq!(
+            strings,
+            vec!["zero", "minus one", "float", "string", "other", "other"]
+        );
+    }
+
+    #[test]
+    fn match_array() {
+        let vm: Vm = eval!(
+            "
+function f(x) begin
+    return match x
+        case [] then 0
+        case [a] then a
+        case [a, b] then a * b
+        case [a, *rest, b] then a + rest[0] + rest[1] + b
+        case _ then -1
+    end
+end
+y = [f([]), f([5]), f([2, 3]), f([1, 10, 100, 1000]), f(3)]
+"
+        );
+        let arr = vm.global().get("y").unwrap().unwraps().array();
+        let ints: Vec<Value> = arr.iter().map(|x| x.unwraps()).collect();
+        assert_eq!(ints[0], Value::Int(0));
+        assert_eq!(ints[1], Value::Int(5));
+        assert_eq!(ints[2], Value::Int(6));
+        assert_eq!(ints[3], Value::Int(1111));
+        assert_eq!(ints[4], Value::Int(-1));
+    }
+
+    #[test]
+    fn match_record() {
+        let vm: Vm = eval!(
+            "
+record Point
+    function constructor(self, x, y) begin
+        self.x = x
+        self.y = y
+        return self
+    end
+end
+function f(p) begin
+    return match p
+        case of Point {x: 0, y} then y
+        case of Point {x} if x > 10 then x
+        case {z} then z
+        case _ then -1
+    end
+end
+r = record
+    z = 3
+end
+y = [f(Point(0, 1)), f(Point(20, 0)), f(Point(5, 0)), f(r)]
+"
+        );
+        let arr = vm.global().get("y").unwrap().unwraps().array();
+        let ints: Vec<Value> = arr.iter().map(|x| x.unwraps()).collect();
+        assert_eq!(ints, vec![Value::Int(1), Value::Int(20), Value::Int(-1), Value::Int(3)]);
+    }
+
+    #[test]
+    fn match_stmts() {
+        let vm: Vm = eval!(
+            "
+y = 0
+z = match [1, 2]
+    case [a, b]
+        y = a + b
+end
+w = match 1
+    case 2 then 3
+end
+"
+        );
+        assert_eq!(vm.global().get("y").unwrap().unwraps(), Value::Int(3));
+        assert_eq!(vm.global().get("z").unwrap().unwraps(), Value::Nil);
+        assert_eq!(vm.global().get("w").unwrap().unwraps(), Value::Nil);
+    }
+    // #endregion
+
+    // #region destructuring
+    #[test]
+    fn destructure_array() {
+        let vm: Vm = eval!(
+            "
+a = 1
+b = 2
+[a, b] = [b, a]
+[x, *rest, y] = [1, 2, 3, 4]
+"
+        );
+        assert_eq!(vm.global().get("a").unwrap().unwraps(), Value::Int(2));
+        assert_eq!(vm.global().get("b").unwrap().unwraps(), Value::Int(1));
+        assert_eq!(vm.global().get("x").unwrap().unwraps(), Value::Int(1));
+        assert_eq!(vm.global().get("y").unwrap().unwraps(), Value::Int(4));
+        let arr = vm.global().get("rest").unwrap().unwraps().array();
+        let ints: Vec<Value> = arr.iter().map(|x| x.unwraps()).collect();
+        assert_eq!(ints, vec![Value::Int(2), Value::Int(3)]);
+    }
+
+    #[test]
+    fn destructure_record() {
+        let vm: Vm = eval!(
+            "
+point = record
+    x = 1
+    y = 2
+end
+{x, y: [z, _]} = record
+    x = 3
+    y = [4, 5]
+end
+function f() begin
+    {x, y} = point
+    return x + y
+end
+w = f()
+"
+        );
+        assert_eq!(vm.global().get("x").unwrap().unwraps(), Value::Int(3));
+        assert_eq!(vm.global().get("z").unwrap().unwraps(), Value::Int(4));
+        assert_eq!(vm.global().get("w").unwrap().unwraps(), Value::Int(3));
+    }
+
+    #[test]
+    fn destructure_for_in() {
+        let vm: Vm = eval!(
+            "
+y = 0
+for a, b in [[1, 2], [3, 4]] then y += a * b
+z = 0
+for [a, *rest] in [[1, 2, 3], [4]] then z += a
+"
+        );
+        assert_eq!(vm.global().get("y").unwrap().unwraps(), Value::Int(14));
+        assert_eq!(vm.global().get("z").unwrap().unwraps(), Value::Int(5));
+    }
+
+    #[test]
+    fn destructure_function_args() {
+        let vm: Vm = eval!(
+            "
+function f([a, b], {c}, d = 1) begin
+    return a + b + c + d
+end
+r = record
+    c = 10
+end
+y = f([1, 2], r)
+"
+        );
+        assert_eq!(vm.global().get("y").unwrap().unwraps(), Value::Int(14));
+    }
+
+    #[test]
+    fn destructure_mismatch() {
+        let vm: Vm = eval!("[a, b] = [1]");
+        assert_eq!(vm.error, VmError::ERROR_PATTERN_MISMATCH);
+    }
+    // #endregion
+
+    // #region generators
+    #[test]
+    fn generator_for_in() {
+        let vm: Vm = eval!(
+            "
+function range(lo, hi) begin
+    i = lo
+    while i < hi begin
+        yield i
+        i += 1
+    end
+end
+y = 0
+for x in range(1, 4) then y = y * 10 + x
+"
+        );
+        assert_eq!(vm.global().get("y").unwrap().unwraps().int(), 123);
+    }
+
+    #[test]
+    fn generator_nested() {
+        let vm: Vm = eval!(
+            "
+function chars(s) begin
+    for c in s then yield c
+end
+function pairs(s, t) begin
+    for a in chars(s) begin
+        for b in chars(t) then yield a + b
+    end
+end
+y = ''
+for p in pairs('ab', 'xy') then y = y + p + ' '
+"
+        );
+        assert_eq!(
+            vm.global().get("y").unwrap().unwraps().string(),
+            &"ax ay bx by ".to_string()
+        );
+    }
+
+    #[test]
+    fn generator_closure() {
+        let vm: Vm = eval!(
+            "
+function counter(by) begin
+    return function() begin
+        n = 0
+        while n < 3 begin
+            n += by
+            yield n
+        end
+        return 'unused'
+    end
+end
+y = 0
+for x in counter(2)() then y += x
+"
+        );
+        assert_eq!(vm.global().get("y").unwrap().unwraps().int(), 2 + 4);
+    }
+
+    #[test]
+    fn generator_finished() {
+        let vm: Vm = eval!(
+            "
+function g() begin
+    yield 1
+end
+gen = g()
+y = 0
+for x in gen then y += x
+for x in gen then y += 10
+z = gen.stopped
+"
+        );
+        assert_eq!(vm.global().get("y").unwrap().unwraps().int(), 1);
+        assert_eq!(vm.global().get("z").unwrap().unwraps().bool(), true);
+    }
     // #endregion
 
     // #region record
@@ -627,6 +1180,25 @@ y = A['x']
         assert_eq!(vm.global().get("y").unwrap().unwraps().int(), 10);
     }
 
+    #[test]
+    fn memexpr_indexed_record_static() {
+        let prog = grammar::start("y = A['x']\nA['f'](1)\n").unwrap();
+        let mut c = compiler::Compiler::new(true);
+        for stmt in prog {
+            stmt.emit(&mut c).unwrap();
+        }
+        let code = c.code_as_bytes();
+        let mut ops = Vec::new();
+        let mut ip = 0;
+        while ip < code.len() {
+            ops.push(code[ip]);
+            ip += instruction_size(code, ip);
+        }
+        assert!(ops.contains(&(VmOpcode::OP_MEMBER_INDEX_GET as u8)));
+        assert!(ops.contains(&(VmOpcode::OP_MEMBER_INDEX_GET_NO_POP as u8)));
+        assert!(!ops.contains(&(VmOpcode::OP_INDEX_GET as u8)));
+    }
+
     #[test]
     fn memexpr_indexed_record_key() {
         let vm: Vm = eval!(
@@ -763,6 +1335,147 @@ y = a.test()
         );
         assert_eq!(vm.global().get("y").unwrap().unwraps(), Value::Int(10));
     }
+
+    #[test]
+    fn record_member_cache_shadowed() {
+        let vm: Vm = eval!(
+            "
+record A
+    x = 1
+end
+record B
+    prototype = A
+end
+function get(r) begin
+    return r.x
+end
+a = get(B)
+B.x = 2
+b = get(B)
+A.x = 3
+y = [a, b, get(B)]
+"
+        );
+        let arr = vm.global().get("y").unwrap().unwraps().array();
+        let ints: Vec<Value> = arr.iter().map(|x| x.unwraps()).collect();
+        assert_eq!(ints, vec![Value::Int(1), Value::Int(2), Value::Int(2)]);
+    }
+
+    #[test]
+    fn record_member_cache_prototype_changed() {
+        let vm: Vm = eval!(
+            "
+record A
+    x = 1
+end
+record B
+    x = 2
+end
+record C
+    prototype = A
+end
+function get(r) begin
+    return r.x
+end
+a = get(C)
+A.x = 3
+b = get(C)
+C.prototype = B
+y = [a, b, get(C)]
+"
+        );
+        let arr = vm.global().get("y").unwrap().unwraps().array();
+        let ints: Vec<Value> = arr.iter().map(|x| x.unwraps()).collect();
+        assert_eq!(ints, vec![Value::Int(1), Value::Int(3), Value::Int(2)]);
+    }
+
+    #[test]
+    fn record_member_cache_same_shape() {
+        let vm: Vm = eval!(
+            "
+record A
+    function constructor(self, x) begin
+        self.x = x
+        return self
+    end
+end
+function get(r) begin
+    return r.x
+end
+y = [get(A(1)), get(A(2)), get(A(3))]
+"
+        );
+        let arr = vm.global().get("y").unwrap().unwraps().array();
+        let ints: Vec<Value> = arr.iter().map(|x| x.unwraps()).collect();
+        assert_eq!(ints, vec![Value::Int(1), Value::Int(2), Value::Int(3)]);
+    }
+    // #endregion
+
+    // #region map
+    #[test]
+    fn map_literal() {
+        let vm: Vm = eval!(
+            "
+m = {1 => 'a', 1.5 => 'b', 'prototype' => 'c', [1, 'x'] => 'd', 1 == 2 => 'e'}
+y = m[1] + m[1.5] + m['prototype'] + m[[1, 'x']] + m[not 1]
+"
+        );
+        assert_eq!(vm.global().get("y").unwrap().unwraps().string(), "abcde");
+    }
+
+    #[test]
+    fn map_index_set() {
+        let vm: Vm = eval!(
+            "
+m = {}
+key = [1, 2]
+m[key] = 1
+key[0] = 3
+m[[1, 2]] += 1
+m[[3, 2]] = 10
+y = m[[1, 2]]
+z = m[key]
+"
+        );
+        assert_eq!(vm.global().get("y").unwrap().unwraps().int(), 2);
+        assert_eq!(vm.global().get("z").unwrap().unwraps().int(), 10);
+    }
+
+    #[test]
+    fn map_for_in() {
+        let vm: Vm = eval!(
+            "
+m = {3 => 0, 1 => 0}
+m[2] = 0
+m[3] = 1
+y = 0
+for k in m then y = y * 10 + k
+for k in {} then y = 0
+"
+        );
+        assert_eq!(vm.global().get("y").unwrap().unwraps().int(), 312);
+    }
+
+    #[test]
+    fn map_get_unk() {
+        let vm: Vm = eval!(
+            "
+y = {1 => 2}['1']
+"
+        );
+        assert_eq!(vm.error, VmError::ERROR_UNKNOWN_KEY);
+    }
+
+    #[test]
+    fn map_key_unhashable() {
+        let vm: Vm = eval!(
+            "
+m = {}
+m[{}] = 1
+"
+        );
+        assert_eq!(vm.error, VmError::ERROR_MAP_KEY_UNHASHABLE);
+    }
     // #endregion
 
     // #region array
@@ -828,6 +1541,81 @@ y = a[0]
             &"x".to_string()
         );
     }
+
+    #[test]
+    fn array_negative_index() {
+        let vm: Vm = eval!(
+            "
+a = [1, 2, 3]
+a[-1] = 4
+y = a[-3] + a[-1]
+"
+        );
+        assert_eq!(vm.global().get("y").unwrap().unwraps(), Value::Int(5));
+    }
+
+    #[test]
+    fn array_slice() {
+        let vm: Vm = eval!(
+            "
+a = [0, 1, 2, 3, 4, 5]
+b = a[1:3]
+c = a[:-2]
+d = a[::-2]
+e = a[4:100]
+"
+        );
+        let ints = |name: &str| -> Vec<Value> {
+            let arr = vm.global().get(name).unwrap().unwraps().array();
+            arr.iter().map(|x| x.unwraps()).collect()
+        };
+        assert_eq!(ints("b"), vec![Value::Int(1), Value::Int(2)]);
+        assert_eq!(ints("c").len(), 4);
+        assert_eq!(ints("d"), vec![Value::Int(5), Value::Int(3), Value::Int(1)]);
+        assert_eq!(ints("e"), vec![Value::Int(4), Value::Int(5)]);
+    }
+
+    #[test]
+    fn array_slice_set() {
+        let vm: Vm = eval!(
+            "
+a = [0, 1, 2, 3]
+a[1:3] = [5, 6, 7]
+b = [0, 1, 2, 3]
+b[::2] = [4, 5]
+"
+        );
+        let ints = |name: &str| -> Vec<Value> {
+            let arr = vm.global().get(name).unwrap().unwraps().array();
+            arr.iter().map(|x| x.unwraps()).collect()
+        };
+        assert_eq!(
+            ints("a"),
+            vec![Value::Int(0), Value::Int(5), Value::Int(6), Value::Int(7), Value::Int(3)]
+        );
+        assert_eq!(
+            ints("b"),
+            vec![Value::Int(4), Value::Int(1), Value::Int(5), Value::Int(3)]
+        );
+    }
+
+    #[test]
+    fn array_slice_set_mismatch() {
+        let vm: Vm = eval!(
+            "
+a = [0, 1, 2, 3]
+a[::2] = [1]
+"
+        );
+        assert_eq!(vm.error, VmError::ERROR_SLICE_ASSIGN_MISMATCH);
+        assert_eq!(vm.error_expected, 2);
+    }
+
+    #[test]
+    fn array_slice_zero_step() {
+        let vm: Vm = eval!("a = [1][::0]");
+        assert_eq!(vm.error, VmError::ERROR_SLICE_ZERO_STEP);
+    }
     // #endregion
 
     // #region string
@@ -844,6 +1632,39 @@ y = a[0]
             &"a".to_string()
         );
     }
+
+    #[test]
+    fn string_slice() {
+        let vm: Vm = eval!(
+            "
+a = 'héllo🇫🇷'
+y = a[-1]
+z = a[1:3] + a[::-1]
+"
+        );
+        assert_eq!(
+            vm.global().get("y").unwrap().unwraps().string(),
+            &"🇫🇷".to_string()
+        );
+        assert_eq!(
+            vm.global().get("z").unwrap().unwraps().string(),
+            &"él🇫🇷olléh".to_string()
+        );
+    }
+
+    #[test]
+    fn string_interpolation() {
+        let vm: Vm = eval!(
+            "
+name = 'bob'
+y = \"#{name}, #{1 + 1}#{\"!#{1.5}\"}\"
+"
+        );
+        assert_eq!(
+            vm.global().get("y").unwrap().unwraps().string(),
+            &"bob, 2!1.5".to_string()
+        );
+    }
     // #endregion
 
     // #region modules
@@ -0,0 +1,317 @@
+extern crate haru;
+use haru::ast::{ast, grammar};
+use haru::diagnostics::Severity;
+use haru::hanayo;
+use haru::lint::{self, DefinitionKind};
+use haru::vmbindings::vm::Vm;
+
+#[cfg(test)]
+pub mod lint_tests {
+
+    use super::*;
+
+    macro_rules! lint {
+        ($x:expr) => {{
+            let prog = grammar::start($x).unwrap();
+            let mut vm = Vm::new(None, None, None);
+            hanayo::init(&mut vm);
+            let builtins: Vec<String> = vm.global().keys().map(|key| key.to_string()).collect();
+            let diagnostics = lint::lint(&prog, "/tmp/lint.hana", &builtins);
+            let messages: Vec<(Severity, String)> = diagnostics
+                .into_iter()
+                .map(|diagnostic| (diagnostic.severity, diagnostic.message))
+                .collect();
+            messages
+        }};
+    }
+
+    fn warning(message: &str) -> (Severity, String) {
+        (Severity::Warning, message.to_string())
+    }
+
+    fn error(message: &str) -> (Severity, String) {
+        (Severity::Error, message.to_string())
+    }
+
+    // #region variables
+    #[test]
+    fn clean_program() {
+        let diagnostics = lint!(
+            "
+function add(a, b) begin
+    c = a + b
+    return c
+end
+x = add(1, 2)
+print(x, y)
+y = 10
+"
+        );
+        assert!(diagnostics.is_empty());
+    }
+
+    #[test]
+    fn unused_local() {
+        let diagnostics = lint!(
+            "
+function f() begin
+    a = 1
+    _b = 2
+    return 0
+end
+"
+        );
+        assert_eq!(diagnostics, vec![warning("unused local variable `a`")]);
+    }
+
+    #[test]
+    fn local_used_by_closure() {
+        let diagnostics = lint!(
+            "
+function f() begin
+    a = 1
+    return function() begin
+        return a
+    end
+end
+"
+        );
+        assert!(diagnostics.is_empty());
+    }
+
+    #[test]
+    fn undefined_global() {
+        let diagnostics = lint!(
+            "
+function f() begin
+    return undefined_var + $also_undefined
+end
+print(undefined_var)
+"
+        );
+        assert_eq!(
+            diagnostics,
+            vec![
+                warning("undefined global variable `undefined_var`"),
+                warning("undefined global variable `also_undefined`"),
+            ]
+        );
+    }
+
+    #[test]
+    fn global_from_function() {
+        let diagnostics = lint!(
+            "
+function f() begin
+    $x = 1
+end
+print(x)
+"
+        );
+        assert!(diagnostics.is_empty());
+    }
+
+    #[test]
+    fn global_from_module() {
+        std::fs::write("/tmp/hana_lint_module.hana", "module_var = 1\n").unwrap();
+        let diagnostics = lint!(
+            "
+use './hana_lint_module'
+print(module_var)
+"
+        );
+        assert!(diagnostics.is_empty());
+    }
+
+    #[test]
+    fn module_not_found() {
+        let diagnostics = lint!(
+            "
+use './hana_lint_nonexistent'
+print(module_var)
+"
+        );
+        assert_eq!(
+            diagnostics,
+            vec![warning("can't find module `./hana_lint_nonexistent`")]
+        );
+    }
+
+    #[test]
+    fn shadowed_argument() {
+        let diagnostics = lint!(
+            "
+function f(a, a) begin
+    g = function(a) begin
+        return a
+    end
+    h = function() begin
+        a = 2
+        return a
+    end
+    return g(h())
+end
+"
+        );
+        assert_eq!(
+            diagnostics,
+            vec![
+                warning("duplicate argument `a`"),
+                warning("argument `a` shadows a variable of the enclosing function"),
+                warning("assignment to `a` shadows an argument of the enclosing function"),
+            ]
+        );
+    }
+
+    #[test]
+    fn match_bindings() {
+        let diagnostics = lint!(
+            "
+function f(x) begin
+    return match x
+        case [a, *rest] if a > 0 then rest
+        case {key: [b, _]} then c
+        case of Missing then 0
+    end
+end
+"
+        );
+        assert_eq!(
+            diagnostics,
+            vec![
+                warning("unused local variable `b`"),
+                warning("undefined global variable `c`"),
+                warning("undefined global variable `Missing`"),
+            ]
+        );
+    }
+
+    #[test]
+    fn destructuring() {
+        let diagnostics = lint!(
+            "
+function f([a, b], {c}) begin
+    [x, *y] = a
+    for k, v in b then print(v)
+    return x + c + d
+end
+"
+        );
+        assert_eq!(
+            diagnostics,
+            vec![
+                warning("unused local variable `y`"),
+                warning("undefined global variable `d`"),
+            ]
+        );
+    }
+
+    #[test]
+    fn yield_outside_function() {
+        let diagnostics = lint!("yield 1\n");
+        assert_eq!(
+            diagnostics,
+            vec![error("yield expression outside of a function")]
+        );
+    }
+
+    #[test]
+    fn default_and_keyword_args() {
+        let diagnostics = lint!(
+            "
+function f(a, b = a + 1, *rest) begin
+    return f(b, a: rest, b: c)
+end
+"
+        );
+        assert_eq!(
+            diagnostics,
+            vec![warning("undefined global variable `c`")]
+        );
+    }
+    // #endregion
+
+    // #region control flow
+    #[test]
+    fn return_outside_function() {
+        let diagnostics = lint!("if true then return 1\n");
+        assert_eq!(
+            diagnostics,
+            vec![error("return statement outside of a function")]
+        );
+    }
+
+    #[test]
+    fn break_outside_loop() {
+        let diagnostics = lint!(
+            "
+while true begin
+    break
+end
+if true then continue
+"
+        );
+        assert_eq!(
+            diagnostics,
+            vec![error("continue statement outside of a loop")]
+        );
+    }
+
+    #[test]
+    fn break_in_function_in_loop() {
+        let diagnostics = lint!(
+            "
+while true begin
+    f = function() begin
+        break
+    end
+end
+"
+        );
+        assert_eq!(
+            diagnostics,
+            vec![error("break statement outside of a loop")]
+        );
+    }
+
+    #[test]
+    fn unreachable_code() {
+        let diagnostics = lint!(
+            "
+function f(x) begin
+    if x then return 1
+    else raise 2
+    print(x)
+    print(x)
+end
+"
+        );
+        assert_eq!(diagnostics, vec![warning("unreachable code")]);
+    }
+    // #endregion
+
+    // #region definitions
+    #[test]
+    fn resolve_definitions() {
+        let prog = grammar::start("function f(a) begin\n    b = a\n    return b\nend\nf(1)\n").unwrap();
+        let analysis = lint::analyze(&prog, "/tmp/lint.hana", &[]);
+        let resolved: Vec<(ast::Span, &str, &DefinitionKind)> = analysis
+            .references
+            .iter()
+            .map(|reference| {
+                let definition = &analysis.definitions[reference.definition];
+                (reference.span, definition.name.as_str(), &definition.kind)
+            })
+            .collect();
+        assert_eq!(
+            resolved,
+            vec![
+                ((28, 29), "a", &DefinitionKind::Argument),
+                ((41, 42), "b", &DefinitionKind::Variable),
+                ((47, 48), "f", &DefinitionKind::Function(vec!["a".to_string()])),
+            ]
+        );
+    }
+    // #endregion
+
+}
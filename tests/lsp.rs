@@ -0,0 +1,225 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::process::{Child, ChildStdout, Command, Stdio};
+
+#[cfg(test)]
+pub mod lsp_tests {
+
+    use super::*;
+
+    const URI: &str = "file:///tmp/lsp.hana";
+
+    // scripted client talking to the haru-lsp binary
+    struct Client {
+        child: Child,
+        stdout: BufReader<ChildStdout>,
+        id: usize,
+    }
+
+    impl Client {
+        fn new(text: &str) -> Client {
+            // target/debug/deps/lsp-* -> target/debug/haru-lsp
+            let mut path = std::env::current_exe().unwrap();
+            path.pop();
+            path.pop();
+            path.push("haru-lsp");
+            let mut child = Command::new(path)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .spawn()
+                .unwrap();
+            let stdout = BufReader::new(child.stdout.take().unwrap());
+            let mut client = Client {
+                child,
+                stdout,
+                id: 0,
+            };
+            client.request("initialize", "{\"capabilities\":{}}");
+            client.notify("initialized", "{}");
+            client.notify(
+                "textDocument/didOpen",
+                &format!(
+                    "{{\"textDocument\":{{\"uri\":\"{}\",\"languageId\":\"hana\",\"version\":1,\"text\":{:?}}}}}",
+                    URI, text
+                ),
+            );
+            client
+        }
+
+        fn send(&mut self, body: &str) {
+            let stdin = self.child.stdin.as_mut().unwrap();
+            write!(stdin, "Content-Length: {}\r\n\r\n{}", body.len(), body).unwrap();
+            stdin.flush().unwrap();
+        }
+
+        fn receive(&mut self) -> String {
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                self.stdout.read_line(&mut line).unwrap();
+                let line = line.trim_end();
+                if line.is_empty() {
+                    break;
+                }
+                if line.starts_with("Content-Length: ") {
+                    length = line["Content-Length: ".len()..].parse().unwrap();
+                }
+            }
+            let mut body = vec![0; length];
+            self.stdout.read_exact(&mut body).unwrap();
+            String::from_utf8(body).unwrap()
+        }
+
+        fn notify(&mut self, method: &str, params: &str) {
+            self.send(&format!(
+                "{{\"jsonrpc\":\"2.0\",\"method\":\"{}\",\"params\":{}}}",
+                method, params
+            ));
+        }
+
+        // sends a request, returning its response
+        fn request(&mut self, method: &str, params: &str) -> String {
+            self.id += 1;
+            self.send(&format!(
+                "{{\"jsonrpc\":\"2.0\",\"id\":{},\"method\":\"{}\",\"params\":{}}}",
+                self.id, method, params
+            ));
+            loop {
+                let message = self.receive();
+                if message.contains(&format!("\"id\":{},", self.id)) {
+                    return message;
+                }
+            }
+        }
+
+        fn request_at(&mut self, method: &str, line: usize, character: usize) -> String {
+            self.request(
+                method,
+                &format!(
+                    "{{\"textDocument\":{{\"uri\":\"{}\"}},\"position\":{{\"line\":{},\"character\":{}}}}}",
+                    URI, line, character
+                ),
+            )
+        }
+
+        fn exit(mut self) -> i32 {
+            self.request("shutdown", "null");
+            self.notify("exit", "null");
+            self.child.wait().unwrap().code().unwrap()
+        }
+    }
+
+    fn range(start: (usize, usize), end: (usize, usize)) -> String {
+        format!(
+            "\"range\":{{\"start\":{{\"line\":{},\"character\":{}}},\"end\":{{\"line\":{},\"character\":{}}}}}",
+            start.0, start.1, end.0, end.1
+        )
+    }
+
+    #[test]
+    fn diagnostics() {
+        let mut client = Client::new("x = +\nfunction f() begin\n    break\nend\n");
+        let message = client.receive();
+        assert!(message.contains("\"method\":\"textDocument/publishDiagnostics\""));
+        assert!(message.contains(&range((0, 4), (0, 5))));
+        assert!(message.contains("\"message\":\"unexpected `+`, expected expression\""));
+        assert!(message.contains(&range((2, 4), (2, 9))));
+        assert!(message.contains("\"message\":\"break statement outside of a loop\""));
+        assert_eq!(client.exit(), 0);
+    }
+
+    #[test]
+    fn diagnostics_on_change() {
+        let mut client = Client::new("x = 1\n");
+        assert!(client.receive().contains("\"diagnostics\":[]"));
+        client.notify(
+            "textDocument/didChange",
+            &format!(
+                "{{\"textDocument\":{{\"uri\":\"{}\",\"version\":2}},\"contentChanges\":[{{\"text\":\"x = (1\\n\"}}]}}",
+                URI
+            ),
+        );
+        assert!(client.receive().contains("`(` on line 1 is never closed"));
+        assert_eq!(client.exit(), 0);
+    }
+
+    #[test]
+    fn document_symbols() {
+        let mut client = Client::new(
+            "function f(a) begin\n    return a\nend\nrecord R\n    function g() begin\n    end\nend\n",
+        );
+        let message = client.request(
+            "textDocument/documentSymbol",
+            &format!("{{\"textDocument\":{{\"uri\":\"{}\"}}}}", URI),
+        );
+        assert!(message.contains(&format!(
+            "{{\"name\":\"f\",\"kind\":12,{},\"selectionRange\":{{\"start\":{{\"line\":0,\"character\":9}}",
+            range((0, 0), (2, 3))
+        )));
+        assert!(message.contains(&format!(
+            "{{\"name\":\"R\",\"kind\":23,{}",
+            range((3, 0), (6, 3))
+        )));
+        assert!(message.contains(&format!(
+            "\"children\":[{{\"name\":\"g\",\"kind\":12,{}",
+            range((4, 4), (5, 7))
+        )));
+        assert_eq!(client.exit(), 0);
+    }
+
+    #[test]
+    fn definition_local() {
+        let mut client = Client::new("function f(a) begin\n    b = a\n    return b\nend\n");
+        let message = client.request_at("textDocument/definition", 2, 11);
+        assert!(message.contains(&range((1, 4), (1, 5))));
+        let message = client.request_at("textDocument/definition", 1, 8);
+        assert!(message.contains(&range((0, 11), (0, 12))));
+        assert_eq!(client.exit(), 0);
+    }
+
+    #[test]
+    fn definition_global() {
+        let mut client = Client::new("function f() begin\n    return x\nend\nx = 1\nprint(f())\n");
+        let message = client.request_at("textDocument/definition", 1, 11);
+        assert!(message.contains(&format!("\"uri\":\"{}\"", URI)));
+        assert!(message.contains(&range((3, 0), (3, 1))));
+        let message = client.request_at("textDocument/definition", 4, 6);
+        assert!(message.contains(&range((0, 9), (0, 10))));
+        let message = client.request_at("textDocument/definition", 4, 1);
+        assert!(message.contains("\"result\":null"));
+        assert_eq!(client.exit(), 0);
+    }
+
+    #[test]
+    fn hover_arity() {
+        let mut client =
+            Client::new("function add(a, b) begin\n    return a + b\nend\nprint(add(1, 2))\n");
+        let message = client.request_at("textDocument/hover", 3, 7);
+        assert!(message.contains("function add(a, b)"));
+        assert!(message.contains("takes 2 arguments"));
+        let message = client.request_at("textDocument/hover", 3, 2);
+        assert!(message.contains("`print`: Function from the standard library"));
+        assert_eq!(client.exit(), 0);
+    }
+
+    #[test]
+    fn completion() {
+        let mut client = Client::new("my_var = 1\n");
+        let message = client.request_at("textDocument/completion", 0, 0);
+        assert!(
+            message.contains("{\"label\":\"print\",\"kind\":3,\"detail\":\"standard library\"}")
+        );
+        assert!(
+            message.contains("{\"label\":\"String\",\"kind\":7,\"detail\":\"standard library\"}")
+        );
+        assert!(message.contains("{\"label\":\"my_var\",\"kind\":6}"));
+        assert_eq!(client.exit(), 0);
+    }
+
+    #[test]
+    fn unknown_method() {
+        let mut client = Client::new("");
+        let message = client.request("textDocument/unknown", "{}");
+        assert!(message.contains("\"error\":{\"code\":-32601"));
+        assert_eq!(client.exit(), 0);
+    }
+}
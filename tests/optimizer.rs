@@ -0,0 +1,203 @@
+mod value_ext;
+use value_ext::*;
+extern crate haru;
+use haru::ast::grammar;
+use haru::compiler;
+use haru::hanayo;
+use haru::vmbindings::value::Value;
+use haru::vmbindings::vm::{instruction_size, Vm, VmOpcode};
+
+#[cfg(test)]
+pub mod optimizer_tests {
+
+    use super::*;
+
+    macro_rules! compile {
+        ($x:expr) => {{
+            let prog = grammar::start($x).unwrap();
+            let mut c = compiler::Compiler::new(true);
+            c.modules_info.borrow_mut().files.push("[test]".to_string());
+            for stmt in prog {
+                stmt.emit(&mut c).unwrap();
+            }
+            c.optimize(0);
+            c.cpushop(VmOpcode::OP_HALT);
+            c.modules_info.borrow_mut().sources.push($x.to_string());
+            c
+        }};
+    }
+
+    macro_rules! eval {
+        ($x:expr) => {{
+            let mut c = compile!($x);
+            let mut vm = c.into_vm();
+            vm.gc_enable();
+            vm.execute();
+            vm
+        }};
+    }
+
+    // opcodes of the instructions in the code
+    fn opcodes(c: &compiler::Compiler) -> Vec<u8> {
+        let code = c.code_as_bytes();
+        let mut ops = Vec::new();
+        let mut ip = 0;
+        while ip < code.len() {
+            ops.push(code[ip]);
+            ip += instruction_size(code, ip);
+        }
+        ops
+    }
+
+    fn contains(c: &compiler::Compiler, op: VmOpcode) -> bool {
+        opcodes(c).contains(&(op as u8))
+    }
+
+    // #region constant folding
+    #[test]
+    fn fold_int_arithmetic() {
+        let c = compile!("y = 1 + 2 * 3 - 4");
+        assert!(!contains(&c, VmOpcode::OP_ADD));
+        assert!(!contains(&c, VmOpcode::OP_MUL));
+        assert!(!contains(&c, VmOpcode::OP_SUB));
+        let vm: Vm = eval!("y = 1 + 2 * 3 - 4");
+        assert_eq!(vm.global().get("y").unwrap().unwraps(), Value::Int(3));
+    }
+
+    #[test]
+    fn fold_negative_result() {
+        let vm: Vm = eval!("y = 1 - 300");
+        assert_eq!(vm.global().get("y").unwrap().unwraps(), Value::Int(-299));
+    }
+
+    #[test]
+    fn fold_float_arithmetic() {
+        let c = compile!("y = 1.5 * -2.0");
+        assert!(!contains(&c, VmOpcode::OP_MUL));
+        assert!(!contains(&c, VmOpcode::OP_NEGATE));
+        let vm: Vm = eval!("y = 1.5 * -2.0");
+        assert_eq!(vm.global().get("y").unwrap().unwraps(), Value::Float(-3.0));
+    }
+
+    #[test]
+    fn fold_keeps_overflow() {
+        // promoted to a big integer at runtime
+        let c = compile!("y = 9223372036854775807 + 1");
+        assert!(contains(&c, VmOpcode::OP_ADD));
+    }
+
+    #[test]
+    fn fold_keeps_mod_by_zero() {
+        let c = compile!("y = 1 mod 0");
+        assert!(contains(&c, VmOpcode::OP_MOD));
+    }
+
+    #[test]
+    fn fold_string_concat() {
+        let c = compile!("y = 'hello' + ' ' + 'world'");
+        assert!(!contains(&c, VmOpcode::OP_ADD));
+        let vm: Vm = eval!("y = 'hello' + ' ' + 'world'");
+        assert_eq!(
+            vm.global().get("y").unwrap().unwraps().string(),
+            "hello world"
+        );
+    }
+    // #endregion
+
+    // #region dead code and jumps
+    #[test]
+    fn dead_code_after_return() {
+        let unoptimized = {
+            let prog = grammar::start("function f() begin\nreturn 1\ny = 2\nend").unwrap();
+            let mut c = compiler::Compiler::new(true);
+            for stmt in prog {
+                stmt.emit(&mut c).unwrap();
+            }
+            c.code_as_bytes().len()
+        };
+        let c = compile!("function f() begin\nreturn 1\ny = 2\nend");
+        assert!(c.code_as_bytes().len() < unoptimized);
+        assert!(!contains(&c, VmOpcode::OP_SET_LOCAL_POP));
+        let vm: Vm = eval!("function f() begin\nreturn 1\ny = 2\nend\nz = f()");
+        assert_eq!(vm.global().get("z").unwrap().unwraps(), Value::Int(1));
+    }
+
+    #[test]
+    fn jump_threading() {
+        let prog = "
+a = 1
+y = 0
+while a < 5 begin
+    if a > 2 begin
+        if a > 3 then y = y + 1
+        else y = y + 2
+    end
+    else y = y + 4
+    a += 1
+end
+";
+        let c = compile!(prog);
+        let code = c.code_as_bytes();
+        let mut ip = 0;
+        while ip < code.len() {
+            if code[ip] == VmOpcode::OP_JMP as u8 {
+                let label = i16::from_be_bytes([code[ip + 1], code[ip + 2]]);
+                let target = (ip as isize + 1 + label as isize) as usize;
+                assert_ne!(code[target], VmOpcode::OP_JMP as u8);
+            }
+            ip += instruction_size(code, ip);
+        }
+        let vm: Vm = eval!(prog);
+        assert_eq!(vm.global().get("y").unwrap().unwraps(), Value::Int(11));
+    }
+
+    #[test]
+    fn not_condition() {
+        let c = compile!("a = 0\nif not a then y = 1\nelse y = 2");
+        assert!(!contains(&c, VmOpcode::OP_NOT));
+        let vm: Vm = eval!("a = 0\nif not a then y = 1\nelse y = 2");
+        assert_eq!(vm.global().get("y").unwrap().unwraps(), Value::Int(1));
+    }
+    // #endregion
+
+    // #region superinstructions
+    #[test]
+    fn set_global_pop() {
+        let c = compile!("y = 1");
+        assert!(contains(&c, VmOpcode::OP_SET_GLOBAL_POP));
+        assert!(!contains(&c, VmOpcode::OP_POP));
+    }
+
+    #[test]
+    fn set_local_pop() {
+        let prog = "function f() begin\nx = 1\nz = 2\nreturn x + z\nend\ny = f()";
+        let c = compile!(prog);
+        assert!(contains(&c, VmOpcode::OP_SET_LOCAL_POP));
+        let vm: Vm = eval!(prog);
+        assert_eq!(vm.global().get("y").unwrap().unwraps(), Value::Int(3));
+    }
+
+    #[test]
+    fn set_local_get_local() {
+        // x = 1; return x keeps the set value on the stack
+        let c = compile!("function f() begin\nx = 1\nreturn x\nend");
+        assert!(!contains(&c, VmOpcode::OP_GET_LOCAL));
+        let vm: Vm = eval!("function f() begin\nx = 1\nreturn x\nend\ny = f()");
+        assert_eq!(vm.global().get("y").unwrap().unwraps(), Value::Int(1));
+    }
+    // #endregion
+
+    // #region source map
+    #[test]
+    fn error_location() {
+        let prog = "y = 1 + 2 * 3\nz = undefined_var\n";
+        let mut c = compile!(prog);
+        let mut vm: Vm = c.into_vm();
+        hanayo::init(&mut vm);
+        vm.gc_enable();
+        vm.execute();
+        let smap = c.lookup_smap(vm.ip() as usize).unwrap();
+        assert_eq!(&prog[smap.file.0..smap.file.1], "undefined_var");
+    }
+    // #endregion
+}
@@ -5,6 +5,7 @@ pub mod parser_tests {
 
     use haru::ast::ast;
     use haru::ast::grammar;
+    use haru::diagnostics;
 
     macro_rules! parse_ast_statement {
         ($x:expr) => {
@@ -58,6 +59,18 @@ pub mod parser_tests {
         assert_eq!(cast_box!(stmt.expr, ast::IntLiteral).val, 0xf);
     }
 
+    #[test]
+    fn big_int() {
+        let progast: Vec<std::boxed::Box<ast::AST>> =
+            parse_ast_statement!("0x10000000000000000");
+        assert_eq!(progast.len(), 1);
+        let stmt = cast_box!(progast[0], ast::ExprStatement);
+        assert_eq!(
+            cast_box!(stmt.expr, ast::BigIntLiteral).val.to_string(),
+            "18446744073709551616"
+        );
+    }
+
     #[test]
     fn simple_float() {
         let progast: Vec<std::boxed::Box<ast::AST>> = parse_ast_statement!("12.6");
@@ -65,6 +78,49 @@ pub mod parser_tests {
         let stmt = cast_box!(progast[0], ast::ExprStatement);
         assert_eq!(cast_box!(stmt.expr, ast::FloatLiteral).val, 12.6);
     }
+
+    #[test]
+    fn simple_str_escapes() {
+        let progast: Vec<std::boxed::Box<ast::AST>> =
+            parse_ast_statement!("'\\u{48}\\u{1F338}\\x41\\x7e\\#{a}'");
+        let stmt = cast_box!(progast[0], ast::ExprStatement);
+        assert_eq!(cast_box!(stmt.expr, ast::StrLiteral).val, "H🌸A~#{a}");
+        assert!(grammar::start("'\\xff'").is_err());
+        assert!(grammar::start("'\\u{d800}'").is_err());
+    }
+
+    #[test]
+    fn str_interpolation() {
+        let progast: Vec<std::boxed::Box<ast::AST>> =
+            parse_ast_statement!("\"hello #{name}, #{age + 1}\"");
+        let stmt = cast_box!(progast[0], ast::ExprStatement);
+        let expr = cast_box!(stmt.expr, ast::StrInterpolation);
+        assert_eq!(expr.parts.len(), 4);
+        assert_eq!(cast_box!(expr.parts[0], ast::StrLiteral).val, "hello ");
+        assert_eq!(cast_box!(expr.parts[1], ast::Identifier).val, "name");
+        assert_eq!(cast_box!(expr.parts[2], ast::StrLiteral).val, ", ");
+        cast_box!(expr.parts[3], ast::BinExpr);
+        // single quoted strings and escaped interpolations are kept as they are
+        let progast: Vec<std::boxed::Box<ast::AST>> =
+            parse_ast_statement!("'#{a}' + \"\\#{a}\"");
+        let stmt = cast_box!(progast[0], ast::ExprStatement);
+        let expr = cast_box!(stmt.expr, ast::BinExpr);
+        assert_eq!(cast_box!(expr.left, ast::StrLiteral).val, "#{a}");
+        assert_eq!(cast_box!(expr.right, ast::StrLiteral).val, "#{a}");
+    }
+
+    #[test]
+    fn map_expr() {
+        let progast: Vec<std::boxed::Box<ast::AST>> =
+            parse_ast_statement!("{1 => 'a', [x, 2.0] => {}}");
+        let stmt = cast_box!(progast[0], ast::ExprStatement);
+        let expr = cast_box!(stmt.expr, ast::MapExpr);
+        assert_eq!(expr.entries.len(), 2);
+        assert_eq!(cast_box!(expr.entries[0].0, ast::IntLiteral).val, 1);
+        assert_eq!(cast_box!(expr.entries[0].1, ast::StrLiteral).val, "a");
+        cast_box!(expr.entries[1].0, ast::ArrayExpr);
+        assert_eq!(cast_box!(expr.entries[1].1, ast::MapExpr).entries.len(), 0);
+    }
     // #endregion
 
     // #region simple comments
@@ -104,6 +160,23 @@ multiline
         assert_eq!(cast_box!(memexpr.left, ast::Identifier).val, "a");
         assert_eq!(cast_box!(memexpr.right, ast::StrLiteral).val, "b");
     }
+
+    #[test]
+    fn slice_expr() {
+        let progast: Vec<std::boxed::Box<ast::AST>> = parse_ast_statement!("a[1:].b[::-1]");
+        assert_eq!(progast.len(), 1);
+        let stmt = cast_box!(progast[0], ast::ExprStatement);
+        let slice = cast_box!(stmt.expr, ast::SliceExpr);
+        assert!(slice.from.is_none());
+        assert!(slice.to.is_none());
+        assert!(slice.step.is_some());
+        let memexpr = cast_box!(slice.left, ast::MemExpr);
+        let slice = cast_box!(memexpr.left, ast::SliceExpr);
+        assert_eq!(cast_box!(slice.left, ast::Identifier).val, "a");
+        assert!(slice.from.is_some());
+        assert!(slice.to.is_none());
+        assert!(slice.step.is_none());
+    }
     // #endregion
 
     // #region call expr
@@ -118,6 +191,20 @@ multiline
         assert_eq!(cast_box!(callexpr.args[0], ast::IntLiteral).val, 1);
         assert_eq!(cast_box!(callexpr.args[1], ast::IntLiteral).val, 2);
     }
+
+    #[test]
+    fn call_expr_kwargs() {
+        let progast: Vec<std::boxed::Box<ast::AST>> = parse_ast_statement!("a(1, b: 2, c:3)");
+        let stmt = cast_box!(progast[0], ast::ExprStatement);
+        let callexpr = cast_box!(stmt.expr, ast::CallExpr);
+        assert_eq!(callexpr.args.len(), 1);
+        assert_eq!(callexpr.kwargs.len(), 2);
+        assert_eq!(callexpr.kwargs[0].0, "b");
+        assert_eq!(cast_box!(callexpr.kwargs[0].1, ast::IntLiteral).val, 2);
+        assert_eq!(callexpr.kwargs[1].0, "c");
+        // positional args can't follow keyword args
+        assert!(grammar::start("a(b: 2, 1)").is_err());
+    }
     // #endregion
 
     // #region bin expr
@@ -293,6 +380,142 @@ end
     }
     // #endregion
 
+    // #region match expression
+    #[test]
+    fn match_expr() {
+        let progast: Vec<std::boxed::Box<ast::AST>> = parse_ast_statement!(
+            "
+y = match x
+    case 0 then 1
+    case [a, *rest, b] if a > b then rest
+    case of Point {x: 0, y}
+        print(y)
+    case _ then 2
+end
+"
+        );
+        let stmt = cast_box!(progast[0], ast::ExprStatement);
+        let expr = cast_box!(stmt.expr, ast::BinExpr);
+        let expr = cast_box!(expr.right, ast::MatchExpr);
+        assert_eq!(expr.cases.len(), 4);
+        match &expr.cases[1].pattern {
+            ast::Pattern::Array(elems, Some((1, _))) => assert_eq!(elems.len(), 2),
+            _ => panic!("expected array pattern with a rest element"),
+        }
+        assert!(expr.cases[1].guard.is_some());
+        match &expr.cases[2].pattern {
+            ast::Pattern::Of(_, fields) => assert_eq!(fields.len(), 2),
+            _ => panic!("expected of pattern"),
+        }
+        match &expr.cases[2].body {
+            ast::MatchBody::Stmts(stmts) => assert_eq!(stmts.len(), 1),
+            _ => panic!("expected statements"),
+        }
+        assert!(format!("{:?}", expr).contains("\"type\": \"match\""));
+    }
+
+    #[test]
+    fn match_expr_multiple_rest() {
+        assert!(grammar::start("match x
+case [*a, *b] then 0
+end
+").is_err());
+    }
+    // #endregion
+
+    // #region destructuring
+    #[test]
+    fn destructure_expr() {
+        let progast: Vec<std::boxed::Box<ast::AST>> = parse_ast_statement!(
+            "
+[a, *rest] = x
+{x, y: [z]} = p
+"
+        );
+        let stmt = cast_box!(progast[0], ast::ExprStatement);
+        let expr = cast_box!(stmt.expr, ast::DestructureExpr);
+        match &expr.pattern {
+            ast::Pattern::Array(elems, Some((1, _))) => assert_eq!(elems.len(), 1),
+            _ => panic!("expected array pattern with a rest element"),
+        }
+        let stmt = cast_box!(progast[1], ast::ExprStatement);
+        let expr = cast_box!(stmt.expr, ast::DestructureExpr);
+        match &expr.pattern {
+            ast::Pattern::Record(fields) => assert_eq!(fields.len(), 2),
+            _ => panic!("expected record pattern"),
+        }
+    }
+
+    #[test]
+    fn destructure_for_in() {
+        let progast: Vec<std::boxed::Box<ast::AST>> =
+            parse_ast_statement!("for k, v in pairs then print(k)");
+        let stmt = cast_box!(progast[0], ast::ForInStatement);
+        match &stmt.pattern {
+            ast::Pattern::Array(elems, None) => assert_eq!(elems.len(), 2),
+            _ => panic!("expected array pattern"),
+        }
+    }
+
+    #[test]
+    fn destructure_fn_args() {
+        let progast: Vec<std::boxed::Box<ast::AST>> = parse_ast_statement!(
+            "
+function f(a, [b, c], {d}) begin
+end
+"
+        );
+        let stmt = cast_box!(progast[0], ast::FunctionStatement);
+        let def = stmt.def();
+        assert_eq!(def.args.len(), 3);
+        assert_eq!(def.args[0], "a");
+        assert_eq!(def.patterns.len(), 2);
+        assert_eq!(def.patterns[0].0, 1);
+        assert_eq!(def.patterns[1].0, 2);
+    }
+    // #endregion
+
+    // #region generators
+    #[test]
+    fn yield_expr() {
+        let progast: Vec<std::boxed::Box<ast::AST>> = parse_ast_statement!(
+            "
+function f() begin
+    x = yield 1 + 2
+    yield
+end
+"
+        );
+        let stmt = cast_box!(progast[0], ast::FunctionStatement);
+        let block = cast_box!(stmt.def().stmt, ast::BlockStatement);
+        let stmt = cast_box!(block.stmts[0], ast::ExprStatement);
+        let assign = cast_box!(stmt.expr, ast::BinExpr);
+        let expr = cast_box!(assign.right, ast::YieldExpr);
+        cast_box!(expr.expr.as_ref().unwrap(), ast::BinExpr);
+        let stmt = cast_box!(block.stmts[1], ast::ExprStatement);
+        assert!(cast_box!(stmt.expr, ast::YieldExpr).expr.is_none());
+    }
+    // #endregion
+
+    // #region use statement
+    #[test]
+    fn use_stmt() {
+        let progast: Vec<std::boxed::Box<ast::AST>> = parse_ast_statement!("use 'module'");
+        let stmt = cast_box!(progast[0], ast::UseStatement);
+        assert_eq!(stmt.path, "module");
+        assert!(stmt.namespace.is_none());
+    }
+
+    #[test]
+    fn use_stmt_as() {
+        let progast: Vec<std::boxed::Box<ast::AST>> =
+            parse_ast_statement!("use 'module' as ns");
+        let stmt = cast_box!(progast[0], ast::UseStatement);
+        assert_eq!(stmt.path, "module");
+        assert_eq!(stmt.namespace.as_ref().unwrap(), "ns");
+    }
+    // #endregion
+
     // #region function statement
     #[test]
     fn fn_stmt() {
@@ -308,6 +531,31 @@ end
         assert_eq!(stmt.def().args.len(), 1);
         assert_eq!(stmt.def().args[0], "y");
     }
+
+    #[test]
+    fn fn_stmt_default_rest_args() {
+        let progast: Vec<std::boxed::Box<ast::AST>> = parse_ast_statement!(
+            "
+function X(a, b = 1, c = a + b, *d) begin
+
+end
+"
+        );
+        let stmt = cast_box!(progast[0], ast::FunctionStatement);
+        assert_eq!(stmt.def().args, vec!["a", "b", "c", "d"]);
+        assert_eq!(stmt.def().defaults.len(), 2);
+        assert_eq!(cast_box!(stmt.def().defaults[0], ast::IntLiteral).val, 1);
+        assert!(stmt.def().rest);
+        assert_eq!(stmt.def().nrequired(), 1);
+    }
+
+    #[test]
+    fn fn_stmt_args_order() {
+        assert!(grammar::start("function X(a = 1, b) begin\nend\n").is_err());
+        assert!(grammar::start("function X(*a, b) begin\nend\n").is_err());
+        assert!(grammar::start("function X(*a, *b) begin\nend\n").is_err());
+        assert!(grammar::start("f = |a, *b| a").is_ok());
+    }
     // #endregion
 
     // #region nested
@@ -352,4 +600,135 @@ outer()
     }
     // #endregion
 
+    // #region code generation errors
+    macro_rules! emit_error {
+        ($x:expr) => {{
+            let prog = grammar::start($x).unwrap();
+            let mut c = haru::compiler::Compiler::new(true);
+            let mut error = None;
+            for stmt in prog {
+                if let Err(err) = stmt.emit(&mut c) {
+                    error = Some(err);
+                    break;
+                }
+            }
+            error.unwrap()
+        }};
+    }
+
+    #[test]
+    fn codegen_error_invalid_lhs() {
+        let err = emit_error!("a = 1\n1 = 2");
+        assert_eq!(err.kind, ast::CodeGenErrorKind::InvalidLeftHandSide);
+        assert_eq!(err.span, (6, 7));
+        assert_eq!(err.message(), "invalid left hand side of assignment");
+    }
+
+    #[test]
+    fn codegen_error_expected_identifier() {
+        let err = emit_error!("f(1) = 2");
+        assert_eq!(err.kind, ast::CodeGenErrorKind::ExpectedIdentifier);
+        assert_eq!(err.span, (2, 3));
+    }
+
+    #[test]
+    fn codegen_error_return_outside_function() {
+        let err = emit_error!("x = 1\nbegin\nreturn x\nend");
+        assert_eq!(err.kind, ast::CodeGenErrorKind::ExpectedInFunction);
+        assert_eq!(err.span.0, 12);
+    }
+
+    #[test]
+    fn codegen_error_yield_outside_function() {
+        let err = emit_error!("x = yield 1");
+        assert_eq!(err.kind, ast::CodeGenErrorKind::ExpectedYieldInFunction);
+        assert_eq!(err.span, (4, 11));
+    }
+
+    #[test]
+    fn codegen_error_break_outside_loop() {
+        let err = emit_error!("if x then break\n");
+        assert_eq!(err.kind, ast::CodeGenErrorKind::ExpectedInLoop);
+        assert_eq!(err.span, (10, 15));
+    }
+    // #endregion
+
+    // #region error recovery
+    #[test]
+    fn recover_multiple_errors() {
+        let (prog, errors) = diagnostics::parse("x = (\ny = 1\nz = 2\nw = +\n");
+        assert_eq!(prog.len(), 1);
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].span.0, 12);
+        assert_eq!(errors[0].message, "unexpected `z`, `(` on line 1 is never closed");
+        assert_eq!(errors[1].message, "unexpected `+`, expected expression");
+    }
+
+    #[test]
+    fn recover_unclosed_paren_at_end() {
+        let (_, errors) = diagnostics::parse("y = (");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].span.0, 5);
+        assert_eq!(errors[0].message, "unexpected end of file, expected `[`, `{`, expression");
+
+        let (_, errors) = diagnostics::parse("x = (1 +");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].span.0, 8);
+        assert_eq!(errors[0].message, "unexpected end of file, expected expression");
+
+        let (_, errors) = diagnostics::parse("x = ((1 +");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].span.0, 9);
+    }
+
+    #[test]
+    fn recover_in_block() {
+        let (prog, errors) = diagnostics::parse(
+            "
+function f(a) begin
+    x = a +
+    y = a *
+    return a
+end
+f(1)
+",
+        );
+        assert_eq!(prog.len(), 2);
+        assert_eq!(errors.len(), 2);
+    }
+
+    #[test]
+    fn recover_missing_end() {
+        let (_, errors) = diagnostics::parse("x = 1\nwhile x begin\n    x = 0\n");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].message,
+            "missing `end`: the statement starting at line 2 is never closed"
+        );
+    }
+
+    #[test]
+    fn recover_then_without_stmt() {
+        let (_, errors) = diagnostics::parse("if x then\n");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].span, (5, 9));
+        assert_eq!(errors[0].message, "expected a statement after `then`");
+    }
+
+    #[test]
+    fn recover_unterminated_string() {
+        let (_, errors) = diagnostics::parse("x = 1\nprint('abc)\n");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].span, (12, 13));
+        assert_eq!(errors[0].message, "unterminated string literal");
+    }
+
+    #[test]
+    fn recover_no_errors() {
+        let (prog, errors) = diagnostics::parse("x = 1\ny = 2\n");
+        assert_eq!(prog.len(), 2);
+        assert!(errors.is_empty());
+    }
+    // #endregion
+
 }
@@ -5,6 +5,7 @@ use haru::vmbindings::value::Value;
 
 pub trait ValueExt {
     fn int(&self) -> i64;
+    fn bool(&self) -> bool;
     fn float(&self) -> f64;
     fn string(&self) -> &'static String;
     fn array(&self) -> &'static Vec<NativeValue>;
@@ -23,6 +24,17 @@ impl ValueExt for Value {
         }
     }
 
+    #[cfg_attr(tarpaulin, skip)]
+    fn bool(&self) -> bool {
+        match self {
+            Value::True => true,
+            Value::False => false,
+            _ => {
+                panic!("Expected boolean");
+            }
+        }
+    }
+
     #[cfg_attr(tarpaulin, skip)]
     fn float(&self) -> f64 {
         match self {
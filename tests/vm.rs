@@ -191,7 +191,7 @@ pub mod vm_tests {
         let mut vm = c.into_vm();
         vm.execute();
         assert_eq!(vm.stack.len(), 1);
-        assert_eq!(vm.stack.last().unwrap().unwraps(), Value::Int(0));
+        assert_eq!(vm.stack.last().unwrap().unwraps(), Value::False);
     }
 
     #[test]